@@ -0,0 +1,121 @@
+use std::collections::HashMap;
+use crate::ast::{Expr, Module, Ty, Type, TypeAlias};
+use crate::error::{ParseError, ParseET};
+use crate::source::span::Span;
+use crate::util::cycles::{cycle_error, Cycles};
+
+impl Module {
+    /// Replaces every use of a type alias by the type it stands for, in signatures, constants and function bodies.
+    pub(crate) fn resolve_aliases(&mut self) -> Result<(), ParseError> {
+        let mut resolved = HashMap::new();
+        let mut names = self.aliases.keys().cloned().collect::<Vec<_>>();
+        // sorted, so a cycle is always reported starting from the same alias
+        names.sort();
+        for name in names {
+            expand_alias(&name, &self.aliases, &mut resolved, &mut Cycles::new())?;
+        }
+        let mut lookup = |name: &str, _: &Span| Ok(resolved.get(name).cloned());
+        for func in self.functions.values_mut() {
+            for (_, ty) in &mut func.args {
+                ty.substitute(&mut lookup)?;
+            }
+            func.ret.substitute(&mut lookup)?;
+            if let Some(body) = &mut func.body {
+                body.walk_mut(&mut |expr| match &mut expr.1 {
+                    Expr::VarCreate(_, _, Some(ty), _) | Expr::LetTuple(_, Some(ty), _) | Expr::Ascribe(_, ty) => ty.substitute(&mut lookup),
+                    _ => Ok(())
+                })?;
+            }
+        }
+        for constant in self.constants.values_mut() {
+            constant.ty.substitute(&mut lookup)?;
+            constant.val.walk_mut(&mut |expr| match &mut expr.1 {
+                Expr::VarCreate(_, _, Some(ty), _) | Expr::LetTuple(_, Some(ty), _) | Expr::Ascribe(_, ty) => ty.substitute(&mut lookup),
+                _ => Ok(())
+            })?;
+        }
+        for s in self.statics.values_mut() {
+            s.ty.substitute(&mut lookup)?;
+        }
+        for module in self.sub_modules.values_mut() {
+            module.resolve_aliases()?;
+        }
+        Ok(())
+    }
+}
+
+/// Target of the alias with all aliases inside of it expanded, `cycles` tracks the aliases currently being expanded.
+fn expand_alias(name: &str, aliases: &HashMap<String, TypeAlias>, resolved: &mut HashMap<String, Type>, cycles: &mut Cycles<String>) -> Result<Type, ParseError> {
+    if let Some(ty) = resolved.get(name) {
+        return Ok(ty.clone())
+    }
+    cycles.enter(&name.to_string()).map_err(|cycle| cycle_error("type alias", &cycle, |a| aliases[a].name.1.clone()))?;
+    let mut ty = aliases[name].ty.clone();
+    ty.substitute(&mut |n, _| if aliases.contains_key(n) {
+        expand_alias(n, aliases, resolved, cycles).map(Some)
+    } else {
+        Ok(None)
+    })?;
+    cycles.leave();
+    resolved.insert(name.to_string(), ty.clone());
+    Ok(ty)
+}
+
+impl Type {
+    /// Replaces single segment named types for which `lookup` returns a type, recursing into all parts of the type.
+    /// The replacement keeps the span of the replaced use.
+    fn substitute<F: FnMut(&str, &Span) -> Result<Option<Type>, ParseError>>(&mut self, lookup: &mut F) -> Result<(), ParseError> {
+        match &mut self.0 {
+            Ty::Single(generics, item) => {
+                for generic in generics.iter_mut() {
+                    generic.substitute(lookup)?;
+                }
+                if let [name] = item.0.as_slice() {
+                    if let Some(target) = lookup(&name.0, &self.1)? {
+                        if !generics.is_empty() {
+                            return Err(ParseET::CompilationError(format!("type alias {} does not take generic arguments", name.0)).at(self.1.clone()))
+                        }
+                        self.0 = target.0;
+                    }
+                }
+            }
+            Ty::Pointer(ty) | Ty::Array(ty, _) | Ty::Slice(ty) => ty.substitute(lookup)?,
+            Ty::Tuple(tys) => tys.iter_mut().try_for_each(|ty| ty.substitute(lookup))?,
+            Ty::Signature(args, ret, _, _) => {
+                args.iter_mut().try_for_each(|ty| ty.substitute(lookup))?;
+                ret.substitute(lookup)?;
+            }
+            Ty::RawPointer => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::testing::{ir, parse_module};
+
+    fn resolve_err(code: &str) -> String {
+        let mut module = parse_module(code).unwrap_or_else(|e| panic!("{e}"));
+        match module.resolve_aliases() {
+            Ok(()) => panic!("expected the aliases to be recursive:\n{code}"),
+            Err(e) => e.render(false)
+        }
+    }
+
+    #[test]
+    fn recursive_aliases() {
+        let err = resolve_err("type A = A;");
+        assert!(err.contains("type alias A is recursive: A -> A"), "{err}");
+        let err = resolve_err("type C = &A;\ntype A = [B; 2];\ntype B = (u8, C);");
+        assert!(err.contains("type alias A is recursive: A -> B -> C -> A"), "{err}");
+        // every alias of the cycle is pointed at
+        assert!(err.contains("<string>: 1:6") && err.contains("<string>: 2:6") && err.contains("<string>: 3:6"), "{err}");
+    }
+
+    #[test]
+    fn alias_in_signature() {
+        let ir = ir("type Int = i32;\ntype Pair = (Int, u8);\n#[export]\nfn first(p: Pair) -> Int { p.0 }", &["--shared"]);
+        assert!(ir.contains("define i32 @first({ i32, i8 }"), "{ir}");
+    }
+}
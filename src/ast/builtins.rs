@@ -0,0 +1,147 @@
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::ast::{AstLiteral, Expr, Expression, Module};
+use crate::ast::code_printer::CodePrinter;
+use crate::error::{ParseError, ParseET};
+use crate::source::span::Span;
+use crate::tokens::{Literal, NumLit, NumLitTy};
+
+/// Functions evaluated while compiling, their calls are replaced by literals
+const BUILTINS: [&str; 6] = ["include_bytes", "include_str", "env", "env_opt", "compiler_version", "build_timestamp"];
+
+impl Module {
+    /// Replaces calls of builtins by the literal they evaluate to:
+    /// - `include_bytes("path")` and `include_str("path")`, the contents of a file as `[u8; N]` and string,
+    ///   relative to the directory of the source file containing the call
+    /// - `env("NAME")` and `env_opt("NAME")`, a variable of the environment of the compiler,
+    ///   `env_opt` is empty if it is unset
+    /// - `compiler_version()`, the version of this compiler
+    /// - `build_timestamp()`, seconds since the unix epoch as `u64`, `SOURCE_DATE_EPOCH` if it is set
+    ///
+    /// Functions of the module with the same name shadow the builtins.
+    pub(crate) fn expand_builtins(&mut self, max_literal_bytes: usize) -> Result<(), ParseError> {
+        let shadowed = self.functions.values().map(|f| f.name.0.clone()).collect::<Vec<_>>();
+        let mut expand = |expr: &mut Expression| expr.expand_builtin(&shadowed, max_literal_bytes);
+        for func in self.functions.values_mut() {
+            if let Some(body) = &mut func.body {
+                body.walk_mut(&mut expand).map_err(|e| e.when_fn(&func.name.0))?;
+            }
+        }
+        for constant in self.constants.values_mut() {
+            constant.val.walk_mut(&mut expand).map_err(|e| e.when_const(&constant.name.0))?;
+        }
+        for s in self.statics.values_mut() {
+            s.val.walk_mut(&mut expand).map_err(|e| e.when_static(&s.name.0))?;
+        }
+        for module in self.sub_modules.values_mut() {
+            module.expand_builtins(max_literal_bytes)?;
+        }
+        Ok(())
+    }
+}
+
+impl Expression {
+    fn expand_builtin(&mut self, shadowed: &[String], max_literal_bytes: usize) -> Result<(), ParseError> {
+        let Expr::FuncCall(fun, args) = &self.1 else {
+            return Ok(())
+        };
+        let builtin = match fun.0.as_slice() {
+            [name] if BUILTINS.contains(&name.0.as_str()) && !shadowed.contains(&name.0) => name.0.clone(),
+            _ => return Ok(())
+        };
+        let loc = self.2.clone();
+        let err = |msg: String| ParseET::CompilationError(msg).at(loc.clone()).when(format!("evaluating builtin {builtin}"));
+        let lit = match builtin.as_str() {
+            "compiler_version" | "build_timestamp" => {
+                if !args.is_empty() {
+                    return Err(err(format!("{builtin} takes no arguments, found ({})", args.iter().map(|a| a.print()).collect::<Vec<_>>().join(", "))))
+                }
+                if builtin == "compiler_version" {
+                    Literal::String(env!("CARGO_PKG_VERSION").to_string())
+                } else {
+                    Literal::Number(NumLit::Integer(build_timestamp().map_err(err)? as u128), Some(NumLitTy::U64), None)
+                }
+            }
+            _ => {
+                let arg = match args.as_slice() {
+                    [Expression(_, Expr::Literal(AstLiteral(Literal::String(arg), _)), _)] => arg,
+                    _ => return Err(err(format!("{builtin} takes a single string literal, found ({})", args.iter().map(|a| a.print()).collect::<Vec<_>>().join(", "))))
+                };
+                match builtin.as_str() {
+                    "env" => Literal::String(std::env::var(arg).map_err(|e| err(format!("environment variable {arg}: {e}")))?),
+                    "env_opt" => Literal::String(std::env::var(arg).unwrap_or_default()),
+                    _ => include(&builtin, arg, &loc, max_literal_bytes).map_err(err)?
+                }
+            }
+        };
+        self.1 = Expr::Literal(AstLiteral(lit, loc));
+        Ok(())
+    }
+}
+
+fn include(builtin: &str, rel_path: &str, loc: &Span, max_literal_bytes: usize) -> Result<Literal, String> {
+    let path = resolve(loc.source.path(), rel_path);
+    let size = std::fs::metadata(&path).map_err(|e| format!("could not read {}: {e}", path.display()))?.len();
+    if size > max_literal_bytes as u64 {
+        return Err(format!("{} takes up {size} bytes, exceeding the limit of {max_literal_bytes} bytes (see --max-literal-bytes)", path.display()))
+    }
+    let bytes = std::fs::read(&path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+    Ok(if builtin == "include_str" {
+        Literal::String(String::from_utf8(bytes).map_err(|e| format!("{} is not valid utf-8: {e}", path.display()))?)
+    } else {
+        Literal::Bytes(bytes)
+    })
+}
+
+/// Absolute path of `path`, relative to the directory of `source` if it is relative itself
+fn resolve(source: Option<&Path>, path: &str) -> PathBuf {
+    let dir = source.and_then(|s| s.parent()).unwrap_or(Path::new(""));
+    let joined = dir.join(path);
+    std::path::absolute(&joined).unwrap_or(joined)
+}
+
+/// `SOURCE_DATE_EPOCH` keeps builds reproducible, see <https://reproducible-builds.org/specs/source-date-epoch/>
+fn build_timestamp() -> Result<u64, String> {
+    match std::env::var("SOURCE_DATE_EPOCH") {
+        Ok(epoch) => epoch.parse().map_err(|_| format!("SOURCE_DATE_EPOCH has to be a number of seconds, found '{epoch}'")),
+        Err(_) => Ok(SystemTime::now().duration_since(UNIX_EPOCH).map_err(|e| e.to_string())?.as_secs())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::source::Source;
+    use crate::util::testing::{build_err, ir, run, run_source, temp_dir};
+
+    #[test]
+    fn included_files() {
+        let dir = temp_dir();
+        std::fs::write(dir.join("data.bin"), [0x7f, b'E', b'L', b'F', 0, 200]).unwrap();
+        std::fs::write(dir.join("text.txt"), "hi\n").unwrap();
+        let main = dir.join("main.li");
+        std::fs::write(&main, "const DATA: &[u8; 6] = &include_bytes(\"data.bin\");\nconst TEXT: &[u8; 4] = &include_str(\"text.txt\");\nfn main() -> i32 {\n    \
+            let mut n = 0i32;\n    while DATA[0u64] == 127u8 && DATA[3u64] == 70u8 && DATA[5u64] == 200u8 && TEXT[1u64] == 105u8 {\n        n = 6i32;\n        break;\n    }\n    n\n}").unwrap();
+        let source = Rc::new(Source::from_file(main.display().to_string(), 1024).unwrap());
+        assert_eq!(run_source(source, &[], &[]).code, Some(6));
+        let err = build_err("fn main() {\n    let d = include_bytes(\"missing.bin\");\n}", &[]);
+        let missing = std::path::absolute("missing.bin").unwrap();
+        assert!(err.contains(&format!("could not read {}", missing.display())) && err.contains("while evaluating builtin include_bytes")
+            && err.contains("<string>: 2:13..2:40\n"), "{err}");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn environment_of_the_compiler() {
+        // only read by this test, the program itself runs without it
+        std::env::set_var("LITHIA_TEST_GREETING", "hey");
+        let code = "const CONST: &[u8; 4] = &env(\"LITHIA_TEST_GREETING\");\nfn main() -> i32 {\n    let local = &env(\"LITHIA_TEST_GREETING\");\n    \
+            let unset = &env_opt(\"LITHIA_TEST_UNSET\");\n    let mut n = 0i32;\n    while CONST[0u64] == 104u8 && local[2u64] == 121u8 && unset[0u64] == 0u8 {\n        n = 3i32;\n        break;\n    }\n    n\n}";
+        assert_eq!(run(code, &[], &[]).code, Some(3));
+        let err = build_err("fn main() {\n    let v = env(\"LITHIA_TEST_UNSET\");\n}", &[]);
+        assert!(err.contains("environment variable LITHIA_TEST_UNSET: environment variable not found") && err.contains("<string>: 2:13..2:36\n"), "{err}");
+        let len = env!("CARGO_PKG_VERSION").len() + 1;
+        let version = ir(&format!("#[export]\nfn version() -> &[u8; {len}] {{\n    &compiler_version()\n}}"), &["--shared"]);
+        assert!(version.contains(&format!("c\"{}\\00\"", env!("CARGO_PKG_VERSION"))), "{version}");
+    }
+}
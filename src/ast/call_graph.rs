@@ -0,0 +1,130 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::convert::Infallible;
+use std::fmt::{Display, Formatter};
+use crate::ast::{Block, Expr, Expression, Module};
+use crate::util::glob::glob_matches;
+
+/// Names used by an expression, not yet resolved to symbols. Locals are included.
+#[derive(Debug, Default)]
+pub(crate) struct References {
+    pub(crate) calls: Vec<String>,
+    pub(crate) variables: Vec<String>
+}
+
+/// Target of a call, calls through function pointers can't be resolved statically.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) enum Callee {
+    Function(String),
+    Unknown
+}
+
+/// Functions of a module by symbol, with the functions each of them calls.
+/// Calls of overloaded functions aren't resolved, they have an edge to every overload.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct CallGraph {
+    pub(crate) edges: BTreeMap<String, BTreeSet<Callee>>
+}
+
+impl CallGraph {
+    pub(crate) fn callees(&self, symbol: &str) -> impl Iterator<Item=&Callee> {
+        self.edges.get(symbol).into_iter().flatten()
+    }
+}
+
+impl Display for CallGraph {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for (caller, callees) in &self.edges {
+            let callees = callees.iter().map(|c| match c {
+                Callee::Function(s) => s.as_str(),
+                Callee::Unknown => "<unknown>"
+            }).collect::<Vec<_>>();
+            writeln!(f, "{caller} -> [{}]", callees.join(", "))?;
+        }
+        Ok(())
+    }
+}
+
+impl Module {
+    /// Symbols of the functions by their source name, overloads share one name.
+    pub(crate) fn symbols_by_name(&self) -> HashMap<&str, Vec<&str>> {
+        let mut symbols: HashMap<&str, Vec<&str>> = HashMap::new();
+        for (symbol, func) in &self.functions {
+            symbols.entry(func.name.0.as_str()).or_default().push(symbol.as_str());
+        }
+        symbols
+    }
+
+    /// Symbols of the functions whose source name or symbol matches the glob `pattern`, sorted
+    pub(crate) fn functions_matching(&self, pattern: &str) -> Vec<&str> {
+        let mut symbols = self.functions.iter()
+            .filter(|(symbol, func)| glob_matches(pattern, &func.name.0) || glob_matches(pattern, symbol))
+            .map(|(symbol, _)| symbol.as_str())
+            .collect::<Vec<_>>();
+        symbols.sort();
+        symbols
+    }
+
+    pub(crate) fn call_graph(&self) -> CallGraph {
+        let symbols = self.symbols_by_name();
+        let edges = self.functions.iter().map(|(symbol, func)| {
+            let mut refs = References::default();
+            if let Some(body) = &func.body {
+                body.references(&mut refs);
+            }
+            let callees = refs.calls.iter().flat_map(|name| match symbols.get(name.as_str()) {
+                Some(overloads) => overloads.iter().map(|s| Callee::Function(s.to_string())).collect(),
+                // not a function of this module, so it has to be a function pointer
+                None => vec![Callee::Unknown]
+            }).collect();
+            (symbol.clone(), callees)
+        }).collect();
+        CallGraph { edges }
+    }
+}
+
+impl Block {
+    pub(crate) fn references(&self, refs: &mut References) {
+        let Ok(()) = self.walk(&mut |expr| expr.add_references(refs));
+    }
+}
+
+impl Expression {
+    pub(crate) fn references(&self, refs: &mut References) {
+        let Ok(()) = self.walk(&mut |expr| expr.add_references(refs));
+    }
+
+    /// The names this expression uses itself, not the ones of the expressions in it
+    fn add_references(&self, refs: &mut References) -> Result<(), Infallible> {
+        match &self.1 {
+            Expr::Variable(ident) => refs.variables.push(ident.0.clone()),
+            Expr::FuncCall(fun, _) => refs.calls.push(fun.0.first().unwrap().0.clone()),
+            _ => {}
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+    use crate::ast::call_graph::{CallGraph, Callee};
+    use crate::util::testing::parse_module;
+
+    #[test]
+    fn extracts_calls() {
+        let module = parse_module("fn double(x: i32) -> i32 { x + x }\n\
+            fn twice(x: i32) -> i32 { double(double(x)) }\n\
+            fn apply(x: i32) -> i32 {\n    let f = double;\n    f(x)\n}\n\
+            fn main() {\n    apply(1i32);\n    twice(2i32);\n}").unwrap_or_else(|e| panic!("{e}"));
+        let function = |s: &str| Callee::Function(s.to_string());
+        let expected = [
+            ("double", vec![]),
+            ("twice", vec![function("double")]),
+            // `f` is a function pointer, naming `double` is no call of it
+            ("apply", vec![Callee::Unknown]),
+            ("main", vec![function("apply"), function("twice")])
+        ].into_iter().map(|(caller, callees)| (caller.to_string(), callees.into_iter().collect::<BTreeSet<_>>())).collect::<BTreeMap<_, _>>();
+        assert_eq!(module.call_graph(), CallGraph { edges: expected });
+        assert!(module.call_graph().to_string().contains("apply -> [<unknown>]\n"));
+    }
+}
@@ -0,0 +1,130 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use crate::ast::{AstLiteral, Expr, Expression, Module, Tag, TagValue};
+use crate::ast::code_printer::CodePrinter;
+use crate::error::{ParseError, ParseET};
+use crate::source::span::Span;
+use crate::tokens::Literal;
+
+/// What `#[cfg(...)]` on items and `cfg!(...)` in expressions are evaluated against.
+/// Predicates are `key = "value"`, `not(p)`, `any(p, ...)` and `all(p, ...)`.
+pub(crate) struct Cfg {
+    /// `target = "..."` matches the whole triple or any of its components, like `linux` or `x86_64`
+    triple: String,
+    /// `key = "value"` pairs set by `--cfg`
+    options: Vec<(String, String)>,
+    /// warnings for keys that are neither built in nor set, they evaluate to false
+    warnings: RefCell<Vec<ParseError>>
+}
+
+impl Cfg {
+    pub(crate) fn new(triple: String, options: Vec<(String, String)>) -> Self {
+        Self { triple, options, warnings: RefCell::new(vec![]) }
+    }
+
+    pub(crate) fn take_warnings(&self) -> Vec<ParseError> {
+        self.warnings.take()
+    }
+
+    /// Whether an item with these tags is compiled, items without `#[cfg(...)]` always are
+    pub(crate) fn enabled(&self, tags: &HashMap<String, Tag>) -> Result<bool, ParseError> {
+        tags.get("cfg").map_or(Ok(true), |tag| self.eval_tag(tag))
+    }
+
+    fn eval_tag(&self, tag: &Tag) -> Result<bool, ParseError> {
+        match tag.1.as_slice() {
+            [predicate] => self.eval(predicate, &tag.2),
+            _ => Err(ParseET::TagError(format!("cfg takes a single predicate, found {}", tag.print())).at(tag.2.clone()))
+        }
+    }
+
+    fn eval(&self, predicate: &TagValue, loc: &Span) -> Result<bool, ParseError> {
+        match predicate {
+            TagValue::Assign(key, AstLiteral(Literal::String(value), _)) => Ok(match key.0.as_str() {
+                "target" => value == &self.triple || self.triple.split('-').any(|part| part == value
+                    || (value == "macos" && (part.starts_with("darwin") || part.starts_with("macos")))),
+                _ => {
+                    if key.0 != "feature" && !self.options.iter().any(|(k, _)| k == &key.0) {
+                        self.warnings.borrow_mut().push(ParseET::Warning(format!("unknown cfg key {}, it is never set", key.0))
+                            .at(key.1.clone()).when("evaluating cfg"));
+                    }
+                    self.options.iter().any(|(k, v)| k == &key.0 && v == value)
+                }
+            }),
+            TagValue::Assign(key, value) => Err(ParseET::TagError(format!("value of cfg key {} has to be a string, found {}", key.0, value.print()))
+                .at(value.1.clone())),
+            TagValue::Tag(tag) => match (tag.0.0.as_str(), tag.1.as_slice()) {
+                ("not", [predicate]) => Ok(!self.eval(predicate, &tag.2)?),
+                ("any", predicates) => predicates.iter().try_fold(false, |any, p| Ok(self.eval(p, &tag.2)? || any)),
+                ("all", predicates) => predicates.iter().try_fold(true, |all, p| Ok(self.eval(p, &tag.2)? && all)),
+                _ => Err(ParseET::TagError(format!("expected not(predicate), any(predicates) or all(predicates), found {}", tag.print())).at(tag.2.clone()))
+            },
+            TagValue::Ident(_) | TagValue::Lit(_) => Err(ParseET::TagError(format!("expected key = \"value\", not(...), any(...) or all(...) as cfg predicate, found {}", predicate.print()))
+                .at(loc.clone()))
+        }
+    }
+}
+
+impl Module {
+    /// Replaces `cfg!(...)` expressions by the bool they evaluate to.
+    pub(crate) fn fold_cfg(&mut self, cfg: &Cfg) -> Result<(), ParseError> {
+        let mut fold = |expr: &mut Expression| {
+            if let Expr::Cfg(predicate) = &expr.1 {
+                let value = cfg.eval_tag(predicate)?;
+                expr.1 = Expr::Literal(AstLiteral(Literal::Bool(value), expr.2.clone()));
+            }
+            Ok(())
+        };
+        for func in self.functions.values_mut() {
+            if let Some(body) = &mut func.body {
+                body.walk_mut(&mut fold).map_err(|e| e.when_fn(&func.name.0))?;
+            }
+        }
+        for constant in self.constants.values_mut() {
+            constant.val.walk_mut(&mut fold).map_err(|e| e.when_const(&constant.name.0))?;
+        }
+        for s in self.statics.values_mut() {
+            s.val.walk_mut(&mut fold).map_err(|e| e.when_static(&s.name.0))?;
+        }
+        for module in self.sub_modules.values_mut() {
+            module.fold_cfg(cfg)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::ast::cfg::Cfg;
+    use crate::ast::parser::parse;
+    use crate::source::Source;
+    use crate::tokens::tokenizer::tokenize;
+    use crate::util::testing::{build_err, ir};
+
+    #[test]
+    fn items_are_compiled_per_target() {
+        let code = "#[unsafe]\n#[extern(\"C\")]\n#[cfg(target = \"linux\")]\nfn getpid() -> i32;\n\
+            #[export]\n#[cfg(target = \"x86_64\")]\nfn arch() -> i32 {\n    unsafe { getpid() }\n}\n\
+            #[export]\n#[cfg(not(target = \"x86_64\"))]\nfn arch() -> i32 {\n    only_on_arm()\n}\n\
+            #[export]\n#[cfg(any(target = \"macos\", feature = \"fast\"))]\nfn fast() -> bool {\n    cfg!(all(feature = \"fast\", not(target = \"linux\")))\n}";
+        let x86 = ir(code, &["--shared", "--target", "x86_64-pc-linux-gnu"]);
+        assert!(x86.contains("call i32 @getpid()") && !x86.contains("@fast"), "{x86}");
+        let arm = build_err(code, &["--shared", "--target", "aarch64-apple-darwin"]);
+        assert!(arm.contains("only_on_arm") && !arm.contains("getpid"), "{arm}");
+        let fast = ir(code, &["--shared", "--target", "x86_64-pc-linux-gnu", "--cfg", "feature=fast"]);
+        assert!(fast.contains("define i1 @fast()") && fast.contains("ret i1 false"), "{fast}");
+        let err = build_err("#[cfg(target)]\nfn main() {}", &[]);
+        assert!(err.contains("expected key = \"value\", not(...), any(...) or all(...) as cfg predicate, found target") && err.contains("<string>: 1:3..1:13\n"), "{err}");
+    }
+
+    #[test]
+    fn unknown_keys_warn() {
+        let cfg = Cfg::new("x86_64-pc-linux-gnu".to_string(), vec![]);
+        let tokens = tokenize(Rc::new(Source::from_string("#[cfg(os = \"linux\")]\nfn f() {}\nfn main() {}".to_string())), false).unwrap();
+        let module = parse(tokens, ("main".to_string(), None), &cfg).unwrap();
+        assert_eq!(module.functions.len(), 1);
+        let warnings = cfg.take_warnings().iter().map(|w| w.render(false)).collect::<Vec<_>>();
+        assert!(matches!(warnings.as_slice(), [w] if w.contains("unknown cfg key os, it is never set") && w.contains("<string>: 1:7..1:8\n")), "{warnings:?}");
+    }
+}
@@ -1,5 +1,8 @@
 use std::collections::HashMap;
-use crate::ast::{AstLiteral, Block, Const, Expr, Expression, Func, Ident, Item, Module, Op, Operator, Statement, Tag, TagValue, Ty, Type};
+use crate::ast::create_patterns::ModuleContent;
+use crate::ast::parser::FormatItems;
+use crate::source::span::Span;
+use crate::ast::{Comment, Trivia, AstLiteral, Block, Const, Expr, Expression, Func, Ident, Item, Module, Op, Operator, Range, Statement, Tag, TagValue, Ty, Type, TypeAlias, ExternType, Static};
 use crate::tokens::{Literal, NumLit};
 
 pub(crate) trait CodePrinter{
@@ -55,16 +58,33 @@ impl CodePrinter for Item {
 impl CodePrinter for Literal {
     fn print(&self) -> String {
         match self {
-            Literal::String(s) => format!("{s:?}"),
-            Literal::Char(c) => format!("{c:?}"),
-            Literal::Number(NumLit::Integer(i), ty) => format!("{i}{}", ty.as_ref().map_or(String::new(), |t| format!("{t}"))),
-            Literal::Number(NumLit::Float(f), ty) => format!("{f}{}", ty.as_ref().map_or(String::new(), |t| format!("{t}"))),
+            // escapes are kept as written
+            Literal::String(s) => format!("\"{s}\""),
+            Literal::Char(c) => format!("'{}'", escape_char(*c)),
+            Literal::Number(_, _, Some(text)) => text.clone(),
+            Literal::Number(NumLit::Integer(i), ty, None) => format!("{i}{}", ty.as_ref().map_or(String::new(), |t| format!("{t}"))),
+            Literal::Number(NumLit::Float(f), ty, None) => format!("{f}{}", ty.as_ref().map_or(String::new(), |t| format!("{t}"))),
             Literal::Bool(b) => format!("{b}"),
             Literal::Array(v, _ty, _) => format!("[{}]", v.iter().map(|v|v.print()).collect::<Vec<_>>().join(", ")),
+            Literal::Repeat(v, count) => format!("[{}; {count}]", v.print()),
+            Literal::Bytes(b) => format!("[{}]", b.iter().map(|b| format!("{b}u8")).collect::<Vec<_>>().join(", ")),
         }
     }
 }
 
+/// The escape [`tokenize`](crate::tokens::tokenizer::tokenize) reads back as `c`, or `c` itself
+fn escape_char(c: char) -> String {
+    match c {
+        '\n' => "\\n".to_string(),
+        '\r' => "\\r".to_string(),
+        '\t' => "\\t".to_string(),
+        '\0' => "\\0".to_string(),
+        '\\' | '\'' => format!("\\{c}"),
+        c if c.is_ascii_control() => format!("\\x{:02x}", c as u8),
+        c => c.to_string()
+    }
+}
+
 impl CodePrinter for AstLiteral {
     fn print(&self) -> String {
         self.0.print()
@@ -73,7 +93,9 @@ impl CodePrinter for AstLiteral {
 
 impl CodePrinter for HashMap<String, Tag> {
     fn print(&self) -> String {
-        self.iter().map(|(name, tag)| format!("#[{}]", tag.print())).collect::<Vec<_>>().join("\n")
+        let mut tags = self.values().collect::<Vec<_>>();
+        tags.sort_by_key(|tag| tag.2.start);
+        tags.iter().map(|tag| format!("#[{}]", tag.print())).collect::<Vec<_>>().join("\n")
     }
 }
 
@@ -90,22 +112,38 @@ impl CodePrinter for TagValue {
         match self {
             TagValue::Lit(lit) => lit.print(),
             TagValue::Ident(ident) => ident.print(),
-            TagValue::Tag(tag) => tag.print()
+            TagValue::Tag(tag) => tag.print(),
+            TagValue::Assign(key, value) => format!("{} = {}", key.print(), value.print())
         }
     }
 }
 
 impl CodePrinter for Expression {
     fn print(&self) -> String {
-        format!("{}{}", if self.0.len() > 0 { format!("{}\n", self.0.print()) } else { String::new() },
+        let mut tags = self.0.clone();
+        // `unsafe { ... }` blocks are stored as tagged block expressions
+        let unsafe_block = matches!(self.1, Expr::Block(_)) && tags.remove("unsafe").is_some();
+        format!("{}{}{}", if tags.len() > 0 { format!("{}\n", tags.print()) } else { String::new() },
+                if unsafe_block { "unsafe" } else { "" },
                 match &self.1 {
             Expr::FuncCall(ident, args) => format!("{}({})", ident.print(), args.iter().map(|e|e.print()).collect::<Vec<_>>().join(", ")),
             Expr::Point(expr) => format!("&{}", expr.print()),
             Expr::Deref(expr) => format!("*{}", expr.print()),
+            Expr::Index(base, index) => format!("{}[{}]", base.print(), index.print()),
+            Expr::Field(base, i) => format!("{}.{i}", base.print()),
             Expr::Literal(lit) => lit.print(),
             Expr::Variable(var) => var.print(),
             Expr::UnaryOp(op, box expr) => format!("{}{}", op.print(), expr.print()),
-            Expr::BinaryOp(op, box left, box right) => format!("({} {} {})", left.print(), op.print(), right.print()),
+            Expr::BinaryOp(op, box left, box right) => {
+                // parentheses of the source are groups, only operations built by the compiler may lack them
+                let operand = |expr: &Expression, right_side: bool| match &expr.1 {
+                    Expr::BinaryOp(inner, ..) if inner.0.precedence() < op.0.precedence()
+                        || ((right_side || op.0.is_comparison()) && inner.0.precedence() == op.0.precedence()) =>
+                        format!("({})", expr.print()),
+                    _ => expr.print()
+                };
+                format!("{} {} {}", operand(left, false), op.print(), operand(right, true))
+            }
             Expr::VarCreate(ident, mutable, ty, expr) =>
             format!("let {}{}{} = {}",
                     if *mutable { "mut "} else {""}.to_string(),
@@ -113,14 +151,54 @@ impl CodePrinter for Expression {
                     ty.as_ref().map(|t|format!(": {}", t.0.print())).unwrap_or("".to_string()),
                     expr.print()
             ),
-            Expr::VarAssign(ident, Some(op), expr) => format!("{} {}= {}", ident.print(), op.print(), expr.print()),
-            Expr::VarAssign(ident, None, expr) => format!("{} = {};", ident.print(), expr.print()),
+            Expr::Group(expr) => format!("({})", expr.print()),
+            Expr::Ascribe(expr, ty) => format!("({}: {})", expr.print(), ty.print()),
+            Expr::LetTuple(idents, ty, expr) => format!("let ({}){} = {}",
+                    idents.iter().map(|i| i.print()).collect::<Vec<_>>().join(", "),
+                    ty.as_ref().map(|t| format!(": {}", t.print())).unwrap_or(String::new()),
+                    expr.print()
+            ),
+            Expr::Tuple(elems) => format!("({})", elems.iter().map(|e| e.print()).collect::<Vec<_>>().join(", ")),
+            Expr::LetElse(ident, expr, else_block) => format!("let {} = {} else{}", ident.print(), expr.print(), else_block.print()),
+            Expr::VarAssign(target, Some(op), expr) => format!("{} {}= {}", target.print(), op.print(), expr.print()),
+            Expr::VarAssign(target, None, expr) => format!("{} = {}", target.print(), expr.print()),
+            Expr::Const(c) => format!("const {}: {} = {}", c.name.print(), c.ty.print(), c.val.print()),
+            Expr::Static(s) => format!("static {}: {} = {}", s.name.print(), s.ty.print(), s.val.print()),
             Expr::Block(block) => block.print(),
-            Expr::Return(expr) => match expr { Some(e) => format!("return {}", e.print()), None => format!("return") }
+            Expr::Return(expr) => match expr { Some(e) => format!("return {}", e.print()), None => format!("return") },
+            Expr::For(label, var, range, step, body) => format!("{}for {} in {}{}{}",
+                    label.as_ref().map(|l| format!("'{}: ", l.print())).unwrap_or(String::new()),
+                    var.print(),
+                    range.print(),
+                    step.as_ref().map(|s| format!(" step {}", s.print())).unwrap_or(String::new()),
+                    body.print()
+            ),
+            Expr::ForIn(label, var, iterable, body) => format!("{}for {} in {}{}",
+                    label.as_ref().map(|l| format!("'{}: ", l.print())).unwrap_or(String::new()),
+                    var.print(),
+                    iterable.print(),
+                    body.print()
+            ),
+            Expr::While(label, cond, body) => format!("{}while {}{}",
+                    label.as_ref().map(|l| format!("'{}: ", l.print())).unwrap_or(String::new()),
+                    cond.print(),
+                    body.print()
+            ),
+            Expr::Defer(expr) => format!("defer {}", expr.print()),
+            Expr::Cfg(predicate) => format!("cfg!({})", predicate.1.iter().map(|v| v.print()).collect::<Vec<_>>().join(", ")),
+            Expr::Break(label) => format!("break{}", label.as_ref().map(|l| format!(" '{}", l.print())).unwrap_or(String::new())),
+            Expr::Continue(label) => format!("continue{}", label.as_ref().map(|l| format!(" '{}", l.print())).unwrap_or(String::new())),
+            Expr::Error => verbatim(self.2.text(), self.2.start().pos().1)
         })
     }
 }
 
+impl CodePrinter for Range {
+    fn print(&self) -> String {
+        format!("{}{}{}", self.0.print(), if self.2 { "..=" } else { ".." }, self.1.print())
+    }
+}
+
 impl CodePrinter for Operator {
     fn print(&self) -> String {
         match self.0 {
@@ -128,25 +206,62 @@ impl CodePrinter for Operator {
             Op::Sub => "-",
             Op::Mul => "*",
             Op::Div => "/",
+            Op::Mod => "%",
             Op::And => "&&",
             Op::Or => "||",
             Op::Not => "!",
             Op::LShift => "<<",
             Op::RShift => ">>",
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Lt => "<",
+            Op::Gt => ">",
+            Op::Le => "<=",
+            Op::Ge => ">=",
         }.to_string()
     }
 }
 
 impl CodePrinter for Statement {
     fn print(&self) -> String {
-        format!("{}{}", self.0.print(), if self.1 {";"} else {""} )
+        self.3.around(format!("{}{}", self.0.print(), if self.1 {";"} else {""}), &self.2)
+    }
+}
+
+impl CodePrinter for Comment {
+    fn print(&self) -> String {
+        verbatim(&self.0, self.1.start().pos().1)
+    }
+}
+
+/// `code` as written at column `indent`, lines after the first lose that indentation,
+/// so printing it indented elsewhere doesn't add up
+fn verbatim(code: &str, indent: usize) -> String {
+    code.split('\n').enumerate().map(|(i, line)| if i == 0 { line } else {
+        line.char_indices().find(|(j, c)| *j >= indent || !c.is_whitespace()).map_or("", |(j, _)| &line[j..])
+    }).collect::<Vec<_>>().join("\n")
+}
+
+impl Trivia {
+    /// `code` printed from `loc`, with the comments around it
+    fn around(&self, code: String, loc: &Span) -> String {
+        let mut out = self.leading.iter().map(|c| c.print() + "\n").collect::<String>() + &code;
+        for (i, comment) in self.trailing.iter().enumerate() {
+            let same_line = i == 0 && comment.1.start().pos().0 == loc.end().pos().0;
+            out += if same_line { " " } else { "\n" };
+            out += &comment.print();
+        }
+        out
     }
 }
 
 impl CodePrinter for Func {
     fn print(&self) -> String {
-        format!("{}fn {}({}){}{}",
-            if self.tags.len() > 0 { format!("{}\n", self.tags.print()) } else { String::new() },
+        let mut tags = self.tags.clone();
+        let is_const = tags.remove("const").is_some();
+        format!("{}{}fn {}({}){}{}",
+            if !tags.is_empty() { format!("{}\n", tags.print()) } else { String::new() },
+            if is_const { "const " } else { "" },
             self.name.print(),
             self.args.iter().map(|(ident, ty)| format!("{}: {}", ident.print(), ty.print())).collect::<Vec<_>>().join(", "),
             if self.ret.0.is_empty() {
@@ -163,14 +278,38 @@ impl CodePrinter for Func {
 
 impl CodePrinter for Const {
     fn print(&self) -> String {
-        format!("const {}: {} = {};", self.name.print(), self.ty.print(), self.val.print())
+        format!("{}const {}: {} = {};",
+            if !self.tags.is_empty() { format!("{}\n", self.tags.print()) } else { String::new() },
+            self.name.print(), self.ty.print(), self.val.print())
+    }
+}
+
+impl CodePrinter for Static {
+    fn print(&self) -> String {
+        format!("{}static {}: {} = {};",
+            if !self.tags.is_empty() { format!("{}\n", self.tags.print()) } else { String::new() },
+            self.name.print(), self.ty.print(), self.val.print())
+    }
+}
+
+impl CodePrinter for TypeAlias {
+    fn print(&self) -> String {
+        format!("type {} = {};", self.name.print(), self.ty.print())
+    }
+}
+
+impl CodePrinter for ExternType {
+    fn print(&self) -> String {
+        format!("extern type {};", self.name.print())
     }
 }
 
 impl CodePrinter for Block {
     fn print(&self) -> String {
-        if self.0.is_empty() {
+        if self.0.is_empty() && self.2.is_empty() {
             String::from(" {}")
+        } else if self.0.is_empty() {
+            format!(" {{\n{}\n}}", self.2.iter().map(|c| c.print_indented()).collect::<Vec<_>>().join("\n"))
         } else {
             format!(" {{\n{}\n}}", self.0.iter().map(|t| t.print_indented()).collect::<Vec<_>>().join("\n"))
         }
@@ -185,8 +324,78 @@ impl CodePrinter for Module {
 
 impl Module {
     fn print_content(&self) -> String {
-        format!("{}\n\n{}",
+        format!("{}\n{}\n\n{}\n\n{}\n\n{}",
+                self.extern_types.values().map(|t| t.print()).collect::<Vec<_>>().join("\n"),
+                self.aliases.values().map(|a| a.print()).collect::<Vec<_>>().join("\n"),
                 self.constants.values().map(|c| c.print()).collect::<Vec<_>>().join("\n\n"),
+                self.statics.values().map(|s| s.print()).collect::<Vec<_>>().join("\n\n"),
                 self.functions.values().map(|t| t.print()).collect::<Vec<_>>().join("\n\n"))
     }
-}
\ No newline at end of file
+}
+
+/// Source in canonical form, the items in source order separated by blank lines, see `fmt`.
+/// `dangling` are the comments of a file without items.
+pub(crate) fn format_items(items: &FormatItems, dangling: &[Comment]) -> String {
+    if items.is_empty() {
+        return dangling.iter().map(|c| c.print() + "\n").collect()
+    }
+    let with_tags = |tags: &HashMap<String, Tag>, item: String| if !tags.is_empty() { format!("{}\n{item}", tags.print()) } else { item };
+    items.iter().map(|(tags, content, loc, trivia)| (trivia, loc, match content {
+        ModuleContent::Function(f) => {
+            let mut f = f.clone();
+            f.tags.extend(tags.clone());
+            f.print()
+        }
+        ModuleContent::Const(c) => {
+            let mut c = c.clone();
+            c.tags.extend(tags.clone());
+            c.print()
+        }
+        ModuleContent::Static(s) => {
+            let mut s = s.clone();
+            s.tags.extend(tags.clone());
+            s.print()
+        }
+        ModuleContent::Alias(a) => with_tags(tags, a.print()),
+        ModuleContent::ExternType(t) => with_tags(tags, t.print()),
+        ModuleContent::Error(loc) => verbatim(loc.text(), loc.start().pos().1)
+    })).map(|(trivia, loc, item)| trivia.around(item, loc)).collect::<Vec<_>>().join("\n\n") + "\n"
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::ast::code_printer::format_items;
+    use crate::ast::parser::parse_items_tolerant;
+    use crate::source::Source;
+    use crate::tokens::tokenizer::tokenize;
+
+    fn fmt(code: &str) -> String {
+        let (items, dangling, skipped) = parse_items_tolerant(tokenize(Rc::new(Source::from_string(code.to_string())), true).unwrap()).unwrap();
+        assert!(skipped.is_empty(), "{}", skipped.iter().map(|e| e.render(false)).collect::<String>());
+        format_items(&items, &dangling)
+    }
+
+    const MESSY: &str = "// adds things\nfn   add( a:i32,b :i32)->i32{a+b}\n\
+        #[export]\nfn main( ) -> i32 {\n  let x=add(1i32,2i32);   // three\n    let mut y   =  x*2i32; y+=1i32;\n  /* block\n     comment */\n  if_zero (y)\n}\n\
+        const   C : i32=5i32;\n";
+
+    #[test]
+    fn formats_to_canonical_form() {
+        assert_eq!(fmt(MESSY), "// adds things\nfn add(a: i32, b: i32) -> i32 {\n    a + b\n}\n\n\
+            #[export]\nfn main() -> i32 {\n    let x = add(1i32, 2i32); // three\n    let mut y = x * 2i32;\n    y += 1i32;\n    /* block\n       comment */\n    if_zero(y)\n}\n\n\
+            const C: i32 = 5i32;\n");
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let once = fmt(MESSY);
+        assert_eq!(fmt(&once), once);
+    }
+
+    #[test]
+    fn numbers_keep_their_spelling() {
+        let code = "fn f() -> u32 {\n    let mask = 0xff_ffu32;\n    let bits = 0b1010'u8;\n    let big = 1_000_000i64;\n    let half = 0.50f64;\n    mask\n}\n";
+        assert_eq!(fmt(code), code);
+    }
+}
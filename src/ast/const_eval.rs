@@ -0,0 +1,183 @@
+use std::collections::HashMap;
+use crate::ast::{AstLiteral, Block, Expr, Expression, Func, Module, Op};
+use crate::ast::code_printer::CodePrinter;
+use crate::error::{OnParseErr, ParseError, ParseET};
+use crate::tokens::{Literal, NumLit};
+
+/// Evaluates expressions at compile time, calls are only allowed into `const fn`s.
+/// Every evaluated expression takes one step, so runaway recursion ends in an error.
+pub(crate) struct ConstEval<'a> {
+    functions: &'a HashMap<String, Func>,
+    steps: usize,
+    max_steps: usize
+}
+
+type Scopes = Vec<HashMap<String, AstLiteral>>;
+
+impl<'a> ConstEval<'a> {
+    pub(crate) fn new(functions: &'a HashMap<String, Func>, max_steps: usize) -> Self {
+        Self { functions, steps: 0, max_steps }
+    }
+
+    pub(crate) fn eval(&mut self, expr: &Expression) -> Result<AstLiteral, ParseError> {
+        self.eval_in(expr, &mut vec![HashMap::new()])?
+            .ok_or_else(|| ParseET::CompilationError("expected a value, found ()".to_string()).at(expr.2.clone()))
+    }
+
+    /// `None` is the unit value
+    fn eval_in(&mut self, expr: &Expression, scopes: &mut Scopes) -> Result<Option<AstLiteral>, ParseError> {
+        self.steps += 1;
+        if self.steps > self.max_steps {
+            return Err(ParseET::CompilationError(format!("constant evaluation exceeded the limit of {} steps (see --const-eval-steps)", self.max_steps)).at(expr.2.clone()))
+        }
+        Ok(match &expr.1 {
+            Expr::Literal(lit) => Some(lit.clone()),
+            Expr::Group(inner) => self.eval_in(inner, scopes)?,
+            Expr::Ascribe(inner, ty) => {
+                let v = self.value(inner, scopes)?.with_expected(ty)?;
+                v.get_type()?.satisfies_or_err(ty)?;
+                Some(v)
+            }
+            Expr::Variable(ident) => Some(scopes.iter().rev().find_map(|s| s.get(&ident.0)).cloned()
+                .ok_or_else(|| ParseET::VariableNotFound(ident.0.clone()).at(ident.1.clone()))?),
+            Expr::Block(block) => {
+                scopes.push(HashMap::new());
+                let v = self.eval_block(block, scopes);
+                scopes.pop();
+                v?
+            }
+            Expr::VarCreate(name, _, ty, value) => {
+                let mut v = self.value(value, scopes)?;
+                if let Some(ty) = ty {
+                    v = v.with_expected(ty)?;
+                    v.get_type()?.satisfies_or_err(ty)?;
+                }
+                scopes.last_mut().unwrap().insert(name.0.clone(), v);
+                None
+            }
+            Expr::FuncCall(fun, args) => {
+                let args = args.iter().map(|arg| self.value(arg, scopes)).collect::<Result<Vec<_>, _>>()?;
+                self.call(&fun.0.first().unwrap().0, args, expr)?
+            }
+            Expr::BinaryOp(op, left, right) => {
+                let (left, right) = (self.value(left, scopes)?, self.value(right, scopes)?);
+                Some(Self::binary_op(&op.0, left, right, expr)?)
+            }
+            _ => return Err(ParseET::CompilationError(format!("{} can't be evaluated at compile time", expr.print())).at(expr.2.clone()))
+        })
+    }
+
+    fn value(&mut self, expr: &Expression, scopes: &mut Scopes) -> Result<AstLiteral, ParseError> {
+        self.eval_in(expr, scopes)?
+            .ok_or_else(|| ParseET::CompilationError("expected a value, found ()".to_string()).at(expr.2.clone()))
+    }
+
+    fn eval_block(&mut self, block: &Block, scopes: &mut Scopes) -> Result<Option<AstLiteral>, ParseError> {
+        let mut last = None;
+        for stmt in &block.0 {
+            let v = self.eval_in(&stmt.0, scopes)?;
+            last = if stmt.1 { None } else { v };
+        }
+        Ok(last)
+    }
+
+    fn call(&mut self, name: &str, args: Vec<AstLiteral>, call: &Expression) -> Result<Option<AstLiteral>, ParseError> {
+        let candidates = self.functions.values().filter(|f| f.name.0 == name && f.args.len() == args.len()).collect::<Vec<_>>();
+        let func = match candidates.as_slice() {
+            [] => return Err(ParseET::CompilationError(format!("no function {name} taking {} arguments", args.len())).at(call.2.clone())),
+            [func] => *func,
+            _ => return Err(ParseET::CompilationError(format!("overloaded function {name} can't be called at compile time")).at(call.2.clone()))
+        };
+        if !func.tags.contains_key("const") {
+            return Err(ParseET::CompilationError(format!("only const functions can be called at compile time, {name} is not const"))
+                .ats(vec![call.2.clone(), func.name.1.clone()]))
+        }
+        let mut frame = HashMap::new();
+        for ((ident, ty), arg) in func.args.iter().zip(args) {
+            let arg = arg.with_expected(ty).e_at_add(call.2.clone())?;
+            arg.get_type()?.satisfies_or_err(ty).e_at_add(call.2.clone())?;
+            frame.insert(ident.0.clone(), arg);
+        }
+        let body = func.body.as_ref().unwrap();
+        // the body only sees its parameters, not the locals of the caller
+        let ret = self.eval_block(body, &mut vec![frame]).map_err(|e| e.when_fn(&func.name.0))?;
+        match ret {
+            Some(ret) => {
+                let ret = ret.with_expected(&func.ret).map_err(|e| e.when_fn(&func.name.0))?;
+                ret.get_type()?.satisfies_or_err(&func.ret).map_err(|e| e.when_fn(&func.name.0))?;
+                Ok(Some(ret))
+            }
+            None if func.ret.0.is_empty() => Ok(None),
+            None => Err(ParseET::CompilationError(format!("expected {}, found ()", func.ret.print())).at(body.1.clone()).when_fn(&func.name.0))
+        }
+    }
+
+    fn binary_op(op: &Op, left: AstLiteral, right: AstLiteral, expr: &Expression) -> Result<AstLiteral, ParseError> {
+        let (Literal::Number(NumLit::Integer(l), lty, _), Literal::Number(NumLit::Integer(r), rty, _)) = (&left.0, &right.0) else {
+            return Err(ParseET::CompilationError("only integer arithmetic can be evaluated at compile time".to_string()).at(expr.2.clone()))
+        };
+        let ty = match (lty, rty) {
+            (Some(l), Some(r)) if l != r => return Err(ParseET::TypeError(l.to_string(), r.to_string()).ats(vec![left.1.clone(), right.1.clone()])),
+            (ty, None) | (None, ty) => ty.clone(),
+            (ty, _) => ty.clone()
+        };
+        let result = match op {
+            Op::Add => l.checked_add(*r),
+            Op::Sub => l.checked_sub(*r),
+            Op::Mul => l.checked_mul(*r),
+            Op::Div if *r == 0 => return Err(ParseET::CompilationError("division by zero".to_string()).at(expr.2.clone())),
+            Op::Div => l.checked_div(*r),
+            Op::Mod if *r == 0 => return Err(ParseET::CompilationError("remainder of division by zero".to_string()).at(expr.2.clone())),
+            Op::Mod => l.checked_rem(*r),
+            _ => return Err(ParseET::CompilationError(format!("{} can't be evaluated at compile time", expr.print())).at(expr.2.clone()))
+        };
+        let result = result.ok_or_else(|| ParseET::CompilationError(format!("arithmetic overflow in {}", expr.print())).at(expr.2.clone()))?;
+        Ok(AstLiteral(Literal::Number(NumLit::Integer(result), ty, None), expr.2.clone()))
+    }
+}
+
+impl Module {
+    /// Replaces const and static initializers that aren't literals by their compile time value.
+    pub(crate) fn evaluate_consts(&mut self, max_steps: usize) -> Result<(), ParseError> {
+        for constant in self.constants.values_mut() {
+            if let Expr::Point(inner) = &mut constant.val.1 {
+                if !matches!(inner.1, Expr::Literal(_)) {
+                    let value = ConstEval::new(&self.functions, max_steps).eval(inner).map_err(|e| e.when_const(&constant.name.0))?;
+                    inner.1 = Expr::Literal(value);
+                }
+            }
+        }
+        for s in self.statics.values_mut() {
+            if !matches!(s.val.1, Expr::Literal(_)) {
+                let value = ConstEval::new(&self.functions, max_steps).eval(&s.val).map_err(|e| e.when_static(&s.name.0))?;
+                s.val.1 = Expr::Literal(value);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::testing::{build_err, ir, run};
+
+    const SIZE: &str = "const fn size(rows: i32, cols: i32) -> i32 {\n    let cells = rows * cols;\n    cells + 1i32\n}\n";
+
+    #[test]
+    fn const_fns_run_at_compile_time_and_at_runtime() {
+        let code = format!("{SIZE}static TABLE: i32 = size(3i32, 4i32);\nfn main() -> i32 {{\n    size(1i32, 1i32) + unsafe {{ TABLE }}\n}}");
+        assert_eq!(run(&code, &[], &[]).code, Some(15));
+        let ir = ir(&format!("{SIZE}static TABLE: i32 = size(3i32, 4i32);\n#[export]\nfn table() -> i32 {{ unsafe {{ TABLE }} }}"), &["--shared"]);
+        assert!(ir.contains("@TABLE = internal global i32 13"), "{ir}");
+    }
+
+    #[test]
+    fn const_fn_errors() {
+        let err = build_err("const fn div(a: i32, b: i32) -> i32 {\n    a / b\n}\nstatic S: i32 = div(1i32, 0i32);\nfn main() {}", &[]);
+        assert!(err.contains("division by zero") && err.contains("<string>: 2:5..2:9") && err.contains("while compiling static S"), "{err}");
+        let err = build_err("const fn forever(n: i32) -> i32 {\n    forever(n)\n}\nstatic S: i32 = forever(1i32);\nfn main() {}", &["--const-eval-steps", "50"]);
+        assert!(err.contains("constant evaluation exceeded the limit of 50 steps"), "{err}");
+        let err = build_err("fn one() -> i32 { 1i32 }\nstatic S: i32 = one();\nfn main() {}", &[]);
+        assert!(err.contains("only const functions can be called at compile time, one is not const"), "{err}");
+    }
+}
@@ -1,19 +1,45 @@
 use std::collections::HashMap;
 use std::hash::Hash;
-use crate::ast::{Block, Expr, Expression, Type, Func, Item, Statement, Ty, Const, AstLiteral, TagValue, Tag};
+use crate::ast::{Block, Expr, Expression, Type, Func, Item, Statement, Ty, Const, AstLiteral, TagValue, Tag, Range, Ident, TypeAlias, ExternType, Static, Trivia, Op, Operator};
 use crate::ast::patterns::{Consumer, Pat, Pattern};
 use crate::ast::patterns::conditional::{While, Match, Succeed, Fail, IsOk, Optional};
 use crate::ast::patterns::dynamic::{Latent, Mapping};
-use crate::ast::patterns::simple::{ExpectIdent, ExpectParticle, ExpectParticleExact, GetIdent, GetLiteral, GetNext};
-use crate::error::{ParseET};
+use crate::ast::patterns::recover::{Recover, Recovered};
+use crate::ast::patterns::simple::{ExpectIdent, ExpectParticle, ExpectParticleExact, GetIdent, GetLabel, GetLiteral, GetNext, GetParticle};
+use crate::ast::cfg::Cfg;
+use crate::ast::code_printer::CodePrinter;
+use crate::error::{OnParseErr, ParseError, ParseET};
 use crate::source::span::Span;
 use crate::tokens::{Literal, NumLit, NumLitTy};
 
 pub(crate) struct Patterns{
-    pub(crate) module_content: Pat<((HashMap<String, Func>, HashMap<String, Const>), Span)>
+    pub(crate) ty: Pat<Type>,
+    /// a single item with its tags and the code it spans, tags included
+    pub(crate) item: Pat<(HashMap<String, Tag>, ModuleContent, Span)>,
+    pub(crate) module_content: Pat<(Vec<(HashMap<String, Tag>, ModuleContent, Span)>, Span)>,
+    /// breaks the cycles of the recursive patterns when they are dropped
+    unlinkers: Vec<Box<dyn Fn()>>
 }
 
-pub(crate) fn build_patterns() -> Patterns {
+impl Drop for Patterns {
+    fn drop(&mut self) {
+        self.unlinkers.iter().for_each(|unlink| unlink());
+    }
+}
+
+/// An item of a module, with the tags in front of it
+pub(crate) enum ModuleContent{
+    Function(Func),
+    Const(Const),
+    Static(Static),
+    Alias(TypeAlias),
+    ExternType(ExternType),
+    /// code that didn't parse, only produced by [`parse_items_tolerant`](crate::ast::parser::parse_items_tolerant)
+    Error(Span)
+}
+
+/// With `recovered`, statements that don't parse become [`Expr::Error`] and their errors are collected in it
+pub(crate) fn build_patterns(recovered: Option<Recovered>) -> Patterns {
     let item = Pattern::named("identifier",
               (
                   GetIdent,
@@ -36,21 +62,65 @@ pub(crate) fn build_patterns() -> Patterns {
                                                     ExpectParticle(']'))
             .map_res(|(_, ty, maybe_count, _), _| {
                 if let Some((_, count) ) = maybe_count {
-                    if let AstLiteral(Literal::Number(NumLit::Integer(c), th), loc) = count.clone() {
-                        if th.as_ref().map(|t| t == &NumLitTy::UPtr).unwrap_or(true) {
-                            Ok(Ty::Array(Box::new(ty), c as usize))
-                        } else {
-                            Err(ParseET::LiteralError(count.0, format!("expected uptr, found {}", th.unwrap())).at(loc).when("parsing array type"))
-                        }
-                    } else {
-                        Err(ParseET::LiteralError(count.0, "expected uptr".to_string()).at(count.1).when("parsing array type"))
-                    }
+                    Ok(Ty::Array(Box::new(ty), array_len(count).e_when("parsing array type")?))
                 } else {
                     Ok(Ty::Slice(Box::new(ty)))
                 }
             }).pat()),
-        (Succeed(item.clone()).pat(), item.clone().map(|item, loc| Ty::Single(vec![], item)).pat()),
+        (Succeed(ExpectParticle('(').pat()).pat(), (ExpectParticle('('),
+                                                    Optional(Fail(ExpectParticle(')').pat()).pat(), (
+                                                        type_pat.clone(),
+                                                        While(Fail(ExpectParticle(')').pat()).pat(), (ExpectParticle(','), type_pat.clone()).map(|(_, ty), _| ty).pat())
+                                                    ).pat()),
+                                                    ExpectParticle(')'))
+            .map(|(_, elems, _), _| Ty::Tuple(elems.map(|(first, mut rest)| {
+                rest.insert(0, first);
+                rest
+            }).unwrap_or_default())).pat()),
+        (Succeed(item.clone()).pat(), (item.clone(), Optional(ExpectParticle('<').pat(), (
+            ExpectParticle('<'),
+            type_pat.clone(),
+            While(Fail(ExpectParticle('>').pat()).pat(), (ExpectParticle(','), type_pat.clone()).map(|(_, ty), _| ty).pat()),
+            ExpectParticle('>')
+        ).map(|(_, first, mut rest, _), _| {
+            rest.insert(0, first);
+            rest
+        }).pat())).map(|(item, generics), _| Ty::Single(generics.unwrap_or_default(), item)).pat()),
     ]), |ty, loc| Type(ty, loc)));
+    let (literal, literal_finalizer) = Latent::<AstLiteral>::new();
+    let array_literal = Pattern::named("array literal", (
+        ExpectParticle('['),
+        literal.clone(),
+        Optional(ExpectParticle(';').pat(), (ExpectParticle(';'), GetLiteral).map(|(_, count), _| count).pat()),
+        While(
+            Fail(ExpectParticle(']').pat()).pat(),
+            (ExpectParticle(','), literal.clone()).map(|(_, lit), _| lit).pat()
+        ),
+        ExpectParticle(']')
+    ).map_res(|(_, first, repeat, rest, _), loc| {
+        if let Some(count) = repeat {
+            if let Some(extra) = rest.first() {
+                return Err(ParseET::ParsingError("repeat literal takes exactly one element".to_string()).at(extra.1.clone()))
+            }
+            return Ok(AstLiteral(Literal::Repeat(Box::new(first), array_len(count)?), loc))
+        }
+        let mut elems = vec![first];
+        elems.extend(rest);
+        // elements without suffix take the type of the first one that has one
+        let elem_ty = elems.iter().find_map(|elem| elem.get_type().ok());
+        if let Some(elem_ty) = &elem_ty {
+            elems = elems.iter().map(|elem| elem.with_expected(elem_ty)).collect::<Result<_, _>>()?;
+            for elem in &elems {
+                elem.get_type()?.satisfies_or_err(elem_ty)?;
+            }
+        }
+        let len = elems.len();
+        Ok(AstLiteral(Literal::Array(elems, elem_ty, len), loc))
+    }), |lit, _| lit);
+    literal_finalizer.finalize(Pattern::named("literal", Match(vec![
+        (Succeed(ExpectParticle('[').pat()).pat(), array_literal.clone()),
+        (Succeed(GetLiteral.pat()).pat(), GetLiteral.pat())
+    ]), |lit, _| lit));
     let (tag_args, tag_arg_finalizer) = Latent::new();
     let tag = Pattern::inline((
         GetIdent,
@@ -68,6 +138,8 @@ pub(crate) fn build_patterns() -> Patterns {
         Tag(name, args, loc)
     });
     tag_arg_finalizer.finalize(Pattern::named("tag arg", Match(vec![
+        (Succeed((GetIdent, ExpectParticle('=')).pat()).pat(), (GetIdent, ExpectParticle('='), GetLiteral)
+            .map(|(key, _, value), _| TagValue::Assign(key, value)).pat()),
         (Succeed((GetIdent, ExpectParticle('(')).pat()).pat(), tag.clone().map(|f, _| TagValue::Tag(Box::new(f))).pat()),
         (Succeed(GetIdent.pat()).pat(), GetIdent.map(|id, _| TagValue::Ident(id)).pat()),
         (Succeed(GetLiteral.pat()).pat(), GetLiteral.map(|lit, _| TagValue::Lit(lit)).pat()),
@@ -83,12 +155,8 @@ pub(crate) fn build_patterns() -> Patterns {
                                     |tags, _| tags.into_iter().map(|tag| (tag
                                                                               .0.0.clone(), tag)).collect::<HashMap<String, Tag>>());
     let (expression, expression_finalizer) = Latent::new();
-    let let_create = Pattern::named("variable creation", (
-        ExpectIdent("let".to_string()),
-        GetIdent,
-        ExpectParticle('='),
-        expression.clone()
-    ), |(_, name, _, expr), loc| Expr::VarCreate(name, false, None, Box::new(expr)));
+    // an expression without binary operators, or with them in parentheses
+    let (term, term_finalizer) = Latent::new();
     let function_call = Pattern::named("function call", (
         item.clone(),
         ExpectParticle('('),
@@ -103,25 +171,283 @@ pub(crate) fn build_patterns() -> Patterns {
         arg0.map(|arg0| args.insert(0, arg0));
         Expr::FuncCall(item, args)
     });
-    expression_finalizer.finalize(Pattern::named("expression",(
-        tags.clone(),
-        Match(vec![
-            (Succeed(ExpectIdent("let".to_string()).pat()).pat(), let_create.clone()),
-            (Succeed((item.clone(), ExpectParticle('(')).pat()).pat(), function_call.clone()),
-            (Succeed(ExpectParticle('&').pat()).pat(), (ExpectParticle('&'), expression.clone()).map(|(_, expr), loc| Expr::Point(Box::new(expr))).pat()),
-            (Succeed(ExpectParticle('*').pat()).pat(), (ExpectParticle('*'), expression.clone()).map(|(_, expr), loc| Expr::Deref(Box::new(expr))).pat()),
-            (Succeed(GetIdent.pat()).pat(), GetIdent.map(|ident, loc| Expr::Variable(ident)).pat()),
-            (Succeed(GetLiteral.pat()).pat(), GetLiteral.map(|lit, loc| Expr::Literal(lit)).pat())
-        ])), |(tags, expr), loc| Expression(tags, expr, loc)));
+    // `=` or a compound assignment like `+=`, the `=` glued to the operator.
+    // Any particle followed by a glued `=` starts an assignment, so `&=` is reported instead of failing the next statement
+    let assign_start = Pattern::inline(Match(vec![
+        (Succeed(ExpectParticle('=').pat()).pat(), ExpectParticle('=').pat()),
+        (Succeed(GetNext.pat()).pat(), (GetParticle, ExpectParticleExact('=', true)).map(|_, _| ()).pat())
+    ]), |_, _| ());
+    let assign_operator = Pattern::inline(Match(vec![
+        (Succeed(ExpectParticle('=').pat()).pat(), ExpectParticle('=').map(|_, _| None).pat()),
+        (Succeed(GetNext.pat()).pat(), (GetParticle, ExpectParticleExact('=', true)).map_res(|((c, _), _), loc| Ok(Some(Operator(match c {
+            '+' => Op::Add,
+            '-' => Op::Sub,
+            '*' => Op::Mul,
+            '/' => Op::Div,
+            '%' => Op::Mod,
+            c => return Err(ParseET::ParsingError(format!("expected an assignment, found '{c}='")).at(loc))
+        }, loc)))).pat())
+    ]), |op, _| op);
     let statement = Pattern::named("statement", (
             expression.clone(),
+            Optional(ExpectParticle(':').pat(), (ExpectParticle(':'), type_pat.clone()).map(|(_, ty), _| ty).pat()),
+            Optional(assign_start, (assign_operator, expression.clone()).pat()),
             IsOk(ExpectParticle(';').pat())
-        ), |(expr, terminated), loc| Statement(expr, terminated, loc));
+        ).map_res(|(expr, ascription, assignment, terminated), loc| {
+            let expr = match ascription {
+                None => expr,
+                // `let x = 3: u64;` would ascribe the let, not the value
+                Some(ty) if matches!(expr.1, Expr::VarCreate(..) | Expr::LetTuple(..)) =>
+                    return Err(ParseET::ParsingError("a let can't be ascribed, annotate the variable with `let x: T = ...` or the value with `(value: T)`".to_string())
+                        .at(ty.1).when("parsing type ascription")),
+                Some(ty) => Expression(HashMap::new(), Expr::Ascribe(Box::new(expr), ty), loc.clone())
+            };
+            let expr = match assignment {
+                None => expr,
+                Some((op, value)) => {
+                    if !expr.1.is_place() {
+                        return Err(ParseET::ParsingError("cannot assign to this expression, only variables, dereferenced pointers and array elements can be assigned to".to_string())
+                            .at(expr.2.clone()).when("parsing assignment"))
+                    }
+                    Expression(HashMap::new(), Expr::VarAssign(Box::new(expr), op, Box::new(value)), loc.clone())
+                }
+            };
+            Ok(Statement(expr, terminated, loc, Trivia::default()))
+        }), |stmt, _| stmt);
+    let statement = Recover(statement, recovered,
+        |loc| Statement(Expression(HashMap::new(), Expr::Error, loc.clone()), false, loc, Trivia::default())).pat();
     let block = Pattern::named("block",
         While(
             Fail(ExpectParticle('}').pat()).pat(),
             statement.clone()
-        ), |stmts, loc| Block(stmts, loc));
+        ).map_res(|stmts: Vec<Statement>, _| {
+            // `let a = 1 let b = 2`, only block like statements and the last one may leave out the `;`
+            for pair in stmts.windows(2) {
+                if !pair[0].1 && !pair[0].0.1.is_block_like() && !matches!(pair[0].0.1, Expr::Error) {
+                    return Err(ParseET::ParsingError("expected ';' after this statement, another one follows it".to_string())
+                        .ats(vec![pair[0].2.end().span(), pair[1].2.clone()]))
+                }
+            }
+            Ok(stmts)
+        }), |stmts, loc| Block(stmts, loc, vec![]));
+    let let_create = Pattern::named("variable creation", (
+        ExpectIdent("let".to_string()),
+        Optional(ExpectIdent("mut".to_string()).pat(), ExpectIdent("mut".to_string()).pat()),
+        GetIdent,
+        Optional(ExpectParticle(':').pat(), (ExpectParticle(':'), type_pat.clone()).map(|(_, ty), _| ty).pat()),
+        ExpectParticle('='),
+        expression.clone(),
+        Optional(ExpectIdent("else".to_string()).pat(), (ExpectIdent("else".to_string()), ExpectParticle('{'), block.clone(), ExpectParticle('}'))
+            .map(|(_, _, block, _), _| block).pat())
+    ), |(_, mutable, name, ty, _, expr, else_block), _| match else_block {
+        None => Expr::VarCreate(name, mutable.is_some(), ty, Box::new(expr)),
+        Some(else_block) => Expr::LetElse(name, Box::new(expr), else_block)
+    });
+    let let_tuple = Pattern::named("tuple destructuring", (
+        ExpectIdent("let".to_string()),
+        ExpectParticle('('),
+        GetIdent,
+        While(
+            Fail(ExpectParticle(')').pat()).pat(),
+            (ExpectParticle(','), GetIdent).map(|(_, name), _| name).pat()
+        ),
+        ExpectParticle(')'),
+        Optional(ExpectParticle(':').pat(), (ExpectParticle(':'), type_pat.clone()).map(|(_, ty), _| ty).pat()),
+        ExpectParticle('='),
+        expression.clone()
+    ), |(_, _, name0, mut names, _, ty, _, expr), _| {
+        names.insert(0, name0);
+        Expr::LetTuple(names, ty, Box::new(expr))
+    });
+    let for_loop = Pattern::named("for loop", (
+        ExpectIdent("for".to_string()),
+        GetIdent,
+        ExpectIdent("in".to_string()),
+        expression.clone(),
+        Optional(ExpectParticle('.').pat(), (
+            ExpectParticle('.'),
+            ExpectParticleExact('.', true),
+            IsOk(ExpectParticleExact('=', true).pat()),
+            expression.clone(),
+            Optional(ExpectIdent("step".to_string()).pat(), (ExpectIdent("step".to_string()), expression.clone()).map(|(_, step), _| step).pat())
+        ).map(|(_, _, inclusive, end, step), _| (inclusive, end, step)).pat()),
+        ExpectParticle('{'),
+        block.clone(),
+        ExpectParticle('}')
+    ), |(_, var, _, start, range, _, body, _), _| match range {
+        Some((inclusive, end, step)) => {
+            let mut range_loc = start.2.clone();
+            range_loc.combine(end.2.clone());
+            Expr::For(None, var, Range(Box::new(start), Box::new(end), inclusive, range_loc), step.map(Box::new), body)
+        }
+        None => Expr::ForIn(None, var, Box::new(start), body)
+    });
+    let while_loop = Pattern::named("while loop", (
+        ExpectIdent("while".to_string()),
+        expression.clone(),
+        ExpectParticle('{'),
+        block.clone(),
+        ExpectParticle('}')
+    ), |(_, cond, _, body, _), _| Expr::While(None, Box::new(cond), body));
+    // `'outer: for ...`, only loops can be labeled
+    let labeled_loop = Pattern::named("loop", (
+        Optional(GetLabel.pat(), (GetLabel, ExpectParticle(':')).map(|(label, _), _| label).pat()),
+        Match(vec![
+            (Succeed(ExpectIdent("for".to_string()).pat()).pat(), for_loop),
+            (Succeed(ExpectIdent("while".to_string()).pat()).pat(), while_loop),
+            (Succeed(GetNext.pat()).pat(), GetNext.map_res(|_, loc| Err(ParseET::ParsingError("expected a loop after the label, only for and while loops can be labeled".to_string()).at(loc))).pat())
+        ])
+    ), |(label, mut lp), _| {
+        if let Expr::For(l, ..) | Expr::ForIn(l, ..) | Expr::While(l, ..) = &mut lp {
+            *l = label;
+        }
+        lp
+    });
+    // also declared in function bodies, where the `;` ends the statement
+    let constant_decl = Pattern::named("constant", (
+        ExpectIdent("const".to_string()),
+        GetIdent,
+        ExpectParticle(':'),
+        type_pat.clone(),
+        ExpectParticle('='),
+        expression.clone()
+        ), |(_, name, _, ty, _, val), _| Const { tags: HashMap::new(), name, ty, val });
+    let static_decl = Pattern::named("static", (
+        ExpectIdent("static".to_string()),
+        GetIdent,
+        ExpectParticle(':'),
+        type_pat.clone(),
+        ExpectParticle('='),
+        expression.clone()
+        ), |(_, name, _, ty, _, val), _| Static { tags: HashMap::new(), name, ty, val });
+    let unsafe_block_tag = Pattern::inline(ExpectIdent("unsafe".to_string()),
+        |_, loc| Tag(Ident("unsafe".to_string(), loc.clone()), vec![], loc));
+    term_finalizer.finalize(Pattern::named("expression",(
+        tags.clone(),
+        Optional((ExpectIdent("unsafe".to_string()), ExpectParticle('{')).pat(), unsafe_block_tag),
+        Match(vec![
+            (Succeed(ExpectParticle('{').pat()).pat(), (ExpectParticle('{'), block.clone(), ExpectParticle('}')).map(|(_, block, _), _| Expr::Block(block)).pat()),
+            (Succeed((ExpectIdent("let".to_string()), ExpectParticle('(')).pat()).pat(), let_tuple.clone()),
+            (Succeed(ExpectIdent("let".to_string()).pat()).pat(), let_create.clone()),
+            (Succeed(ExpectIdent("for".to_string()).pat()).pat(), labeled_loop.clone()),
+            (Succeed(ExpectIdent("while".to_string()).pat()).pat(), labeled_loop.clone()),
+            (Succeed(ExpectIdent("const".to_string()).pat()).pat(), constant_decl.clone().map(|c, _| Expr::Const(Box::new(c))).pat()),
+            (Succeed(ExpectIdent("static".to_string()).pat()).pat(), static_decl.clone().map(|s, _| Expr::Static(Box::new(s))).pat()),
+            (Succeed(GetLabel.pat()).pat(), labeled_loop),
+            (Succeed(ExpectIdent("return".to_string()).pat()).pat(), (ExpectIdent("return".to_string()),
+                Optional((Fail(ExpectParticle(';').pat()), Fail(ExpectParticle('}').pat())).pat(), expression.clone()))
+                .map(|(_, expr), _| Expr::Return(expr.map(Box::new))).pat()),
+            (Succeed(ExpectIdent("defer".to_string()).pat()).pat(), (ExpectIdent("defer".to_string()), expression.clone())
+                .map(|(_, expr), _| Expr::Defer(Box::new(expr))).pat()),
+            (Succeed(ExpectIdent("break".to_string()).pat()).pat(), (ExpectIdent("break".to_string()), Optional(GetLabel.pat(), GetLabel.pat()))
+                .map(|(_, label), _| Expr::Break(label)).pat()),
+            (Succeed(ExpectIdent("continue".to_string()).pat()).pat(), (ExpectIdent("continue".to_string()), Optional(GetLabel.pat(), GetLabel.pat()))
+                .map(|(_, label), _| Expr::Continue(label)).pat()),
+            (Succeed((ExpectIdent("cfg".to_string()), ExpectParticle('!')).pat()).pat(), (ExpectIdent("cfg".to_string()), ExpectParticle('!'),
+                ExpectParticle('('), tag_args.clone(), ExpectParticle(')'))
+                .map(|(_, _, _, predicate, _), loc| Expr::Cfg(Tag(Ident("cfg".to_string(), loc.clone()), vec![predicate], loc))).pat()),
+            (Succeed((item.clone(), ExpectParticle('(')).pat()).pat(), function_call.clone()),
+            (Succeed(ExpectParticle('&').pat()).pat(), (ExpectParticle('&'), term.clone()).map(|(_, expr), _| Expr::Point(Box::new(expr))).pat()),
+            (Succeed(ExpectParticle('*').pat()).pat(), (ExpectParticle('*'), term.clone()).map(|(_, expr), _| Expr::Deref(Box::new(expr))).pat()),
+            (Succeed(ExpectParticle('!').pat()).pat(), (ExpectParticle('!'), term.clone())
+                .map(|(_, expr), loc| Expr::UnaryOp(Operator(Op::Not, loc.start().span()), Box::new(expr))).pat()),
+            // `-5` is a negative literal, `- 5` and `-x` are negations
+            (Succeed(ExpectParticle('-').pat()).pat(), (ExpectParticle('-'), term.clone()).map_res(|(_, expr), loc| {
+                let minus = loc.start().span();
+                Ok(match expr {
+                    Expression(tags, Expr::Literal(lit @ AstLiteral(Literal::Number(..), _)), lit_loc) if tags.is_empty() && lit_loc.start == minus.end + 1 =>
+                        Expr::Literal(lit.negate(&minus)?),
+                    expr => Expr::UnaryOp(Operator(Op::Sub, minus), Box::new(expr))
+                })
+            }).pat()),
+            (Succeed(GetIdent.pat()).pat(), GetIdent.map(|ident, loc| Expr::Variable(ident)).pat()),
+            (Succeed(ExpectParticle('(').pat()).pat(), (ExpectParticle('('), expression.clone(),
+                Optional(ExpectParticle(':').pat(), (ExpectParticle(':'), type_pat.clone()).map(|(_, ty), _| ty).pat()),
+                While(
+                    Fail(ExpectParticle(')').pat()).pat(),
+                    (ExpectParticle(','), expression.clone()).map(|(_, expr), _| expr).pat()
+                ),
+                ExpectParticle(')'))
+                .map_res(|(_, expr, ty, mut elems, _), _| Ok(match (ty, elems.is_empty()) {
+                    (Some(ty), true) => Expr::Ascribe(Box::new(expr), ty),
+                    (None, true) => Expr::Group(Box::new(expr)),
+                    (Some(ty), false) => return Err(ParseET::ParsingError("a tuple can't be ascribed through its first element, use ((a, b): (A, B))".to_string())
+                        .at(ty.1).when("parsing tuple")),
+                    (None, false) => {
+                        elems.insert(0, expr);
+                        Expr::Tuple(elems)
+                    }
+                })).pat()),
+            (Succeed(ExpectParticle('[').pat()).pat(), array_literal.clone().map(|lit, _| Expr::Literal(lit)).pat()),
+            (Succeed(GetLiteral.pat()).pat(), GetLiteral.map(|lit, loc| Expr::Literal(lit)).pat())
+        ]).map(|expr, loc| (expr, loc)),
+        // `.0` needs the number, `..` of a range follows expressions too
+        While(
+            Match(vec![
+                (Succeed(ExpectParticle('[').pat()).pat(), Succeed(ExpectParticle('[').pat()).pat()),
+                (Succeed((ExpectParticle('.'), GetLiteral).pat()).pat(), Succeed((ExpectParticle('.'), GetLiteral).pat()).pat())
+            ]).pat(),
+            Match(vec![
+                (Succeed(ExpectParticle('[').pat()).pat(), (ExpectParticle('['), expression.clone(), ExpectParticle(']'))
+                    .map(|(_, index, _), loc| (Postfix::Index(index), loc)).pat()),
+                (Succeed(ExpectParticle('.').pat()).pat(), (ExpectParticle('.'), GetLiteral).map_res(|(_, lit), loc| match lit.0 {
+                    Literal::Number(NumLit::Integer(i), None, _) => Ok((Postfix::Field(i as usize), loc)),
+                    lit => Err(ParseET::ParsingError(format!("expected the position of a tuple element like .0, found {lit:?}")).at(loc).when("parsing tuple element"))
+                }).pat())
+            ]).pat()
+        )), |(mut tags, unsafe_block, (expr, expr_loc), postfixes), loc| {
+        if let Some(tag) = unsafe_block {
+            tags.insert(tag.0.0.clone(), tag);
+        }
+        if postfixes.is_empty() {
+            return Expression(tags, expr, loc)
+        }
+        // tags belong to the indexed expression, each index wraps around the previous one
+        postfixes.into_iter().fold(Expression(tags, expr, expr_loc), |base, (postfix, postfix_loc)| {
+            let mut loc = base.2.clone();
+            loc.combine(postfix_loc);
+            Expression(HashMap::new(), match postfix {
+                Postfix::Index(index) => Expr::Index(Box::new(base), Box::new(index)),
+                Postfix::Field(i) => Expr::Field(Box::new(base), i)
+            }, loc)
+        })
+    }));
+    // `==` is two particles, the second glued to the first, a single `=` is an assignment.
+    // `&&` and `||` are checked first, a single `&` takes the address of the following term
+    let binary_operator = Pattern::inline(Match(vec![
+        (Succeed((ExpectParticle('&'), ExpectParticleExact('&', true)).pat()).pat(),
+            (ExpectParticle('&'), ExpectParticleExact('&', true)).map(|_, loc| Operator(Op::And, loc)).pat()),
+        (Succeed((ExpectParticle('|'), ExpectParticleExact('|', true)).pat()).pat(),
+            (ExpectParticle('|'), ExpectParticleExact('|', true)).map(|_, loc| Operator(Op::Or, loc)).pat()),
+        (Succeed(GetNext.pat()).pat(), (GetParticle, IsOk(ExpectParticleExact('=', true).pat())).map_res(|((c, _), eq), loc| Ok(Operator(match (c, eq) {
+        ('+', false) => Op::Add,
+        ('-', false) => Op::Sub,
+        ('*', false) => Op::Mul,
+        ('/', false) => Op::Div,
+        ('%', false) => Op::Mod,
+        ('=', true) => Op::Eq,
+        ('!', true) => Op::Ne,
+        ('<', false) => Op::Lt,
+        ('>', false) => Op::Gt,
+        ('<', true) => Op::Le,
+        ('>', true) => Op::Ge,
+        (c, false) => return Err(ParseET::ParsingError(format!("expected an operator, found '{c}'")).at(loc)),
+        (c, true) => return Err(ParseET::ParsingError(format!("expected an operator, found '{c}='")).at(loc))
+    }, loc))).pat())
+    ]), |op, _| op);
+    let binary = Pattern::inline((
+        term.clone(),
+        While(Succeed(binary_operator.clone()).pat(), (binary_operator, term.clone()).pat())
+    ).map_res(|(first, rest), _| fold_binary(first, rest)), |expr, _| expr);
+    // block like expressions end a statement, `for i in 0..n {} *p = 1;` doesn't multiply
+    expression_finalizer.finalize(Pattern::inline(Match(vec![
+        (Succeed(ExpectParticle('{').pat()).pat(), term.clone()),
+        (Succeed((ExpectIdent("unsafe".to_string()), ExpectParticle('{')).pat()).pat(), term.clone()),
+        (Succeed(ExpectIdent("for".to_string()).pat()).pat(), term.clone()),
+        (Succeed(ExpectIdent("while".to_string()).pat()).pat(), term.clone()),
+        (Succeed(GetLabel.pat()).pat(), term.clone()),
+        (Succeed(GetNext.pat()).pat(), binary)
+    ]), |expr, _| expr));
     let function = Pattern::named("function", (
             ExpectIdent("fn".to_string()),
             GetIdent,
@@ -150,59 +476,348 @@ pub(crate) fn build_patterns() -> Patterns {
             body,
             loc,
     }});
-    let constant = Pattern::named("constant", (
-        ExpectIdent("const".to_string()),
+    let constant = Pattern::named("constant", (constant_decl.clone(), ExpectParticle(';')), |(c, _), _| c);
+    let static_item = Pattern::named("static", (static_decl.clone(), ExpectParticle(';')), |(s, _), _| s);
+    // `const fn` is sugar for the const tag
+    let const_function = Pattern::named("const function", (
+        ExpectIdent("const".to_string()).map(|_, loc| loc),
+        function.clone()
+    ), |(const_loc, mut f), _| {
+        f.tags.insert("const".to_string(), Tag(Ident("const".to_string(), const_loc.clone()), vec![], const_loc));
+        f
+    });
+    let type_alias = Pattern::named("type alias", (
+        ExpectIdent("type".to_string()),
         GetIdent,
-        ExpectParticle(':'),
-        type_pat.clone(),
         ExpectParticle('='),
-        expression.clone(),
+        type_pat.clone(),
         ExpectParticle(';'),
-        ), |(_, name, _, ty, _, val, _), loc| Const { name, ty, val });
-    enum ModuleContent{
-        Function(Func),
-        Const(Const)
-    }
-    let module_content = Pattern::named("module content",
-        While(
-        GetNext.pat(),
-        (tags.clone(),
+        ), |(_, name, _, ty, _), _| TypeAlias { name, ty });
+    let extern_type = Pattern::named("extern type", (
+        ExpectIdent("extern".to_string()),
+        ExpectIdent("type".to_string()),
+        GetIdent,
+        ExpectParticle(';'),
+        ), |(_, _, name, _), _| ExternType { name });
+    let module_item = Pattern::inline((tags.clone(),
          Match(vec![
             (Succeed(ExpectIdent("fn".to_string()).pat()).pat(), function.clone().map(|f, _| ModuleContent::Function(f)).pat()),
-            (Succeed(ExpectIdent("const".to_string()).pat()).pat(), constant.clone().map(|c, _| ModuleContent::Const(c)).pat())
-        ])).pat()
-        ).map_res(|content, _| {
-            let mut functions = HashMap::new();
-            let mut constants = HashMap::new();
-            for (tags, c) in content.into_iter() {
-                match c {
-                    ModuleContent::Function(mut f) => {
-                        f.tags = tags;
-                        let l = f.name.1.clone();
-                        if constants.contains_key(&f.name.0){
-                            return Err(ParseET::AlreadyDefinedError("constant".to_string(), f.name.0).ats(vec![l, f.name.1]))
-                        }
-                        if let Some(f) = functions.insert(f.name.0.clone(), f){
-                            return Err(ParseET::AlreadyDefinedError("function".to_string(), f.name.0).ats(vec![l, f.name.1]))
-                        }
-                    },
-                    ModuleContent::Const(c) => {
-                        if tags.len() > 0 {
-                            return Err(ParseET::TagError("tags not applicable for consts".to_string()).at(c.name.1.clone()))
-                        }
-                        let l = c.name.1.clone();
-                        if functions.contains_key(&c.name.0){
-                            return Err(ParseET::AlreadyDefinedError("function".to_string(), c.name.0).ats(vec![l, c.name.1]))
-                        }
-                        if let Some(c) = constants.insert(c.name.0.clone(), c){
-                            return Err(ParseET::AlreadyDefinedError("constant".to_string(), c.name.0).ats(vec![l, c.name.1]))
-                        }
+            (Succeed((ExpectIdent("const".to_string()), ExpectIdent("fn".to_string())).pat()).pat(), const_function.clone().map(|f, _| ModuleContent::Function(f)).pat()),
+            (Succeed(ExpectIdent("const".to_string()).pat()).pat(), constant.clone().map(|c, _| ModuleContent::Const(c)).pat()),
+            (Succeed(ExpectIdent("static".to_string()).pat()).pat(), static_item.clone().map(|s, _| ModuleContent::Static(s)).pat()),
+            (Succeed(ExpectIdent("type".to_string()).pat()).pat(), type_alias.clone().map(|a, _| ModuleContent::Alias(a)).pat()),
+            (Succeed((ExpectIdent("extern".to_string()), ExpectIdent("type".to_string())).pat()).pat(), extern_type.clone().map(|t, _| ModuleContent::ExternType(t)).pat())
+        ])), |(tags, content), loc| (tags, content, loc));
+    let module_content = Pattern::named("module content",
+        While(GetNext.pat(), module_item.clone()), |content, loc| (content, loc));
+    Patterns {
+        ty: type_pat,
+        item: module_item,
+        module_content,
+        unlinkers: vec![type_finalizer.unlinker(), literal_finalizer.unlinker(), tag_arg_finalizer.unlinker(), expression_finalizer.unlinker(), term_finalizer.unlinker()]
+    }
+}
+
+/// Items of a module, by symbol for functions and by name for everything else
+pub(crate) type ModuleItems = (HashMap<String, Func>, HashMap<String, Const>, HashMap<String, Static>, HashMap<String, TypeAlias>, HashMap<String, ExternType>);
+
+/// Sorts the items of a module by kind, dropping items disabled by `#[cfg(...)]`
+/// before they can collide with their enabled counterparts.
+pub(crate) fn collect_module_content(content: Vec<(HashMap<String, Tag>, ModuleContent)>, cfg: &Cfg) -> Result<ModuleItems, ParseError> {
+    let mut overloads: HashMap<String, Vec<Func>> = HashMap::new();
+    let mut constants = HashMap::new();
+    let mut statics: HashMap<String, Static> = HashMap::new();
+    let mut aliases: HashMap<String, TypeAlias> = HashMap::new();
+    let mut extern_types: HashMap<String, ExternType> = HashMap::new();
+    for (mut tags, c) in content.into_iter() {
+        if !cfg.enabled(&tags)? {
+            continue
+        }
+        tags.remove("cfg");
+        match c {
+            ModuleContent::Function(mut f) => {
+                f.tags.extend(tags);
+                if let Some(tag) = f.tags.get("thread_local") {
+                    return Err(ParseET::TagError("tag thread_local not applicable for functions, only for statics".to_string()).at(tag.2.clone()))
+                }
+                let l = f.name.1.clone();
+                if constants.contains_key(&f.name.0){
+                    return Err(ParseET::AlreadyDefinedError("constant".to_string(), f.name.0).ats(vec![l, f.name.1]))
+                }
+                if let Some(s) = statics.get(&f.name.0) {
+                    return Err(ParseET::AlreadyDefinedError("static".to_string(), f.name.0).ats(vec![l, s.name.1.clone()]))
+                }
+                let same_name = overloads.entry(f.name.0.clone()).or_default();
+                for other in same_name.iter() {
+                    // extern symbols can't be mangled, so they can't be overloaded either
+                    if f.tags.contains_key("extern") || other.tags.contains_key("extern") {
+                        return Err(ParseET::AlreadyDefinedError("function".to_string(), f.name.0).ats(vec![l, other.name.1.clone()])
+                            .when("overloading extern function"))
                     }
-                };
+                    if f.mangled_name() == other.mangled_name() {
+                        return Err(ParseET::AlreadyDefinedError("function".to_string(), f.mangled_name()).ats(vec![l, other.name.1.clone()]))
+                    }
+                }
+                same_name.push(f);
+            },
+            ModuleContent::Const(mut c) => {
+                if let Some(tag) = tags.values().find(|t| t.0.0 != "export" && t.0.0 != "hidden") {
+                    return Err(ParseET::TagError(format!("tag {} not applicable for consts", tag.0.0)).at(tag.2.clone()))
+                }
+                c.tags = tags;
+                let l = c.name.1.clone();
+                if overloads.contains_key(&c.name.0){
+                    return Err(ParseET::AlreadyDefinedError("function".to_string(), c.name.0).ats(vec![l, c.name.1]))
+                }
+                if let Some(s) = statics.get(&c.name.0) {
+                    return Err(ParseET::AlreadyDefinedError("static".to_string(), c.name.0).ats(vec![l, s.name.1.clone()]))
+                }
+                if let Some(c) = constants.insert(c.name.0.clone(), c){
+                    return Err(ParseET::AlreadyDefinedError("constant".to_string(), c.name.0).ats(vec![l, c.name.1]))
+                }
             }
-            Ok((functions, constants))
-        }), |content, loc| (content, loc));
-    Patterns {
-        module_content
+            ModuleContent::Static(mut s) => {
+                if let Some(tag) = tags.values().find(|t| !["export", "hidden", "thread_local"].contains(&t.0.0.as_str())) {
+                    return Err(ParseET::TagError(format!("tag {} not applicable for statics", tag.0.0)).at(tag.2.clone()))
+                }
+                s.tags = tags;
+                let l = s.name.1.clone();
+                if overloads.contains_key(&s.name.0){
+                    return Err(ParseET::AlreadyDefinedError("function".to_string(), s.name.0).ats(vec![l, s.name.1]))
+                }
+                if let Some(c) = constants.get(&s.name.0) {
+                    return Err(ParseET::AlreadyDefinedError("constant".to_string(), s.name.0).ats(vec![l, c.name.1.clone()]))
+                }
+                if let Some(s) = statics.insert(s.name.0.clone(), s){
+                    return Err(ParseET::AlreadyDefinedError("static".to_string(), s.name.0).ats(vec![l, s.name.1]))
+                }
+            }
+            ModuleContent::Alias(a) => {
+                if let Some(tag) = tags.values().next() {
+                    return Err(ParseET::TagError(format!("tag {} not applicable for type aliases", tag.0.0)).at(tag.2.clone()))
+                }
+                let l = a.name.1.clone();
+                if let Some(t) = extern_types.get(&a.name.0) {
+                    return Err(ParseET::AlreadyDefinedError("extern type".to_string(), a.name.0).ats(vec![l, t.name.1.clone()]))
+                }
+                if let Some(a) = aliases.insert(a.name.0.clone(), a){
+                    return Err(ParseET::AlreadyDefinedError("type alias".to_string(), a.name.0).ats(vec![l, a.name.1]))
+                }
+            }
+            ModuleContent::ExternType(t) => {
+                if let Some(tag) = tags.values().next() {
+                    return Err(ParseET::TagError(format!("tag {} not applicable for extern types", tag.0.0)).at(tag.2.clone()))
+                }
+                let l = t.name.1.clone();
+                if let Some(a) = aliases.get(&t.name.0) {
+                    return Err(ParseET::AlreadyDefinedError("type alias".to_string(), t.name.0).ats(vec![l, a.name.1.clone()]))
+                }
+                if let Some(t) = extern_types.insert(t.name.0.clone(), t){
+                    return Err(ParseET::AlreadyDefinedError("extern type".to_string(), t.name.0).ats(vec![l, t.name.1]))
+                }
+            }
+            ModuleContent::Error(loc) => return Err(ParseET::ParsingError("code that didn't parse can't be compiled".to_string()).at(loc))
+        };
+    }
+    let mut functions = HashMap::new();
+    for (name, mut same_name) in overloads {
+        if same_name.len() == 1 {
+            functions.insert(name, same_name.pop().unwrap());
+        } else {
+            for f in same_name {
+                functions.insert(f.mangled_name(), f);
+            }
+        }
+    }
+    Ok((functions, constants, statics, aliases, extern_types))
+}
+
+/// Folds `first op operand op operand ...` into binary operations,
+/// operators of higher precedence bind first, those of equal precedence from the left.
+/// Comparisons can't follow each other, which one binds first would be a guess.
+fn fold_binary(first: Expression, rest: Vec<(Operator, Expression)>) -> Result<Expression, ParseError> {
+    if let Some(pair) = rest.windows(2).find(|pair| pair[0].0.0.is_comparison() && pair[1].0.0.is_comparison()) {
+        return Err(ParseET::ParsingError(format!("comparisons can't be chained, put parentheses around one of {} and {}", pair[0].0.print(), pair[1].0.print()))
+            .ats(vec![pair[0].0.1.clone(), pair[1].0.1.clone()]).when("parsing comparison"))
+    }
+    let mut operands = vec![first];
+    let mut operators: Vec<Operator> = vec![];
+    let reduce = |operands: &mut Vec<Expression>, op: Operator| {
+        let right = operands.pop().unwrap();
+        let left = operands.pop().unwrap();
+        let mut loc = left.2.clone();
+        loc.combine(right.2.clone());
+        operands.push(Expression(HashMap::new(), Expr::BinaryOp(op, Box::new(left), Box::new(right)), loc));
+    };
+    for (op, operand) in rest {
+        while operators.last().map_or(false, |top| top.0.precedence() >= op.0.precedence()) {
+            reduce(&mut operands, operators.pop().unwrap());
+        }
+        operators.push(op);
+        operands.push(operand);
+    }
+    while let Some(op) = operators.pop() {
+        reduce(&mut operands, op);
+    }
+    Ok(operands.pop().unwrap())
+}
+
+/// Indexing or tuple element access following an expression
+enum Postfix {
+    Index(Expression),
+    Field(usize)
+}
+
+fn array_len(count: AstLiteral) -> Result<usize, ParseError> {
+    if let AstLiteral(Literal::Number(NumLit::Integer(c), th, _), loc) = count.clone() {
+        if th.as_ref().map(|t| t == &NumLitTy::UPtr).unwrap_or(true) {
+            Ok(c as usize)
+        } else {
+            Err(ParseET::LiteralError(count.0, format!("expected uptr, found {}", th.unwrap())).at(loc))
+        }
+    } else {
+        Err(ParseET::LiteralError(count.0, "expected uptr".to_string()).at(count.1))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::{AstLiteral, Expr, Op, Operator};
+    use crate::tokens::{Literal, NumLit, NumLitTy};
+    use crate::util::testing::{build_err, parse_module, run};
+
+    /// The expression of the last statement in the body of `fn f`
+    fn tail(code: &str) -> Expr {
+        let module = parse_module(code).unwrap_or_else(|e| panic!("{e}"));
+        let f = module.functions.values().find(|f| f.name.0 == "f").expect("no fn f");
+        f.body.as_ref().unwrap().0.last().expect("empty body").0.1.clone()
+    }
+
+    fn int(expr: &Expr) -> Option<(u128, Option<NumLitTy>)> {
+        match expr {
+            Expr::Literal(AstLiteral(Literal::Number(NumLit::Integer(i), ty, _), _)) => Some((*i, ty.clone())),
+            _ => None
+        }
+    }
+
+    #[test]
+    fn minus_before_literal_is_negative_literal() {
+        assert_eq!(int(&tail("fn f() -> i32 { -5i32 }")), Some(((-5i128) as u128, Some(NumLitTy::I32))));
+        // the minimum doesn't fit its type before it is negated
+        assert_eq!(int(&tail("fn f() -> i8 { -128i8 }")), Some(((-128i128) as u128, Some(NumLitTy::I8))));
+        assert!(matches!(tail("fn f() -> i32 { - 5i32 }"), Expr::UnaryOp(Operator(Op::Sub, _), _)));
+        assert!(matches!(tail("fn f(x: i32) -> i32 { -x }"), Expr::UnaryOp(Operator(Op::Sub, _), _)));
+        assert!(matches!(tail("fn f(x: i32) -> i32 { x - 5i32 }"), Expr::BinaryOp(Operator(Op::Sub, _), _, _)));
+    }
+
+    #[test]
+    fn arithmetic_operators() {
+        let op = |expr: &Expr| match expr {
+            Expr::BinaryOp(Operator(op, _), left, right) => Some((op.clone(), left.1.clone(), right.1.clone())),
+            _ => None
+        };
+        // `*` after an operand multiplies, before one it dereferences
+        let (mul, _, right) = op(&tail("fn f(x: i32, p: &i32) -> i32 { x * *p }")).unwrap();
+        assert_eq!(mul, Op::Mul);
+        assert!(matches!(right, Expr::Deref(_)));
+        let (add, _, right) = op(&tail("fn f(x: i32) -> i32 { x + 2i32 * x }")).unwrap();
+        assert_eq!((add, op(&right).map(|(op, _, _)| op)), (Op::Add, Some(Op::Mul)));
+        let (rem, left, _) = op(&tail("fn f(x: i32) -> i32 { x / 2i32 % 3i32 }")).unwrap();
+        assert_eq!((rem, op(&left).map(|(op, _, _)| op)), (Op::Mod, Some(Op::Div)));
+        // `//` starts a comment instead of dividing twice
+        let (div, _, right) = op(&tail("fn f(x: i32) -> i32 {\n    x / 2i32 // halved\n}")).unwrap();
+        assert_eq!(div, Op::Div);
+        assert!(int(&right).is_some());
+        let code = "fn main() -> i32 {\n    let x = 7i32;\n    let p = &x;\n    x * *p - 40i32 / 3i32 + 17i32 % 5i32\n}";
+        assert_eq!(run(code, &[], &[]).code, Some(49 - 13 + 2));
+    }
+
+    /// Exit code of a program that is 1 if `cond` holds, plus 10 for every call of `t` or `f`
+    fn truth(cond: &str) -> Option<i32> {
+        let code = format!("static CALLS: i32 = 0;\nfn t() -> bool {{\n    unsafe {{ CALLS += 1i32; }}\n    true\n}}\n\
+            fn f() -> bool {{\n    unsafe {{ CALLS += 1i32; }}\n    false\n}}\n\
+            fn main() -> i32 {{\n    let mut n = 0i32;\n    while {cond} {{\n        n = 1i32;\n        break;\n    }}\n    n + unsafe {{ CALLS }} * 10i32\n}}");
+        run(&code, &[], &[]).code
+    }
+
+    #[test]
+    fn logical_operators() {
+        let op = |expr: &Expr| match expr {
+            Expr::BinaryOp(Operator(op, _), left, right) => Some((op.clone(), left.1.clone(), right.1.clone())),
+            _ => None
+        };
+        // `&&` binds stronger than `||`, `!` stronger than both
+        let (or, _, right) = op(&tail("fn f(a: bool, b: bool, c: bool) -> bool { a || b && !c }")).unwrap();
+        assert_eq!(or, Op::Or);
+        let (and, _, not) = op(&right).unwrap();
+        assert_eq!(and, Op::And);
+        assert!(matches!(not, Expr::UnaryOp(Operator(Op::Not, _), _)));
+        let (ne, _, _) = op(&tail("fn f(a: i32) -> bool { a != 1i32 }")).unwrap();
+        assert_eq!(ne, Op::Ne);
+        assert_eq!(truth("t() && f()"), Some(20));
+        assert_eq!(truth("t() || f()"), Some(11));
+        assert_eq!(truth("!f() && !!t()"), Some(21));
+        // the right side only runs if the left doesn't decide the result
+        assert_eq!(truth("f() && t()"), Some(10));
+        assert_eq!(truth("f() || t() && f()"), Some(30));
+        let err = build_err("#[export]\nfn f(a: bool) -> bool {\n    a && 1i32\n}", &["--shared"]);
+        assert!(err.contains("expected bool found i32") && err.contains("while compiling logical and"), "{err}");
+    }
+
+    #[test]
+    fn comparison_operators() {
+        let cmp = |body: &str| match tail(&format!("fn f(a: i32, b: i32) -> bool {{ {body} }}")) {
+            Expr::BinaryOp(Operator(op, _), _, _) => op,
+            other => panic!("expected a comparison, found {other:?}")
+        };
+        for (body, op) in [("a == b", Op::Eq), ("a != b", Op::Ne), ("a < b", Op::Lt), ("a > b", Op::Gt), ("a <= b", Op::Le), ("a >= b", Op::Ge)] {
+            assert_eq!(cmp(body), op, "{body}");
+        }
+        // `<` after a type name still opens its generics
+        assert!(parse_module("fn f(v: Vec<u8>) -> bool { 1i32 <= 2i32 }").is_ok());
+        assert_eq!(truth("-1i32 < 1i32"), Some(1));
+        assert_eq!(truth("255u8 > 1u8"), Some(1));
+        assert_eq!(truth("3i32 <= 3i32 && 3i32 >= 4i32"), Some(0));
+        let err = parse_module("fn f(a: i32) -> bool { 1i32 < a < 3i32 }").unwrap_err();
+        assert!(err.contains("comparisons can't be chained, put parentheses around one of < and <") && err.contains("<string>: 1:29\n") && err.contains("<string>: 1:33\n"), "{err}");
+    }
+
+    #[test]
+    fn statements_on_one_line() {
+        let module = parse_module("fn f() { let a: u8 = 1; let b: u8 = 2; }").unwrap_or_else(|e| panic!("{e}"));
+        let body = module.functions.values().next().unwrap().body.clone().unwrap();
+        let spans = body.0.iter().map(|stmt| (format!("{:?}", stmt.0.2), stmt.1)).collect::<Vec<_>>();
+        assert_eq!(spans, [("1:10..1:22".to_string(), true), ("1:25..1:37".to_string(), true)]);
+        let err = parse_module("fn f() { let a: u8 = 1 let b: u8 = 2; }").unwrap_err();
+        assert!(err.contains("expected ';' after this statement, another one follows it") && err.contains("<string>: 1:22\n") && err.contains("<string>: 1:24..1:37\n"), "{err}");
+    }
+
+    #[test]
+    fn assignment_to_non_places() {
+        for (target, col) in [("5", "2:5"), ("f()", "2:5..2:7"), ("(x + 1i32)", "2:5..2:14"), ("-x", "2:5..2:6")] {
+            let code = format!("fn f() -> i32 {{\n    {target} = 1i32;\n    0i32\n}}");
+            let err = parse_module(&code).unwrap_err();
+            assert!(err.contains("cannot assign to this expression") && err.contains(&format!("<string>: {col}")), "{target}: {err}");
+        }
+        // places parse, whether they may be assigned is checked later
+        parse_module("fn f(p: &i32, a: [i32; 2], t: (i32, u8)) {\n    *p = 1i32;\n    a[0] = 1i32;\n    t.0 = 1i32;\n}").unwrap();
+        let err = build_err("fn main() {\n    5i32 = 1i32;\n}", &[]);
+        assert!(err.contains("cannot assign to this expression"), "{err}");
+    }
+
+    #[test]
+    fn compound_assignment_operators() {
+        for (c, op) in [('+', Op::Add), ('-', Op::Sub), ('*', Op::Mul), ('/', Op::Div), ('%', Op::Mod)] {
+            let assign = tail(&format!("fn f(x: i32) {{ x {c}= 1i32 }}"));
+            assert!(matches!(&assign, Expr::VarAssign(_, Some(Operator(o, _)), _) if *o == op), "{c}: {assign:?}");
+        }
+        assert!(matches!(tail("fn f(x: i32) { x = 1i32 }"), Expr::VarAssign(_, None, _)));
+        // comparisons are still binary operators
+        assert!(matches!(tail("fn f(x: i32) { x == 1i32 }"), Expr::BinaryOp(Operator(Op::Eq, _), _, _)));
+        assert!(matches!(tail("fn f(x: i32) { x <= 1i32 }"), Expr::BinaryOp(Operator(Op::Le, _), _, _)));
+        let err = parse_module("fn f(x: i32) { x &= 1i32 }").unwrap_err();
+        assert!(err.contains("expected an assignment, found '&='"), "{err}");
+        let err = parse_module("fn f() { 5i32 += 1i32 }").unwrap_err();
+        assert!(err.contains("cannot assign to this expression"), "{err}");
     }
 }
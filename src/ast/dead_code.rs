@@ -0,0 +1,112 @@
+use std::collections::{HashMap, HashSet};
+use crate::ast::Module;
+use crate::ast::call_graph::{Callee, References};
+use crate::error::{ParseError, ParseET};
+
+impl Module {
+    /// Removes functions, constants and statics that are neither reachable from the `entry` point
+    /// nor visible outside of the module, so no code is generated for them.
+    pub(crate) fn eliminate_dead_code(&mut self, entry: Option<&str>) {
+        let symbols = self.symbols_by_name();
+        let graph = self.call_graph();
+        let is_visible = |tags: &HashMap<String, _>| tags.contains_key("export") || tags.contains_key("hidden");
+        let mut todo = self.functions.iter()
+            .filter(|(s, f)| is_visible(&f.tags) || entry == Some(s.as_str())
+                // run by the loader, nothing calls them
+                || f.tags.contains_key("constructor") || f.tags.contains_key("destructor"))
+            .map(|(s, _)| s.clone())
+            .chain(self.constants.iter().filter(|(_, c)| is_visible(&c.tags)).map(|(s, _)| s.clone()))
+            .chain(self.statics.iter().filter(|(_, s)| is_visible(&s.tags)).map(|(s, _)| s.clone()))
+            .collect::<Vec<_>>();
+        let mut reachable = HashSet::new();
+        while let Some(symbol) = todo.pop() {
+            if !reachable.insert(symbol.clone()) {
+                continue
+            }
+            let mut refs = References::default();
+            if let Some(func) = self.functions.get(&symbol) {
+                todo.extend(graph.callees(&symbol).filter_map(|c| match c {
+                    Callee::Function(s) => Some(s.clone()),
+                    Callee::Unknown => None
+                }));
+                func.body.iter().for_each(|body| body.references(&mut refs));
+                refs.calls.clear();
+            } else if let Some(constant) = self.constants.get(&symbol) {
+                constant.val.references(&mut refs);
+            } else if let Some(s) = self.statics.get(&symbol) {
+                s.val.references(&mut refs);
+            }
+            // naming a function takes its address, so it may be called through a pointer
+            for name in refs.calls.iter().chain(&refs.variables) {
+                todo.extend(symbols.get(name.as_str()).into_iter().flatten().map(|s| s.to_string()));
+                if self.constants.contains_key(name) || self.statics.contains_key(name) {
+                    todo.push(name.clone());
+                }
+            }
+        }
+        self.functions.retain(|symbol, _| reachable.contains(symbol));
+        self.constants.retain(|symbol, _| reachable.contains(symbol));
+        self.statics.retain(|symbol, _| reachable.contains(symbol));
+    }
+
+    /// Keeps only the functions matching one of the glob `patterns`, see `--only-fn`.
+    /// Functions they reference lose their body, so they are only declared, constants and statics are kept as they are.
+    pub(crate) fn keep_only_fns(&mut self, patterns: &[String], entry: Option<&str>) -> Result<(), ParseError> {
+        let mut kept = HashSet::new();
+        for pattern in patterns {
+            let symbols = self.functions_matching(pattern);
+            if symbols.is_empty() {
+                return Err(ParseET::ArgumentError(format!("--only-fn {pattern} matches no function")).error())
+            }
+            kept.extend(symbols.into_iter().map(str::to_string));
+        }
+        let mut refs = References::default();
+        for symbol in &kept {
+            self.functions[symbol].body.iter().for_each(|body| body.references(&mut refs));
+        }
+        let symbols = self.symbols_by_name();
+        let names = refs.calls.iter().chain(&refs.variables).map(String::as_str).collect::<HashSet<_>>();
+        // the wrapper of the executable calls the entry point
+        let declared = names.iter()
+            .flat_map(|name| symbols.get(name).into_iter().flatten().map(|s| s.to_string()))
+            .chain(entry.map(str::to_string))
+            .filter(|symbol| !kept.contains(symbol))
+            .collect::<HashSet<_>>();
+        self.functions.retain(|symbol, _| kept.contains(symbol) || declared.contains(symbol));
+        for symbol in &declared {
+            let func = self.functions.get_mut(symbol).unwrap();
+            func.body = None;
+            // would run a function that isn't there
+            func.tags.retain(|name, _| name != "constructor" && name != "destructor");
+        }
+        self.constants.retain(|symbol, _| names.contains(symbol.as_str()));
+        self.statics.retain(|symbol, _| names.contains(symbol.as_str()));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::testing::ir;
+
+    const CODE: &str = "fn helper() -> i32 { 1i32 }\nfn excluded() -> i32 { 2i32 }\nfn kept() -> i32 { helper() }\nfn main() {\n    kept();\n    excluded();\n}";
+
+    #[test]
+    fn only_fn_keeps_matches_and_declares_callees() {
+        let ir = ir(CODE, &["--only-fn", "kep*"]);
+        assert!(ir.contains("define internal i32 @kept()"), "{ir}");
+        assert!(ir.contains("declare dso_local i32 @helper()"), "{ir}");
+        assert!(!ir.contains("@excluded"), "{ir}");
+    }
+    #[test]
+    fn unreachable_items_are_not_emitted() {
+        let code = "static USED: i32 = 1i32;\nstatic UNUSED: i32 = 2i32;\nfn unused() -> i32 { UNUSED }\nfn called() -> i32 { USED }\nfn pointed() {}\n\
+            #[constructor]\nfn init() {}\nfn main() -> i32 {\n    let p = &pointed;\n    called()\n}";
+        let emitted = ir(code, &[]);
+        assert!(emitted.contains("@called()") && emitted.contains("@USED") && emitted.contains("@pointed()") && emitted.contains("@init()"), "{emitted}");
+        assert!(!emitted.contains("@unused") && !emitted.contains("@UNUSED"), "{emitted}");
+        // exported functions are reachable from outside
+        let ir = ir("#[export]\nfn lib() -> i32 { helper() }\nfn helper() -> i32 { 1i32 }\nfn unused() {}", &["--shared"]);
+        assert!(ir.contains("@helper()") && !ir.contains("@unused"), "{ir}");
+    }
+}
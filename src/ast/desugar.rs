@@ -0,0 +1,309 @@
+use std::collections::{HashMap, HashSet};
+use crate::ast::{Block, Const, Expr, Expression, Func, Ident, Item, Module, Op, Operator, Static, Statement, Trivia, Ty, Type};
+use crate::ast::code_printer::CodePrinter;
+use crate::ast::visit::ChildMut;
+use crate::error::{ParseError, ParseET};
+use crate::source::span::Span;
+
+/// Functions a type has to provide to be iterated by a for-in loop,
+/// they are overloaded on the iterated type `C`
+const ITER_PROTOCOL: [(&str, &str); 3] = [
+    ("iter_start", "fn iter_start(iterable: &C) -> S"),
+    ("iter_done", "fn iter_done(iterable: &C, state: &S) -> bool"),
+    ("iter_next", "fn iter_next(iterable: &C, state: &S) -> T")
+];
+
+impl Module {
+    /// Moves constants and statics declared in function bodies to the module, named `function.NAME`.
+    /// They are visible in the whole block declaring them, the innermost item or variable of a name shadows the others.
+    pub(crate) fn hoist_local_items(&mut self) -> Result<(), ParseError> {
+        let mut items = LocalItems {
+            function: String::new(),
+            taken: self.constants.keys().chain(self.statics.keys()).chain(self.functions.keys()).cloned().collect(),
+            scopes: vec![],
+            constants: vec![],
+            statics: vec![]
+        };
+        for func in self.functions.values_mut() {
+            if let Some(body) = &mut func.body {
+                items.function = func.name.0.clone();
+                items.scopes = vec![func.args.iter().map(|(arg, _)| (arg.0.clone(), None)).collect()];
+                items.block(body).map_err(|e| e.when_fn(&func.name.0))?;
+            }
+        }
+        self.constants.extend(items.constants.into_iter().map(|c| (c.name.0.clone(), c)));
+        self.statics.extend(items.statics.into_iter().map(|s| (s.name.0.clone(), s)));
+        for module in self.sub_modules.values_mut() {
+            module.hoist_local_items()?;
+        }
+        Ok(())
+    }
+
+    /// Rewrites for-in loops over iterables into calls of the iterator protocol:
+    /// ```text
+    /// {
+    ///     let for.iter = &iterable;
+    ///     let mut for.state = iter_start(for.iter);
+    ///     while !iter_done(for.iter, &for.state) {
+    ///         let x = iter_next(for.iter, &for.state);
+    ///         { body }
+    ///     }
+    /// }
+    /// ```
+    pub(crate) fn desugar_for_in(&mut self) -> Result<(), ParseError> {
+        let protocol = self.functions.values().filter(|f| ITER_PROTOCOL.iter().any(|(name, _)| f.name.0 == *name)).cloned().collect::<Vec<_>>();
+        for func in self.functions.values_mut() {
+            if let Some(body) = &mut func.body {
+                body.walk_mut(&mut |expr| expr.desugar_for_in(&protocol)).map_err(|e| e.when_fn(&func.name.0))?;
+            }
+        }
+        for module in self.sub_modules.values_mut() {
+            module.desugar_for_in()?;
+        }
+        Ok(())
+    }
+}
+
+impl Expression {
+    fn desugar_for_in(&mut self, protocol: &[Func]) -> Result<(), ParseError> {
+        if let Expr::ForIn(label, var, iterable, body) = &mut self.1 {
+            let missing = ITER_PROTOCOL.iter().filter(|(name, _)| !protocol.iter().any(|f| f.name.0 == *name)).collect::<Vec<_>>();
+            if !missing.is_empty() {
+                return Err(ParseET::CompilationError(format!("iterating over something other than a range needs the iterator protocol, missing:\n        {}",
+                    missing.iter().map(|(_, sig)| *sig).collect::<Vec<_>>().join("\n        ")))
+                    .at(iterable.2.clone()).when("desugaring for loop"))
+            }
+            check_iter_protocol(protocol, &iterable.2)?;
+            let loc = self.2.expanded("desugaring for loop");
+            let expr = |e: Expr| Expression(HashMap::new(), e, loc.clone());
+            let var_ref = |name: &str| expr(Expr::Variable(Ident(name.to_string(), loc.clone())));
+            let call = |name: &str, args: Vec<Expression>| expr(Expr::FuncCall(Item::new(&vec![name], loc.clone()), args));
+            let stmt = |e: Expression| Statement(e, true, loc.clone(), Trivia::default());
+            let state_args = || vec![var_ref("for.iter"), expr(Expr::Point(Box::new(var_ref("for.state"))))];
+            let iterable = std::mem::replace(iterable, Box::new(expr(Expr::Block(Block(vec![], loc.clone(), vec![])))));
+            let body = std::mem::replace(body, Block(vec![], loc.clone(), vec![]));
+            let loop_body = Block(vec![
+                stmt(expr(Expr::VarCreate(var.clone(), false, None, Box::new(call("iter_next", state_args()))))),
+                stmt(expr(Expr::Block(body)))
+            ], loc.clone(), vec![]);
+            let cond = expr(Expr::UnaryOp(Operator(Op::Not, loc.clone()), Box::new(call("iter_done", state_args()))));
+            self.1 = Expr::Block(Block(vec![
+                stmt(expr(Expr::VarCreate(Ident("for.iter".to_string(), loc.clone()), false, None, Box::new(expr(Expr::Point(iterable)))))),
+                stmt(expr(Expr::VarCreate(Ident("for.state".to_string(), loc.clone()), true, None, Box::new(call("iter_start", vec![var_ref("for.iter")]))))),
+                stmt(expr(Expr::While(label.take(), Box::new(cond), loop_body)))
+            ], loc.clone(), vec![]));
+        }
+        Ok(())
+    }
+}
+
+/// Checks the signatures of the protocol functions, so a wrong one is reported at the loop using it instead of where codegen trips over it.
+/// Every `iter_done` and `iter_next` needs an `iter_start` of the same iterable, its state is what they get a pointer to
+fn check_iter_protocol(protocol: &[Func], iterable: &Span) -> Result<(), ParseError> {
+    let pointee = |ty: &Type| match &ty.0 {
+        Ty::Pointer(pointee) => Some(pointee.as_ref().clone()),
+        _ => None
+    };
+    for (name, sig) in ITER_PROTOCOL {
+        for f in protocol.iter().filter(|f| f.name.0 == name) {
+            let mismatch = |reason: String| Err(ParseET::CompilationError(format!("{name} does not fit the iterator protocol, {reason}, expected\n        {sig}"))
+                .ats(vec![iterable.clone(), f.name.1.clone()]).when("desugaring for loop"));
+            let params = f.args.iter().map(|(_, ty)| pointee(ty)).collect::<Vec<_>>();
+            if params.len() != if name == "iter_start" { 1 } else { 2 } {
+                return mismatch(format!("it takes {} parameters", params.len()))
+            }
+            if let Some(i) = params.iter().position(Option::is_none) {
+                return mismatch(format!("parameter {} is not a pointer", f.args[i].0.0))
+            }
+            if name == "iter_start" {
+                continue
+            }
+            if name == "iter_done" && !f.ret.is_bool() {
+                return mismatch(format!("it returns {} instead of bool", f.ret.print()))
+            }
+            let iterated = params[0].as_ref().unwrap();
+            let start = protocol.iter().find(|s| s.name.0 == "iter_start" && s.args.len() == 1 && s.args[0].1.satisfies(&f.args[0].1));
+            match start {
+                None => return mismatch(format!("there is no iter_start for {}", iterated.print())),
+                Some(start) if !start.ret.satisfies(params[1].as_ref().unwrap()) =>
+                    return mismatch(format!("its state is {} but iter_start starts with {}", params[1].as_ref().unwrap().print(), start.ret.print())),
+                _ => ()
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Local items of the functions of a module while hoisting them, see [`Module::hoist_local_items`]
+struct LocalItems {
+    function: String,
+    /// symbols of the module, hoisted items get a name that isn't one of them
+    taken: HashSet<String>,
+    /// by name the symbol of the item, `None` for variables
+    scopes: Vec<HashMap<String, Option<String>>>,
+    constants: Vec<Const>,
+    statics: Vec<Static>
+}
+
+impl LocalItems {
+    fn symbol(&mut self, name: &str) -> String {
+        let mut symbol = format!("{}.{name}", self.function);
+        let mut n = 1;
+        while self.taken.contains(&symbol) {
+            symbol = format!("{}.{name}.{n}", self.function);
+            n += 1;
+        }
+        self.taken.insert(symbol.clone());
+        symbol
+    }
+
+    fn bind(&mut self, var: &Ident) {
+        self.scopes.last_mut().unwrap().insert(var.0.clone(), None);
+    }
+
+    fn block(&mut self, block: &mut Block) -> Result<(), ParseError> {
+        // items can be used before they are declared, unlike variables
+        let mut declared: HashMap<String, Ident> = HashMap::new();
+        let mut scope = HashMap::new();
+        for stmt in &mut block.0 {
+            let name = match &mut stmt.0.1 {
+                Expr::Const(c) => &mut c.name,
+                Expr::Static(s) => &mut s.name,
+                _ => continue
+            };
+            if let Some(other) = declared.insert(name.0.clone(), name.clone()) {
+                return Err(ParseET::AlreadyDefinedError("local item".to_string(), name.0.clone()).ats(vec![name.1.clone(), other.1]))
+            }
+            let symbol = self.symbol(&name.0);
+            scope.insert(std::mem::replace(&mut name.0, symbol.clone()), Some(symbol));
+        }
+        self.scopes.push(scope);
+        let hoisted = block.0.iter_mut().try_for_each(|stmt| self.expr(&mut stmt.0));
+        self.scopes.pop();
+        block.0.retain(|stmt| !matches!(stmt.0.1, Expr::Const(_) | Expr::Static(_)));
+        hoisted
+    }
+
+    /// `block` with `var` bound in it
+    fn block_with(&mut self, var: &Ident, block: &mut Block) -> Result<(), ParseError> {
+        self.scopes.push(HashMap::from([(var.0.clone(), None)]));
+        let hoisted = self.block(block);
+        self.scopes.pop();
+        hoisted
+    }
+
+    fn expr(&mut self, expr: &mut Expression) -> Result<(), ParseError> {
+        match &mut expr.1 {
+            Expr::Variable(var) => {
+                if let Some(Some(symbol)) = self.scopes.iter().rev().find_map(|scope| scope.get(&var.0)) {
+                    var.0 = symbol.clone();
+                }
+            }
+            Expr::Const(c) => {
+                if let Some(tag) = expr.0.values().next() {
+                    return Err(ParseET::TagError(format!("tag {} not applicable for local consts", tag.0.0)).at(tag.2.clone()))
+                }
+                self.expr(&mut c.val)?;
+                self.constants.push((**c).clone());
+            }
+            Expr::Static(s) => {
+                if let Some(tag) = expr.0.values().find(|t| t.0.0 != "thread_local") {
+                    return Err(ParseET::TagError(format!("tag {} not applicable for local statics", tag.0.0)).at(tag.2.clone()))
+                }
+                self.expr(&mut s.val)?;
+                s.tags = std::mem::take(&mut expr.0);
+                self.statics.push((**s).clone());
+            }
+            Expr::VarCreate(var, _, _, value) => {
+                self.expr(value)?;
+                self.bind(var);
+            }
+            Expr::LetTuple(vars, _, value) => {
+                self.expr(value)?;
+                vars.iter().for_each(|var| self.bind(var));
+            }
+            Expr::LetElse(var, value, else_block) => {
+                self.expr(value)?;
+                self.block(else_block)?;
+                self.bind(var);
+            }
+            Expr::ForIn(_, var, iterable, body) => {
+                self.expr(iterable)?;
+                self.block_with(var, body)?;
+            }
+            Expr::For(_, var, range, step, body) => {
+                self.expr(&mut range.0)?;
+                self.expr(&mut range.1)?;
+                if let Some(step) = step {
+                    self.expr(step)?;
+                }
+                self.block_with(var, body)?;
+            }
+            // binds nothing, its parts are hoisted in order
+            _ => expr.children_mut().into_iter().try_for_each(|child| match child {
+                ChildMut::Expr(expr) => self.expr(expr),
+                ChildMut::Block(block) => self.block(block)
+            })?
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::code_printer::CodePrinter;
+    use crate::util::testing::{build_err, parse_module, run};
+
+    const PROTOCOL: &str = "type Pair = [i32; 2];\nfn iter_start(p: &Pair) -> u64 { 0u64 }\nfn iter_done(p: &Pair, i: &u64) -> bool { *i >= 2u64 }\nfn iter_next(p: &Pair, i: &u64) -> i32 {\n    *i += 1u64;\n    (*p)[*i - 1u64]\n}\n";
+
+    #[test]
+    fn iter_example_sums_its_elements() {
+        let run = run(include_str!("../../examples/iter/iter.li"), &[], &[]);
+        assert_eq!((run.code, run.stdout.as_str(), run.stderr.as_str()), (Some(15), "", ""));
+    }
+
+    #[test]
+    fn for_in_lists_missing_protocol_functions() {
+        let err = build_err("fn iter_start(a: &[i32; 2]) -> u64 { 0u64 }\nfn main() {\n    for x in [1i32, 2i32] {}\n}", &[]);
+        // only the absent ones are listed
+        assert!(err.contains("missing:\n        fn iter_done(iterable: &C, state: &S) -> bool\n        fn iter_next(iterable: &C, state: &S) -> T\n    while"), "{err}");
+        assert!(err.contains("<string>: 3:14..3:25"), "{err}");
+    }
+
+    #[test]
+    fn state_of_for_in_is_mutable() {
+        let mut module = parse_module(&format!("{PROTOCOL}fn f(p: Pair) {{\n    for x in p {{}}\n}}")).unwrap();
+        module.desugar_for_in().unwrap();
+        let printed = module.print();
+        assert!(printed.contains("let mut for.state = iter_start(for.iter);"), "{printed}");
+    }
+
+    #[test]
+    fn for_in_checks_protocol_signatures() {
+        let err = |protocol: &str| build_err(&format!("{protocol}fn main() {{\n    let p: Pair = [1i32, 2i32];\n    for x in p {{}}\n}}"), &[]);
+        let e = err(&PROTOCOL.replace("fn iter_start(p: &Pair)", "fn iter_start(p: &Pair, q: &Pair)"));
+        assert!(e.contains("iter_start does not fit the iterator protocol, it takes 2 parameters, expected\n        fn iter_start(iterable: &C) -> S"), "{e}");
+        // points at the loop and the function
+        assert!(e.contains("<string>: 10:14") && e.contains("<string>: 2:4..2:13"), "{e}");
+        let e = err(&PROTOCOL.replace("fn iter_done(p: &Pair, i: &u64)", "fn iter_done(p: &Pair, i: u64)"));
+        assert!(e.contains("iter_done does not fit the iterator protocol, parameter i is not a pointer"), "{e}");
+        let e = err(&PROTOCOL.replace("-> bool { *i >= 2u64 }", "-> u8 { 0u8 }"));
+        assert!(e.contains("iter_done does not fit the iterator protocol, it returns u8 instead of bool"), "{e}");
+        let e = err(&PROTOCOL.replace("fn iter_next(p: &Pair, i: &u64)", "fn iter_next(p: &Pair, i: &u32)"));
+        assert!(e.contains("iter_next does not fit the iterator protocol, its state is u32 but iter_start starts with u64"), "{e}");
+        let e = err(&PROTOCOL.replace("fn iter_next(p: &Pair, i: &u64) -> i32 {\n    *i += 1u64;\n    (*p)[*i - 1u64]\n}", "fn iter_next(p: &u8, i: &u64) -> i32 { 0i32 }"));
+        assert!(e.contains("iter_next does not fit the iterator protocol, there is no iter_start for u8"), "{e}");
+        assert_eq!(run(&format!("{PROTOCOL}fn main() -> i32 {{\n    let p: Pair = [3i32, 4i32];\n    let mut sum = 0i32;\n    for x in p {{\n        sum += x;\n    }}\n    sum\n}}"), &[], &[]).code, Some(7));
+    }
+
+    #[test]
+    fn local_items() {
+        let code = "const S: &[u8; 4] = &\"abc\";\nfn other() -> u8 {\n    S[0]\n}\n\
+            fn main() -> i32 {\n    static TABLE: [i32; 4] = [1i32, 2i32, 3i32, 4i32];\n    let mut sum = 0i32;\n    for i in 0u64..4u64 {\n        sum += TABLE[i];\n    }\n    const S: &[u8; 4] = &\"xyz\";\n    \
+            // the innermost S wins, other functions still see the module's\n    while S[0] == 'x' && other() == 'a' {\n        sum += 100i32;\n        break;\n    }\n    sum\n}";
+        assert_eq!(run(code, &[], &[]).code, Some(110));
+        let err = build_err("fn a() {\n    static T: i32 = 1i32;\n}\nfn main() -> i32 {\n    T\n}", &[]);
+        // hoisting doesn't make it visible outside of its scope
+        assert!(err.contains("could not find variable T") && err.contains("<string>: 5:5\n"), "{err}");
+    }
+}
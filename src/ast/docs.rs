@@ -0,0 +1,122 @@
+use std::fmt::{Display, Formatter};
+use crate::ast::code_printer::CodePrinter;
+use crate::ast::create_patterns::ModuleContent;
+use crate::ast::parser::parse_items;
+use crate::ast::{Comment, Ident};
+use crate::error::{ParseError, ParseET};
+use crate::source::span::Span;
+use crate::tokens::{Token, TokenType};
+
+// documentation of items from the `///` comments before them, see `--emit docs-json`
+
+/// An item with the text of the doc comments before it
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ItemDoc {
+    pub(crate) kind: &'static str,
+    pub(crate) name: String,
+    pub(crate) signature: String,
+    /// one line per doc comment, without the `///`
+    pub(crate) doc: String
+}
+
+/// Documentation of the items of a module in source order
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Docs(pub(crate) Vec<ItemDoc>);
+
+impl Comment {
+    /// `/// ...`, but not `//// ...`
+    pub(crate) fn is_doc(&self) -> bool {
+        self.0.starts_with("///") && !self.0.starts_with("////")
+    }
+}
+
+/// Extracts the docs of every item from `tokens`, which have to include comments.
+/// Doc comments that don't document an item are returned as warnings.
+pub(crate) fn extract_docs(tokens: Vec<Token>) -> Result<(Docs, Vec<ParseError>), ParseError> {
+    let mut unused = tokens.iter().filter_map(|t| match &t.tt {
+        TokenType::Comment(text) => Some(Comment(text.clone(), t.loc.clone())),
+        _ => None
+    }).filter(Comment::is_doc).map(|c| c.1).collect::<Vec<Span>>();
+    let (items, _) = parse_items(tokens)?;
+    let docs = items.iter().filter_map(|(_, content, _, trivia)| describe_item(content).map(|d| (d, trivia))).map(|((kind, name, signature), trivia)| {
+        // before the name, so also between the tags, but not in the body
+        let doc = trivia.leading.iter().filter(|c| c.is_doc() && c.1.start < name.1.start).map(|c| {
+            unused.retain(|loc| loc.start != c.1.start);
+            let line = &c.0["///".len()..];
+            line.strip_prefix(' ').unwrap_or(line).trim_end().to_string()
+        }).collect::<Vec<_>>().join("\n");
+        ItemDoc { kind, name: name.0.clone(), signature, doc }
+    }).collect();
+    let warnings = unused.into_iter()
+        .map(|loc| ParseET::Warning("doc comment doesn't document anything, it has to be right before an item".to_string()).at(loc))
+        .collect();
+    Ok((Docs(docs), warnings))
+}
+
+/// Kind, name and signature of an item, `None` for code that didn't parse
+pub(crate) fn describe_item(content: &ModuleContent) -> Option<(&'static str, &Ident, String)> {
+    Some(match content {
+        ModuleContent::Function(f) => ("fn", &f.name, format!("{}fn {}({}){}",
+            if f.tags.contains_key("const") { "const " } else { "" },
+            f.name.print(),
+            f.args.iter().map(|(ident, ty)| format!("{}: {}", ident.print(), ty.print())).collect::<Vec<_>>().join(", "),
+            if f.ret.0.is_empty() { String::new() } else { format!(" -> {}", f.ret.print()) })),
+        ModuleContent::Const(c) => ("const", &c.name, format!("const {}: {}", c.name.print(), c.ty.print())),
+        ModuleContent::Static(s) => ("static", &s.name, format!("static {}: {}", s.name.print(), s.ty.print())),
+        ModuleContent::Alias(a) => ("type", &a.name, format!("type {} = {}", a.name.print(), a.ty.print())),
+        ModuleContent::ExternType(t) => ("extern type", &t.name, format!("extern type {}", t.name.print())),
+        ModuleContent::Error(_) => return None
+    })
+}
+
+pub(crate) fn json_string(s: &str) -> String {
+    let mut json = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => json.push_str("\\\""),
+            '\\' => json.push_str("\\\\"),
+            '\n' => json.push_str("\\n"),
+            c if c.is_control() => json.push_str(&format!("\\u{:04x}", c as u32)),
+            c => json.push(c)
+        }
+    }
+    json.push('"');
+    json
+}
+
+/// As json array of objects with kind, name, signature and doc
+impl Display for Docs {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let items = self.0.iter().map(|item| format!("  {{\"kind\": {}, \"name\": {}, \"signature\": {}, \"doc\": {}}}",
+            json_string(item.kind), json_string(&item.name), json_string(&item.signature), json_string(&item.doc))).collect::<Vec<_>>();
+        if items.is_empty() {
+            write!(f, "[]")
+        } else {
+            write!(f, "[\n{}\n]", items.join(",\n"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::ast::docs::extract_docs;
+    use crate::source::Source;
+    use crate::tokens::tokenizer::tokenize;
+
+    #[test]
+    fn docs_of_items() {
+        let code = "/// Adds two numbers.\n///\n/// Wraps on overflow.\n#[export]\nfn add(a: i32, b: &[u8; 2]) -> i32 {\n    /// not for the body\n    a\n}\n\
+            //// a plain comment\nconst C: &[u8; 3] = &\"hi\";\n/// \"quoted\"\nstatic S: u8 = 0u8;\n/// nothing follows\n";
+        let (docs, warnings) = extract_docs(tokenize(Rc::new(Source::from_string(code.to_string())), true).unwrap()).unwrap();
+        assert_eq!(docs.to_string(), "[\n  \
+            {\"kind\": \"fn\", \"name\": \"add\", \"signature\": \"fn add(a: i32, b: &[u8;2]) -> i32\", \"doc\": \"Adds two numbers.\\n\\nWraps on overflow.\"},\n  \
+            {\"kind\": \"const\", \"name\": \"C\", \"signature\": \"const C: &[u8;3]\", \"doc\": \"\"},\n  \
+            {\"kind\": \"static\", \"name\": \"S\", \"signature\": \"static S: u8\", \"doc\": \"\\\"quoted\\\"\"}\n\
+            ]");
+        // the one in the body and the one at the end
+        let warnings = warnings.iter().map(|w| w.render(false)).collect::<Vec<_>>();
+        assert!(matches!(warnings.as_slice(), [body, end] if body.contains("<string>: 6:5..6:24\n") && end.contains("<string>: 13:1..13:19\n")
+            && end.contains("doc comment doesn't document anything, it has to be right before an item")), "{warnings:?}");
+    }
+}
@@ -0,0 +1,66 @@
+use crate::ast::{Module, Ty};
+use crate::error::{ParseError, ParseET};
+
+impl Module {
+    /// Symbol of the function an executable starts in, the one tagged `#[entry]`, else the one named `main`.
+    /// It has to take no arguments and return `()` or the exit code as `i32`.
+    pub(crate) fn entry_point(&self) -> Result<String, ParseError> {
+        let mut tagged = self.functions.iter().filter(|(_, f)| f.tags.contains_key("entry")).collect::<Vec<_>>();
+        tagged.sort_by_key(|(_, f)| f.name.1.start);
+        let (symbol, func) = match tagged.as_slice() {
+            [entry] => *entry,
+            [] => {
+                let mut mains = self.functions.iter().filter(|(_, f)| f.name.0 == "main").collect::<Vec<_>>();
+                mains.sort_by_key(|(_, f)| f.name.1.start);
+                match mains.as_slice() {
+                    [main] => *main,
+                    [] => return Err(ParseET::CompilationError("executable has no entry point, name a function main or tag it #[entry]".to_string())
+                        .at(self.loc.start().span())),
+                    _ => return Err(ParseET::CompilationError("main is overloaded, tag the one to start in with #[entry]".to_string())
+                        .ats(mains.iter().map(|(_, f)| f.name.1.clone()).collect()))
+                }
+            }
+            _ => return Err(ParseET::TagError(format!("{} functions are tagged #[entry], an executable has exactly one entry point", tagged.len()))
+                .ats(tagged.iter().map(|(_, f)| f.tags["entry"].2.clone()).collect()))
+        };
+        let tag_loc = func.tags.get("entry").map(|tag| tag.2.clone());
+        if func.tags.contains_key("extern") {
+            return Err(ParseET::TagError(format!("extern function {} has no body to start in", func.name.0))
+                .ats(tag_loc.into_iter().chain([func.name.1.clone()]).collect()))
+        }
+        let exit_code = matches!(&func.ret.0, Ty::Single(generics, base_type) if generics.is_empty() && base_type.0.len() == 1 && base_type.0[0].0 == "i32");
+        if !func.args.is_empty() || !(func.ret.0.is_empty() || exit_code) {
+            let mut signature = func.name.1.clone();
+            signature.combine(func.ret.1.clone());
+            return Err(ParseET::CompilationError(format!("entry point {} has to take no arguments and return () or i32", func.name.0))
+                .ats(tag_loc.into_iter().chain([signature]).collect()))
+        }
+        Ok(symbol.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::testing::{build_err, run};
+
+    #[test]
+    fn entry_tag_picks_the_start() {
+        let code = "fn main() -> i32 {\n    1i32\n}\n#[entry]\nfn start() -> i32 {\n    5i32\n}";
+        assert_eq!(run(code, &[], &[]).code, Some(5));
+        assert_eq!(run("fn main() {}", &[], &[]).code, Some(0));
+    }
+
+    #[test]
+    fn entry_point_errors() {
+        let err = build_err("fn start() {}", &[]);
+        assert!(err.contains("executable has no entry point, name a function main or tag it #[entry]"), "{err}");
+        let err = build_err("#[entry]\nfn a() {}\n#[entry]\nfn b() {}", &[]);
+        assert!(err.contains("2 functions are tagged #[entry], an executable has exactly one entry point"), "{err}");
+        let err = build_err("#[entry]\nfn start(x: i32) {}", &[]);
+        assert!(err.contains("entry point start has to take no arguments and return () or i32"), "{err}");
+        let err = build_err("#[entry]\nfn start() -> u8 {\n    0u8\n}", &[]);
+        assert!(err.contains("entry point start has to take no arguments and return () or i32"), "{err}");
+        let err = build_err("fn main(x: i32) {}\nfn main() {}", &[]);
+        assert!(err.contains("main is overloaded, tag the one to start in with #[entry]"), "{err}");
+    }
+}
@@ -0,0 +1,55 @@
+use std::rc::Rc;
+use crate::ast::Type;
+use crate::source::CodePoint;
+use crate::source::span::Span;
+
+/// Types of the expressions of a module as resolved while generating code, for tooling like hovers.
+/// Only functions and constants that survived dead code elimination are included.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct ExprTypes {
+    types: Vec<(Span, Type)>
+}
+
+impl ExprTypes {
+    pub(crate) fn record(&mut self, loc: &Span, ty: &Type) {
+        self.types.push((loc.clone(), ty.clone()));
+    }
+
+    /// Type of the innermost expression containing `p`, `None` if `p` is not inside of an expression.
+    pub(crate) fn type_at(&self, p: &CodePoint) -> Option<&Type> {
+        // parents are recorded after their children, of expressions sharing a span the outermost one wins
+        self.types.iter().rev()
+            .filter(|(loc, _)| Rc::ptr_eq(&loc.source, &p.0) && loc.start <= p.1 && p.1 <= loc.end)
+            .min_by_key(|(loc, _)| loc.end - loc.start)
+            .map(|(_, ty)| ty)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::ast::code_printer::CodePrinter;
+    use crate::source::{CodePoint, Source};
+    use crate::util::testing::build_source;
+
+    #[test]
+    fn type_at_positions() {
+        let code = "#[export]\nfn f(x: i32) -> (u64, i32) {\n    let t = (&\"éé\", 5u64, 7i32);\n    (t.1, x)\n}";
+        let source = Rc::new(Source::from_string(code.to_string()));
+        let module = build_source(source.clone(), &["--shared"]).unwrap_or_else(|e| panic!("{}", e.render(false)));
+        let type_at = |line: usize, col: usize| source.index_of(line, col)
+            .and_then(|i| module.expr_types.type_at(&CodePoint(source.clone(), i))).map(|ty| ty.print());
+        // columns count chars, like the ones shown in diagnostics
+        let col = |line: usize, text: &str| {
+            let l = code.lines().nth(line - 1).unwrap();
+            l[..l.find(text).unwrap()].chars().count() + 1
+        };
+        assert_eq!(type_at(3, col(3, "5u64")).as_deref(), Some("u64"));
+        assert_eq!(type_at(3, col(3, "7i32")).as_deref(), Some("i32"));
+        assert_eq!(type_at(3, col(3, "&\"")).as_deref(), Some("&[u8;5]"));
+        assert_eq!(type_at(4, 6).as_deref(), Some("(&[u8;5], u64, i32)"));
+        assert_eq!(type_at(4, 11).as_deref(), Some("i32"));
+        assert_eq!(type_at(1, 1), None);
+        assert_eq!(type_at(9, 1), None);
+    }
+}
@@ -1,13 +1,28 @@
+pub(crate) mod aliases;
+pub(crate) mod builtins;
 pub(crate) mod parser;
 pub(crate) mod patterns;
+pub(crate) mod call_graph;
+pub(crate) mod cfg;
 pub(crate) mod code_printer;
+pub(crate) mod const_eval;
 pub(crate) mod create_patterns;
+pub(crate) mod dead_code;
+pub(crate) mod desugar;
+pub(crate) mod docs;
+pub(crate) mod entry;
+pub(crate) mod expr_types;
+pub(crate) mod outline;
+pub(crate) mod trivia;
+pub(crate) mod verify;
+pub(crate) mod visit;
 
 use std::collections::HashMap;
 use std::fmt::Debug;
-use crate::error::ParseError;
+use crate::ast::code_printer::CodePrinter;
+use crate::error::{ParseError, ParseET};
 use crate::source::span::Span;
-use crate::tokens::Literal;
+use crate::tokens::{Literal, NumLit, NumLitTy};
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Ident(pub(crate) String, pub(crate) Span);
@@ -26,7 +41,9 @@ pub(crate) struct Tag(pub(crate) Ident, pub(crate) Vec<TagValue>, pub(crate) Spa
 pub(crate) enum TagValue {
     Lit(AstLiteral),
     Ident(Ident),
-    Tag(Box<Tag>)
+    Tag(Box<Tag>),
+    /// `key = "value"`
+    Assign(Ident, AstLiteral)
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -38,6 +55,10 @@ pub(crate) struct Expression(pub(crate) HashMap<String, Tag>, pub(crate) Expr, p
 pub(crate) enum Expr {
     Point(Box<Expression>),
     Deref(Box<Expression>),
+    /// `array[index]`
+    Index(Box<Expression>, Box<Expression>),
+    /// `tuple.0`
+    Field(Box<Expression>, usize),
     Literal(AstLiteral),
     Variable(Ident),
     Block(Block),
@@ -45,10 +66,68 @@ pub(crate) enum Expr {
     BinaryOp(Operator, Box<Expression>, Box<Expression>),
     UnaryOp(Operator, Box<Expression>),
     VarCreate(Ident, bool, Option<Type>, Box<Expression>),
-    VarAssign(Ident, Option<Operator>, Box<Expression>),
+    /// `let name = ptr else { ... }`, binds `name` only if the pointer is not null
+    LetElse(Ident, Box<Expression>, Block),
+    /// `let (a, b): (A, B) = tuple`, binds each element of the tuple
+    LetTuple(Vec<Ident>, Option<Type>, Box<Expression>),
+    /// `const NAME: Type = value` in a function body, hoisted to the module by [`Module::hoist_local_items`]
+    Const(Box<Const>),
+    /// `static NAME: Type = value` in a function body, hoisted like [`Expr::Const`]
+    Static(Box<Static>),
+    /// `(a, b)`, at least two elements
+    Tuple(Vec<Expression>),
+    /// `(expr)`
+    Group(Box<Expression>),
+    /// `(expr: Type)`, checks that `expr` satisfies `Type` and treats it as such
+    Ascribe(Box<Expression>, Type),
+    /// `target = value`, the target has to be a place, see [`Expr::is_place`]
+    VarAssign(Box<Expression>, Option<Operator>, Box<Expression>),
     Return(Option<Box<Expression>>),
+    For(Option<Ident>, Ident, Range, Option<Box<Expression>>, Block),
+    /// `for x in iterable { ... }` over anything but a range, desugared by [`Module::desugar_for_in`]
+    ForIn(Option<Ident>, Ident, Box<Expression>, Block),
+    /// `while cond { ... }`, loops as long as the condition holds
+    While(Option<Ident>, Box<Expression>, Block),
+    /// built whenever the enclosing block is left, in reverse order of the defers of the block
+    Defer(Box<Expression>),
+    /// `cfg!(predicate)`, the tag holds the predicate like `#[cfg(predicate)]` does.
+    /// Folded to a bool literal while parsing, see [`Cfg`](cfg::Cfg)
+    Cfg(Tag),
+    Break(Option<Ident>),
+    Continue(Option<Ident>),
+    /// code that didn't parse, only produced by [`parse_items_tolerant`](parser::parse_items_tolerant)
+    Error,
 }
 
+impl Expr {
+    /// Block like expressions end in `}` and can be used as statement without a terminating `;`.
+    pub(crate) fn is_block_like(&self) -> bool {
+        matches!(self, Expr::Block(_) | Expr::For(..) | Expr::ForIn(..) | Expr::While(..))
+    }
+
+    /// Places are expressions that denote a memory location and can be assigned to.
+    pub(crate) fn is_place(&self) -> bool {
+        match self {
+            Expr::Variable(_) | Expr::Deref(_) | Expr::Index(..) | Expr::Field(..) => true,
+            Expr::Group(inner) => inner.1.is_place(),
+            _ => false
+        }
+    }
+
+    /// Whether evaluating the expression always leaves the enclosing block.
+    pub(crate) fn diverges(&self) -> bool {
+        match self {
+            Expr::Return(_) | Expr::Break(_) | Expr::Continue(_) => true,
+            Expr::Block(block) => block.diverges(),
+            _ => false
+        }
+    }
+}
+
+/// `start..end` or, if the flag is set, the inclusive `start..=end`
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Range(pub(crate) Box<Expression>, pub(crate) Box<Expression>, pub(crate) bool, pub(crate) Span);
+
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Operator(pub(crate) Op, pub(crate) Span);
 #[derive(Debug, Clone, PartialEq)]
@@ -57,15 +136,57 @@ pub(crate) enum Op {
     Sub,
     Mul,
     Div,
+    Mod,
     And,
     Or,
     Not,
     LShift,
     RShift,
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+}
+
+impl Op {
+    /// Binary operators of higher precedence bind first, like in Rust
+    pub(crate) fn precedence(&self) -> u8 {
+        match self {
+            Op::Mul | Op::Div | Op::Mod => 6,
+            Op::Add | Op::Sub => 5,
+            Op::LShift | Op::RShift => 4,
+            Op::Eq | Op::Ne | Op::Lt | Op::Gt | Op::Le | Op::Ge => 3,
+            Op::And => 2,
+            Op::Or => 1,
+            Op::Not => 0
+        }
+    }
+
+    /// Comparisons result in a bool and can't be chained, `a < b < c` needs parentheses
+    pub(crate) fn is_comparison(&self) -> bool {
+        matches!(self, Op::Eq | Op::Ne | Op::Lt | Op::Gt | Op::Le | Op::Ge)
+    }
 }
 
+/// `.1` is whether the expression is terminated by `;`, which discards its value.
+/// The last statement of a block without `;` is the value of the block.
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Statement(pub(crate) Expression, pub(crate) bool, pub(crate) Span, pub(crate) Trivia);
+
+/// `// ...` or `/* ... */` as written
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) struct Statement(pub(crate) Expression, pub(crate) bool, pub(crate) Span);
+pub(crate) struct Comment(pub(crate) String, pub(crate) Span);
+
+/// Comments around a statement or item, only kept for formatting, see [`crate::ast::parser::parse_items`]
+#[derive(Debug, Clone, PartialEq, Default)]
+pub(crate) struct Trivia {
+    /// on the lines before it
+    pub(crate) leading: Vec<Comment>,
+    /// after it, the first one may be on its last line
+    pub(crate) trailing: Vec<Comment>
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Module{
@@ -73,11 +194,20 @@ pub(crate) struct Module{
     pub(crate) sub_modules: HashMap<String, Module>,
     pub(crate) functions: HashMap<String, Func>,
     pub(crate) constants: HashMap<String, Const>,
+    pub(crate) statics: HashMap<String, Static>,
+    pub(crate) aliases: HashMap<String, TypeAlias>,
+    pub(crate) extern_types: HashMap<String, ExternType>,
     pub(crate) loc: Span
 }
 
+/// `.2` are the comments of a block without statements, only kept for formatting
 #[derive(Debug, Clone, PartialEq)]
-pub(crate) struct Block(pub(crate) Vec<Statement>, pub(crate) Span);
+pub(crate) struct Block(pub(crate) Vec<Statement>, pub(crate) Span, pub(crate) Vec<Comment>);
+impl Block {
+    pub(crate) fn diverges(&self) -> bool {
+        self.0.iter().any(|stmt| stmt.0.1.diverges())
+    }
+}
 
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Func {
@@ -89,15 +219,55 @@ pub(crate) struct Func {
     pub(crate) loc: Span
 }
 
+impl Func {
+    /// Symbol of an overloaded function, made unique by its parameter types
+    pub(crate) fn mangled_name(&self) -> String {
+        format!("{}({})", self.name.0, self.args.iter().map(|(_, t)| t.print()).collect::<Vec<_>>().join(","))
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Const {
+    pub(crate) tags: HashMap<String, Tag>,
     pub(crate) name: Ident,
     pub(crate) ty: Type,
     pub(crate) val: Expression
 }
 
+/// `static NAME: Type = literal;`, a global holding a value of `Type` that can be read and assigned to
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct Static {
+    pub(crate) tags: HashMap<String, Tag>,
+    pub(crate) name: Ident,
+    pub(crate) ty: Type,
+    pub(crate) val: Expression
+}
+
+/// `type Name = Type;`, replaced by its target before codegen, see [`Module::resolve_aliases`]
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct TypeAlias {
+    pub(crate) name: Ident,
+    pub(crate) ty: Type
+}
+
+/// `extern type Name;`, a type defined by foreign code whose size is unknown,
+/// so it can only be used behind pointers, like incomplete types in c
+#[derive(Debug, Clone, PartialEq)]
+pub(crate) struct ExternType {
+    pub(crate) name: Ident
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) struct Type(pub(crate) Ty, pub(crate) Span);
+impl Type {
+    /// The literal suffix matching this type, if it is a plain number type
+    pub(crate) fn num_lit_ty(&self) -> Option<NumLitTy> {
+        match &self.0 {
+            Ty::Single(generics, item) if generics.is_empty() && item.0.len() == 1 => NumLitTy::from_name(&item.0[0].0),
+            _ => None
+        }
+    }
+}
 #[derive(Debug, Clone, PartialEq)]
 pub(crate) enum Ty {
     Single(Vec<Type>, Item),
@@ -110,8 +280,8 @@ pub(crate) enum Ty {
 }
 impl Ty {
     #[allow(non_camel_case_types)]
-    type unsafe_func = bool;
-    type vararg_func = bool;
+    pub(crate) type unsafe_func = bool;
+    pub(crate) type vararg_func = bool;
     pub(crate) fn empty() -> Self{
         Ty::Tuple(vec![])
     }
@@ -129,11 +299,129 @@ impl AstLiteral {
         Ok(match &self.0 {
             Literal::String(s) => Type(Ty::Array(Box::new(Type(Ty::Single(vec![], Item::new(&vec!["u8"], self.1.clone())), self.1.clone())), s.len() + 1), self.1.clone()),
             Literal::Char(_) => Type(Ty::Single(vec![], Item::new(&vec!["u8"], self.1.clone())), self.1.clone()),
-            Literal::Number(_, ty) => if let Some(ty) = ty {
+            Literal::Number(_, ty, _) => if let Some(ty) = ty {
                 Type(Ty::Single(vec![], Item::new(&vec![&format!("{ty}")], self.1.clone())), self.1.clone())
-            } else { unimplemented!()},
+            } else {
+                return Err(ParseET::LiteralError(self.0.clone(), "cannot infer type of number literal, add a suffix or a type annotation".to_string()).at(self.1.clone()))
+            },
             Literal::Bool(_) => Type(Ty::Single(vec![], Item::new(&vec!["bool"], self.1.clone())), self.1.clone()),
-            Literal::Array(_, elem_ty, len) =>  Type(Ty::Array(Box::new(elem_ty.clone()), *len), self.1.clone())
+            Literal::Array(_, Some(elem_ty), len) =>  Type(Ty::Array(Box::new(elem_ty.clone()), *len), self.1.clone()),
+            Literal::Array(_, None, _) => return Err(ParseET::LiteralError(self.0.clone(), "cannot infer type of array literal, add a suffix to an element or a type annotation".to_string()).at(self.1.clone())),
+            Literal::Repeat(elem, count) => Type(Ty::Array(Box::new(elem.get_type()?), *count), self.1.clone()),
+            Literal::Bytes(b) => Type(Ty::Array(Box::new(Type(Ty::Single(vec![], Item::new(&vec!["u8"], self.1.clone())), self.1.clone())), b.len()), self.1.clone())
         })
     }
+
+    /// Source of this number literal with a different suffix, `None` if it can't have that type.
+    /// Keeps the digits as they were written.
+    pub(crate) fn with_suffix(&self, suffix: &NumLitTy) -> Option<String> {
+        if let (Literal::Number(NumLit::Integer(num), _, _), Some(bits)) = (&self.0, suffix.int_bits()) {
+            // like `AstLiteral::check_int_range`, a suggestion that doesn't fit would only fail again
+            let fits = match (suffix.is_unsigned(), self.is_negative()) {
+                (true, true) => false,
+                (false, true) => num.wrapping_neg() <= 1 << (bits - 1),
+                (false, false) => *num < 1 << (bits - 1),
+                (true, false) => *num <= u128::MAX >> (128 - bits)
+            };
+            if !fits {
+                return None
+            }
+        }
+        match &self.0 {
+            Literal::Number(NumLit::Float(_), _, _) if !suffix.is_float() => None,
+            // a float suffix after hex digits would be read as more digits
+            Literal::Number(_, ty, Some(text)) if !(suffix.is_float() && text.trim_start_matches('-').starts_with("0x")) => {
+                let digits = ty.as_ref().and_then(|ty| text.strip_suffix(&ty.to_string())).unwrap_or(text);
+                Some(format!("{digits}{suffix}"))
+            }
+            Literal::Number(NumLit::Integer(i), _, _) => Some(format!("{i}{suffix}")),
+            Literal::Number(NumLit::Float(f), _, _) => Some(format!("{f}{suffix}")),
+            _ => None
+        }
+    }
+
+    /// The number literal with `-` in front of it, which starts at `minus`.
+    /// Integers are stored as their two's complement, so `-128i8` is in range like any other `i8`.
+    pub(crate) fn negate(&self, minus: &Span) -> Result<AstLiteral, ParseError> {
+        let Literal::Number(num, suffix, text) = &self.0 else {
+            unreachable!("only number literals are negated")
+        };
+        let mut loc = minus.clone();
+        loc.combine(self.1.clone());
+        if let Some(suffix) = suffix.as_ref().filter(|suffix| suffix.is_unsigned()) {
+            return Err(ParseET::LiteralError(self.0.clone(), format!("negative literal can't have unsigned type {suffix}")).at(loc))
+        }
+        let num = match num {
+            NumLit::Integer(i) => NumLit::Integer(i.wrapping_neg()),
+            NumLit::Float(f) => NumLit::Float(-f)
+        };
+        let text = text.as_ref().map(|text| format!("-{text}"));
+        Ok(AstLiteral(Literal::Number(num, suffix.clone(), text), loc))
+    }
+
+    /// The literal was written with `-` in front of it
+    pub(crate) fn is_negative(&self) -> bool {
+        matches!(&self.0, Literal::Number(_, _, Some(text)) if text.starts_with('-'))
+    }
+
+    /// Types a number literal by the type it is expected to have.
+    /// An explicit suffix wins and has to agree with the expectation,
+    /// a literal without suffix takes the expected type.
+    /// Array literals pass the expected element type on to their elements.
+    pub(crate) fn with_expected(&self, expected: &Type) -> Result<AstLiteral, ParseError> {
+        let Literal::Number(num, suffix, text) = &self.0 else {
+            return Ok(match (&self.0, &expected.0) {
+                (Literal::Array(elems, elem_ty, len), Ty::Array(expected_elem, _)) => AstLiteral(Literal::Array(
+                    elems.iter().map(|elem| elem.with_expected(expected_elem)).collect::<Result<_, _>>()?,
+                    Some(elem_ty.clone().unwrap_or_else(|| *expected_elem.clone())),
+                    *len
+                ), self.1.clone()),
+                (Literal::Repeat(elem, count), Ty::Array(expected_elem, _)) =>
+                    AstLiteral(Literal::Repeat(Box::new(elem.with_expected(expected_elem)?), *count), self.1.clone()),
+                _ => self.clone()
+            })
+        };
+        Ok(match (suffix, expected.num_lit_ty()) {
+            (Some(suffix), Some(expected_ty)) if suffix != &expected_ty => {
+                let err = ParseET::LiteralError(self.0.clone(), format!("suffix {suffix} conflicts with expected type {}", expected.print()))
+                    .ats(vec![self.1.clone(), expected.1.clone()]);
+                return Err(match self.with_suffix(&expected_ty) {
+                    Some(fixed) => err.suggest("change the suffix", self.1.clone(), fixed),
+                    None => err
+                })
+            }
+            (None, Some(expected_ty)) => match num {
+                _ if self.is_negative() && expected_ty.is_unsigned() =>
+                    return Err(ParseET::LiteralError(self.0.clone(), format!("negative literal can't have unsigned type {}", expected.print()))
+                        .ats(vec![self.1.clone(), expected.1.clone()])),
+                NumLit::Float(_) if !expected_ty.is_float() =>
+                    return Err(ParseET::LiteralError(self.0.clone(), format!("floating point literal cannot have integer type {}", expected.print()))
+                        .ats(vec![self.1.clone(), expected.1.clone()])),
+                NumLit::Integer(i) if expected_ty.is_float() => AstLiteral(Literal::Number(NumLit::Float(*i as f64), Some(expected_ty), text.clone()), self.1.clone()),
+                _ => AstLiteral(Literal::Number(num.clone(), Some(expected_ty), text.clone()), self.1.clone())
+            },
+            _ => self.clone()
+        })
+    }
+
+    /// Size in bytes the literal occupies once lowered, saturating instead of overflowing
+    /// so absurdly large repeat literals can still be reported.
+    pub(crate) fn byte_size(&self) -> usize {
+        match &self.0 {
+            Literal::String(s) => s.len() + 1,
+            Literal::Char(_) => 1,
+            Literal::Number(_, ty, _) => match ty {
+                Some(NumLitTy::U8 | NumLitTy::I8) => 1,
+                Some(NumLitTy::U16 | NumLitTy::I16) => 2,
+                Some(NumLitTy::U32 | NumLitTy::I32 | NumLitTy::F32) => 4,
+                Some(NumLitTy::U64 | NumLitTy::I64 | NumLitTy::F64) => 8,
+                Some(NumLitTy::U128 | NumLitTy::I128) => 16,
+                Some(NumLitTy::UPtr | NumLitTy::IPtr) | None => std::mem::size_of::<usize>(),
+            },
+            Literal::Bool(_) => 1,
+            Literal::Array(elems, _, _) => elems.iter().fold(0usize, |acc, e| acc.saturating_add(e.byte_size())),
+            Literal::Repeat(elem, count) => elem.byte_size().saturating_mul(*count),
+            Literal::Bytes(b) => b.len()
+        }
+    }
 }
\ No newline at end of file
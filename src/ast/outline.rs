@@ -0,0 +1,91 @@
+use std::fmt::{Display, Formatter};
+use crate::ast::docs::{describe_item, json_string};
+use crate::ast::parser::FormatItems;
+use crate::source::CodePoint;
+use crate::source::span::Span;
+
+// the items of a module for the outline of editors, see `--emit symbols`
+
+/// An item with where its name is and the code it spans, tags included
+#[derive(Debug, Clone)]
+pub(crate) struct Symbol {
+    pub(crate) kind: &'static str,
+    pub(crate) name: String,
+    pub(crate) signature: String,
+    pub(crate) name_loc: Span,
+    pub(crate) loc: Span
+}
+
+/// The items of a module in source order
+#[derive(Debug, Clone)]
+pub(crate) struct Outline {
+    pub(crate) module: String,
+    pub(crate) symbols: Vec<Symbol>
+}
+
+/// Outline of the items of `module`, which may come from [`parse_items_tolerant`](crate::ast::parser::parse_items_tolerant).
+/// Code that didn't parse is left out, so broken files still show their intact items.
+pub(crate) fn outline(module: &str, items: &FormatItems) -> Outline {
+    let symbols = items.iter().filter_map(|(_, content, loc, _)| describe_item(content).map(|(kind, name, signature)| Symbol {
+        kind,
+        name: name.0.clone(),
+        signature,
+        name_loc: name.1.clone(),
+        loc: loc.clone()
+    })).collect();
+    Outline { module: module.to_string(), symbols }
+}
+
+/// `{"start": {"line": 1, "col": 1}, "end": ...}`, one based like the locations of diagnostics, the end is inclusive
+fn json_span(loc: &Span) -> String {
+    let point = |p: CodePoint| {
+        let (line, col) = p.line_col();
+        format!("{{\"line\": {line}, \"col\": {col}}}")
+    };
+    format!("{{\"start\": {}, \"end\": {}}}", point(loc.start()), point(loc.end()))
+}
+
+/// As json object with the module name and its symbols with kind, name, signature, name_span and span
+impl Display for Outline {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let symbols = self.symbols.iter().map(|s| format!("    {{\"kind\": {}, \"name\": {}, \"signature\": {}, \"name_span\": {}, \"span\": {}}}",
+            json_string(s.kind), json_string(&s.name), json_string(&s.signature), json_span(&s.name_loc), json_span(&s.loc))).collect::<Vec<_>>();
+        let symbols = if symbols.is_empty() { "[]".to_string() } else { format!("[\n{}\n  ]", symbols.join(",\n")) };
+        write!(f, "{{\n  \"module\": {},\n  \"symbols\": {symbols}\n}}", json_string(&self.module))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::ast::outline::outline;
+    use crate::ast::parser::parse_items_tolerant;
+    use crate::source::Source;
+    use crate::tokens::tokenizer::tokenize;
+
+    fn symbols(code: &str) -> String {
+        let (items, _, _) = parse_items_tolerant(tokenize(Rc::new(Source::from_string(code.to_string())), false).unwrap()).unwrap();
+        outline("main", &items).to_string()
+    }
+
+    #[test]
+    fn outline_of_the_iter_example() {
+        assert_eq!(symbols(include_str!("../../examples/iter/iter.li")), "{\n  \
+            \"module\": \"main\",\n  \
+            \"symbols\": [\n    \
+            {\"kind\": \"type\", \"name\": \"Numbers\", \"signature\": \"type Numbers = [i32;5]\", \"name_span\": {\"start\": {\"line\": 3, \"col\": 6}, \"end\": {\"line\": 3, \"col\": 12}}, \"span\": {\"start\": {\"line\": 3, \"col\": 1}, \"end\": {\"line\": 3, \"col\": 24}}},\n    \
+            {\"kind\": \"fn\", \"name\": \"iter_start\", \"signature\": \"fn iter_start(numbers: &Numbers) -> u64\", \"name_span\": {\"start\": {\"line\": 5, \"col\": 4}, \"end\": {\"line\": 5, \"col\": 13}}, \"span\": {\"start\": {\"line\": 5, \"col\": 1}, \"end\": {\"line\": 7, \"col\": 1}}},\n    \
+            {\"kind\": \"fn\", \"name\": \"iter_done\", \"signature\": \"fn iter_done(numbers: &Numbers, index: &u64) -> bool\", \"name_span\": {\"start\": {\"line\": 9, \"col\": 4}, \"end\": {\"line\": 9, \"col\": 12}}, \"span\": {\"start\": {\"line\": 9, \"col\": 1}, \"end\": {\"line\": 11, \"col\": 1}}},\n    \
+            {\"kind\": \"fn\", \"name\": \"iter_next\", \"signature\": \"fn iter_next(numbers: &Numbers, index: &u64) -> i32\", \"name_span\": {\"start\": {\"line\": 13, \"col\": 4}, \"end\": {\"line\": 13, \"col\": 12}}, \"span\": {\"start\": {\"line\": 13, \"col\": 1}, \"end\": {\"line\": 17, \"col\": 1}}},\n    \
+            {\"kind\": \"fn\", \"name\": \"main\", \"signature\": \"fn main() -> i32\", \"name_span\": {\"start\": {\"line\": 19, \"col\": 4}, \"end\": {\"line\": 19, \"col\": 7}}, \"span\": {\"start\": {\"line\": 19, \"col\": 1}, \"end\": {\"line\": 26, \"col\": 1}}}\n  \
+            ]\n\
+            }");
+        // the function that doesn't parse is left out, the items after it are still there
+        assert_eq!(symbols("fn broken( -> i32 {\n}\nconst C: &[u8; 3] = &\"hi\";\n"), "{\n  \
+            \"module\": \"main\",\n  \
+            \"symbols\": [\n    \
+            {\"kind\": \"const\", \"name\": \"C\", \"signature\": \"const C: &[u8;3]\", \"name_span\": {\"start\": {\"line\": 3, \"col\": 7}, \"end\": {\"line\": 3, \"col\": 7}}, \"span\": {\"start\": {\"line\": 3, \"col\": 1}, \"end\": {\"line\": 3, \"col\": 26}}}\n  \
+            ]\n\
+            }");
+    }
+}
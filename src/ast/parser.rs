@@ -1,18 +1,127 @@
-use crate::ast::{Ident, Module};
-use crate::ast::create_patterns::build_patterns;
-use crate::error::ParseError;
+use std::collections::HashMap;
+use crate::ast::{Comment, Ident, Module, Tag, Trivia, Type};
+use crate::ast::trivia::attach_comments;
+use crate::ast::cfg::Cfg;
+use crate::ast::create_patterns::{build_patterns, collect_module_content, ModuleContent};
+use crate::ast::patterns::Consumer;
+use crate::ast::patterns::recover::{skip_item, Recovered};
+use crate::error::{ParseError, ParseET};
 use crate::source::span::Span;
-use crate::tokens::{Token, TokIter};
+use crate::tokens::{Token, TokenType, TokIter};
 
-pub(crate) fn parse(tokens: Vec<Token>, mod_name: (String, Option<Span>)) -> Result<Module, ParseError>{
-    let patterns = build_patterns();
+pub(crate) fn parse(tokens: Vec<Token>, mod_name: (String, Option<Span>), cfg: &Cfg) -> Result<Module, ParseError>{
+    let patterns = build_patterns(None);
     let mut tokens = TokIter::new(tokens);
-    let ((functions, constants), loc) = patterns.module_content.consume(&mut tokens)?;
-    Ok(Module{
+    let (content, loc) = patterns.module_content.consume(&mut tokens)?;
+    let content = content.into_iter().map(|(tags, content, _)| (tags, content)).collect();
+    let (functions, constants, statics, aliases, extern_types) = collect_module_content(content, cfg)?;
+    let mut module = Module{
         name: Ident(mod_name.0, mod_name.1.unwrap_or(loc.clone())),
         sub_modules: Default::default(),
         functions,
         constants,
+        statics,
+        aliases,
+        extern_types,
         loc
-    })
-}
\ No newline at end of file
+    };
+    module.fold_cfg(cfg)?;
+    Ok(module)
+}
+
+/// A single type, like the annotation of a `let`, aliases are not resolved
+pub(crate) fn parse_type(tokens: Vec<Token>) -> Result<Type, ParseError>{
+    let mut tokens = TokIter::new(tokens);
+    let ty = build_patterns(None).ty.consume(&mut tokens)?;
+    if let Ok(rest) = tokens.this() {
+        return Err(ParseET::ParsingError(format!("expected the type to end, found {:?}", rest.tt)).at(rest.loc).when("parsing type"))
+    }
+    Ok(ty)
+}
+
+/// Items of a module in source order with their tags, the code they span and their comments, see [`parse_items`]
+pub(crate) type FormatItems = Vec<(HashMap<String, Tag>, ModuleContent, Span, Trivia)>;
+
+/// The items of a module in source order with their tags, `#[cfg(...)]` is not evaluated.
+/// For formatting, where nothing may get lost, so the comment tokens are attached to the items and statements around them.
+/// Comments of a file without items are returned as they are.
+pub(crate) fn parse_items(tokens: Vec<Token>) -> Result<(FormatItems, Vec<Comment>), ParseError>{
+    parse_items_with(tokens, None)
+}
+
+/// Like [`parse_items`], but code that doesn't parse becomes [`Expr::Error`](crate::ast::Expr::Error) statements
+/// and [`ModuleContent::Error`] items, so tooling gets the rest of the file. Returns the errors of the skipped code too.
+/// Statements are skipped to the next `;`, items to the start of the next item.
+pub(crate) fn parse_items_tolerant(tokens: Vec<Token>) -> Result<(FormatItems, Vec<Comment>, Vec<ParseError>), ParseError>{
+    let recovered = Recovered::default();
+    let (items, dangling) = parse_items_with(tokens, Some(recovered.clone()))?;
+    Ok((items, dangling, recovered.take()))
+}
+
+fn parse_items_with(tokens: Vec<Token>, recovered: Option<Recovered>) -> Result<(FormatItems, Vec<Comment>), ParseError>{
+    let (comments, tokens): (Vec<_>, Vec<_>) = tokens.into_iter().partition(|t| matches!(t.tt, TokenType::Comment(_)));
+    let comments = comments.into_iter().map(|t| match t.tt {
+        TokenType::Comment(text) => Comment(text, t.loc),
+        _ => unreachable!()
+    }).collect();
+    if tokens.is_empty() {
+        return Ok((vec![], comments))
+    }
+    let locs = tokens.iter().map(|t| t.loc.clone()).collect::<Vec<_>>();
+    let patterns = build_patterns(recovered.clone());
+    let mut tokens = TokIter::new(tokens);
+    let content = match recovered {
+        None => patterns.module_content.consume(&mut tokens)?.0,
+        Some(recovered) => {
+            let mut content = vec![];
+            while tokens.elems_left() > 0 {
+                let start = tokens.clone();
+                let errors = recovered.borrow().len();
+                match patterns.item.consume(&mut tokens) {
+                    Ok(item) => content.push(item),
+                    Err(e) => {
+                        // the statements skipped in it are part of the item now
+                        recovered.borrow_mut().truncate(errors);
+                        recovered.borrow_mut().push(e);
+                        tokens = start;
+                        let loc = skip_item(&mut tokens).expect("a token is left to skip");
+                        content.push((HashMap::new(), ModuleContent::Error(loc.clone()), loc));
+                    }
+                }
+            }
+            content
+        }
+    };
+    let mut items = content.into_iter().map(|(tags, content, loc)| (tags, content, loc, Trivia::default())).collect::<Vec<_>>();
+    let dangling = attach_comments(&mut items, comments, &locs);
+    Ok((items, dangling))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::ast::code_printer::format_items;
+    use crate::ast::outline::outline;
+    use crate::ast::parser::parse_items_tolerant;
+    use crate::source::Source;
+    use crate::tokens::tokenizer::tokenize;
+    use crate::util::testing::build_err;
+
+    const HALF_TYPED: &str = "fn f() -> i32 {\n    let x = ;\n    g(1i32;\n    x\n}\nfn g(n: i32) -> i32 {\n    n\n}\nconst C: &[u8; 3] = &\"hi\";\n";
+
+    #[test]
+    fn half_typed_code_keeps_the_rest() {
+        let (items, dangling, skipped) = parse_items_tolerant(tokenize(Rc::new(Source::from_string(HALF_TYPED.to_string())), true).unwrap()).unwrap();
+        let skipped = skipped.iter().map(|e| e.render(false)).collect::<Vec<_>>();
+        assert!(matches!(skipped.as_slice(), [missing, unclosed] if missing.contains("<string>: 2:13\n")
+            && unclosed.contains("expected ',', found ';'") && unclosed.contains("<string>: 3:11\n")), "{skipped:?}");
+        let symbols = outline("main", &items).symbols.into_iter().map(|s| s.name).collect::<Vec<_>>();
+        assert_eq!(symbols, ["f", "g", "C"]);
+        // the broken statements are printed as they were written
+        let formatted = format_items(&items, &dangling);
+        assert!(formatted.starts_with("fn f() -> i32 {\n    let x = ;\n    g(1i32;\n    x\n}\n"), "{formatted}");
+        // compiling parses strictly and stops at the first error
+        let err = build_err(HALF_TYPED, &["--shared"]);
+        assert!(err.contains("<string>: 2:13\n") && !err.contains("<string>: 3:11\n"), "{err}");
+    }
+}
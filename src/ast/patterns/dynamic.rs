@@ -28,9 +28,12 @@ impl<T: Consumer + 'static> Mapping for T  {
 impl<Out, Mapped> Consumer for MapperRes<Out, Mapped>{
     type Output = Mapped;
     fn consume(&self, iter: &mut TokIter) -> Result<Self::Output, ParseError> {
-        let mut start = iter.here();
+        let (first, mut start) = (iter.index, iter.here());
         let out = self.0.consume(iter)?;
-        start.combine(iter.here());
+        // up to the last token consumed, not the one following it
+        if let Some(last) = iter.index.checked_sub(1).filter(|last| *last >= first) {
+            start.combine(iter.get(last)?.loc);
+        }
         self.1(out, start)
     }
 }
@@ -38,9 +41,12 @@ impl<Out, Mapped> Consumer for MapperRes<Out, Mapped>{
 impl<Out, Mapped> Consumer for Mapper<Out, Mapped>{
     type Output = Mapped;
     fn consume(&self, iter: &mut TokIter) -> Result<Self::Output, ParseError> {
-        let mut start = iter.here();
+        let (first, mut start) = (iter.index, iter.here());
         let out = self.0.consume(iter)?;
-        start.combine(iter.here());
+        // up to the last token consumed, not the one following it
+        if let Some(last) = iter.index.checked_sub(1).filter(|last| *last >= first) {
+            start.combine(iter.get(last)?.loc);
+        }
         Ok(self.1(out, start))
     }
 }
@@ -67,4 +73,9 @@ impl<Out: 'static> Latent<Out> {
     pub(crate) fn finalize(&self, p: Pat<Out>){
         unsafe {*self.0.get() = Some(p);}
     }
+    /// Drops the finalized pattern when called, recursive patterns contain themselves and are never freed otherwise
+    pub(crate) fn unlinker(self: &Rc<Self>) -> Box<dyn Fn()> {
+        let latent = Rc::clone(self);
+        Box::new(move || unsafe {*latent.0.get() = None;})
+    }
 }
\ No newline at end of file
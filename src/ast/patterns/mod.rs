@@ -8,6 +8,7 @@ use crate::tokens::TokIter;
 pub(crate) mod simple;
 pub(crate) mod conditional;
 pub(crate) mod dynamic;
+pub(crate) mod recover;
 
 pub(crate) struct Pattern<T: Consumer, Out> {
     name: Option<String>,
@@ -39,12 +40,15 @@ impl<T: Consumer, Out> Consumer for Pattern<T, Out> {
     type Output = Out;
 
     fn consume(&self, iter: &mut TokIter) -> Result<Self::Output, ParseError> {
-        let mut start = iter.here();
+        let (first, mut start) = (iter.index, iter.here());
         let out = self.consumer.consume(iter);
         if out.is_err() && self.name.is_some() {
             return Err(unsafe {out.unwrap_err_unchecked()}.when(format!("parsing {}", self.name.clone().unwrap())));
         }
-        start.combine(iter.peekn(-1)?.loc);
+        // up to the last token consumed, a pattern that consumed nothing at the start of the file has no token before it
+        if let Some(last) = iter.index.checked_sub(1).filter(|last| *last >= first) {
+            start.combine(iter.get(last)?.loc);
+        }
         Ok((self.mapper)(out?, start))
     }
 }
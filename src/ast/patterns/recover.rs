@@ -0,0 +1,80 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::ast::patterns::{Consumer, Pat};
+use crate::error::ParseError;
+use crate::source::span::Span;
+use crate::tokens::{TokIter, TokenType};
+
+// skipping code that doesn't parse, so tooling still gets an ast of the rest, see `parse_items_tolerant`
+
+/// Errors of the regions skipped while parsing tolerantly, in the order they were skipped
+pub(crate) type Recovered = Rc<RefCell<Vec<ParseError>>>;
+
+/// Consumes the pattern, on error skips the region, records the error and returns `skipped` of the region instead.
+/// The region ends after the next `;` or before the `}` closing the enclosing braces, see [`skip_statement`].
+/// Without a sink, or if nothing can be skipped, it fails like the pattern.
+pub(crate) struct Recover<Out>(pub(crate) Pat<Out>, pub(crate) Option<Recovered>, pub(crate) fn(Span) -> Out);
+impl<Out> Consumer for Recover<Out> {
+    type Output = Out;
+
+    fn consume(&self, iter: &mut TokIter) -> Result<Self::Output, ParseError> {
+        let start = iter.clone();
+        match (self.0.consume(iter), &self.1) {
+            (Err(e), Some(recovered)) => {
+                *iter = start;
+                match skip_statement(iter) {
+                    Some(region) => {
+                        recovered.borrow_mut().push(e);
+                        Ok((self.2)(region))
+                    }
+                    None => Err(e)
+                }
+            }
+            (out, _) => out
+        }
+    }
+}
+
+/// Skips to after the next `;` or before the next unmatched `}`, returns the skipped region if it isn't empty
+pub(crate) fn skip_statement(iter: &mut TokIter) -> Option<Span> {
+    skip(iter, false, |tt, depth| match tt {
+        TokenType::Particle(';', _) if depth == 0 => Some(true),
+        TokenType::Particle('}', _) if depth == 0 => Some(false),
+        _ => None
+    })
+}
+
+/// Skips at least one token, then to the start of the next item outside of braces, returns the skipped region.
+/// `fn` always starts an item, as functions can't be nested.
+pub(crate) fn skip_item(iter: &mut TokIter) -> Option<Span> {
+    skip(iter, true, |tt, depth| match tt {
+        TokenType::Ident(kw) if kw == "fn" => Some(false),
+        TokenType::Ident(kw) if depth == 0 && matches!(kw.as_str(), "const" | "static" | "type" | "extern") => Some(false),
+        TokenType::Particle('#', _) if depth == 0 => Some(false),
+        _ => None
+    })
+}
+
+/// Skips tokens until `stop` returns whether to include the token it stopped at, or the end.
+/// `stop` gets the token and the depth of braces it is in.
+fn skip(iter: &mut TokIter, skip_first: bool, stop: impl Fn(&TokenType, usize) -> Option<bool>) -> Option<Span> {
+    let mut region: Option<Span> = None;
+    let mut depth = 0usize;
+    while let Ok(token) = iter.this() {
+        let include = if skip_first && region.is_none() { None } else { stop(&token.tt, depth) };
+        match (include, &token.tt) {
+            (Some(false), _) => break,
+            (Some(true), _) => {
+                iter.next();
+                region.get_or_insert(token.loc.clone()).combine(token.loc);
+                break
+            }
+            (None, TokenType::Particle('{', _)) => depth += 1,
+            (None, TokenType::Particle('}', _)) => depth = depth.saturating_sub(1),
+            _ => ()
+        }
+        iter.next();
+        region.get_or_insert(token.loc.clone()).combine(token.loc);
+    }
+    region
+}
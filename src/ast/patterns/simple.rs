@@ -57,6 +57,21 @@ impl Consumer for GetIdent {
     }
 }
 
+pub(crate) struct GetLabel;
+impl Consumer for GetLabel {
+    type Output = Ident;
+
+    fn consume(&self, iter: &mut TokIter) -> Result<Self::Output, ParseError> {
+        let Token { tt, loc } = iter.this()?;
+        if let TokenType::Label(s) = tt {
+            iter.next();
+            Ok(Ident(s, loc))
+        } else {
+            Err(ParseET::ParsingError(format!("expected Label, found {:?}", tt)).at(loc))
+        }
+    }
+}
+
 pub(crate) struct ExpectParticle(pub(crate) char);
 impl Consumer for ExpectParticle {
     type Output = ();
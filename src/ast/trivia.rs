@@ -0,0 +1,121 @@
+use crate::ast::{Block, Comment, Expression, Statement, Trivia};
+use crate::ast::create_patterns::ModuleContent;
+use crate::ast::parser::FormatItems;
+use crate::ast::visit::ChildMut;
+use crate::source::span::Span;
+
+// attaching comments to the statements and items around them, so fmt can print them again
+
+/// Something comments can be attached to
+struct Node<'a> {
+    loc: Span,
+    trivia: &'a mut Trivia,
+    /// blocks nested in it, comments inside of them go to their statements
+    blocks: Vec<&'a mut Block>
+}
+
+/// Attaches `comments`, in source order, to the items and statements they belong to.
+/// `tokens` are the locations of all other tokens, in source order.
+/// Returns the comments if there are no items to attach them to.
+pub(crate) fn attach_comments(items: &mut FormatItems, comments: Vec<Comment>, tokens: &[Span]) -> Vec<Comment> {
+    let nodes = items.iter_mut().map(|(_, content, loc, trivia)| {
+        let blocks = match content {
+            ModuleContent::Function(f) => f.body.iter_mut().collect(),
+            ModuleContent::Const(c) => c.val.blocks_mut(),
+            ModuleContent::Static(s) => s.val.blocks_mut(),
+            ModuleContent::Alias(_) | ModuleContent::ExternType(_) | ModuleContent::Error(_) => vec![]
+        };
+        Node { loc: loc.clone(), trivia, blocks }
+    }).collect();
+    attach(nodes, comments, tokens)
+}
+
+/// A comment goes into the node it is in, else to the node it follows on the same line,
+/// else to the node after it, else to the last node. Returns the comments if there are no nodes.
+/// Every comment ends up where it is printed, so formatting again doesn't move it.
+fn attach(mut nodes: Vec<Node>, comments: Vec<Comment>, tokens: &[Span]) -> Vec<Comment> {
+    if nodes.is_empty() {
+        return comments
+    }
+    let mut inner = nodes.iter().map(|_| vec![]).collect::<Vec<_>>();
+    for comment in comments {
+        let at = comment.1.start;
+        if let Some(i) = nodes.iter().position(|n| n.loc.start <= at && at <= n.loc.end) {
+            inner[i].push(comment);
+            continue
+        }
+        let next = nodes.iter().position(|n| n.loc.start > at);
+        let prev = next.map_or(Some(nodes.len() - 1), |next| next.checked_sub(1));
+        match (prev, next) {
+            (Some(prev), _) if nodes[prev].loc.end().pos().0 == comment.1.start().pos().0 => nodes[prev].trivia.trailing.push(comment),
+            (_, Some(next)) => nodes[next].trivia.leading.push(comment),
+            (Some(prev), None) => nodes[prev].trivia.trailing.push(comment),
+            (None, None) => unreachable!("there is a node before or after every comment")
+        }
+    }
+    for (node, comments) in nodes.into_iter().zip(inner) {
+        let mut in_blocks = node.blocks.iter().map(|_| vec![]).collect::<Vec<_>>();
+        for comment in comments {
+            match node.blocks.iter().position(|b| inside_braces(&b.1, &comment.1, tokens)) {
+                Some(i) => in_blocks[i].push(comment),
+                // between the parts of a statement, like `let x /* here */ = 5;`
+                None => node.trivia.leading.push(comment)
+            }
+        }
+        node.trivia.leading.sort_by_key(|c| c.1.start);
+        for (block, comments) in node.blocks.into_iter().zip(in_blocks) {
+            // empty blocks have no statements to attach to
+            block.2 = attach(block.0.iter_mut().map(Statement::node).collect(), comments, tokens);
+        }
+    }
+    vec![]
+}
+
+/// Whether `at` is between the braces around `block`, which is the code in between them unless it is empty
+fn inside_braces(block: &Span, at: &Span, tokens: &[Span]) -> bool {
+    let open = tokens.iter().rev().find(|t| t.end < block.start).map_or(0, |t| t.end);
+    let close = tokens.iter().find(|t| t.start > block.end).map_or(usize::MAX, |t| t.start);
+    open < at.start && at.start < close
+}
+
+impl Statement {
+    fn node(&mut self) -> Node<'_> {
+        let Statement(expr, _, loc, trivia) = self;
+        Node { loc: loc.clone(), trivia, blocks: expr.blocks_mut() }
+    }
+}
+
+impl Expression {
+    /// The blocks in this expression, not the ones nested in those
+    fn blocks_mut(&mut self) -> Vec<&mut Block> {
+        self.children_mut().into_iter().flat_map(|child| match child {
+            ChildMut::Expr(expr) => expr.blocks_mut(),
+            ChildMut::Block(block) => vec![block]
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::ast::code_printer::format_items;
+    use crate::ast::parser::parse_items;
+    use crate::source::Source;
+    use crate::tokens::tokenizer::tokenize;
+
+    #[test]
+    fn comments_survive_a_round_trip() {
+        let code = "// leading\n#[export]\nfn f() -> i32 {\n    // inside\n    let x = 1i32; // trailing\n    /* block */\n    x\n} // after\n\n\
+            /* between */\nconst C: i32 = 5i32;\n// dangling\n";
+        let (items, dangling) = parse_items(tokenize(Rc::new(Source::from_string(code.to_string())), true).unwrap()).unwrap();
+        let (_, _, _, trivia) = &items[0];
+        assert_eq!(trivia.leading.iter().map(|c| c.0.as_str()).collect::<Vec<_>>(), ["// leading"]);
+        assert_eq!(trivia.trailing.iter().map(|c| c.0.as_str()).collect::<Vec<_>>(), ["// after"]);
+        // comments after the last item belong to it
+        let (_, _, _, trivia) = &items[1];
+        assert_eq!(trivia.leading.iter().map(|c| c.0.as_str()).collect::<Vec<_>>(), ["/* between */"]);
+        assert_eq!(trivia.trailing.iter().map(|c| c.0.as_str()).collect::<Vec<_>>(), ["// dangling"]);
+        assert!(dangling.is_empty());
+        assert_eq!(format_items(&items, &dangling), code);
+    }
+}
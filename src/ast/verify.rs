@@ -0,0 +1,48 @@
+use crate::ast::{Block, Expression, Module};
+use crate::error::{ParseError, ParseET};
+use crate::source::span::Span;
+
+// internal invariants of the ast, checked with --verify-internal
+
+fn check_span(loc: &Span, outer: &Span, what: &str) -> Result<(), ParseError> {
+    if !loc.in_bounds() {
+        return Err(ParseET::InternalError(format!("span of {what} is out of bounds of its source")).at(outer.clone()))
+    }
+    if !loc.is_within(outer) {
+        return Err(ParseET::InternalError(format!("span of {what} is not inside of its enclosing item")).ats(vec![loc.clone(), outer.clone()]))
+    }
+    Ok(())
+}
+
+impl Module {
+    pub(crate) fn verify_internal(&self) -> Result<(), ParseError> {
+        for (_, func) in &self.functions {
+            check_span(&func.loc, &self.loc, "function").map_err(|e| e.when_fn(&func.name.0))?;
+            if let Some(body) = &func.body {
+                body.verify_internal(&func.loc).map_err(|e| e.when_fn(&func.name.0))?;
+            }
+        }
+        for (_, constant) in &self.constants {
+            constant.val.verify_internal(&self.loc).map_err(|e| e.when_const(&constant.name.0))?;
+        }
+        for (_, s) in &self.statics {
+            s.val.verify_internal(&self.loc).map_err(|e| e.when_static(&s.name.0))?;
+        }
+        for (_, module) in &self.sub_modules {
+            module.verify_internal()?;
+        }
+        Ok(())
+    }
+}
+
+impl Block {
+    fn verify_internal(&self, outer: &Span) -> Result<(), ParseError> {
+        self.walk(&mut |expr| check_span(&expr.2, outer, "expression"))
+    }
+}
+
+impl Expression {
+    fn verify_internal(&self, outer: &Span) -> Result<(), ParseError> {
+        self.walk(&mut |expr| check_span(&expr.2, outer, "expression"))
+    }
+}
@@ -0,0 +1,115 @@
+use crate::ast::{Block, Const, Expr, Expression, Range, Static};
+use crate::error::ParseError;
+
+// the one place listing what every kind of expression contains, passes walk the ast through it
+
+/// An expression or block directly contained in an expression
+pub(crate) enum Child<'a> {
+    Expr(&'a Expression),
+    Block(&'a Block)
+}
+
+/// Mutable [`Child`]
+pub(crate) enum ChildMut<'a> {
+    Expr(&'a mut Expression),
+    Block(&'a mut Block)
+}
+
+/// `$child` is [`Child`] or [`ChildMut`], `$iter` the matching `iter` or `iter_mut`
+macro_rules! children {
+    ($expr:expr, $child:ident, $iter:ident) => {
+        match $expr {
+            Expr::Literal(_) | Expr::Variable(_) | Expr::Break(_) | Expr::Continue(_) | Expr::Return(None) | Expr::Cfg(_) | Expr::Error => vec![],
+            Expr::Point(expr) | Expr::Deref(expr) | Expr::Field(expr, _) | Expr::UnaryOp(_, expr) | Expr::VarCreate(_, _, _, expr) | Expr::LetTuple(_, _, expr)
+            | Expr::Ascribe(expr, _) | Expr::Group(expr) | Expr::Return(Some(expr)) | Expr::Defer(expr) => vec![$child::Expr(expr)],
+            Expr::Const(box Const { val, .. }) | Expr::Static(box Static { val, .. }) => vec![$child::Expr(val)],
+            Expr::Block(block) => vec![$child::Block(block)],
+            Expr::FuncCall(_, args) | Expr::Tuple(args) => args.$iter().map($child::Expr).collect(),
+            Expr::BinaryOp(_, left, right) | Expr::VarAssign(left, _, right) | Expr::Index(left, right) => vec![$child::Expr(left), $child::Expr(right)],
+            Expr::ForIn(_, _, expr, body) | Expr::While(_, expr, body) | Expr::LetElse(_, expr, body) => vec![$child::Expr(expr), $child::Block(body)],
+            Expr::For(_, _, Range(start, end, ..), step, body) => {
+                let mut children = vec![$child::Expr(start), $child::Expr(end)];
+                children.extend(step.$iter().map(|step| $child::Expr(step)));
+                children.push($child::Block(body));
+                children
+            }
+        }
+    };
+}
+
+impl Block {
+    /// See [`Expression::walk`]
+    pub(crate) fn walk<E, F: FnMut(&Expression) -> Result<(), E>>(&self, f: &mut F) -> Result<(), E> {
+        self.0.iter().try_for_each(|stmt| stmt.0.walk(f))
+    }
+
+    /// See [`Expression::walk_mut`]
+    pub(crate) fn walk_mut<F: FnMut(&mut Expression) -> Result<(), ParseError>>(&mut self, f: &mut F) -> Result<(), ParseError> {
+        self.0.iter_mut().try_for_each(|stmt| stmt.0.walk_mut(f))
+    }
+}
+
+impl Expression {
+    /// The expressions and blocks directly in this one, in source order
+    pub(crate) fn children(&self) -> Vec<Child<'_>> {
+        children!(&self.1, Child, iter)
+    }
+
+    /// See [`Expression::children`]
+    pub(crate) fn children_mut(&mut self) -> Vec<ChildMut<'_>> {
+        children!(&mut self.1, ChildMut, iter_mut)
+    }
+
+    /// Calls `f` on every expression of the tree, parents before their children
+    pub(crate) fn walk<E, F: FnMut(&Expression) -> Result<(), E>>(&self, f: &mut F) -> Result<(), E> {
+        f(self)?;
+        self.children().into_iter().try_for_each(|child| match child {
+            Child::Expr(expr) => expr.walk(f),
+            Child::Block(block) => block.walk(f)
+        })
+    }
+
+    /// Calls `f` on every expression of the tree, children before their parent,
+    /// so `f` may replace an expression without visiting the replacement again.
+    pub(crate) fn walk_mut<F: FnMut(&mut Expression) -> Result<(), ParseError>>(&mut self, f: &mut F) -> Result<(), ParseError> {
+        self.children_mut().into_iter().try_for_each(|child| match child {
+            ChildMut::Expr(expr) => expr.walk_mut(f),
+            ChildMut::Block(block) => block.walk_mut(f)
+        })?;
+        f(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use crate::ast::Expr;
+    use crate::util::testing::parse_module;
+
+    #[test]
+    fn walks_every_expression_in_order() {
+        let mut module = parse_module("fn f(p: &i32) {\n    for i in 0i32..*p step 2i32 {\n        g(i, { 1i32 });\n    }\n}").unwrap_or_else(|e| panic!("{e}"));
+        let name = |expr: &Expr| match expr {
+            Expr::For(..) => "for".to_string(),
+            Expr::Deref(_) => "deref".to_string(),
+            Expr::Block(_) => "block".to_string(),
+            Expr::FuncCall(..) => "call".to_string(),
+            Expr::Literal(_) => "literal".to_string(),
+            Expr::Variable(var) => var.0.clone(),
+            other => panic!("unexpected {other:?}")
+        };
+        let body = module.functions.values_mut().next().unwrap().body.as_mut().unwrap();
+        let mut visited = vec![];
+        let Ok(()) = body.walk(&mut |expr| {
+            visited.push(name(&expr.1));
+            Ok::<_, Infallible>(())
+        });
+        assert_eq!(visited, ["for", "literal", "deref", "p", "literal", "call", "i", "block", "literal"]);
+        visited.clear();
+        body.walk_mut(&mut |expr| {
+            visited.push(name(&expr.1));
+            Ok(())
+        }).unwrap();
+        assert_eq!(visited, ["literal", "p", "deref", "literal", "i", "literal", "block", "call", "for"]);
+    }
+}
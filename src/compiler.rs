@@ -1,26 +1,510 @@
+use std::io::IsTerminal;
+use std::path::PathBuf;
 use std::process::Command;
+use std::rc::Rc;
 use crate::ast::code_printer::CodePrinter;
-use crate::ast::parser::parse;
-use crate::error::ParseError;
+use crate::ast::cfg::Cfg;
+use crate::ast::code_printer::format_items;
+use crate::ast::docs::extract_docs;
+use crate::ast::parser::{parse, parse_items_tolerant, parse_type};
+use crate::ast::outline::outline;
+use crate::error::{print_diagnostics, OnParseErr, ParseError, ParseET, Phase};
 use crate::llvm::gen_llvm::{build_exe, build_llvm_ir};
-use crate::source::Source;
+use crate::llvm::LLVMModule;
+use crate::llvm::setup::{check_llvm, take_llvm_diagnostics};
+use crate::llvm::target::target_triple;
+use crate::source::{CodePoint, Source};
 use crate::tokens::tokenizer::tokenize;
+use crate::util::log::set_phase;
+use crate::log;
 
+/// Relocation model of the emitted code, see `--relocation-model`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum RelocModel {
+    /// whatever the linker of the platform defaults to
+    Default,
+    Static,
+    /// position independent code, for shared libraries
+    Pic,
+    /// position independent executable
+    Pie
+}
+
+/// What the driver produces, see `--shared`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum OutputKind {
+    Executable,
+    /// only functions tagged with `#[export]` are visible outside of the library
+    SharedLibrary
+}
+
+impl OutputKind {
+    pub(crate) fn file_extension(&self) -> &'static str {
+        match self {
+            OutputKind::Executable => "exe",
+            OutputKind::SharedLibrary => if cfg!(windows) { "dll" } else if cfg!(target_os = "macos") { "dylib" } else { "so" }
+        }
+    }
+}
+
+/// Extra output printed while compiling, see `--emit`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Emit {
+    /// every global with its size, alignment, linkage and initializer bytes
+    DataLayout,
+    /// the functions each function calls, before dead code is removed
+    CallGraph,
+    /// assembly of the target, next to the executable as `.s`
+    Asm,
+    /// the items with their signatures and `///` doc comments, as json
+    DocsJson,
+    /// the items with their signatures and locations for the outline of editors, as json
+    Symbols
+}
+
+/// What a failed runtime bounds check does, see `--bounds-checks`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum BoundsChecks {
+    /// print the location of the index to stderr and abort
+    Abort,
+    /// execute `llvm.trap`, no message but no dependency on libc either
+    Trap,
+    /// don't check indices at runtime, constant indices are still checked while compiling
+    Off
+}
+
+/// Whether diagnostics are colored, see `--color`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum ColorChoice {
+    Always,
+    Never,
+    /// only if diagnostics are printed to a terminal, not when redirected to a file
+    Auto
+}
+
+impl ColorChoice {
+    /// Diagnostics are printed to stderr, output like `--emit ir` to stdout
+    pub(crate) fn enabled(&self) -> bool {
+        self.enabled_for(std::io::stderr().is_terminal())
+    }
+
+    fn enabled_for(&self, terminal: bool) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => terminal
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
 pub(crate) struct Arguments{
+    /// upper bound for the size of a source file, see `--max-source-bytes`
+    pub(crate) max_source_bytes: usize,
+    /// upper bound for the lowered size of a single literal, see `--max-literal-bytes`
+    pub(crate) max_literal_bytes: usize,
+    /// check internal invariants of the ast and the ir, see `--verify-internal`
+    pub(crate) verify_internal: bool,
+    /// count the calls of every function and write them to `LITHIA_COVERAGE_FILE` at exit, see `--instrument-coverage`
+    pub(crate) instrument_coverage: bool,
+    /// upper bound for the expressions evaluated for a single constant, see `--const-eval-steps`
+    pub(crate) const_eval_steps: usize,
+    /// upper bound for the errors and warnings printed, see `--max-diagnostics`
+    pub(crate) max_diagnostics: usize,
+    pub(crate) bounds_checks: BoundsChecks,
+    pub(crate) color: ColorChoice,
+    pub(crate) relocation_model: RelocModel,
+    pub(crate) output: OutputKind,
+    pub(crate) emit: Vec<Emit>,
+    /// triple to compile for instead of the host, see `--target`
+    pub(crate) target: Option<String>,
+    /// `key = "value"` options for `#[cfg(...)]`, see `--cfg`
+    pub(crate) cfg: Vec<(String, String)>,
+    /// line and column of an expression to print the type of, see `--type-at`
+    pub(crate) type_at: Option<(usize, usize)>,
+    /// name unnamed values in the ir after what they are, see `--no-value-names`
+    pub(crate) value_names: bool,
+    /// fail once there are warnings, before building the executable, see `--deny-warnings`
+    pub(crate) deny_warnings: bool,
+    /// symbol of `void handler(i8* file, i32 line, i8* reason)` failing runtime checks call, see `--panic-handler`
+    pub(crate) panic_handler: Option<String>,
+    /// globs of the only functions to generate code for, see `--only-fn`
+    pub(crate) only_fns: Vec<String>,
+    /// globs of the functions to print the ir of, see `--print-fn-ir`
+    pub(crate) print_fn_ir: Vec<String>,
+    /// file to rewrite in canonical form instead of compiling, see `fmt`
+    pub(crate) format: Option<String>,
+    /// found and expected type to check instead of compiling, see `--type-satisfies`
+    pub(crate) type_satisfies: Option<(String, String)>,
+    /// how much the compiler logs about itself, see `-v` and [`log`](crate::util::log)
+    pub(crate) verbosity: u8,
+    /// source file to compile, the first argument that isn't a flag
+    pub(crate) input: Option<PathBuf>,
+    /// path of the built binary, other artifacts are placed next to it, see `-o`
+    pub(crate) out_file: Option<PathBuf>
+}
 
+impl Default for Arguments {
+    fn default() -> Self {
+        Self {
+            max_source_bytes: 64 * 1024 * 1024,
+            max_literal_bytes: 64 * 1024 * 1024,
+            verify_internal: cfg!(debug_assertions),
+            instrument_coverage: false,
+            const_eval_steps: 100_000,
+            max_diagnostics: 20,
+            bounds_checks: BoundsChecks::Abort,
+            color: ColorChoice::Auto,
+            relocation_model: RelocModel::Default,
+            output: OutputKind::Executable,
+            emit: vec![],
+            target: None,
+            cfg: vec![],
+            type_at: None,
+            value_names: true,
+            deny_warnings: false,
+            panic_handler: None,
+            only_fns: vec![],
+            print_fn_ir: vec![],
+            format: None,
+            type_satisfies: None,
+            verbosity: 0,
+            input: None,
+            out_file: None
+        }
+    }
+}
+
+impl Arguments {
+    pub(crate) fn parse<I: Iterator<Item=String>>(cli: I) -> Result<Self, ParseError>{
+        let mut args = Self::default();
+        // `--flag=value` is the same as `--flag value`
+        let mut cli = cli.flat_map(|arg| match arg.split_once('=') {
+            Some((flag, value)) if flag.starts_with("--") => vec![flag.to_string(), value.to_string()],
+            _ => vec![arg]
+        });
+        while let Some(arg) = cli.next() {
+            match arg.as_str() {
+                "--max-source-bytes" => {
+                    let value = cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a value after {arg}")).error())?;
+                    args.max_source_bytes = value.parse().map_err(|_|
+                        ParseET::ArgumentError(format!("expected a byte count for {arg}, found '{value}'")).error())?;
+                }
+                "--max-literal-bytes" => {
+                    let value = cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a value after {arg}")).error())?;
+                    args.max_literal_bytes = value.parse().map_err(|_|
+                        ParseET::ArgumentError(format!("expected a byte count for {arg}, found '{value}'")).error())?;
+                }
+                "--const-eval-steps" => {
+                    let value = cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a value after {arg}")).error())?;
+                    args.const_eval_steps = value.parse().map_err(|_|
+                        ParseET::ArgumentError(format!("expected a step count for {arg}, found '{value}'")).error())?;
+                }
+                "--max-diagnostics" => {
+                    let value = cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a value after {arg}")).error())?;
+                    args.max_diagnostics = value.parse().map_err(|_|
+                        ParseET::ArgumentError(format!("expected a count for {arg}, found '{value}'")).error())?;
+                }
+                "--relocation-model" => {
+                    let value = cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a value after {arg}")).error())?;
+                    args.relocation_model = match value.as_str() {
+                        "default" => RelocModel::Default,
+                        "static" => RelocModel::Static,
+                        "pic" => RelocModel::Pic,
+                        "pie" => RelocModel::Pie,
+                        _ => return Err(ParseET::ArgumentError(format!("expected one of default, static, pic or pie for {arg}, found '{value}'")).error())
+                    };
+                }
+                "--bounds-checks" => {
+                    let value = cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a value after {arg}")).error())?;
+                    args.bounds_checks = match value.as_str() {
+                        "abort" => BoundsChecks::Abort,
+                        "trap" => BoundsChecks::Trap,
+                        "off" => BoundsChecks::Off,
+                        _ => return Err(ParseET::ArgumentError(format!("expected one of abort, trap or off for {arg}, found '{value}'")).error())
+                    };
+                }
+                "--color" => {
+                    let value = cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a value after {arg}")).error())?;
+                    args.color = match value.as_str() {
+                        "always" => ColorChoice::Always,
+                        "never" => ColorChoice::Never,
+                        "auto" => ColorChoice::Auto,
+                        _ => return Err(ParseET::ArgumentError(format!("expected one of always, never or auto for {arg}, found '{value}'")).error())
+                    };
+                }
+                "--emit" => {
+                    let value = cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a value after {arg}")).error())?;
+                    let emit = match value.as_str() {
+                        "data-layout" => Emit::DataLayout,
+                        "call-graph" => Emit::CallGraph,
+                        "asm" => Emit::Asm,
+                        "docs-json" => Emit::DocsJson,
+                        "symbols" => Emit::Symbols,
+                        _ => return Err(ParseET::ArgumentError(format!("expected data-layout, call-graph, asm, docs-json or symbols for {arg}, found '{value}'")).error())
+                    };
+                    if !args.emit.contains(&emit) {
+                        args.emit.push(emit);
+                    }
+                }
+                "--target" => {
+                    args.target = Some(cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a value after {arg}")).error())?);
+                }
+                "--cfg" => {
+                    let value = cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a value after {arg}")).error())?;
+                    let (key, option) = value.split_once('=').ok_or_else(||
+                        ParseET::ArgumentError(format!("expected key=value for {arg}, found '{value}'")).error())?;
+                    args.cfg.push((key.to_string(), option.trim_matches('"').to_string()));
+                }
+                "--type-satisfies" => {
+                    let mut next = || cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a found and an expected type after {arg}")).error());
+                    args.type_satisfies = Some((next()?, next()?));
+                }
+                "--type-at" => {
+                    let value = cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a value after {arg}")).error())?;
+                    let pos = value.split_once(':').and_then(|(line, col)| Some((line.parse().ok()?, col.parse().ok()?)));
+                    args.type_at = Some(pos.ok_or_else(||
+                        ParseET::ArgumentError(format!("expected line:column for {arg}, found '{value}'")).error())?);
+                }
+                "--panic-handler" => {
+                    let value = cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a symbol after {arg}")).error())?;
+                    if value.is_empty() {
+                        return Err(ParseET::ArgumentError(format!("expected a symbol after {arg}, found ''")).error())
+                    }
+                    args.panic_handler = Some(value);
+                }
+                "--only-fn" => {
+                    args.only_fns.push(cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a function name or glob after {arg}")).error())?);
+                }
+                "--print-fn-ir" => {
+                    args.print_fn_ir.push(cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a function name or glob after {arg}")).error())?);
+                }
+                "fmt" => {
+                    args.format = Some(cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a file to format after {arg}")).error())?);
+                }
+                "-o" => {
+                    args.out_file = Some(cli.next().ok_or_else(|| ParseET::ArgumentError(format!("expected a path after {arg}")).error())?.into());
+                }
+                "--shared" => args.output = OutputKind::SharedLibrary,
+                "--no-value-names" => args.value_names = false,
+                "-v" | "-vv" | "-vvv" => args.verbosity += arg.len() as u8 - 1,
+                "--deny-warnings" => args.deny_warnings = true,
+                "--instrument-coverage" => args.instrument_coverage = true,
+                "--verify-internal" => args.verify_internal = true,
+                "--no-verify-internal" => args.verify_internal = false,
+                _ if !arg.starts_with('-') && args.input.is_none() => args.input = Some(arg.into()),
+                _ if !arg.starts_with('-') => return Err(ParseET::ArgumentError(format!("expected a single file to compile, found '{arg}' after {}", args.input.as_ref().unwrap().display())).error()),
+                _ => return Err(ParseET::ArgumentError(format!("unknown argument '{arg}'")).error())
+            }
+        }
+        if args.output == OutputKind::SharedLibrary {
+            match args.relocation_model {
+                RelocModel::Default | RelocModel::Pic => args.relocation_model = RelocModel::Pic,
+                _ => return Err(ParseET::ArgumentError(format!("shared libraries need position independent code, found relocation model {:?}", args.relocation_model)).error())
+            }
+        }
+        Ok(args)
+    }
+
+    /// Path of an artifact with the extension, next to the `-o` path or else the input
+    pub(crate) fn artifact(&self, extension: &str) -> PathBuf {
+        self.out_file.as_ref().or(self.input.as_ref()).map(|path| path.with_extension(extension)).unwrap_or_default()
+    }
+
+    /// Path of the built binary, the `-o` path as given or else the input with the extension of the output kind
+    pub(crate) fn binary(&self) -> PathBuf {
+        self.out_file.clone().unwrap_or_else(|| self.artifact(self.output.file_extension()))
+    }
 }
 
 pub(crate) fn compile(args: Arguments) -> Result<(), ParseError>{
-    let source = Source::from_file("examples/testing/hello_world.li")?;
-    let tokens = tokenize(source)?;
-    println!("{tokens:?}");
-    let module = parse(tokens, ("main".to_string(), None))?;
-    println!("{}", module.print());
-    let llvm_mod = build_llvm_ir(module)?;
-    build_exe(llvm_mod, env!("LLVM_SYS_150_PREFIX"), "examples/testing/hello_world.bc", "examples/testing/hello_world.exe",  true, true)?;
+    let input = args.input.clone().ok_or_else(|| ParseET::ArgumentError("expected a file to compile, like `lithia main.li`".to_string()).error())?;
+    check_llvm().e_phase(Phase::CheckingLlvm)?;
+    let mut warning_count = 0;
+    let source = Rc::new(Source::from_file(input.to_string_lossy().to_string(), args.max_source_bytes).e_phase(Phase::ReadingSource)?);
+    if args.emit.contains(&Emit::DocsJson) {
+        let (docs, warnings) = extract_docs(tokenize(source.clone(), true).e_phase(Phase::Tokenizing)?).e_phase(Phase::ExtractingDocs)?;
+        warning_count += print_diagnostics(warnings.into_iter().map(|w| w.phase(Phase::ExtractingDocs)).collect(), args.max_diagnostics, args.color.enabled());
+        println!("{docs}");
+    }
+    if args.emit.contains(&Emit::Symbols) {
+        // parsed tolerantly, so a broken file still has an outline, the strict parse below reports its errors
+        let (items, _, _) = parse_items_tolerant(tokenize(source.clone(), false).e_phase(Phase::Tokenizing)?).e_phase(Phase::Parsing)?;
+        println!("{}", outline("main", &items));
+    }
+    let llvm_mod = build_ir(source.clone(), &args, &mut warning_count)?;
+    if let Some((line, col)) = args.type_at {
+        match source.index_of(line, col).and_then(|i| llvm_mod.expr_types.type_at(&CodePoint(source.clone(), i))) {
+            Some(ty) => println!("type at {line}:{col}: {}", ty.print()),
+            None => println!("no expression at {line}:{col}")
+        }
+    }
+    if args.deny_warnings && warning_count > 0 {
+        return Err(ParseET::DeniedWarnings(warning_count).error())
+    }
+    set_phase(Phase::BuildingExecutable);
+    let out_file = args.binary();
+    build_exe(llvm_mod, &args, env!("LLVM_SYS_150_PREFIX").into(), args.artifact("bc"), out_file.clone(), true, true).e_phase(Phase::BuildingExecutable)?;
+    if args.output == OutputKind::SharedLibrary {
+        return Ok(())
+    }
     println!();
-    let code = Command::new("examples/testing/hello_world.exe")
+    let code = Command::new(out_file)
         .spawn().unwrap().wait().unwrap();
     println!("executed with {code}");
     Ok(())
-}
\ No newline at end of file
+}
+
+/// Tokenizes, parses and checks `source` and generates its llvm ir, printing the warnings on the way and counting them into `warning_count`
+pub(crate) fn build_ir(source: Rc<Source>, args: &Arguments, warning_count: &mut usize) -> Result<LLVMModule, ParseError>{
+    set_phase(Phase::Tokenizing);
+    let tokens = tokenize(source, false).e_phase(Phase::Tokenizing)?;
+    log!(Tokenizer, Debug, "{tokens:?}");
+    set_phase(Phase::Parsing);
+    let cfg = Cfg::new(target_triple(args).to_string_lossy().to_string(), args.cfg.clone());
+    let mut module = parse(tokens, ("main".to_string(), None), &cfg).e_phase(Phase::Parsing)?;
+    *warning_count += print_diagnostics(cfg.take_warnings().into_iter().map(|w| w.phase(Phase::Parsing)).collect(), args.max_diagnostics, args.color.enabled());
+    log!(Parser, Info, "parsed {} functions, {} constants and {} statics", module.functions.len(), module.constants.len(), module.statics.len());
+    set_phase(Phase::Desugaring);
+    module.hoist_local_items().e_phase(Phase::Desugaring)?;
+    module.resolve_aliases().e_phase(Phase::ResolvingAliases)?;
+    module.desugar_for_in().e_phase(Phase::Desugaring)?;
+    module.expand_builtins(args.max_literal_bytes).e_phase(Phase::EvaluatingBuiltins)?;
+    if args.verify_internal {
+        module.verify_internal().e_phase(Phase::VerifyingAst)?;
+    }
+    log!(Parser, Debug, "{}", module.print());
+    module.evaluate_consts(args.const_eval_steps).e_phase(Phase::EvaluatingConstants)?;
+    if args.emit.contains(&Emit::CallGraph) {
+        println!("{}", module.call_graph());
+    }
+    let entry = match args.output {
+        OutputKind::Executable => Some(module.entry_point().e_phase(Phase::FindingEntryPoint)?),
+        _ => None
+    };
+    if args.only_fns.is_empty() {
+        module.eliminate_dead_code(entry.as_deref());
+    } else {
+        module.keep_only_fns(&args.only_fns, entry.as_deref()).e_phase(Phase::SelectingFunctions)?;
+    }
+    set_phase(Phase::GeneratingCode);
+    let mut llvm_mod = build_llvm_ir(module, entry.as_deref(), args).e_phase(Phase::GeneratingCode)?;
+    let warnings = std::mem::take(&mut llvm_mod.warnings).into_iter().chain(take_llvm_diagnostics());
+    *warning_count += print_diagnostics(warnings.map(|w| w.phase(Phase::GeneratingCode)).collect(), args.max_diagnostics, args.color.enabled());
+    Ok(llvm_mod)
+}
+
+/// Checks if a value of type `found` can be used where `expected` is expected, by the rules of code generation.
+/// Prints that it can, fails with the type error otherwise, see `--type-satisfies`.
+pub(crate) fn check_type_satisfies(found: &str, expected: &str) -> Result<(), ParseError>{
+    let parse = |ty: &str| {
+        let source = Source::from_string(ty.to_string());
+        parse_type(tokenize(Rc::new(source), false).e_phase(Phase::Tokenizing)?).e_phase(Phase::Parsing)
+    };
+    let (found, expected) = (parse(found)?, parse(expected)?);
+    found.satisfies_or_err(&expected).e_phase(Phase::CheckingTypes)?;
+    println!("{} satisfies {}", found.print(), expected.print());
+    Ok(())
+}
+
+/// Rewrites the file at `path` in canonical form, see `fmt`.
+/// Code that doesn't parse is kept as written and reported, the rest is formatted anyway.
+pub(crate) fn format_file(path: &str, args: &Arguments) -> Result<(), ParseError>{
+    let source = Source::from_file(path, args.max_source_bytes).e_phase(Phase::ReadingSource)?;
+    let tokens = tokenize(Rc::new(source), true).e_phase(Phase::Tokenizing)?;
+    let (items, dangling, skipped) = parse_items_tolerant(tokens).e_phase(Phase::Parsing)?;
+    std::fs::write(path, format_items(&items, &dangling))?;
+    let skipped = print_diagnostics(skipped.into_iter().map(|e| e.phase(Phase::Parsing)).collect(), args.max_diagnostics, args.color.enabled());
+    if skipped > 0 {
+        return Err(ParseET::ParsingError(format!("{skipped} regions didn't parse and were kept as written")).error().phase(Phase::Parsing))
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+    use crate::compiler::{check_type_satisfies, compile, Arguments, BoundsChecks, ColorChoice, Emit, OutputKind, RelocModel};
+    use crate::util::testing::build_err;
+
+    fn parse(args: &[&str]) -> Result<Arguments, String> {
+        Arguments::parse(args.iter().map(|a| a.to_string())).map_err(|e| e.render(false))
+    }
+
+    #[test]
+    fn input_and_output() {
+        let args = parse(&["main.li"]).unwrap();
+        assert_eq!(args.input, Some(PathBuf::from("main.li")));
+        assert_eq!(args.binary(), PathBuf::from("main.exe"));
+        assert_eq!(args.artifact("bc"), PathBuf::from("main.bc"));
+        let args = parse(&["-o", "out/app", "src/main.li"]).unwrap();
+        assert_eq!(args.input, Some(PathBuf::from("src/main.li")));
+        assert_eq!(args.binary(), PathBuf::from("out/app"));
+        assert_eq!(args.artifact("bc"), PathBuf::from("out/app.bc"));
+        let err = compile(parse(&[]).unwrap()).unwrap_err().render(false);
+        assert!(err.contains("expected a file to compile, like `lithia main.li`"), "{err}");
+        let err = parse(&["a.li", "b.li"]).unwrap_err();
+        assert!(err.contains("expected a single file to compile, found 'b.li' after a.li"), "{err}");
+        let err = parse(&["main.li", "-o"]).unwrap_err();
+        assert!(err.contains("expected a path after -o"), "{err}");
+    }
+
+    #[test]
+    fn flags_with_values() {
+        let args = parse(&["--bounds-checks=trap", "--emit", "asm", "--emit=asm", "--max-literal-bytes", "16", "-vv", "main.li"]).unwrap();
+        assert_eq!(args.bounds_checks, BoundsChecks::Trap);
+        assert_eq!(args.emit, vec![Emit::Asm]);
+        assert_eq!(args.max_literal_bytes, 16);
+        assert_eq!(args.verbosity, 2);
+        let err = parse(&["--bounds-checks", "maybe"]).unwrap_err();
+        assert!(err.contains("expected one of abort, trap or off for --bounds-checks, found 'maybe'"), "{err}");
+        let err = parse(&["--max-literal-bytes", "lots"]).unwrap_err();
+        assert!(err.contains("expected a byte count for --max-literal-bytes, found 'lots'"), "{err}");
+        let err = parse(&["--frobnicate"]).unwrap_err();
+        assert!(err.contains("unknown argument '--frobnicate'"), "{err}");
+    }
+
+    #[test]
+    fn shared_libraries_are_position_independent() {
+        let args = parse(&["--shared", "lib.li"]).unwrap();
+        assert_eq!(args.output, OutputKind::SharedLibrary);
+        assert_eq!(args.relocation_model, RelocModel::Pic);
+        let err = parse(&["--shared", "--relocation-model", "static", "lib.li"]).unwrap_err();
+        assert!(err.contains("shared libraries need position independent code, found relocation model Static"), "{err}");
+    }
+
+    #[test]
+    fn color_choice() {
+        for (value, choice) in [("always", ColorChoice::Always), ("never", ColorChoice::Never), ("auto", ColorChoice::Auto)] {
+            assert_eq!(parse(&["--color", value, "main.li"]).unwrap().color, choice);
+        }
+        assert_eq!(parse(&["main.li"]).unwrap().color, ColorChoice::Auto);
+        let err = parse(&["--color", "sometimes"]).unwrap_err();
+        assert!(err.contains("expected one of always, never or auto for --color, found 'sometimes'"), "{err}");
+        assert!(ColorChoice::Always.enabled_for(false) && !ColorChoice::Never.enabled_for(true));
+        assert!(ColorChoice::Auto.enabled_for(true) && !ColorChoice::Auto.enabled_for(false));
+    }
+
+    #[test]
+    fn colored_diagnostics() {
+        let code = "fn main() -> i32 {\n    x\n}";
+        let plain = build_err(code, &[]);
+        assert!(!plain.contains('\x1b'), "{plain}");
+        let err = crate::util::testing::build(code, &[]).err().unwrap();
+        let colored = err.render(true);
+        assert!(colored.contains('\x1b'), "{colored}");
+        // the same text, only with escape codes
+        let stripped = colored.split('\x1b').enumerate().map(|(i, part)| if i == 0 { part } else { part.split_once('m').map_or(part, |(_, rest)| rest) }).collect::<String>();
+        assert_eq!(stripped, plain);
+    }
+    #[test]
+    fn type_compatibility() {
+        for (found, expected) in [("&u8", "&"), ("&&u8", "&&u8"), ("&[u8; 4]", "&[u8]"), ("(&u8, i32)", "(&, i32)"), ("((), (u8, &i8))", "((), (u8, &))")] {
+            assert!(check_type_satisfies(found, expected).is_ok(), "{found} should satisfy {expected}");
+        }
+        for (found, expected) in [("&", "&u8"), ("&u8", "&i8"), ("u8", "i8"), ("(u8, i32)", "(u8, i32, i32)"), ("(&u8, u8)", "(&, i8)")] {
+            let err = check_type_satisfies(found, expected).unwrap_err().render(false);
+            assert!(err.starts_with("Type Error:\n    expected"), "{found} should not satisfy {expected}: {err}");
+        }
+    }
+}
@@ -1,17 +1,110 @@
 use std::fmt::{Display, Formatter};
+use std::ops::{Deref, DerefMut};
 use crate::source::span::Span;
 use crate::tokens::{Literal, NumLit};
 
+/// Boxed, so results carrying it stay as small as their value
 #[derive(Debug)]
-pub(crate) struct ParseError {
+pub(crate) struct ParseError(Box<ParseErrorData>);
+
+#[derive(Debug)]
+pub(crate) struct ParseErrorData {
     et: ParseET,
     locs: Vec<Span>,
-    context: Vec<String>
+    context: Vec<String>,
+    phase: Option<Phase>,
+    suggestions: Vec<Suggestion>
+}
+
+impl Deref for ParseError {
+    type Target = ParseErrorData;
+
+    fn deref(&self) -> &ParseErrorData {
+        &self.0
+    }
+}
+
+impl DerefMut for ParseError {
+    fn deref_mut(&mut self) -> &mut ParseErrorData {
+        &mut self.0
+    }
+}
+
+/// Step of the compiler an error happened in, decides the exit code
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Phase {
+    CheckingLlvm,
+    ReadingSource,
+    Tokenizing,
+    Parsing,
+    ExtractingDocs,
+    Desugaring,
+    ResolvingAliases,
+    EvaluatingBuiltins,
+    VerifyingAst,
+    EvaluatingConstants,
+    FindingEntryPoint,
+    SelectingFunctions,
+    CheckingTypes,
+    GeneratingCode,
+    BuildingExecutable
+}
+
+impl Phase {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Phase::CheckingLlvm => "checking llvm",
+            Phase::ReadingSource => "reading source",
+            Phase::Tokenizing => "tokenizing",
+            Phase::Parsing => "parsing",
+            Phase::ExtractingDocs => "extracting docs",
+            Phase::Desugaring => "desugaring",
+            Phase::ResolvingAliases => "resolving type aliases",
+            Phase::EvaluatingBuiltins => "evaluating builtins",
+            Phase::VerifyingAst => "verifying ast",
+            Phase::EvaluatingConstants => "evaluating constants",
+            Phase::FindingEntryPoint => "finding entry point",
+            Phase::SelectingFunctions => "selecting functions",
+            Phase::CheckingTypes => "checking types",
+            Phase::GeneratingCode => "generating code",
+            Phase::BuildingExecutable => "building executable"
+        }
+    }
+}
+
+/// A fix for an error, replacing the code at `loc` by `replacement`
+#[derive(Debug, Clone)]
+pub(crate) struct Suggestion {
+    pub(crate) message: String,
+    pub(crate) loc: Span,
+    pub(crate) replacement: String
 }
 
 impl ParseError {
     pub(crate) fn when<T: Into<String>>(mut self, reason: T) -> Self{
-        self.context.push(reason.into());
+        let reason = reason.into();
+        if self.context.last() != Some(&reason) {
+            self.context.push(reason);
+        }
+        self
+    }
+    /// names the function the error occurred in
+    pub(crate) fn when_fn(self, name: &str) -> Self{
+        self.when(format!("compiling fn {name}"))
+    }
+    /// names the constant the error occurred in
+    pub(crate) fn when_const(self, name: &str) -> Self{
+        self.when(format!("compiling const {name}"))
+    }
+    /// names the static the error occurred in
+    pub(crate) fn when_static(self, name: &str) -> Self{
+        self.when(format!("compiling static {name}"))
+    }
+    /// sets the compiler phase, the innermost phase wins
+    pub(crate) fn phase(mut self, phase: Phase) -> Self{
+        if self.phase.is_none() {
+            self.phase = Some(phase);
+        }
         self
     }
     pub(crate) fn at(mut self, loc: Span) -> Self{
@@ -26,6 +119,56 @@ impl ParseError {
         self.locs = locs;
         self
     }
+    pub(crate) fn suggest<M: Into<String>, R: Into<String>>(mut self, message: M, loc: Span, replacement: R) -> Self{
+        self.suggestions.push(Suggestion { message: message.into(), loc, replacement: replacement.into() });
+        self
+    }
+    /// The exit code of the compiler failing with this error, by what failed:
+    /// - 1: anything not listed, like reading the source
+    /// - 2: invalid arguments
+    /// - 3: invalid source, found while tokenizing, parsing or evaluating it
+    /// - 4: generating code
+    /// - 5: building the executable, like linking
+    /// - 6: warnings with `--deny-warnings`
+    pub(crate) fn exit_code(&self) -> i32 {
+        match (&self.et, self.phase) {
+            (ParseET::ArgumentError(_), _) => 2,
+            (ParseET::DeniedWarnings(_), _) => 6,
+            (_, Some(Phase::Tokenizing | Phase::Parsing | Phase::ExtractingDocs | Phase::Desugaring | Phase::ResolvingAliases | Phase::EvaluatingBuiltins
+                | Phase::EvaluatingConstants | Phase::FindingEntryPoint)) => 3,
+            (_, Some(Phase::GeneratingCode)) => 4,
+            (_, Some(Phase::BuildingExecutable)) => 5,
+            (_, Some(Phase::CheckingLlvm | Phase::ReadingSource | Phase::VerifyingAst | Phase::SelectingFunctions | Phase::CheckingTypes) | None) => 1
+        }
+    }
+}
+
+/// Sorts diagnostics by file and then by position of their first location, so they read top to bottom.
+/// Diagnostics without a location come last, the order of equal positions is kept.
+pub(crate) fn sort_by_position(diagnostics: &mut [ParseError]) {
+    diagnostics.sort_by_cached_key(|d| match d.locs.first() {
+        Some(loc) => (false, format!("{:?}", loc.source), loc.start),
+        None => (true, String::new(), 0)
+    });
+}
+
+/// Prints diagnostics to stderr sorted by position, at most `max` of them and how many were left out.
+/// Returns how many there were, shown or not.
+pub(crate) fn print_diagnostics(diagnostics: Vec<ParseError>, max: usize, color: bool) -> usize {
+    let count = diagnostics.len();
+    eprint!("{}", render_diagnostics(diagnostics, max, color));
+    count
+}
+
+/// The output of [`print_diagnostics`]
+fn render_diagnostics(mut diagnostics: Vec<ParseError>, max: usize, color: bool) -> String {
+    sort_by_position(&mut diagnostics);
+    let hidden = diagnostics.len().saturating_sub(max);
+    let mut out = diagnostics.into_iter().take(max).map(|d| d.render(color) + "\n").collect::<String>();
+    if hidden > 0 {
+        out.push_str(&format!("{hidden} more diagnostics not shown (see --max-diagnostics)\n"));
+    }
+    out
 }
 
 impl From<std::io::Error> for ParseError {
@@ -39,6 +182,9 @@ pub(crate) enum ParseET {
     EOF,
     EmptyInput,
     IOError(std::io::Error),
+    ArgumentError(String),
+    /// llvm is missing or has the wrong version
+    SetupError(String),
     TokenizationError(String),
     LiteralError(Literal, String),
     ParsingError(String),
@@ -47,48 +193,122 @@ pub(crate) enum ParseET {
     VariableNotFound(String),
     TypeError(String, String),
     TagError(String),
-    UnsafeError(String)
+    UnsafeError(String, Option<String>),
+    /// assignment to a variable declared without `mut`
+    ImmutableAssign(String),
+    InternalError(String),
+    /// the number of warnings, which fail compilation with `--deny-warnings`
+    DeniedWarnings(usize),
+    /// not an error, reported without stopping compilation
+    Warning(String)
 }
 
 impl ParseET {
     pub(crate) fn error(self) -> ParseError {
-        ParseError {
-            et: self,
-            locs: vec![],
-            context: vec![]
-        }
+        self.ats(vec![])
     }
     pub(crate) fn at(self, loc: Span) -> ParseError {
-        ParseError {
-            et: self,
-            locs: vec![loc],
-            context: vec![]
-        }
+        self.ats(vec![loc])
     }
     pub(crate) fn ats(self, locs: Vec<Span>) -> ParseError {
-        ParseError {
+        ParseError(Box::new(ParseErrorData {
             et: self,
             locs,
-            context: vec![]
-        }
+            context: vec![],
+            phase: None,
+            suggestions: vec![]
+        }))
     }
 }
 
+const RED: &str = "\x1b[1;31m";
+const YELLOW: &str = "\x1b[1;33m";
+const BLUE: &str = "\x1b[1;34m";
+const RESET: &str = "\x1b[0m";
+
+impl ParseError {
+    /// The diagnostic as printed, with ANSI colors if `color` is set:
+    /// the kind in red, or yellow for warnings, and the carets under the code in blue.
+    pub(crate) fn render(&self, color: bool) -> String {
+        let message = self.et.to_string();
+        let message = match message.split_once('\n') {
+            Some((kind, rest)) if color => {
+                let kind_color = if let ParseET::Warning(_) = self.et { YELLOW } else { RED };
+                format!("{kind_color}{kind}{RESET}\n{rest}")
+            }
+            _ => message
+        };
+        format!("{}{}{}{}",
+           message,
+           self.context.iter().map(String::as_str).chain(self.phase.map(|p| p.name()))
+               .map(|reason| format!("\n    while {reason}"))
+               .collect::<String>(),
+           {
+               let mut locs = String::new();
+               for loc in &self.locs {
+                   locs.push_str(&format!("\n{:?}: {:?}\n{}",
+                                          loc.source,
+                                          loc,
+                                          snippet(loc, 2, color)
+                   ));
+                   if let Some(expansion) = &loc.expansion {
+                       locs.push_str(&format!("\nnote: in code generated by {}", expansion.description));
+                       if !expansion.origin.equals(loc) {
+                           locs.push_str(&format!("\n{:?}: {:?}\n{}", expansion.origin.source, expansion.origin, snippet(&expansion.origin, 0, color)));
+                       }
+                   }
+               }
+               locs
+           },
+           self.suggestions.iter()
+               .map(|s| format!("\nhelp: {}: `{}`\n{:?}: {:?}\n{}", s.message, s.replacement, s.loc.source, s.loc, snippet(&s.loc, 0, color)))
+               .collect::<String>()
+        )
+    }
+}
+
+/// [`Span::render_span_code`], with the caret lines colored if `color` is set
+fn snippet(loc: &Span, line_pad: usize, color: bool) -> String {
+    let code = loc.render_span_code(line_pad);
+    if !color {
+        return code
+    }
+    code.split('\n')
+        .map(|line| match line.strip_prefix("    | ") {
+            Some(carets) => {
+                let indent = carets.len() - carets.trim_start().len();
+                format!("    | {}{BLUE}{}{RESET}", &carets[..indent], carets[indent..].trim_end())
+            }
+            None => line.to_string()
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
 impl Display for ParseError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}{}{}",
-           match &self.et {
+        write!(f, "{}", self.render(false))
+    }
+}
+
+impl Display for ParseET {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}",
+           match self {
                ParseET::EOF => format!("Input Error:\n    reached end of file"),
                ParseET::EmptyInput => format!("Input Error:\n    input was empty"),
                ParseET::IOError(e) => format!("IO Error:\n    {}", e),
+               ParseET::ArgumentError(e) => format!("Argument Error:\n    {}", e),
+               ParseET::SetupError(e) => format!("Setup Error:\n    {}", e),
                ParseET::TokenizationError(e) => format!("Tokenization Error:\n    {}", e),
                ParseET::LiteralError(lit, e) => format!("{} literal Error:\n    {}", match lit {
                    Literal::String(_) => "String",
                    Literal::Char(_) => "Char",
-                   Literal::Number(NumLit::Integer(_), _) => "Integer",
-                   Literal::Number(NumLit::Float(_), _) => "Float",
+                   Literal::Number(NumLit::Integer(_), _, _) => "Integer",
+                   Literal::Number(NumLit::Float(_), _, _) => "Float",
                    Literal::Bool(_) => "Float",
-                   Literal::Array(..) => "Array"
+                   Literal::Array(..) => "Array",
+                   Literal::Repeat(..) | Literal::Bytes(_) => "Array"
                }, e),
                ParseET::ParsingError(e) => format!("Parsing Error:\n    {}", e),
                ParseET::CompilationError(e) => format!("Compilation Error:\n    {}", e),
@@ -98,23 +318,12 @@ impl Display for ParseError {
                ParseET::VariableNotFound(ident) => format!("Name Error:\n    could not find variable {ident}"),
                ParseET::TypeError(expected, found) => format!("Type Error:\n    expected {expected} found {found}"),
                ParseET::TagError(err) => format!("Compiler Flag Error:\n    {err}"),
-               ParseET::UnsafeError(thing) => format!("Unsafe Context Error:\n    cannot use {thing} in safe context.\n    tag the expr or func with #[unsafe]"),
-           },
-           if self.context.len() > 0 {
-               format!("\n    while {}", self.context.join("\n    while "))
-           } else {
-               String::new()
-           },
-           {
-               let mut locs = String::new();
-               for loc in &self.locs {
-                   locs.push_str(&format!("\n{:?}: {:?}\n{}",
-                                          loc.source,
-                                          loc,
-                                          loc.render_span_code(2)
-                   ))
-               }
-               locs
+               ParseET::UnsafeError(thing, wrap) => format!("Unsafe Context Error:\n    cannot use {thing} in safe context.\n    {}tag the expr or func with #[unsafe]",
+                   wrap.as_ref().map(|code| format!("wrap it as `unsafe {{ {code} }}` or ")).unwrap_or(String::new())),
+               ParseET::ImmutableAssign(name) => format!("Mutability Error:\n    cannot assign to immutable variable {name}, it is declared without mut"),
+               ParseET::InternalError(invariant) => format!("Internal Compiler Error:\n    {invariant}"),
+               ParseET::DeniedWarnings(count) => format!("Denied Warnings Error:\n    {count} warning{} with --deny-warnings", if *count == 1 { "" } else { "s" }),
+               ParseET::Warning(warning) => format!("Warning:\n    {warning}"),
            }
         )
     }
@@ -124,6 +333,7 @@ pub(crate) trait OnParseErr{
     fn e_when<S: Into<String>>(self, reason: S) -> Self;
     fn e_at(self, loc: Span) -> Self;
     fn e_at_add(self, loc: Span) -> Self;
+    fn e_phase(self, phase: Phase) -> Self;
 }
 
 impl<T> OnParseErr for Result<T, ParseError> {
@@ -137,4 +347,101 @@ impl<T> OnParseErr for Result<T, ParseError> {
     fn e_at_add(self, loc: Span) -> Self {
         self.map_err(|err| err.at_add(loc))
     }
-}
\ No newline at end of file
+    fn e_phase(self, phase: Phase) -> Self {
+        self.map_err(|err| err.phase(phase))
+    }
+}
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::ast::cfg::Cfg;
+    use crate::ast::parser::parse;
+    use crate::compiler::Arguments;
+    use crate::ast::parser::parse_items_tolerant;
+    use crate::error::{render_diagnostics, ParseError, ParseET, Phase};
+    use crate::source::Source;
+    use crate::tokens::tokenizer::tokenize;
+    use crate::util::testing::{build, build_err, temp_dir};
+
+    fn exit_code(code: &str, args: &[&str]) -> i32 {
+        build(code, args).err().unwrap_or_else(|| panic!("expected {code} to fail")).exit_code()
+    }
+
+    #[test]
+    fn exit_codes() {
+        let args = Arguments::parse(["--emit".to_string(), "nothing".to_string()].into_iter()).err().unwrap();
+        assert_eq!(args.exit_code(), 2);
+        assert_eq!(exit_code("fn main() {\n    let s = \"open;\n}", &[]), 3);
+        assert_eq!(exit_code("fn main() {\n    let x = ;\n}", &[]), 3);
+        assert_eq!(exit_code("static S: i32 = 1i32 / 0i32;\nfn main() {}", &[]), 3);
+        assert_eq!(exit_code("fn start() {}", &[]), 3);
+        assert_eq!(exit_code("fn main() {\n    let x: bool = 1i32;\n}", &[]), 4);
+        // argument errors found late are still argument errors
+        assert_eq!(exit_code("fn main() {}", &["--only-fn", "missing"]), 2);
+        assert_eq!(ParseET::SetupError("no llvm".to_string()).error().phase(Phase::CheckingLlvm).exit_code(), 1);
+        assert_eq!(ParseET::CompilationError("linking failed".to_string()).error().phase(Phase::BuildingExecutable).exit_code(), 5);
+        assert_eq!(ParseET::DeniedWarnings(2).error().phase(Phase::BuildingExecutable).exit_code(), 6);
+    }
+
+    #[test]
+    fn innermost_phase_wins() {
+        let err = ParseET::ParsingError("expected a type".to_string()).error().phase(Phase::Parsing).phase(Phase::GeneratingCode);
+        assert_eq!(err.exit_code(), 3);
+        assert!(err.render(false).ends_with("\n    while parsing"), "{}", err.render(false));
+    }
+
+    #[test]
+    fn context_chain() {
+        let err = ParseET::CompilationError("failed".to_string()).error().when("a").when("a").when("b").when("a").phase(Phase::Parsing);
+        assert_eq!(err.render(false), "Compilation Error:\n    failed\n    while a\n    while b\n    while a\n    while parsing");
+        let err = build_err("type T = i32;\nfn f() -> T {\n    true\n}\nfn g() -> T {\n    1i32\n}\n#[export]\nfn h() -> i32 {\n    f() + g()\n}", &["--shared"]);
+        assert!(err.contains("while compiling fn f\n    while generating code\n"), "{err}");
+    }
+
+    #[test]
+    fn results_stay_small() {
+        assert_eq!(size_of::<Result<(), ParseError>>(), size_of::<usize>());
+    }
+
+    #[test]
+    fn spans_from_two_files() {
+        let dir = temp_dir();
+        let ret_of = |name: &str, code: &str| {
+            let path = dir.join(name);
+            std::fs::write(&path, code).unwrap();
+            let source = Rc::new(Source::from_file(path.display().to_string(), 1024).unwrap());
+            let module = parse(tokenize(source, false).unwrap(), ("main".to_string(), None), &Cfg::new("x86_64-pc-linux-gnu".to_string(), vec![])).unwrap();
+            module.functions.values().next().unwrap().ret.clone()
+        };
+        let used = ret_of("use.li", "fn f() -> i32 {\n    g()\n}");
+        let defined = ret_of("lib.li", "// the library\nfn g() -> bool {\n    true\n}");
+        let err = defined.satisfies_or_err(&used).unwrap_err().render(false);
+        // each span is printed under the header of its own file, with code from that file
+        assert!(err.contains("lib.li: 2:11..2:14\n  1 | // the library\n  2 | fn g() -> bool {\n    |           ^^^^\n"), "{err}");
+        assert!(err.contains("use.li: 1:11..1:13\n  1 | fn f() -> i32 {\n    |           ^^^\n"), "{err}");
+    }
+    #[test]
+    fn diagnostics_are_capped_after_sorting() {
+        let code = (1..=50).map(|i| format!("fn f{i}() {{\n    let = {i};\n}}\n")).collect::<String>();
+        let (_, _, mut skipped) = parse_items_tolerant(tokenize(Rc::new(Source::from_string(code)), false).unwrap()).unwrap();
+        assert_eq!(skipped.len(), 50);
+        skipped.reverse();
+        let out = render_diagnostics(skipped, 20, false);
+        assert!(out.ends_with("\n30 more diagnostics not shown (see --max-diagnostics)\n"), "{out}");
+        // the first ones in the file are shown
+        assert!(out.contains("<string>: 2:") && out.contains("<string>: 59:") && !out.contains("<string>: 62:"), "{out}");
+        assert!(!render_diagnostics(vec![ParseET::EOF.error()], 20, false).contains("not shown"));
+    }
+    #[test]
+    fn diagnostics_read_top_to_bottom() {
+        let code = "fn a() {}\nfn b() { let = 2; }\nfn c() {}\nfn d() {}\nfn e() { let = 5; }";
+        let (_, _, mut skipped) = parse_items_tolerant(tokenize(Rc::new(Source::from_string(code.to_string())), false).unwrap()).unwrap();
+        skipped.reverse();
+        skipped.insert(0, ParseET::EOF.error());
+        let out = render_diagnostics(skipped, 20, false);
+        let line = |l: &str| out.find(&format!("<string>: {l}:")).unwrap_or_else(|| panic!("{out}"));
+        assert!(line("2") < line("5"), "{out}");
+        // diagnostics without a location come last
+        assert!(out.trim_end().ends_with("reached end of file"), "{out}");
+    }
+}
@@ -0,0 +1,51 @@
+use llvm_sys::{core, prelude, target};
+use crate::ast::{Ty, Type};
+use crate::error::ParseError;
+use crate::llvm::LLVMModGenEnv;
+
+// how values cross function boundaries, callers and callees have to agree on it
+
+/// Aggregates up to this size are returned in registers, larger ones through a pointer
+const MAX_DIRECT_RETURN_BYTES: u64 = 16;
+
+impl LLVMModGenEnv {
+    /// Type of the slot a function returning `ret` writes its result to, `None` if it is returned directly.
+    /// The slot is allocated by the caller and passed as first parameter, marked `sret`.
+    pub(crate) fn sret_type(&mut self, ret: &Type) -> Result<Option<prelude::LLVMTypeRef>, ParseError> {
+        if !matches!(&ret.0, Ty::Tuple(elems) if !elems.is_empty()) && !matches!(&ret.0, Ty::Array(..)) {
+            return Ok(None)
+        }
+        let ty = ret.llvm_type(self)?;
+        // the data layout of the target was set on the module before any function is registered
+        let size = unsafe { target::LLVMABISizeOfType(target::LLVMGetModuleDataLayout(self.module), ty) };
+        Ok((size > MAX_DIRECT_RETURN_BYTES).then_some(ty))
+    }
+
+    pub(crate) fn sret_attribute(&self, ty: prelude::LLVMTypeRef) -> prelude::LLVMAttributeRef {
+        unsafe {
+            let kind = core::LLVMGetEnumAttributeKindForName(c"sret".as_ptr(), 4);
+            core::LLVMCreateTypeAttribute(self.context, kind, ty)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::testing::{ir, run};
+
+    const DIVMOD: &str = "fn divmod(a: u32, b: u32) -> (u32, u32) {\n    (a / b, a % b)\n}\n\
+        fn spread(x: i64) -> (i64, i64, i64) {\n    (x, x + 1i64, x + 2i64)\n}\n";
+
+    #[test]
+    fn tuples_are_returned_by_value_or_through_a_slot() {
+        let ir = ir(&DIVMOD.replace("fn ", "#[export]\nfn "), &["--shared"]);
+        // 8 bytes fit in registers, 24 bytes are written to the slot of the caller
+        assert!(ir.contains("define { i32, i32 } @divmod(i32 %0, i32 %1)"), "{ir}");
+        assert!(ir.contains("define void @spread({ i64, i64, i64 }* sret({ i64, i64, i64 }) %0, i64 %1)"), "{ir}");
+        let main = |a: u32, b: u32| format!("{DIVMOD}fn main() -> i32 {{\n    let (q, r) = divmod({a}u32, {b}u32);\n    let (x, y, z) = spread(1i64);\n    \
+            let mut n = 0i32;\n    while q == {}u32 && r == {}u32 && x + y + z == 6i64 {{\n        n = 1i32;\n        break;\n    }}\n    n\n}}", a / b, a % b);
+        for (a, b) in [(17, 5), (4, 9), (4000000000, 3), (0, 1)] {
+            assert_eq!(run(&main(a, b), &[], &[]).code, Some(1), "{a} {b}");
+        }
+    }
+}
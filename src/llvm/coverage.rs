@@ -0,0 +1,118 @@
+use std::ffi::c_uint;
+use llvm_sys::{core, prelude, LLVMAtomicOrdering, LLVMAtomicRMWBinOp, LLVMIntPredicate, LLVMLinkage};
+use llvm_sys::prelude::LLVMBool;
+use crate::c_str_ptr;
+use crate::llvm::LLVMModGenEnv;
+
+// call counting for --instrument-coverage, without depending on the profiling runtime of llvm
+
+/// Environment variable naming the file the counts are written to, nothing is written if it is unset
+const COVERAGE_FILE_VAR: &str = "LITHIA_COVERAGE_FILE";
+
+impl LLVMModGenEnv {
+    /// Creates a counter for each of `symbols` and a function writing them to the coverage file,
+    /// one `symbol count` line each, which is returned and has to run after main.
+    pub(crate) fn build_coverage(&mut self, mut symbols: Vec<String>) -> prelude::LLVMValueRef {
+        symbols.sort();
+        unsafe {
+            let i64_ty = core::LLVMInt64TypeInContext(self.context);
+            for symbol in &symbols {
+                let counter = core::LLVMAddGlobal(self.module, i64_ty, c_str_ptr!(format!("lithia.coverage.{symbol}")));
+                core::LLVMSetInitializer(counter, core::LLVMConstInt(i64_ty, 0, false as LLVMBool));
+                core::LLVMSetLinkage(counter, LLVMLinkage::LLVMInternalLinkage);
+                self.coverage_counters.insert(symbol.clone(), counter);
+            }
+            self.build_coverage_dump(&symbols)
+        }
+    }
+
+    fn build_coverage_dump(&mut self, symbols: &[String]) -> prelude::LLVMValueRef {
+        let var = self.intern_str(COVERAGE_FILE_VAR);
+        let mode = self.intern_str("w");
+        let format = self.intern_str("%s %llu\n");
+        let names = symbols.iter().map(|s| self.intern_str(s)).collect::<Vec<_>>();
+        unsafe {
+            let i8_ptr = core::LLVMPointerType(core::LLVMInt8TypeInContext(self.context), 0);
+            let i32_ty = core::LLVMInt32TypeInContext(self.context);
+            let i64_ty = core::LLVMInt64TypeInContext(self.context);
+            let void = core::LLVMVoidTypeInContext(self.context);
+            let function = core::LLVMAddFunction(self.module, c_str_ptr!("lithia.coverage.dump"), core::LLVMFunctionType(void, [].as_mut_ptr(), 0, false as LLVMBool));
+            core::LLVMSetLinkage(function, LLVMLinkage::LLVMInternalLinkage);
+            let entry = core::LLVMAppendBasicBlockInContext(self.context, function, c_str_ptr!("entry"));
+            let open = core::LLVMAppendBasicBlockInContext(self.context, function, c_str_ptr!("open"));
+            let write = core::LLVMAppendBasicBlockInContext(self.context, function, c_str_ptr!("write"));
+            let done = core::LLVMAppendBasicBlockInContext(self.context, function, c_str_ptr!("done"));
+            let b = core::LLVMCreateBuilderInContext(self.context);
+
+            core::LLVMPositionBuilderAtEnd(b, entry);
+            let mut getenv_params = [i8_ptr];
+            let getenv_ty = core::LLVMFunctionType(i8_ptr, getenv_params.as_mut_ptr(), 1, false as LLVMBool);
+            let mut args = [var];
+            let path = core::LLVMBuildCall2(b, getenv_ty, self.declare_c_fn("getenv", getenv_ty), args.as_mut_ptr(), 1, c_str_ptr!("path"));
+            let no_path = core::LLVMBuildIsNull(b, path, c_str_ptr!(""));
+            core::LLVMBuildCondBr(b, no_path, done, open);
+
+            core::LLVMPositionBuilderAtEnd(b, open);
+            let mut fopen_params = [i8_ptr, i8_ptr];
+            let fopen_ty = core::LLVMFunctionType(i8_ptr, fopen_params.as_mut_ptr(), 2, false as LLVMBool);
+            let mut args = [path, mode];
+            let file = core::LLVMBuildCall2(b, fopen_ty, self.declare_c_fn("fopen", fopen_ty), args.as_mut_ptr(), 2, c_str_ptr!("file"));
+            let not_opened = core::LLVMBuildICmp(b, LLVMIntPredicate::LLVMIntEQ, file, core::LLVMConstNull(i8_ptr), c_str_ptr!(""));
+            core::LLVMBuildCondBr(b, not_opened, done, write);
+
+            core::LLVMPositionBuilderAtEnd(b, write);
+            let mut fprintf_params = [i8_ptr, i8_ptr];
+            let fprintf_ty = core::LLVMFunctionType(i32_ty, fprintf_params.as_mut_ptr(), 2, true as LLVMBool);
+            let fprintf = self.declare_c_fn("fprintf", fprintf_ty);
+            for (symbol, name) in symbols.iter().zip(names) {
+                let count = core::LLVMBuildLoad2(b, i64_ty, self.coverage_counters[symbol], c_str_ptr!("count"));
+                let mut args = [file, format, name, count];
+                core::LLVMBuildCall2(b, fprintf_ty, fprintf, args.as_mut_ptr(), args.len() as c_uint, c_str_ptr!(""));
+            }
+            let mut fclose_params = [i8_ptr];
+            let fclose_ty = core::LLVMFunctionType(i32_ty, fclose_params.as_mut_ptr(), 1, false as LLVMBool);
+            let mut args = [file];
+            core::LLVMBuildCall2(b, fclose_ty, self.declare_c_fn("fclose", fclose_ty), args.as_mut_ptr(), 1, c_str_ptr!(""));
+            core::LLVMBuildBr(b, done);
+
+            core::LLVMPositionBuilderAtEnd(b, done);
+            core::LLVMBuildRetVoid(b);
+            core::LLVMDisposeBuilder(b);
+            function
+        }
+    }
+
+    /// Increments the counter of `symbol` if it has one, atomically as the function may run on several threads.
+    pub(crate) fn build_coverage_count(&mut self, symbol: &str) {
+        if let Some(counter) = self.coverage_counters.get(symbol) {
+            unsafe {
+                let one = core::LLVMConstInt(core::LLVMInt64TypeInContext(self.context), 1, false as LLVMBool);
+                core::LLVMBuildAtomicRMW(self.builder, LLVMAtomicRMWBinOp::LLVMAtomicRMWBinOpAdd, *counter, one, LLVMAtomicOrdering::LLVMAtomicOrderingMonotonic, false as LLVMBool);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::testing::{ir, run, temp_dir};
+
+    #[test]
+    fn counts_calls() {
+        let dir = temp_dir();
+        let report = dir.join("coverage.txt");
+        let code = "fn called() {}\n#[export]\nfn uncalled() {}\nfn main() {\n    called();\n    called();\n}";
+        let instrumented = run(code, &["--instrument-coverage"], &[("LITHIA_COVERAGE_FILE", &report.to_string_lossy())]);
+        assert_eq!(instrumented.code, Some(0), "{instrumented:?}");
+        assert_eq!(std::fs::read_to_string(&report).unwrap(), "called 2\nmain 1\nuncalled 0\n");
+        std::fs::remove_dir_all(dir).unwrap();
+        // nothing to write to
+        assert_eq!(run(code, &["--instrument-coverage"], &[]).code, Some(0));
+    }
+
+    #[test]
+    fn counts_are_printed_as_64_bit() {
+        let ir = ir("fn main() {}", &["--instrument-coverage"]);
+        assert!(ir.contains("c\"%s %llu\\0A\\00\""), "{ir}");
+    }
+}
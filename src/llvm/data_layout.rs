@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::ffi::CStr;
+use llvm_sys::{core, prelude, target, LLVMTypeKind};
+use llvm_sys::target::LLVMByteOrdering;
+use crate::ast::code_printer::CodePrinter;
+use crate::ast::Module;
+
+/// initializers longer than this are cut off in the hex dump
+const MAX_DUMP_BYTES: usize = 64;
+
+/// Lists every global of the module with its layout, for `--emit data-layout`.
+/// Globals are sorted by name so the output doesn't depend on codegen order.
+pub(crate) fn describe_globals(module: prelude::LLVMModuleRef, td: target::LLVMTargetDataRef, ast: &Module) -> String {
+    let lithia_types = ast.constants.iter()
+        .map(|(name, c)| (name.clone(), c.ty.print()))
+        .chain(ast.statics.iter().map(|(name, s)| (name.clone(), s.ty.print())))
+        .collect::<HashMap<_, _>>();
+    let mut globals = vec![];
+    unsafe {
+        let mut global = core::LLVMGetFirstGlobal(module);
+        while !global.is_null() {
+            globals.push((value_name(global), global));
+            global = core::LLVMGetNextGlobal(global);
+        }
+    }
+    globals.sort_by(|(a, _), (b, _)| a.cmp(b));
+    let mut out = String::new();
+    for (name, global) in globals {
+        unsafe {
+            let ty = core::LLVMGlobalGetValueType(global);
+            out.push_str(&format!("global {name}\n"));
+            out.push_str(&format!("    lithia type: {}\n", lithia_types.get(&name).map(|t| t.as_str()).unwrap_or("<none>")));
+            out.push_str(&format!("    llvm type:   {}\n", type_name(ty)));
+            out.push_str(&format!("    size:        {} bytes\n", target::LLVMABISizeOfType(td, ty)));
+            out.push_str(&format!("    alignment:   {} bytes\n", target::LLVMABIAlignmentOfType(td, ty)));
+            out.push_str(&format!("    linkage:     {:?}\n", core::LLVMGetLinkage(global)));
+            let init = core::LLVMGetInitializer(global);
+            if !init.is_null() {
+                match const_bytes(td, init, ty) {
+                    Some(bytes) => out.push_str(&hex_dump(&bytes)),
+                    None => out.push_str("    initializer can't be evaluated to bytes\n")
+                }
+            }
+        }
+    }
+    out
+}
+
+pub(crate) unsafe fn value_name(v: prelude::LLVMValueRef) -> String {
+    let mut len = 0;
+    let name = core::LLVMGetValueName2(v, &mut len);
+    String::from_utf8_lossy(std::slice::from_raw_parts(name as *const u8, len)).to_string()
+}
+
+unsafe fn type_name(ty: prelude::LLVMTypeRef) -> String {
+    let s = core::LLVMPrintTypeToString(ty);
+    let name = CStr::from_ptr(s).to_string_lossy().to_string();
+    core::LLVMDisposeMessage(s);
+    name
+}
+
+/// Evaluates a constant to the bytes it occupies in memory, `None` for constants
+/// that are only known at link time like pointers.
+pub(crate) unsafe fn const_bytes(td: target::LLVMTargetDataRef, value: prelude::LLVMValueRef, ty: prelude::LLVMTypeRef) -> Option<Vec<u8>> {
+    let size = target::LLVMABISizeOfType(td, ty) as usize;
+    if !core::LLVMIsAConstantAggregateZero(value).is_null() || core::LLVMIsNull(value) != 0 {
+        return Some(vec![0; size])
+    }
+    match core::LLVMGetTypeKind(ty) {
+        LLVMTypeKind::LLVMIntegerTypeKind => {
+            if core::LLVMIsAConstantInt(value).is_null() {
+                return None
+            }
+            let store_size = target::LLVMStoreSizeOfType(td, ty) as usize;
+            if store_size > 8 {
+                return None
+            }
+            let v = core::LLVMConstIntGetZExtValue(value);
+            let mut bytes = match target::LLVMByteOrder(td) {
+                LLVMByteOrdering::LLVMLittleEndian => v.to_le_bytes()[..store_size].to_vec(),
+                LLVMByteOrdering::LLVMBigEndian => v.to_be_bytes()[8 - store_size..].to_vec()
+            };
+            bytes.resize(size, 0);
+            Some(bytes)
+        }
+        LLVMTypeKind::LLVMArrayTypeKind => {
+            let elem_ty = core::LLVMGetElementType(ty);
+            let stride = target::LLVMABISizeOfType(td, elem_ty) as usize;
+            let mut bytes = vec![0; size];
+            for i in 0..core::LLVMGetArrayLength(ty) {
+                let elem = if !core::LLVMIsAConstantDataSequential(value).is_null() {
+                    // LLVMGetAggregateElement only exists from llvm 15 on
+                    #[allow(deprecated)]
+                    core::LLVMGetElementAsConstant(value, i)
+                } else {
+                    core::LLVMGetOperand(value, i)
+                };
+                let elem_bytes = const_bytes(td, elem, elem_ty)?;
+                let offset = i as usize * stride;
+                bytes[offset..offset + elem_bytes.len()].copy_from_slice(&elem_bytes);
+            }
+            Some(bytes)
+        }
+        LLVMTypeKind::LLVMStructTypeKind => {
+            let mut bytes = vec![0; size];
+            for i in 0..core::LLVMCountStructElementTypes(ty) {
+                let field_ty = core::LLVMStructGetTypeAtIndex(ty, i);
+                let field_bytes = const_bytes(td, core::LLVMGetOperand(value, i), field_ty)?;
+                let offset = target::LLVMOffsetOfElement(td, ty, i) as usize;
+                bytes[offset..offset + field_bytes.len()].copy_from_slice(&field_bytes);
+            }
+            Some(bytes)
+        }
+        _ => None
+    }
+}
+
+fn hex_dump(bytes: &[u8]) -> String {
+    let mut out = String::new();
+    for (i, line) in bytes[..bytes.len().min(MAX_DUMP_BYTES)].chunks(16).enumerate() {
+        out.push_str(&format!("    {:04x}: {}\n", i * 16, line.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>().join(" ")));
+    }
+    if bytes.len() > MAX_DUMP_BYTES {
+        out.push_str(&format!("    ... {} more bytes\n", bytes.len() - MAX_DUMP_BYTES));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::Arguments;
+    use crate::llvm::data_layout::describe_globals;
+    use crate::llvm::target::Target;
+    use crate::util::testing::{build, parse_module};
+
+    #[test]
+    fn constants_are_dumped_as_bytes() {
+        let code = "const GREETING: &[u8; 6] = &\"hello\";\nconst PRIMES: &[u16; 4] = &[2u16, 3u16, 5u16, 7u16];\n\
+            #[export]\nfn letter() -> u8 {\n    GREETING[0u64]\n}\n#[export]\nfn prime() -> u16 {\n    PRIMES[0u64]\n}";
+        let module = build(code, &["--shared"]).unwrap_or_else(|e| panic!("{}", e.render(false)));
+        let target = Target::new(&Arguments::parse(["--shared".to_string()].into_iter()).unwrap()).unwrap();
+        let layout = describe_globals(module.module, target.data, &parse_module(code).unwrap());
+        assert_eq!(layout, "global GREETING\n    \
+            lithia type: &[u8;6]\n    \
+            llvm type:   [6 x i8]\n    \
+            size:        6 bytes\n    \
+            alignment:   1 bytes\n    \
+            linkage:     LLVMInternalLinkage\n    \
+            0000: 68 65 6c 6c 6f 00\n\
+            global PRIMES\n    \
+            lithia type: &[u16;4]\n    \
+            llvm type:   [4 x i16]\n    \
+            size:        8 bytes\n    \
+            alignment:   2 bytes\n    \
+            linkage:     LLVMInternalLinkage\n    \
+            0000: 02 00 03 00 05 00 07 00\n");
+    }
+}
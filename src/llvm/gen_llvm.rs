@@ -1,39 +1,135 @@
+use std::ffi::{c_char, CStr};
 use std::path::Path;
 use std::process::Command;
-use llvm_sys::{bit_writer, prelude, core};
+use llvm_sys::{bit_writer, core};
 use crate::ast::Module;
-use crate::c_str_ptr;
-use crate::error::ParseError;
-use crate::llvm::LLVMModGenEnv;
+use crate::{c_str_ptr, log};
+use crate::compiler::{Arguments, Emit, OutputKind, RelocModel};
+use crate::error::{OnParseErr, ParseError, ParseET};
+use crate::llvm::{LLVMModGenEnv, LLVMModule};
+use crate::llvm::data_layout::describe_globals;
+use crate::llvm::setup::with_activity;
+use crate::llvm::target::Target;
+use crate::llvm::verify::verify_module;
 
-pub(crate) fn build_llvm_ir(module: Module) -> Result<prelude::LLVMModuleRef, ParseError>{
-    let mut env = LLVMModGenEnv::new(module.name.0.clone());
+/// `entry` is the symbol of the entry point of an executable, see [`Module::entry_point`]
+pub(crate) fn build_llvm_ir(module: Module, entry: Option<&str>, args: &Arguments) -> Result<LLVMModule, ParseError>{
+    let target = Target::new(args)?;
+    let mut env = LLVMModGenEnv::new(module.name.0.clone(), args.clone());
+    target.apply(env.module);
+    set_provenance(&env, &module);
     module.build(&mut env)?;
-    env.finish()
+    let llvm_mod = env.finish(entry)?;
+    if args.verify_internal {
+        verify_module(llvm_mod.module, &module.loc).e_when("verifying ir")?;
+    }
+    for pattern in &args.print_fn_ir {
+        let symbols = module.functions_matching(pattern);
+        if symbols.is_empty() {
+            return Err(ParseET::ArgumentError(format!("--print-fn-ir {pattern} matches no function that was compiled")).error())
+        }
+        for symbol in symbols {
+            unsafe {
+                let ir = core::LLVMPrintValueToString(core::LLVMGetNamedFunction(llvm_mod.module, c_str_ptr!(symbol)));
+                println!("{}", CStr::from_ptr(ir).to_string_lossy());
+                core::LLVMDisposeMessage(ir);
+            }
+        }
+    }
+    if args.emit.contains(&Emit::DataLayout) {
+        println!("{}", describe_globals(llvm_mod.module, target.data, &module));
+    }
+    if args.emit.contains(&Emit::Asm) {
+        let asm_file = args.artifact("s");
+        with_activity("emitting assembly".to_string(), || target.emit_asm(llvm_mod.module, &asm_file.to_string_lossy())).e_when("emitting assembly")?;
+        log!(Codegen, Info, "wrote assembly to {}", asm_file.display());
+    }
+    Ok(llvm_mod)
 }
 
-pub(crate) fn build_exe<P: AsRef<Path>>(module: prelude::LLVMModuleRef, llvm_root: P, bitcode_file: P, exe_file: P, dump_ir: bool, disassemble: bool) -> Result<(), ParseError>{
+/// Records in the ir which file it was compiled from and by which compiler,
+/// as `source_filename` and an `llvm.ident` entry like clang's
+fn set_provenance(env: &LLVMModGenEnv, module: &Module) {
+    if let Some(path) = module.loc.source.path() {
+        let path = path.to_string_lossy();
+        unsafe {
+            core::LLVMSetModuleIdentifier(env.module, path.as_ptr() as *const c_char, path.len());
+            core::LLVMSetSourceFileName(env.module, path.as_ptr() as *const c_char, path.len());
+        }
+    }
+    let ident = format!("lithia version {}", env!("CARGO_PKG_VERSION"));
+    unsafe {
+        let mut operands = [core::LLVMMDStringInContext2(env.context, ident.as_ptr() as *const c_char, ident.len())];
+        let node = core::LLVMMDNodeInContext2(env.context, operands.as_mut_ptr(), 1);
+        core::LLVMAddNamedMetadataOperand(env.module, c_str_ptr!("llvm.ident"), core::LLVMMetadataAsValue(env.context, node));
+    }
+}
+
+pub(crate) fn build_exe<P: AsRef<Path>>(module: LLVMModule, args: &Arguments, llvm_root: P, bitcode_file: P, exe_file: P, dump_ir: bool, disassemble: bool) -> Result<(), ParseError>{
     let llvm_root = llvm_root.as_ref().to_string_lossy().to_string();
     let bitcode_file = bitcode_file.as_ref().to_string_lossy().to_string();
     let exe_file = exe_file.as_ref().to_string_lossy().to_string();
-    let success = unsafe { bit_writer::LLVMWriteBitcodeToFile(module, c_str_ptr!(bitcode_file)) };
-    println!("wrote to file with exit code: {success}");
+    let success = unsafe { bit_writer::LLVMWriteBitcodeToFile(module.module, c_str_ptr!(bitcode_file)) };
+    log!(Codegen, Info, "wrote bitcode to {bitcode_file} with exit code {success}");
     if dump_ir {
-        println!();
-        unsafe { core::LLVMDumpModule(module) }
-        println!();
+        unsafe { core::LLVMDumpModule(module.module) }
     }
-    unsafe { core::LLVMDisposeModule(module) }
-    println!("disposed of module");
+    drop(module);
+    log!(Codegen, Debug, "disposed of module");
     if disassemble {
         let dis_code = Command::new(format!("{}/bin/llvm-dis.exe", llvm_root))
             .args([bitcode_file.clone()])
             .spawn()?.wait()?;
-        println!("disassembled .bc to .ll with {dis_code}");
+        log!(Codegen, Info, "disassembled .bc to .ll with {dis_code}");
     }
-    let compile_code = Command::new(format!("{}/bin/clang.exe", llvm_root))
+    let reloc_flags: &[&str] = match args.relocation_model {
+        RelocModel::Default => &[],
+        RelocModel::Static => &["-fno-pic", "-no-pie"],
+        RelocModel::Pic => &["-fPIC"],
+        RelocModel::Pie => &["-fPIE", "-pie"]
+    };
+    let compile = Command::new(format!("{}/bin/clang.exe", llvm_root))
         .args([bitcode_file, "-v".to_string(), "-o".to_string(), exe_file])
-        .spawn()?.wait()?;
-    println!("compiled to binary with {compile_code}");
+        .args(reloc_flags)
+        .args(if args.output == OutputKind::SharedLibrary { &["-shared"][..] } else { &[] })
+        .output()?;
+    let stderr = String::from_utf8_lossy(&compile.stderr);
+    print!("{}", String::from_utf8_lossy(&compile.stdout));
+    eprint!("{stderr}");
+    log!(Codegen, Info, "compiled to binary with {}", compile.status);
+    if !compile.status.success() {
+        return Err(match &args.panic_handler {
+            // the linker only names it as some undefined symbol
+            Some(handler) if stderr.contains(handler.as_str()) => ParseET::CompilationError(format!("linking failed, the panic handler {handler} is not defined.\n    \
+                link an object defining `void {handler}(char* file, int line, char* reason)` that doesn't return"
+            )).error(),
+            _ => ParseET::CompilationError(format!("linking failed with {}", compile.status)).error()
+        })
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use std::rc::Rc;
+    use llvm_sys::core;
+    use crate::source::Source;
+    use crate::util::testing::{build_source, temp_dir};
+
+    #[test]
+    fn ir_names_its_source_and_producer() {
+        let path = temp_dir().join("lib.li");
+        std::fs::write(&path, "#[export]\nfn f() {}").unwrap();
+        let source = Source::from_file(path.display().to_string(), 1024).unwrap();
+        let module = build_source(Rc::new(source), &["--shared"]).unwrap_or_else(|e| panic!("{}", e.render(false)));
+        let ir = unsafe {
+            let ir = core::LLVMPrintModuleToString(module.module);
+            let text = CStr::from_ptr(ir).to_string_lossy().to_string();
+            core::LLVMDisposeMessage(ir);
+            text
+        };
+        assert!(ir.starts_with(&format!("; ModuleID = '{}'\nsource_filename = \"{}\"\n", path.display(), path.display())), "{ir}");
+        assert!(ir.contains("!llvm.ident = !{!1}\n") && ir.contains(&format!("!1 = !{{!\"lithia version {}\"}}", env!("CARGO_PKG_VERSION"))), "{ir}");
+    }
+}
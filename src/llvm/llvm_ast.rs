@@ -1,35 +1,149 @@
 use std::env::var;
-use std::ffi::{c_uint, c_ulonglong};
-use llvm_sys::{prelude::LLVMBool, prelude, core};
+use std::ffi::{c_char, c_uint, c_ulonglong, CStr};
+use std::collections::HashMap;
+use llvm_sys::{prelude::LLVMBool, prelude, core, LLVMAttributeFunctionIndex, LLVMIntPredicate, LLVMLinkage, LLVMThreadLocalMode, LLVMVisibility};
 use llvm_sys::prelude::{LLVMTypeRef, LLVMValueRef};
-use crate::ast::{AstLiteral, Block, Const, Expr, Expression, Func, Ident, Item, Module, Ty, Type};
-use crate::{c_str_ptr};
+use crate::ast::{AstLiteral, Block, Const, Expr, Expression, Func, Ident, Item, Module, Op, Operator, Range, Static, Tag, TagValue, Ty, Type};
+use crate::{c_str_ptr, log};
 use crate::ast::code_printer::CodePrinter;
+use crate::compiler::{BoundsChecks, OutputKind};
 use crate::error::{OnParseErr, ParseError, ParseET};
-use crate::llvm::{LLVMModGenEnv, Variable};
+use crate::llvm::{Global, LLVMModGenEnv, LoopEnv, Variable, VarKind};
+use crate::llvm::setup::with_activity;
+use crate::llvm::unsafety::allows;
 use crate::source::span::Span;
 use crate::tokens::{Literal, NumLit, NumLitTy};
 
 impl Module {
     pub(crate) fn build(&self, env: &mut LLVMModGenEnv) -> Result<(), ParseError> {
+        if env.args.output == OutputKind::SharedLibrary && !self.functions.values().any(|f| f.tags.contains_key("export")) {
+            return Err(ParseET::CompilationError("shared library does not export any function, tag the ones it provides with #[export]".to_string())
+                .at(self.name.1.clone()).when("compiling library"))
+        }
+        // === extern types ===
+        for (name, _) in &self.extern_types {
+            let ty = unsafe { core::LLVMStructCreateNamed(env.context, c_str_ptr!(name.clone())) };
+            env.opaque_types.insert(name.clone(), ty);
+        }
         // === global consts ===
         for (_ident, constant) in &self.constants {
             constant.build(env)?;
         }
+        // === statics ===
+        for (_ident, s) in &self.statics {
+            s.build(env)?;
+        }
         // === register functions ===
-        for (_ident, func) in &self.functions {
-            func.register(env)?;
+        for (symbol, func) in &self.functions {
+            func.register(env, symbol)?;
+        }
+        // === coverage counters ===
+        let coverage_dump = env.args.instrument_coverage.then(|| env.build_coverage(
+            self.functions.iter().filter(|(_, f)| f.body.is_some() && !f.tags.contains_key("extern")).map(|(symbol, _)| symbol.clone()).collect()
+        ));
+        // === constructors and destructors ===
+        for (tag, global) in [("constructor", "llvm.global_ctors"), ("destructor", "llvm.global_dtors")] {
+            let mut structors = self.functions.iter()
+                .filter_map(|(symbol, func)| func.tags.get(tag).map(|tag| Ok((structor_priority(tag)?, symbol))))
+                .collect::<Result<Vec<_>, ParseError>>()?;
+            // functions of the same priority run in an unspecified order, sorting keeps the ir stable
+            structors.sort();
+            let mut structors = structors.into_iter()
+                .map(|(priority, symbol)| Ok((priority, env.get_var(symbol, None)?.llvm_value)))
+                .collect::<Result<Vec<_>, ParseError>>()?;
+            if let (Some(dump), "destructor") = (coverage_dump, tag) {
+                // destructors of lower priority run later, so calls made by the ones of the program are written too
+                structors.insert(0, (0, dump));
+            }
+            build_structors(env, global, structors);
         }
         // === build functions ===
-        for (_ident, func) in &self.functions {
-            func.build(env)?;
+        for (symbol, func) in &self.functions {
+            func.build(env, symbol)?;
         }
         Ok(())
     }
 }
 
+/// Functions without a priority run after the ones with one, lower priorities run first
+const DEFAULT_STRUCTOR_PRIORITY: u32 = 65535;
+
+/// `#[constructor]` or `#[constructor(priority)]`, the same goes for `#[destructor]`
+fn structor_priority(tag: &Tag) -> Result<u32, ParseError> {
+    match tag.1.as_slice() {
+        [] => Ok(DEFAULT_STRUCTOR_PRIORITY),
+        [TagValue::Lit(AstLiteral(Literal::Number(NumLit::Integer(priority), None, _), _))] if *priority <= DEFAULT_STRUCTOR_PRIORITY as u128 => Ok(*priority as u32),
+        _ => Err(ParseET::TagError(format!("expected {} or {}(priority) with a priority up to {DEFAULT_STRUCTOR_PRIORITY}, found {}", tag.0.0, tag.0.0, tag.print())).at(tag.2.clone()))
+    }
+}
+
+/// `#[section("name")]`, the name has to be non-empty and may not contain whitespace or quotes
+fn section_name(tag: &Tag) -> Result<&str, ParseError> {
+    match tag.1.as_slice() {
+        [TagValue::Lit(AstLiteral(Literal::String(name), loc))] => if name.is_empty() || name.chars().any(|c| c.is_whitespace() || c.is_control() || c == '"') {
+            Err(ParseET::TagError(format!("invalid section name {name:?}, expected a non-empty name without whitespace or quotes")).at(loc.clone()))
+        } else {
+            Ok(name)
+        },
+        _ => Err(ParseET::TagError(format!("expected section(\"name\"), found {}", tag.print())).at(tag.2.clone()))
+    }
+}
+
+/// Llvm attributes of a function set by its tags, `#[cold]` and `#[hot]`
+fn function_attributes(tags: &HashMap<String, Tag>) -> Result<Vec<&'static str>, ParseError> {
+    if let (Some(cold), Some(hot)) = (tags.get("cold"), tags.get("hot")) {
+        return Err(ParseET::TagError("function can't be both cold and hot".to_string()).ats(vec![cold.2.clone(), hot.2.clone()]))
+    }
+    ["cold", "hot"].into_iter().filter_map(|name| tags.get(name).map(|tag| match tag.1.as_slice() {
+        [] => Ok(name),
+        _ => Err(ParseET::TagError(format!("expected {name}, found {}", tag.print())).at(tag.2.clone()))
+    })).collect()
+}
+
+/// Emits `functions` with their priorities as `global`, `llvm.global_ctors` runs them before main
+/// and `llvm.global_dtors` after it returned.
+fn build_structors(env: &mut LLVMModGenEnv, global: &str, functions: Vec<(u32, LLVMValueRef)>) {
+    if functions.is_empty() {
+        return
+    }
+    unsafe {
+        let i32_ty = core::LLVMInt32TypeInContext(env.context);
+        let fn_ptr = core::LLVMPointerType(core::LLVMFunctionType(core::LLVMVoidTypeInContext(env.context), [].as_mut_ptr(), 0, false as LLVMBool), 0);
+        let data_ptr = core::LLVMPointerType(core::LLVMInt8TypeInContext(env.context), 0);
+        let mut fields = [i32_ty, fn_ptr, data_ptr];
+        let entry_ty = core::LLVMStructTypeInContext(env.context, fields.as_mut_ptr(), fields.len() as c_uint, false as LLVMBool);
+        let mut entries = functions.into_iter().map(|(priority, function)| {
+            let mut values = [core::LLVMConstInt(i32_ty, priority as c_ulonglong, false as LLVMBool), function, core::LLVMConstNull(data_ptr)];
+            core::LLVMConstStructInContext(env.context, values.as_mut_ptr(), values.len() as c_uint, false as LLVMBool)
+        }).collect::<Vec<_>>();
+        let array = core::LLVMConstArray(entry_ty, entries.as_mut_ptr(), entries.len() as c_uint);
+        let v = core::LLVMAddGlobal(env.module, core::LLVMTypeOf(array), c_str_ptr!(global));
+        core::LLVMSetInitializer(v, array);
+        core::LLVMSetLinkage(v, LLVMLinkage::LLVMAppendingLinkage);
+    }
+}
+
+/// `#[export]` makes a symbol visible to everyone, `#[hidden]` only to the objects it is linked with,
+/// anything else is internal so unused items can be removed by the optimizer.
+fn set_linkage(global: prelude::LLVMValueRef, tags: &HashMap<String, Tag>) -> Result<(), ParseError> {
+    let (linkage, visibility) = match (tags.get("export"), tags.get("hidden")) {
+        (Some(export), Some(hidden)) => return Err(ParseET::TagError("item can't be both exported and hidden".to_string()).ats(vec![export.2.clone(), hidden.2.clone()])),
+        (Some(_), None) => (LLVMLinkage::LLVMExternalLinkage, LLVMVisibility::LLVMDefaultVisibility),
+        (None, Some(_)) => (LLVMLinkage::LLVMExternalLinkage, LLVMVisibility::LLVMHiddenVisibility),
+        (None, None) => (LLVMLinkage::LLVMInternalLinkage, LLVMVisibility::LLVMDefaultVisibility)
+    };
+    unsafe {
+        core::LLVMSetLinkage(global, linkage);
+        core::LLVMSetVisibility(global, visibility);
+    }
+    Ok(())
+}
+
 impl Const {
     pub(crate) fn build(&self, env: &mut LLVMModGenEnv) -> Result<(), ParseError> {
+        self.build_const(env).map_err(|e| e.when_const(&self.name.0))
+    }
+    fn build_const(&self, env: &mut LLVMModGenEnv) -> Result<(), ParseError> {
         unsafe {
             let ty = if let Ty::Pointer(ty) = &self.ty.0 {
                 ty.llvm_type(env)?
@@ -39,47 +153,120 @@ impl Const {
                 return Err(ParseET::CompilationError(format!("constant can only be pointer, found {}", self.print())).at(self.val.2.clone()).when("compiling constant"))
             };
             let v = core::LLVMAddGlobal(env.module, ty, c_str_ptr!(self.name.0));
+            set_linkage(v, &self.tags)?;
             let val = if let Expr::Point(box Expression(tags, Expr::Literal(lit), _)) = &self.val.1 {
-                let Variable {
-                    ast_type,
-                    llvm_type,
-                    llvm_value
-                } = lit.llvm_literal(env)?;
-                let loc = ast_type.1.clone();
-                Variable {
-                    ast_type: Type(Ty::Pointer(Box::new(ast_type)), loc),
-                    llvm_type,
-                    llvm_value,
-                }
+                let lit = lit.llvm_literal(env)?;
+                let loc = lit.ast_type.1.clone();
+                Variable::value(Type(Ty::Pointer(Box::new(lit.ast_type)), loc), lit.llvm_type, lit.llvm_value)
             } else {
                 return Err(ParseET::CompilationError(format!("constant can only be initialized by literal pointer, found {}", self.print())).at(self.val.2.clone()).when("compiling constant"))
             };
             val.ast_type.satisfies_or_err(&self.ty)?;
             core::LLVMSetInitializer(v, val.llvm_value);
-            env.globals.insert(self.name.0.to_string(), Variable {
-                ast_type: self.ty.clone(),
-                llvm_type: ty,
-                llvm_value: v,
-            });
+            core::LLVMSetGlobalConstant(v, true as LLVMBool);
+            // the constant itself is the pointer to the global
+            env.globals.insert(self.name.0.to_string(), Variable::value(self.ty.clone(), core::LLVMTypeOf(v), v)
+                .of_global(Some(Global::Const(self.name.0.to_string(), self.name.1.clone()))));
         }
         Ok(())
     }
 }
 
+impl Static {
+    pub(crate) fn build(&self, env: &mut LLVMModGenEnv) -> Result<(), ParseError> {
+        self.build_static(env).map_err(|e| e.when_static(&self.name.0))
+    }
+    fn build_static(&self, env: &mut LLVMModGenEnv) -> Result<(), ParseError> {
+        let Expr::Literal(lit) = &self.val.1 else {
+            return Err(ParseET::CompilationError(format!("static can only be initialized by literal, found {}", self.val.print())).at(self.val.2.clone()).when("compiling static"))
+        };
+        let val = lit.with_expected(&self.ty)?.llvm_literal(env)?;
+        self.val.check_type(&val.ast_type, &self.ty)?;
+        let ty = self.ty.llvm_type(env)?;
+        unsafe {
+            let v = core::LLVMAddGlobal(env.module, ty, c_str_ptr!(self.name.0));
+            set_linkage(v, &self.tags)?;
+            core::LLVMSetInitializer(v, val.llvm_value);
+            if let Some(tag) = self.tags.get("thread_local") {
+                let triple = CStr::from_ptr(core::LLVMGetTarget(env.module)).to_string_lossy().to_string();
+                if !supports_tls(&triple) {
+                    return Err(ParseET::TagError(format!("target {triple} does not support thread local storage")).ats(vec![tag.2.clone(), self.name.1.clone()]))
+                }
+                core::LLVMSetThreadLocalMode(v, LLVMThreadLocalMode::LLVMGeneralDynamicTLSModel);
+            }
+            // unlike constants, the name of a static is the place holding the value
+            env.globals.insert(self.name.0.to_string(), Variable::place(self.ty.clone(), ty, v)
+                .of_global(Some(Global::Static(self.name.0.to_string(), self.name.1.clone()))));
+        }
+        Ok(())
+    }
+}
+
+/// Bare metal targets have no loader or runtime setting up thread local storage,
+/// neither has wasm without the atomics proposal.
+fn supports_tls(triple: &str) -> bool {
+    !(triple.starts_with("wasm") || triple.split('-').any(|part| part == "none"))
+}
+
 impl Func {
-    pub(crate) fn register(&self, env: &mut LLVMModGenEnv) -> Result<(), ParseError> {
+    /// `symbol` is the key of the function in its module, mangled if the function is overloaded
+    pub(crate) fn register(&self, env: &mut LLVMModGenEnv, symbol: &str) -> Result<(), ParseError> {
+        for tag in ["constructor", "destructor"].iter().filter_map(|tag| self.tags.get(*tag)) {
+            if !self.args.is_empty() || !self.ret.0.is_empty() {
+                let mut signature = self.name.1.clone();
+                signature.combine(self.ret.1.clone());
+                return Err(ParseET::TagError(format!("{} function has to take no arguments and return ()", tag.0.0))
+                    .ats(vec![signature, tag.2.clone()]).when_fn(symbol))
+            }
+        }
+        let sret = env.sret_type(&self.ret)?;
+        let mut params = sret.map(|ty| unsafe { core::LLVMPointerType(ty, 0) }).into_iter()
+            .map(Ok)
+            .chain(self.args.iter().map(|(_, t)| t.llvm_type(env)))
+            .collect::<Result<Vec<_>, _>>()?;
+        let ret_type = match sret {
+            Some(_) => unsafe { core::LLVMVoidTypeInContext(env.context) },
+            None => self.ret.llvm_type(env)?
+        };
         let function_type = unsafe {
-            core::LLVMFunctionType(self.ret.llvm_type(env)?, self.args.clone().into_iter().map(|(i, t)|t.llvm_type(env)).collect::<Result<Vec<_>, _>>()?.as_mut_ptr(), self.args.len() as u32, self.tags.contains_key("vararg") as LLVMBool)
+            core::LLVMFunctionType(ret_type, params.as_mut_ptr(), params.len() as u32, self.tags.contains_key("vararg") as LLVMBool)
         };
-        let function = unsafe { core::LLVMAddFunction(env.module, c_str_ptr!(self.name.0), function_type) };
-        env.globals.insert(self.name.0.to_string(), Variable {
-            ast_type: Type(Ty::Signature(self.args.clone().into_iter().map(|(i, t)|t).collect(), Box::new(self.ret.clone()), self.tags.contains_key("unsafe"), self.tags.contains_key("vararg")), self.name.1.clone()),
-            llvm_type: function_type,
-            llvm_value: function,
-        });
+        let function = unsafe { core::LLVMAddFunction(env.module, c_str_ptr!(symbol), function_type) };
+        if let Some(ty) = sret {
+            unsafe { core::LLVMAddAttributeAtIndex(function, 1, env.sret_attribute(ty)); }
+        }
+        if !self.tags.contains_key("extern") {
+            set_linkage(function, &self.tags).map_err(|e| e.when_fn(symbol))?;
+        }
+        if let Some(tag) = self.tags.get("section") {
+            if self.tags.contains_key("extern") {
+                return Err(ParseET::TagError("extern function has no body to place in a section".to_string())
+                    .ats(vec![tag.2.clone(), self.name.1.clone()]).when_fn(symbol))
+            }
+            let section = section_name(tag).map_err(|e| e.when_fn(symbol))?;
+            unsafe { core::LLVMSetSection(function, c_str_ptr!(section)); }
+        }
+        for name in function_attributes(&self.tags).map_err(|e| e.when_fn(symbol))? {
+            unsafe {
+                let kind = core::LLVMGetEnumAttributeKindForName(name.as_ptr() as *const c_char, name.len());
+                core::LLVMAddAttributeAtIndex(function, LLVMAttributeFunctionIndex, core::LLVMCreateEnumAttribute(env.context, kind, 0));
+            }
+        }
+        if symbol != self.name.0 {
+            env.overloads.entry(self.name.0.clone()).or_default().push(symbol.to_string());
+        }
+        env.globals.insert(symbol.to_string(), Variable::function(
+            Type(Ty::Signature(self.args.clone().into_iter().map(|(_, t)| t).collect(), Box::new(self.ret.clone()), self.tags.contains_key("unsafe"), self.tags.contains_key("vararg")), self.name.1.clone()),
+            function_type,
+            function
+        ));
         Ok(())
     }
-    pub(crate) fn build(&self, env: &mut LLVMModGenEnv) -> Result<(), ParseError> {
+    pub(crate) fn build(&self, env: &mut LLVMModGenEnv, symbol: &str) -> Result<(), ParseError> {
+        with_activity(format!("compiling fn {symbol}"), || self.build_fn(env, symbol)).map_err(|e| e.when_fn(symbol))
+    }
+    fn build_fn(&self, env: &mut LLVMModGenEnv, symbol: &str) -> Result<(), ParseError> {
+        log!(Codegen, Debug, "building fn({}) -> {}", self.args.iter().map(|(_, ty)| ty.print()).collect::<Vec<_>>().join(", "), self.ret.print());
         if self.tags.contains_key("extern") {
             if self.body.is_some() {
                 return Err(ParseET::CompilationError("extern function may not havea body".to_string()).at(self.name.1.clone()))
@@ -87,18 +274,23 @@ impl Func {
             return if self.tags.contains_key("unsafe") {
                 Ok(())
             } else {
-                Err(ParseET::UnsafeError("extern function".to_string()).at(self.name.1.clone()))
+                Err(ParseET::UnsafeError("extern function".to_string(), None).at(self.name.1.clone()))
             }
         }
-        let body = self.body.as_ref().unwrap();
-        let function = env.get_var(&self.name.0, Some(&self.loc))?.llvm_value;
-        let entry_block = unsafe { core::LLVMAppendBasicBlock(function, c_str_ptr!("entry")) };
-        let entry_builder = env.builder;
-        env.builder = unsafe {
-            let b = core::LLVMCreateBuilder();
-            core::LLVMPositionBuilderAtEnd(b, entry_block);
-            b
+        let function = env.get_var(symbol, Some(&self.loc))?.llvm_value;
+        let Some(body) = self.body.as_ref() else {
+            // the body was left out by --only-fn, the definition is elsewhere
+            unsafe { core::LLVMSetLinkage(function, LLVMLinkage::LLVMExternalLinkage); }
+            return Ok(())
         };
+        let entry_block = unsafe { core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!("entry")) };
+        let sret = env.sret_type(&self.ret)?.map(|_| unsafe { core::LLVMGetParam(function, 0) });
+        let mut scope = env.enter_fn(entry_block, self.ret.clone(), sret, allows(&self.tags, "unused_unsafe"));
+        let env = &mut *scope;
+        env.build_coverage_count(symbol);
+        if let Some(tag) = self.tags.get("unsafe") {
+            env.enter_unsafe(&tag.2, format!("#[unsafe] on fn {}", self.name.0), env.allow_unused_unsafe);
+        }
         env.push_stack(true, self.tags.contains_key("unsafe"));
         self.args.iter()
             .map(|(ident, ty)|(ident, ty, ty.llvm_type(env)))
@@ -106,69 +298,310 @@ impl Func {
             .into_iter()
             .enumerate()
             .map(|(i, (ident, ty, llvm_ty))| {
-                let _ = env.stack.last_mut().unwrap().vars.insert(self.name.0.clone(),
-                                                               Variable {
-                                                                   ast_type: ty.clone(),
-                                                                   llvm_type: llvm_ty?,
-                                                                   llvm_value: unsafe {core::LLVMGetParam(function, i as c_uint)},
-                                                               });
+                // params are copied to the stack so they can be borrowed like any other local
+                let param = Variable::value(ty.clone(), llvm_ty?, unsafe {core::LLVMGetParam(function, (i + sret.is_some() as usize) as c_uint)});
+                let local = env.build_local(param, &ident.0);
+                let _ = env.stack.last_mut().unwrap().vars.insert(ident.0.clone(), local);
                 Ok(())
             })
             .collect::<Result<Vec<()>, ParseError>>()?;
-        let (mut ret, ret_loc) = body.build(env)?;
+        let (ret, ret_loc) = body.build(env, Some(&self.ret))?;
         env.pop_stack();
+        if self.tags.contains_key("unsafe") {
+            env.leave_unsafe();
+        }
         ret.ast_type.satisfies_or_err(&self.ret).e_at_add(ret_loc)?;
         unsafe {
-            core::LLVMBuildRetVoid(env.builder);
-            core::LLVMDisposeBuilder(env.builder);
+            if env.is_block_open() {
+                if body.diverges() {
+                    // only reachable through the dead block following a return
+                    core::LLVMBuildUnreachable(env.builder);
+                } else if let Some(slot) = env.sret {
+                    core::LLVMBuildStore(env.builder, ret.llvm_value, slot);
+                    core::LLVMBuildRetVoid(env.builder);
+                } else if self.ret.0.is_empty() {
+                    core::LLVMBuildRetVoid(env.builder);
+                } else {
+                    core::LLVMBuildRet(env.builder, ret.llvm_value);
+                }
+            }
         }
-        env.builder = entry_builder;
         Ok(())
     }
 }
 
 impl Expression {
+    /// Builds the expression and checks it against `expected`, literals are typed by it directly.
+    pub(crate) fn build_expecting(&self, env: &mut LLVMModGenEnv, ret_name: Option<String>, expected: &Type) -> Result<Variable, ParseError> {
+        let v = match (&self.1, &expected.0) {
+            (Expr::Literal(lit), _) => lit.with_expected(expected)?.llvm_literal(env)?,
+            (Expr::Tuple(elems), Ty::Tuple(tys)) if elems.len() == tys.len() => self.build_tuple(env, elems, Some(tys))?,
+            // tags like `unsafe` are handled by `build`
+            (Expr::Block(block), _) if self.0.is_empty() => {
+                env.push_stack(false, false);
+                let built = block.build(env, Some(expected));
+                env.pop_stack();
+                built?.0
+            }
+            // `- 5`, the literal takes the expected type
+            (Expr::UnaryOp(Operator(Op::Sub, _), inner), _) if matches!(&inner.1, Expr::Literal(lit) if lit.get_type().is_err()) => {
+                let v = inner.build_expecting(env, None, expected)?;
+                Self::build_neg(env, ret_name, v, inner)?
+            }
+            _ => self.build_value(env, ret_name)?
+        };
+        self.check_type(&v.ast_type, expected)?;
+        Ok(Variable {
+            ast_type: expected.clone(),
+            ..v
+        })
+    }
+
+    /// Builds both operands of a binary operation with the same type.
+    /// An unsuffixed literal takes the type of the other side, it has no side effects so it can be built second.
+    fn build_operands(env: &mut LLVMModGenEnv, left: &Expression, right: &Expression) -> Result<(Variable, Variable), ParseError> {
+        Ok(if matches!(&left.1, Expr::Literal(AstLiteral(Literal::Number(_, None, _), _))) {
+            let r = right.build_value(env, None)?;
+            (left.build_expecting(env, None, &r.ast_type)?, r)
+        } else {
+            let l = left.build_value(env, None)?;
+            let r = right.build_expecting(env, None, &l.ast_type)?;
+            (l, r)
+        })
+    }
+
+    /// Applies the arithmetic operator `op` to `l` and `r`, the values of `left` and `right`, which have to be integers of the same type
+    fn build_arithmetic(env: &mut LLVMModGenEnv, ret_name: Option<String>, op: &Op, l: Variable, r: Variable, left: &Expression, right: &Expression) -> Result<Variable, ParseError> {
+        let signed = l.ast_type.int_signedness().ok_or_else(||
+            ParseET::TypeError("integer".to_string(), l.ast_type.print()).at(left.2.clone()).when("compiling arithmetic"))?;
+        unsafe {
+            if matches!(op, Op::Div | Op::Mod) && !core::LLVMIsAConstantInt(r.llvm_value).is_null() && core::LLVMConstIntGetZExtValue(r.llvm_value) == 0 {
+                return Err(ParseET::CompilationError("division by zero".to_string()).at(right.2.clone()).when("compiling arithmetic"))
+            }
+            let (build, kind): (unsafe extern "C" fn(_, _, _, _) -> _, _) = match (op, signed) {
+                (Op::Add, _) => (core::LLVMBuildAdd, "add"),
+                (Op::Sub, _) => (core::LLVMBuildSub, "sub"),
+                (Op::Mul, _) => (core::LLVMBuildMul, "mul"),
+                (Op::Div, true) => (core::LLVMBuildSDiv, "div"),
+                (Op::Div, false) => (core::LLVMBuildUDiv, "div"),
+                (Op::Mod, true) => (core::LLVMBuildSRem, "rem"),
+                (Op::Mod, false) => (core::LLVMBuildURem, "rem"),
+                _ => unreachable!("only arithmetic operators get here")
+            };
+            Ok(Variable::value(l.ast_type, l.llvm_type, build(env.builder, l.llvm_value, r.llvm_value, c_str_ptr!(env.value_name(ret_name, kind)))))
+        }
+    }
+
+    /// Negates `v`, the value of `expr`, which has to be a signed integer
+    fn build_neg(env: &mut LLVMModGenEnv, ret_name: Option<String>, v: Variable, expr: &Expression) -> Result<Variable, ParseError> {
+        if v.ast_type.int_signedness() != Some(true) {
+            return Err(ParseET::TypeError("signed integer".to_string(), v.ast_type.print()).at(expr.2.clone()).when("compiling negation"))
+        }
+        Ok(Variable::value(v.ast_type, v.llvm_type, unsafe { core::LLVMBuildNeg(env.builder, v.llvm_value, c_str_ptr!(env.value_name(ret_name, "neg"))) }))
+    }
+
+    /// Checks that the value of this expression, of type `found`, can be used as `expected`.
+    /// Common mismatches come with a suggestion how to fix them.
+    fn check_type(&self, found: &Type, expected: &Type) -> Result<(), ParseError> {
+        found.satisfies_or_err(expected).map_err(|e| match self.type_fix(found, expected) {
+            Some((message, replacement)) => e.suggest(message, self.2.clone(), replacement),
+            None => e
+        })
+    }
+
+    fn type_fix(&self, found: &Type, expected: &Type) -> Option<(&'static str, String)> {
+        if let (Expr::Literal(lit), Some(suffix)) = (&self.1, expected.num_lit_ty()) {
+            return lit.with_suffix(&suffix).map(|fixed| ("change the suffix", fixed))
+        }
+        match (&found.0, &expected.0) {
+            (_, Ty::Pointer(pointee)) if found.satisfies(pointee) => Some(("take a pointer", format!("&{}", self.2.text()))),
+            (Ty::Pointer(pointee), _) if pointee.satisfies(expected) => Some(("dereference the pointer", format!("*{}", self.2.text()))),
+            _ => None
+        }
+    }
+
+    /// Builds the elements into a struct value, each typed by its counterpart in `expected` if there is one.
+    fn build_tuple(&self, env: &mut LLVMModGenEnv, elems: &[Expression], expected: Option<&[Type]>) -> Result<Variable, ParseError> {
+        let values = elems.iter().enumerate()
+            .map(|(i, elem)| match expected.and_then(|tys| tys.get(i)) {
+                Some(ty) => elem.build_expecting(env, None, ty),
+                None => elem.build_value(env, None)
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let ty = Type(Ty::Tuple(values.iter().map(|v| v.ast_type.clone()).collect()), self.2.clone());
+        let llvm_ty = ty.llvm_type(env)?;
+        let tuple = values.iter().enumerate().fold(unsafe { core::LLVMGetUndef(llvm_ty) }, |tuple, (i, v)| unsafe {
+            core::LLVMBuildInsertValue(env.builder, tuple, v.llvm_value, i as c_uint, c_str_ptr!(env.value_name(None, "tuple")))
+        });
+        Ok(Variable::value(ty, llvm_ty, tuple))
+    }
+
+    /// Builds the expression and loads it if it is a place.
+    pub(crate) fn build_value(&self, env: &mut LLVMModGenEnv, ret_name: Option<String>) -> Result<Variable, ParseError> {
+        let v = self.build(env, ret_name.clone())?;
+        Ok(env.load(v, ret_name))
+    }
+
+    /// Builds the expression as place, values are stored in a temporary so they have an address.
+    pub(crate) fn build_place(&self, env: &mut LLVMModGenEnv, ret_name: Option<String>) -> Result<Variable, ParseError> {
+        let v = self.build(env, ret_name.clone())?;
+        Ok(match v.kind {
+            VarKind::Value => env.build_local(v, &env.value_name(ret_name, "tmp")),
+            VarKind::Place | VarKind::Function => v
+        })
+    }
+
+    /// Builds the expression, variables and dereferences result in places that are not loaded yet.
     pub(crate) fn build(&self, env: &mut LLVMModGenEnv, ret_name: Option<String>) -> Result<Variable, ParseError> {
         let outer_unsafe = env.stack.last().unwrap().unsafe_ctx;
-        if self.0.contains_key("unsafe") {
+        if let Some(tag) = self.0.get("unsafe") {
             env.stack.last_mut().unwrap().unsafe_ctx = true;
+            let what = if let Expr::Block(_) = self.1 { "unsafe block" } else { "#[unsafe] on expression" };
+            env.enter_unsafe(&tag.2, what.to_string(), env.allow_unused_unsafe || allows(&self.0, "unused_unsafe"));
         }
         let r = unsafe {
             Ok(match &self.1 {
                 Expr::Literal(lit) => lit.llvm_literal(env)?,
                 Expr::Point(expr) => {
-                    let v = expr.build(env, None)?;
-                    let ptr = core::LLVMBuildAlloca(env.builder, v.llvm_type, c_str_ptr!(ret_name.unwrap_or(String::new())));
-                    core::LLVMBuildStore(env.builder, v.llvm_value, ptr);
-                    Variable {
-                        ast_type: Type(Ty::Pointer(Box::new(v.ast_type)),self.2.clone()),
-                        llvm_type: core::LLVMPointerType(v.llvm_type, 0), // TODO: replace 0
-                        llvm_value: ptr,
+                    // places already live in memory, pointing to them must not copy
+                    let v = expr.build_place(env, ret_name)?;
+                    Variable::value(Type(Ty::Pointer(Box::new(v.ast_type)),self.2.clone()), core::LLVMPointerType(v.llvm_type, 0), v.llvm_value) // TODO: replace 0
+                        .of_global(v.global)
+                },
+                Expr::Index(base, index) => {
+                    let base_v = base.build(env, None)?;
+                    let not_indexable = || ParseET::TypeError("array or pointer to array".to_string(), base_v.ast_type.print()).at(base.2.clone()).when("compiling index");
+                    // elements of a global array, or of the one a constant points to, are memory of that global,
+                    // elements of an array in an immutable variable are immutable
+                    let (array, array_ty, elem, len, global, immutable) = match &base_v.ast_type.0 {
+                        Ty::Array(elem, len) => {
+                            let array_ty = base_v.llvm_type;
+                            let array = match base_v.kind {
+                                VarKind::Value => env.build_local(base_v.clone(), &env.value_name(None, "tmp")),
+                                _ => base_v.clone()
+                            };
+                            (array.llvm_value, array_ty, elem.clone(), Some(*len), base_v.global.clone(), base_v.immutable.clone())
+                        },
+                        Ty::Pointer(pointee) => {
+                            let pointer = env.load(base_v.clone(), None);
+                            match &pointee.0 {
+                                Ty::Array(elem, len) => (pointer.llvm_value, pointee.llvm_type(env)?, elem.clone(), Some(*len), pointer.global, None),
+                                Ty::Slice(elem) => (pointer.llvm_value, pointee.llvm_type(env)?, elem.clone(), None, pointer.global, None),
+                                _ => return Err(not_indexable())
+                            }
+                        },
+                        _ => return Err(not_indexable())
+                    };
+                    let i = match &index.1 {
+                        // an index without suffix is an uptr
+                        Expr::Literal(lit) if lit.get_type().is_err() =>
+                            index.build_expecting(env, None, &Type(Ty::Single(vec![], Item::new(&vec!["uptr"], index.2.clone())), index.2.clone()))?,
+                        _ => index.build_value(env, None)?
+                    };
+                    let signed = i.ast_type.int_signedness().ok_or_else(||
+                        ParseET::TypeError("integer".to_string(), i.ast_type.print()).at(index.2.clone()).when("compiling index"))?;
+                    // narrowing to the 64 bit gep index would drop the upper bits before the bounds check
+                    if core::LLVMGetIntTypeWidth(core::LLVMTypeOf(i.llvm_value)) > 64 {
+                        return Err(ParseET::TypeError("integer of at most 64 bits".to_string(), i.ast_type.print()).at(index.2.clone()).when("compiling index"))
                     }
+                    let const_i = match (core::LLVMIsAConstantInt(i.llvm_value).is_null(), signed) {
+                        (true, _) => None,
+                        (false, true) => Some(core::LLVMConstIntGetSExtValue(i.llvm_value) as i128),
+                        (false, false) => Some(core::LLVMConstIntGetZExtValue(i.llvm_value) as i128)
+                    };
+                    // gep indices are signed, so unsigned ones have to be zero extended first
+                    let i = core::LLVMBuildIntCast2(env.builder, i.llvm_value, core::LLVMInt64TypeInContext(env.context), signed as LLVMBool, c_str_ptr!(env.value_name(None, "index")));
+                    if let Some(len) = len {
+                        if len == 0 {
+                            // every index would fail the bounds check
+                            return Err(ParseET::CompilationError(format!("array of length 0 has no elements, {} can't be indexed", base.print())).at(self.2.clone()).when("compiling index"))
+                        }
+                        if let Some(const_i) = const_i {
+                            if const_i < 0 || const_i >= len as i128 {
+                                return Err(ParseET::CompilationError(format!("index {const_i} is out of bounds for array of length {len}")).at(index.2.clone()).when("compiling index"))
+                            }
+                        } else if env.args.bounds_checks != BoundsChecks::Off {
+                            // negative indices wrap around to huge unsigned ones, so one compare covers both ends
+                            let in_bounds = core::LLVMBuildICmp(env.builder, LLVMIntPredicate::LLVMIntULT, i,
+                                core::LLVMConstInt(core::LLVMInt64TypeInContext(env.context), len as c_ulonglong, false as LLVMBool), c_str_ptr!("in_bounds"));
+                            env.build_bounds_check(in_bounds, &index.2);
+                        }
+                    }
+                    let mut indices = [core::LLVMConstInt(core::LLVMInt64TypeInContext(env.context), 0, false as LLVMBool), i];
+                    let elem_ptr = core::LLVMBuildGEP2(env.builder, array_ty, array, indices.as_mut_ptr(), indices.len() as c_uint, c_str_ptr!(env.value_name(ret_name, "elem")));
+                    Variable::place(*elem.clone(), elem.llvm_type(env)?, elem_ptr).of_global(global).of_immutable(immutable)
                 },
+                Expr::Field(base, i) => {
+                    let base_v = base.build(env, None)?;
+                    // like indices, elements of places are places, reached through a pointer to the tuple too
+                    let (tuple, global, immutable) = match &base_v.ast_type.0 {
+                        Ty::Pointer(pointee) if matches!(pointee.0, Ty::Tuple(_)) => {
+                            let pointer = env.load(base_v.clone(), None);
+                            (Variable::place(*pointee.clone(), pointee.llvm_type(env)?, pointer.llvm_value), pointer.global, None)
+                        }
+                        _ => (base_v.clone(), base_v.global.clone(), base_v.immutable.clone())
+                    };
+                    let Ty::Tuple(elems) = &tuple.ast_type.0 else {
+                        return Err(ParseET::TypeError("tuple".to_string(), tuple.ast_type.print()).at(base.2.clone()).when("compiling tuple element"))
+                    };
+                    let Some(elem) = elems.get(*i) else {
+                        return Err(ParseET::CompilationError(format!("tuple {} has {} elements, there is no element {i}", tuple.ast_type.print(), elems.len()))
+                            .at(self.2.clone()).when("compiling tuple element"))
+                    };
+                    let elem_llvm_ty = elem.llvm_type(env)?;
+                    match tuple.kind {
+                        VarKind::Value => Variable::value(elem.clone(), elem_llvm_ty,
+                            core::LLVMBuildExtractValue(env.builder, tuple.llvm_value, *i as c_uint, c_str_ptr!(env.value_name(ret_name, "elem")))),
+                        _ => Variable::place(elem.clone(), elem_llvm_ty,
+                            core::LLVMBuildStructGEP2(env.builder, tuple.llvm_type, tuple.llvm_value, *i as c_uint, c_str_ptr!(env.value_name(ret_name, "elem"))))
+                            .of_global(global).of_immutable(immutable)
+                    }
+                }
                 Expr::Deref(expr) => {
-                    let v = expr.build(env, None)?;
+                    let v = expr.build_value(env, None)?;
                     if let Ty::RawPointer = &v.ast_type.0 {
                         return Err(ParseET::TypeError("pointer".to_string(), "raw pointer".to_string()).at(self.2.clone()).when("compiling deref"))
                     }
                     let inner_ty = if let Ty::Pointer(box ty) = &v.ast_type.0 { ty } else {
                         return Err(ParseET::TypeError("pointer".to_string(), v.ast_type.print()).at(self.2.clone()).when("compiling deref"))
                     };
-                    let llvm_ty = inner_ty.llvm_type(env)?;
-                    let deref = core::LLVMBuildLoad2(env.builder, llvm_ty, v.llvm_value, c_str_ptr!(ret_name.unwrap_or(String::new())));
-                    Variable {
-                        ast_type: inner_ty.clone(),
-                        llvm_type: llvm_ty,
-                        llvm_value: deref,
-                    }
+                    let inner_llvm_ty = inner_ty.llvm_type(env).map_err(|e| e.at_add(self.2.clone()).when("compiling deref"))?;
+                    Variable::place(inner_ty.clone(), inner_llvm_ty, v.llvm_value).of_global(v.global)
                 }
                 Expr::Variable(var) => env.get_var(&var.0, Some(&var.1))?,
-                Expr::Block(block) => block.build(env)?.0,
+                Expr::Block(block) => {
+                    env.push_stack(false, false);
+                    let built = block.build(env, None);
+                    env.pop_stack();
+                    built?.0
+                },
                 Expr::FuncCall(fun, args) => {
-                    let var = env.get_var(&fun.0.first().unwrap().0, Some(&fun.1))?;
-                    if let Ty::Signature(arg_types, ret, is_unsafe, vararg) = var.ast_type.0 {
-                        if is_unsafe && !env.stack.last().unwrap().unsafe_ctx {
-                            return Err(ParseET::UnsafeError("unsafe function".to_string()).ats(vec![var.ast_type.1.clone(), fun.1.clone()]))
+                    let name = &fun.0.first().unwrap().0;
+                    // without overloads the parameters are known before the arguments are built
+                    let params = match env.overloads.contains_key(name) {
+                        true => vec![],
+                        false => match env.get_var(name, Some(&fun.1)).map(|v| v.ast_type.0) {
+                            Ok(Ty::Signature(params, ..)) => params,
+                            _ => vec![]
+                        }
+                    };
+                    // number literals without suffix are typed by their parameter, so they are built once the overload is known.
+                    // blocks are built in order, typed by their parameter if it is known already
+                    let built_args = args.iter().enumerate()
+                        .map(|(i, expr)| match (&expr.1, params.get(i)) {
+                            (Expr::Literal(lit), _) if lit.get_type().is_err() => Ok(None),
+                            (Expr::Block(_), Some(param)) => expr.build_expecting(env, None, param).map(Some),
+                            _ => expr.build_value(env, None).map(Some)
+                        })
+                        .collect::<Result<Vec<_>, _>>()?;
+                    let var = env.resolve_fn(name, &fun.1, &built_args.iter().map(|v| v.as_ref().map(|v| &v.ast_type)).collect::<Vec<_>>())
+                        .e_when("compiling function call")?;
+                    if let (VarKind::Function, Ty::Signature(arg_types, ret, is_unsafe, vararg)) = (var.kind, var.ast_type.0.clone()) {
+                        if is_unsafe {
+                            if !env.stack.last().unwrap().unsafe_ctx {
+                                return Err(ParseET::UnsafeError("unsafe function".to_string(), Some(self.print())).ats(vec![var.ast_type.1.clone(), fun.1.clone()]))
+                            }
+                            env.use_unsafe();
                         }
                         if arg_types.len() != args.len() && (arg_types.len() > args.len() || !vararg) {
                             return if vararg {
@@ -177,67 +610,418 @@ impl Expression {
                                 Err(ParseET::CompilationError(format!("expected {} args, got {}", arg_types.len(), args.len())).at(self.2.clone()).when("compiling function call"))
                             }
                         }
-                        let mut args = args.iter().zip(arg_types)
-                            .map(|(expr, t)| expr.build(env, None).map(|v| {
-                                v.ast_type.satisfies_or_err(&t).e_at_add(expr.2.clone())?;
-                                Ok(v.llvm_value)
-                            }).flatten())
-                            .collect::<Result<Vec<_>, _>>()?;
+                        // arguments past the declared ones are vararg and not type checked
+                        let mut args = built_args.into_iter().zip(args).enumerate()
+                            .map(|(i, (v, expr))| Ok(match (v, arg_types.get(i)) {
+                                (Some(v), Some(t)) => {
+                                    expr.check_type(&v.ast_type, t).e_at_add(expr.2.clone())?;
+                                    if let Ty::RawPointer = t.0 {
+                                        // any pointer is passed as raw pointer
+                                        let raw = t.llvm_type(env)?;
+                                        Variable::value(t.clone(), raw, core::LLVMBuildPointerCast(env.builder, v.llvm_value, raw, c_str_ptr!(env.value_name(None, "raw"))))
+                                    } else {
+                                        v
+                                    }
+                                }
+                                (Some(v), None) => v,
+                                (None, Some(t)) => expr.build_expecting(env, None, t).e_at_add(expr.2.clone())?,
+                                // a vararg can't be inferred
+                                (None, None) => expr.build_value(env, None)?
+                            }.llvm_value))
+                            .collect::<Result<Vec<_>, ParseError>>()?;
                         let ty = ret.llvm_type(env)?;
-                        let out = core::LLVMBuildCall2(env.builder, var.llvm_type, var.llvm_value, args.as_mut_ptr(), args.len() as c_uint, c_str_ptr!(ret_name.unwrap_or(String::new())));
-                        Variable {
-                            ast_type: *ret,
-                            llvm_type: ty,
-                            llvm_value: out,
+                        match env.sret_type(&ret)? {
+                            Some(sret_ty) => {
+                                let slot = env.build_entry_alloca(sret_ty, c_str_ptr!(env.value_name(ret_name, &format!("call.{}", fun.print()))));
+                                args.insert(0, slot);
+                                let call = core::LLVMBuildCall2(env.builder, var.llvm_type, var.llvm_value, args.as_mut_ptr(), args.len() as c_uint, c_str_ptr!(""));
+                                core::LLVMAddCallSiteAttribute(call, 1, env.sret_attribute(sret_ty));
+                                Variable::place(*ret, ty, slot)
+                            }
+                            None => {
+                                // void values can't have a name
+                                let name = if ret.0.is_empty() { String::new() } else { env.value_name(ret_name, &format!("call.{}", fun.print())) };
+                                let out = core::LLVMBuildCall2(env.builder, var.llvm_type, var.llvm_value, args.as_mut_ptr(), args.len() as c_uint, c_str_ptr!(name));
+                                Variable::value(*ret, ty, out)
+                            }
                         }
                     } else {
-                        return Err(ParseET::TypeError("function".to_string(), format!("{:?}", var.ast_type.0)).at(self.2.clone()).when("compiling expression"))
+                        return Err(ParseET::TypeError("function".to_string(), var.ast_type.print()).at(self.2.clone()).when("compiling expression"))
                     }
                 },
                 Expr::VarCreate(name, mutable, ty, expr) => {
-                    let v = expr.build(env, Some(name.0.clone()))?;
-                    env.stack.last_mut().unwrap().vars.insert(name.0.clone(), v.clone());
+                    let v = match ty {
+                        Some(ty) => expr.build_expecting(env, Some(name.0.clone()), ty).e_when("checking let annotation")?,
+                        None => expr.build_value(env, Some(name.0.clone()))?
+                    };
+                    let local = env.build_local(v, &name.0).of_immutable((!mutable).then(|| name.clone()));
+                    env.stack.last_mut().unwrap().vars.insert(name.0.clone(), local.clone());
+                    local
+                }
+                Expr::LetTuple(names, ty, expr) => {
+                    let v = match ty {
+                        Some(ty) => expr.build_expecting(env, None, ty).e_when("checking let annotation")?,
+                        None => expr.build_value(env, None)?
+                    };
+                    let elems = match &v.ast_type.0 {
+                        Ty::Tuple(elems) if elems.len() == names.len() => elems.clone(),
+                        _ => return Err(ParseET::TypeError(format!("tuple of {} elements", names.len()), v.ast_type.print())
+                            .at(expr.2.clone()).when("compiling tuple destructuring"))
+                    };
+                    for (i, (name, elem)) in names.iter().zip(elems).enumerate() {
+                        let llvm_ty = elem.llvm_type(env)?;
+                        let value = core::LLVMBuildExtractValue(env.builder, v.llvm_value, i as c_uint, c_str_ptr!(name.0.clone()));
+                        let local = env.build_local(Variable::value(elem, llvm_ty, value), &name.0);
+                        env.stack.last_mut().unwrap().vars.insert(name.0.clone(), local);
+                    }
+                    env.unit(self.2.clone())
+                }
+                Expr::Tuple(elems) => self.build_tuple(env, elems, None)?,
+                Expr::Group(expr) => expr.build(env, ret_name)?,
+                Expr::Ascribe(expr, ty) => expr.build_expecting(env, ret_name, ty).e_when("checking type ascription")?,
+                Expr::LetElse(name, expr, else_block) => {
+                    if !else_block.diverges() {
+                        return Err(ParseET::CompilationError("else block of let else must diverge, use return, break or continue".to_string())
+                            .at(else_block.1.clone()).when("compiling let else"))
+                    }
+                    let v = expr.build_value(env, Some(name.0.clone()))?;
+                    if !matches!(v.ast_type.0, Ty::Pointer(_) | Ty::RawPointer) {
+                        return Err(ParseET::TypeError("pointer".to_string(), v.ast_type.print()).ats(vec![v.ast_type.1.clone(), expr.2.clone()]).when("compiling let else"))
+                    }
+                    let function = env.current_function();
+                    let else_bb = core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!("let.else"));
+                    let bind_bb = core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!("let.bind"));
+                    let is_null = core::LLVMBuildIsNull(env.builder, v.llvm_value, c_str_ptr!(env.value_name(None, "is_null")));
+                    core::LLVMBuildCondBr(env.builder, is_null, else_bb, bind_bb);
+
+                    core::LLVMPositionBuilderAtEnd(env.builder, else_bb);
+                    env.push_stack(false, false);
+                    let built = else_block.build(env, None);
+                    env.pop_stack();
+                    built?;
+                    if env.is_block_open() {
+                        core::LLVMBuildUnreachable(env.builder);
+                    }
+
+                    // the binding is only visible after the statement, never inside the else block
+                    core::LLVMPositionBuilderAtEnd(env.builder, bind_bb);
+                    let local = env.build_local(v, &name.0);
+                    env.stack.last_mut().unwrap().vars.insert(name.0.clone(), local.clone());
+                    local
+                }
+                Expr::For(label, var, Range(start, end, inclusive, _), step, body) => {
+                    let start = start.build_value(env, None)?;
+                    let end = end.build_value(env, None)?;
+                    let signed = start.ast_type.int_signedness().ok_or_else(||
+                        ParseET::TypeError("integer".to_string(), start.ast_type.print()).at(start.ast_type.1.clone()).when("compiling for loop"))?;
+                    end.ast_type.satisfies_or_err(&start.ast_type).e_when("compiling for loop")?;
+                    // the loop only counts up, a step that isn't positive would never reach the end
+                    let step = if let Some(step) = step {
+                        let v = step.build_value(env, None)?;
+                        v.ast_type.satisfies_or_err(&start.ast_type).e_when("compiling for loop")?;
+                        let zero = core::LLVMConstNull(start.llvm_type);
+                        if core::LLVMIsAConstantInt(v.llvm_value).is_null() {
+                            let predicate = if signed { LLVMIntPredicate::LLVMIntSGT } else { LLVMIntPredicate::LLVMIntNE };
+                            let positive = core::LLVMBuildICmp(env.builder, predicate, v.llvm_value, zero, c_str_ptr!(env.value_name(None, "step.positive")));
+                            let fail_block = core::LLVMAppendBasicBlockInContext(env.context, env.current_function(), c_str_ptr!("for.step.fail"));
+                            let ok_block = core::LLVMAppendBasicBlockInContext(env.context, env.current_function(), c_str_ptr!("for.step.ok"));
+                            core::LLVMBuildCondBr(env.builder, positive, ok_block, fail_block);
+                            core::LLVMPositionBuilderAtEnd(env.builder, fail_block);
+                            env.build_fail(&step.2, "step of for loop is not positive");
+                            core::LLVMPositionBuilderAtEnd(env.builder, ok_block);
+                        } else if core::LLVMConstIntGetZExtValue(v.llvm_value) == 0 {
+                            return Err(ParseET::CompilationError("step of for loop must not be zero".to_string()).at(step.2.clone()).when("compiling for loop"))
+                        } else if signed && core::LLVMConstIntGetSExtValue(v.llvm_value) < 0 {
+                            return Err(ParseET::CompilationError("step of for loop must be positive, ranges count up".to_string()).at(step.2.clone()).when("compiling for loop"))
+                        }
+                        v.llvm_value
+                    } else {
+                        core::LLVMConstInt(start.llvm_type, 1, false as LLVMBool)
+                    };
+                    let function = env.current_function();
+                    let counter = env.build_entry_alloca(start.llvm_type, c_str_ptr!(var.0));
+                    core::LLVMBuildStore(env.builder, start.llvm_value, counter);
+                    let cond_block = core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!("for.cond"));
+                    let body_block = core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!("for.body"));
+                    let step_block = core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!("for.step"));
+                    let end_block = core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!("for.end"));
+                    core::LLVMBuildBr(env.builder, cond_block);
+
+                    core::LLVMPositionBuilderAtEnd(env.builder, cond_block);
+                    let current = core::LLVMBuildLoad2(env.builder, start.llvm_type, counter, c_str_ptr!(var.0));
+                    let predicate = match (signed, inclusive) {
+                        (true, true) => LLVMIntPredicate::LLVMIntSLE,
+                        (true, false) => LLVMIntPredicate::LLVMIntSLT,
+                        (false, true) => LLVMIntPredicate::LLVMIntULE,
+                        (false, false) => LLVMIntPredicate::LLVMIntULT,
+                    };
+                    let cond = core::LLVMBuildICmp(env.builder, predicate, current, end.llvm_value, c_str_ptr!(env.value_name(None, "cmp")));
+                    core::LLVMBuildCondBr(env.builder, cond, body_block, end_block);
+
+                    core::LLVMPositionBuilderAtEnd(env.builder, body_block);
+                    env.push_stack(false, false);
+                    // a copy of the counter, so the loop variable can't change the iteration
+                    let loop_var = env.build_local(Variable::value(start.ast_type.clone(), start.llvm_type, current), &var.0);
+                    env.stack.last_mut().unwrap().vars.insert(var.0.clone(), loop_var);
+                    env.loops.push(LoopEnv { label: label.as_ref().map(|l| l.0.clone()), continue_block: step_block, break_block: end_block, depth: env.stack.len() - 1 });
+                    let built = body.build(env, None);
+                    env.loops.pop();
+                    env.pop_stack();
+                    built?;
+                    env.build_br_if_open(step_block);
+
+                    // stepping past the end could overflow, like the last step of `0..=255u8`, so the distance to the end is checked first.
+                    // The counter never exceeds the end, the distance fits the unsigned type of the same width
+                    core::LLVMPositionBuilderAtEnd(env.builder, step_block);
+                    let remaining = core::LLVMBuildSub(env.builder, end.llvm_value, current, c_str_ptr!(env.value_name(None, "remaining")));
+                    let predicate = if *inclusive { LLVMIntPredicate::LLVMIntUGT } else { LLVMIntPredicate::LLVMIntUGE };
+                    let done = core::LLVMBuildICmp(env.builder, predicate, step, remaining, c_str_ptr!(env.value_name(None, "done")));
+                    let next_block = core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!("for.next"));
+                    core::LLVMBuildCondBr(env.builder, done, end_block, next_block);
+                    core::LLVMPositionBuilderAtEnd(env.builder, next_block);
+                    let next = core::LLVMBuildAdd(env.builder, current, step, c_str_ptr!(env.value_name(None, "next")));
+                    core::LLVMBuildStore(env.builder, next, counter);
+                    core::LLVMBuildBr(env.builder, cond_block);
+
+                    core::LLVMPositionBuilderAtEnd(env.builder, end_block);
+                    env.unit(self.2.clone())
+                }
+                Expr::While(label, cond, body) => {
+                    let function = env.current_function();
+                    let cond_block = core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!("while.cond"));
+                    let body_block = core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!("while.body"));
+                    let end_block = core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!("while.end"));
+                    core::LLVMBuildBr(env.builder, cond_block);
+
+                    core::LLVMPositionBuilderAtEnd(env.builder, cond_block);
+                    let c = cond.build_value(env, None)?;
+                    if !c.ast_type.is_bool() {
+                        return Err(ParseET::TypeError("bool".to_string(), c.ast_type.print()).at(cond.2.clone()).when("compiling loop condition"))
+                    }
+                    core::LLVMBuildCondBr(env.builder, c.llvm_value, body_block, end_block);
+
+                    core::LLVMPositionBuilderAtEnd(env.builder, body_block);
+                    env.push_stack(false, false);
+                    env.loops.push(LoopEnv { label: label.as_ref().map(|l| l.0.clone()), continue_block: cond_block, break_block: end_block, depth: env.stack.len() - 1 });
+                    let built = body.build(env, None);
+                    env.loops.pop();
+                    env.pop_stack();
+                    built?;
+                    env.build_br_if_open(cond_block);
+
+                    core::LLVMPositionBuilderAtEnd(env.builder, end_block);
+                    env.unit(self.2.clone())
+                }
+                Expr::Defer(expr) => {
+                    if expr.1.diverges() {
+                        return Err(ParseET::CompilationError("deferred expression can't leave its scope".to_string()).at(expr.2.clone()).when("compiling defer"))
+                    }
+                    env.stack.last_mut().unwrap().defers.push((**expr).clone());
+                    env.unit(self.2.clone())
+                }
+                Expr::ForIn(..) => return Err(ParseET::InternalError("for loop over iterable was not desugared".to_string()).at(self.2.clone())),
+                Expr::Const(_) | Expr::Static(_) => return Err(ParseET::InternalError("local item was not hoisted to its module".to_string()).at(self.2.clone())),
+                Expr::Cfg(_) => return Err(ParseET::InternalError("cfg! was not folded while parsing".to_string()).at(self.2.clone())),
+                Expr::Error => return Err(ParseET::ParsingError("code that didn't parse can't be compiled".to_string()).at(self.2.clone())),
+                // logical not of bools, bitwise not of integers
+                Expr::UnaryOp(Operator(Op::Not, _), expr) => {
+                    let v = expr.build_value(env, None)?;
+                    if !v.ast_type.is_bool() && v.ast_type.int_signedness().is_none() {
+                        return Err(ParseET::TypeError("bool or integer".to_string(), v.ast_type.print()).at(expr.2.clone()).when("compiling not"))
+                    }
+                    Variable::value(v.ast_type, v.llvm_type, core::LLVMBuildNot(env.builder, v.llvm_value, c_str_ptr!(env.value_name(ret_name, "not"))))
+                }
+                Expr::UnaryOp(Operator(Op::Sub, _), expr) => {
+                    let v = expr.build_value(env, None)?;
+                    Self::build_neg(env, ret_name, v, expr)?
+                }
+                Expr::BinaryOp(Operator(op @ (Op::Add | Op::Sub | Op::Mul | Op::Div | Op::Mod), _), left, right) => {
+                    let (l, r) = Self::build_operands(env, left, right).e_when("compiling arithmetic")?;
+                    Self::build_arithmetic(env, ret_name, op, l, r, left, right)?
+                }
+                // the right side is only evaluated if the left one doesn't decide the result
+                Expr::BinaryOp(Operator(op @ (Op::And | Op::Or), _), left, right) => {
+                    let kind = if *op == Op::And { "and" } else { "or" };
+                    let bool_operand = |v: &Variable, operand: &Expression| if v.ast_type.is_bool() { Ok(()) } else {
+                        Err(ParseET::TypeError("bool".to_string(), v.ast_type.print()).at(operand.2.clone()).when(format!("compiling logical {kind}")))
+                    };
+                    let l = left.build_value(env, None)?;
+                    bool_operand(&l, left)?;
+                    let function = env.current_function();
+                    let left_block = core::LLVMGetInsertBlock(env.builder);
+                    let right_block = core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!(format!("{kind}.rhs")));
+                    let end_block = core::LLVMAppendBasicBlockInContext(env.context, function, c_str_ptr!(format!("{kind}.end")));
+                    match op {
+                        Op::And => core::LLVMBuildCondBr(env.builder, l.llvm_value, right_block, end_block),
+                        _ => core::LLVMBuildCondBr(env.builder, l.llvm_value, end_block, right_block)
+                    };
+                    core::LLVMPositionBuilderAtEnd(env.builder, right_block);
+                    let r = right.build_value(env, None)?;
+                    bool_operand(&r, right)?;
+                    let mut incoming = vec![(core::LLVMConstInt(l.llvm_type, (*op == Op::Or) as c_ulonglong, false as LLVMBool), left_block)];
+                    if env.is_block_open() {
+                        incoming.push((r.llvm_value, core::LLVMGetInsertBlock(env.builder)));
+                        core::LLVMBuildBr(env.builder, end_block);
+                    }
+                    core::LLVMPositionBuilderAtEnd(env.builder, end_block);
+                    let phi = core::LLVMBuildPhi(env.builder, l.llvm_type, c_str_ptr!(env.value_name(ret_name, kind)));
+                    let (mut values, mut blocks): (Vec<_>, Vec<_>) = incoming.into_iter().unzip();
+                    core::LLVMAddIncoming(phi, values.as_mut_ptr(), blocks.as_mut_ptr(), values.len() as c_uint);
+                    Variable::value(l.ast_type, l.llvm_type, phi)
+                }
+                Expr::BinaryOp(Operator(op, _), left, right) if op.is_comparison() => {
+                    let (l, r) = Self::build_operands(env, left, right).e_when("compiling comparison")?;
+                    // bools compare like the unsigned integers 0 and 1
+                    let signed = match l.ast_type.int_signedness() {
+                        Some(signed) => signed,
+                        None if l.ast_type.is_bool() => false,
+                        None => return Err(ParseET::TypeError("integer or bool".to_string(), l.ast_type.print()).at(left.2.clone()).when("compiling comparison"))
+                    };
+                    let predicate = match (op, signed) {
+                        (Op::Eq, _) => LLVMIntPredicate::LLVMIntEQ,
+                        (Op::Ne, _) => LLVMIntPredicate::LLVMIntNE,
+                        (Op::Lt, true) => LLVMIntPredicate::LLVMIntSLT,
+                        (Op::Lt, false) => LLVMIntPredicate::LLVMIntULT,
+                        (Op::Gt, true) => LLVMIntPredicate::LLVMIntSGT,
+                        (Op::Gt, false) => LLVMIntPredicate::LLVMIntUGT,
+                        (Op::Le, true) => LLVMIntPredicate::LLVMIntSLE,
+                        (Op::Le, false) => LLVMIntPredicate::LLVMIntULE,
+                        (Op::Ge, true) => LLVMIntPredicate::LLVMIntSGE,
+                        (Op::Ge, false) => LLVMIntPredicate::LLVMIntUGE,
+                        _ => unreachable!("only comparisons get here")
+                    };
+                    Variable::value(Type(Ty::Single(vec![], Item::new(&vec!["bool"], self.2.clone())), self.2.clone()), core::LLVMInt1TypeInContext(env.context),
+                        core::LLVMBuildICmp(env.builder, predicate, l.llvm_value, r.llvm_value, c_str_ptr!(env.value_name(ret_name, "cmp"))))
+                }
+                Expr::Break(label) | Expr::Continue(label) => {
+                    let is_break = matches!(self.1, Expr::Break(_));
+                    let what = if is_break { "break" } else { "continue" };
+                    let lp = env.get_loop(label.as_ref(), &self.2).e_when(format!("compiling {what}"))?;
+                    let (target, depth) = (if is_break { lp.break_block } else { lp.continue_block }, lp.depth);
+                    env.build_defers(depth)?;
+                    core::LLVMBuildBr(env.builder, target);
+                    env.unit(self.2.clone())
+                }
+                Expr::VarAssign(target, op, value) => {
+                    let place = target.build(env, None)?;
+                    match (place.kind, &place.global) {
+                        (VarKind::Value, Some(Global::Const(name, def))) =>
+                            return Err(ParseET::CompilationError(format!("cannot assign to constant {name}")).ats(vec![target.2.clone(), def.clone()]).when("compiling assignment")),
+                        (_, Some(Global::Const(name, def))) =>
+                            return Err(ParseET::CompilationError(format!("cannot assign to the memory of constant {name}, constants are read only")).ats(vec![target.2.clone(), def.clone()]).when("compiling assignment")),
+                        (VarKind::Place, Some(Global::Static(name, def))) => {
+                            if !env.stack.last().unwrap().unsafe_ctx {
+                                return Err(ParseET::UnsafeError(format!("assignment to static {name}"), Some(self.print())).ats(vec![target.2.clone(), def.clone()]))
+                            }
+                            env.use_unsafe();
+                        }
+                        (VarKind::Function, _) =>
+                            return Err(ParseET::CompilationError("cannot assign to a function".to_string()).at(target.2.clone()).when("compiling assignment")),
+                        _ => ()
+                    }
+                    if let Some(name) = &place.immutable {
+                        return Err(ParseET::ImmutableAssign(name.0.clone()).ats(vec![target.2.clone(), name.1.clone()])
+                            .suggest("make it mutable", name.1.clone(), format!("mut {}", name.0)).when("compiling assignment"))
+                    }
+                    // values are assigned to a temporary, like any other place they are built as
+                    let place = match place.kind {
+                        VarKind::Value => env.build_local(place, &env.value_name(None, "tmp")),
+                        _ => place
+                    };
+                    let v = value.build_expecting(env, None, &place.ast_type).e_when("compiling assignment")?;
+                    // `a[f()] += 1` builds the place once, reading and writing through the same pointer
+                    let v = match op {
+                        Some(Operator(op, _)) => {
+                            let current = env.load(place.clone(), None);
+                            Self::build_arithmetic(env, None, op, current, v, target, value).e_when("compiling assignment")?
+                        }
+                        None => v
+                    };
+                    core::LLVMBuildStore(env.builder, v.llvm_value, place.llvm_value);
+                    env.unit(self.2.clone())
+                }
+                Expr::Return(expr) => {
+                    let ret_type = env.ret_type.clone().ok_or_else(|| ParseET::CompilationError("return outside of function".to_string()).at(self.2.clone()))?;
+                    let v = match expr {
+                        Some(expr) => expr.build_expecting(env, None, &ret_type).e_when("compiling return")?,
+                        None => {
+                            let v = env.unit(self.2.clone());
+                            v.ast_type.satisfies_or_err(&ret_type).e_when("compiling return")?;
+                            v
+                        }
+                    };
+                    env.build_defers(env.function_depth())?;
+                    if let Some(slot) = env.sret {
+                        core::LLVMBuildStore(env.builder, v.llvm_value, slot);
+                        core::LLVMBuildRetVoid(env.builder);
+                    } else if v.ast_type.0.is_empty() {
+                        core::LLVMBuildRetVoid(env.builder);
+                    } else {
+                        core::LLVMBuildRet(env.builder, v.llvm_value);
+                    }
                     v
                 }
-                //Expr::BinaryOp(_, _, _) => {}
-                //Expr::UnaryOp(_, _) => {}
-                //Expr::VarAssign(_, _, _) => {}
-                _ => unimplemented!()
+                Expr::UnaryOp(op, _) | Expr::BinaryOp(op, _, _) =>
+                    return Err(ParseET::CompilationError(format!("operator {} is not supported here", op.print())).at(op.1.clone()))
             })
         };
         if self.0.contains_key("unsafe") {
             env.stack.last_mut().unwrap().unsafe_ctx = outer_unsafe;
+            env.leave_unsafe();
+        }
+        if r.is_ok() && !env.is_block_open() {
+            env.enter_dead_block();
+        }
+        if let Ok(v) = &r {
+            env.expr_types.record(&self.2, &v.ast_type);
+            if env.args.verify_internal {
+                v.verify_internal(&self.2)?;
+            }
         }
         r
     }
 }
 
 impl Block {
-    pub(crate) fn build(&self, env: &mut LLVMModGenEnv) -> Result<(Variable, Span), ParseError> {
+    /// Builds the statements, the value of the block is that of the last one if it has no `;`, typed by `expected`.
+    pub(crate) fn build(&self, env: &mut LLVMModGenEnv, expected: Option<&Type>) -> Result<(Variable, Span), ParseError> {
         let mut ret = None;
         for (i, stmt) in self.0.iter().enumerate() {
-            let r = stmt.0.build(env, None)?;
-            if let Expr::Return(_) = stmt.0.1 {
-                ret = Some((r, stmt.2.clone()));
+            let r = match expected {
+                Some(ty) if self.0.len() == i + 1 && !stmt.1 && !matches!(stmt.0.1, Expr::Return(_)) => stmt.0.build_expecting(env, None, ty)?,
+                _ => stmt.0.build(env, None)?
+            };
+            if env.leave_dead_block() {
+                // the rest of the block can't be reached, neither can its defers
+                if let Expr::Return(_) = stmt.0.1 {
+                    ret = Some((r, stmt.2.clone()));
+                }
                 break
             }
-            if !stmt.1 {
-                ret = Some((r, stmt.2.clone()));
-                if self.0.len() != i + 1 {
-                    return Err(ParseET::CompilationError(format!("returning expression needs to be at end of block")).at(stmt.2.clone()).when("compiling block"))
+            if stmt.1 {
+                // still built for its side effects, only the value is dropped
+                continue
+            }
+            if self.0.len() != i + 1 {
+                if stmt.0.1.is_block_like() {
+                    if !r.ast_type.0.is_empty() {
+                        return Err(ParseET::CompilationError(format!("expected (), found {}, add a ; to discard the value", r.ast_type.print())).at(stmt.2.clone()).when("compiling block"))
+                    }
+                    continue
                 }
-                break
+                return Err(ParseET::CompilationError("returning expression needs to be at end of block".to_string()).at(stmt.2.clone()).when("compiling block"))
             }
+            ret = Some((env.load(r, None), stmt.2.clone()));
+            break
+        }
+        if env.is_block_open() {
+            env.build_defers(env.stack.len() - 1)?;
         }
         ret = ret.map(|(mut v, mut l)| {
             std::mem::swap(&mut v.ast_type.1, &mut l);
             (v, l)
         });
-        unsafe {Ok(ret.unwrap_or_else(||(Variable {
-            ast_type: Type(Ty::Tuple(vec![]), self.1.end().span()),
-            llvm_type: core::LLVMVoidType(),
-            llvm_value: *[].as_mut_ptr(),
-        }, self.1.end().span())))}
+        Ok(ret.unwrap_or_else(||(env.unit(self.1.end().span()), self.1.end().span())))
     }
 }
 
@@ -247,75 +1031,172 @@ impl Type {
             Ok(match &self.0 {
                 Ty::Single(generics, base_type) => {
                     if generics.len() > 0 || base_type.0.len() > 1 {
-                        panic!("type was not correctly resolved")
+                        return Err(ParseET::CompilationError(format!("type {} can't be lowered, generic and qualified types are not supported yet", self.print())).at(self.1.clone()))
                     }
-                    match base_type.0.first().unwrap().0.as_str() {
-                        "u8" | "i8" => core::LLVMInt8Type(),
-                        "u16" | "i16" => core::LLVMInt16Type(),
-                        "u32" | "i32" => core::LLVMInt32Type(),
-                        "u64" | "i64" => core::LLVMInt64Type(),
-                        "u128" | "i128" => core::LLVMInt8Type(),
+                    let name = &base_type.0.first().unwrap().0;
+                    if env.opaque_types.contains_key(name) {
+                        return Err(ParseET::CompilationError(format!("extern type {name} has no known size, it can only be used behind a pointer")).at(self.1.clone()))
+                    }
+                    match name.as_str() {
+                        "bool" => core::LLVMInt1TypeInContext(env.context),
+                        "u8" | "i8" => core::LLVMInt8TypeInContext(env.context),
+                        "u16" | "i16" => core::LLVMInt16TypeInContext(env.context),
+                        "u32" | "i32" => core::LLVMInt32TypeInContext(env.context),
+                        "u64" | "i64" => core::LLVMInt64TypeInContext(env.context),
+                        "u128" | "i128" => core::LLVMInt128TypeInContext(env.context),
                         "uptr" | "iptr" => {
                             #[cfg(target_pointer_width = "16")]
-                                let t = core::LLVMInt8Type();
+                                let t = core::LLVMInt8TypeInContext(env.context);
                             #[cfg(target_pointer_width = "32")]
-                                let t = core::LLVMInt32Type();
+                                let t = core::LLVMInt32TypeInContext(env.context);
                             #[cfg(target_pointer_width = "64")]
-                                let t = core::LLVMInt64Type();
+                                let t = core::LLVMInt64TypeInContext(env.context);
                             t
                         }
                         _ => unimplemented!("primitive type not figured out yet, come back tomorrow")
                     }
                 }
-                Ty::RawPointer => core::LLVMPointerType(core::LLVMVoidType(), 0), // TODO: replace 0 with adapting value
-                Ty::Pointer(ty) => core::LLVMPointerType(ty.llvm_type(env)?, 0), // TODO: replace 0 with adapting value
+                // like c's void*, which llvm spells i8*
+                Ty::RawPointer => core::LLVMPointerType(core::LLVMInt8TypeInContext(env.context), 0), // TODO: replace 0 with adapting value
+                Ty::Pointer(ty) => core::LLVMPointerType(ty.pointee_llvm_type(env)?, 0), // TODO: replace 0 with adapting value
                 Ty::Array(ty, usize) => core::LLVMArrayType(ty.llvm_type(env)?, *usize as c_uint),
                 Ty::Slice(ty) => Type(Ty::Array(ty.clone(), 0), self.1.clone()).llvm_type(env)?,
                 Ty::Tuple(tys) => {
                     if tys.len() > 0 {
-                        *tys.iter().map(|ty|ty.llvm_type(env)).collect::<Result<Vec<_>, ParseError>>()?.as_mut_ptr()
+                        let mut elems = tys.iter().map(|ty|ty.llvm_type(env)).collect::<Result<Vec<_>, ParseError>>()?;
+                        core::LLVMStructTypeInContext(env.context, elems.as_mut_ptr(), elems.len() as c_uint, false as LLVMBool)
                     } else {
-                        core::LLVMVoidType()
+                        core::LLVMVoidTypeInContext(env.context)
                     }
                 },
                 Ty::Signature(_, _, _, _) => unimplemented!("signature types to llvm type not implemented yet")
             })
         }
     }
+
+    /// Like [`Type::llvm_type`], but allows extern types, which are lowered to their opaque struct.
+    fn pointee_llvm_type(&self, env: &mut LLVMModGenEnv) -> Result<prelude::LLVMTypeRef, ParseError> {
+        if let Ty::Single(generics, base_type) = &self.0 {
+            if let ([], [name]) = (generics.as_slice(), base_type.0.as_slice()) {
+                if let Some(ty) = env.opaque_types.get(&name.0) {
+                    return Ok(*ty)
+                }
+            }
+        }
+        self.llvm_type(env)
+    }
 }
 
 impl AstLiteral {
+    /// Integer literals are stored as the two's complement bits of their value,
+    /// lowering them to fewer bits would silently drop the rest.
+    fn check_int_range(&self, num: u128, ty: &Type, bits: u32) -> Result<(), ParseError> {
+        let text = match &self.0 {
+            Literal::Number(_, suffix, Some(text)) => suffix.as_ref().and_then(|suffix| text.strip_suffix(&suffix.to_string()))
+                .map(|digits| digits.trim_end_matches('\''))
+                .unwrap_or(text).to_string(),
+            _ if self.is_negative() => format!("-{}", num.wrapping_neg()),
+            _ => num.to_string()
+        };
+        let (fits, bound) = match (ty.int_signedness(), self.is_negative()) {
+            (Some(true), true) => (num.wrapping_neg() <= 1 << (bits - 1), format!("min -{}", 1u128 << (bits - 1))),
+            (Some(true), false) => (num < 1 << (bits - 1), format!("max {}", (1u128 << (bits - 1)) - 1)),
+            _ => {
+                let max = u128::MAX >> (128 - bits);
+                (num <= max, format!("max {max}"))
+            }
+        };
+        if fits { Ok(()) } else {
+            Err(ParseET::LiteralError(self.0.clone(), format!("literal {text} does not fit in {} ({bound})", ty.print())).at(self.1.clone()).when("compiling literal"))
+        }
+    }
+
     pub(crate) fn llvm_literal(&self, env: &mut LLVMModGenEnv) -> Result<Variable, ParseError>{
-        Ok(Variable{
-            ast_type: self.get_type()?,
-            llvm_type: self.get_type()?.llvm_type(env)?,
-            llvm_value: unsafe {
+        let size = self.byte_size();
+        if size > env.args.max_literal_bytes {
+            return Err(ParseET::LiteralError(self.0.clone(), format!("literal takes up {size} bytes, exceeding the limit of {} bytes (see --max-literal-bytes)", env.args.max_literal_bytes))
+                .at(self.1.clone()).when("compiling literal"))
+        }
+        if matches!(&self.0, Literal::Char(c) if !c.is_ascii()) {
+            return Err(ParseET::CompilationError(format!("char literal {} is not ascii, chars are a single byte (u8)", self.0.print()))
+                .at(self.1.clone()).when("compiling literal"))
+        }
+        if let Literal::Number(NumLit::Integer(num), _, _) = &self.0 {
+            let ty = self.get_type()?;
+            self.check_int_range(*num, &ty, unsafe { core::LLVMGetIntTypeWidth(ty.llvm_type(env)?) })?;
+        }
+        Ok(Variable::value(
+            self.get_type()?,
+            self.get_type()?.llvm_type(env)?,
+            unsafe {
             match &self.0 {
-                Literal::String(s) => AstLiteral::llvm_literal(
-                    &AstLiteral(Literal::Array(
-                        {
-                            let mut s = s.clone();
-                            s.push('\0');
-                            s.chars().map(|c| AstLiteral(Literal::Char(c), self.1.clone())).collect()
-                        },
-                        Type(Ty::Single(vec![], Item::new(&vec!["u8"], self.1.clone())), self.1.clone()),
-                        s.len() + 1), self.1.clone()), env)?.llvm_value,
-                Literal::Char(c) => core::LLVMConstInt(core::LLVMInt8Type(), *c as u8 as c_ulonglong, false as LLVMBool),
-                Literal::Number(NumLit::Integer(num), _) => {
-                    core::LLVMConstInt( self.get_type()?.llvm_type(env)?, *num as u8 as c_ulonglong, false as LLVMBool)
+                Literal::String(s) => core::LLVMConstStringInContext(env.context, s.as_ptr() as *const c_char, s.len() as c_uint, false as LLVMBool),
+                Literal::Char(c) => core::LLVMConstInt(core::LLVMInt8TypeInContext(env.context), *c as u8 as c_ulonglong, false as LLVMBool),
+                Literal::Number(NumLit::Integer(num), _, _) => {
+                    // low word first, so 128 bit values keep their upper half.
+                    // signed values are their two's complement bits, truncated to the width of the type, so there is no sign flag
+                    let words = [*num as u64, (*num >> 64) as u64];
+                    core::LLVMConstIntOfArbitraryPrecision(self.get_type()?.llvm_type(env)?, words.len() as c_uint, words.as_ptr())
                 }
-                Literal::Bool(b) => core::LLVMConstInt(core::LLVMInt1Type(), *b as c_ulonglong, false as LLVMBool),
-                Literal::Array(arr, elem_ty , len) =>
-                    core::LLVMConstArray(elem_ty.llvm_type(env)?,
+                Literal::Bool(b) => core::LLVMConstInt(core::LLVMInt1TypeInContext(env.context), *b as c_ulonglong, false as LLVMBool),
+                Literal::Array(arr, _, len) =>
+                    core::LLVMConstArray(core::LLVMGetElementType(self.get_type()?.llvm_type(env)?),
                                          arr.iter().map(|e|e.llvm_literal(env).map(|v|v.llvm_value)).collect::<Result<Vec<_>, ParseError>>()?.as_mut_ptr(),
                                          *len as c_uint),
+                // zero initialized repeats are lowered to a single null constant. Everything else lowers the element once,
+                // but llvm takes a handle to it per element, which is counted against the limit like the bytes of the literal
+                Literal::Repeat(elem, count) => if elem.is_zero() {
+                    core::LLVMConstNull(self.get_type()?.llvm_type(env)?)
+                } else {
+                    let handles = count.saturating_mul(std::mem::size_of::<LLVMValueRef>());
+                    if handles > env.args.max_literal_bytes {
+                        return Err(ParseET::LiteralError(self.0.clone(), format!("lowering the {count} elements of the literal takes up {handles} bytes, exceeding the limit of {} bytes (see --max-literal-bytes)",
+                            env.args.max_literal_bytes)).at(self.1.clone()).when("compiling literal"))
+                    }
+                    let elem = elem.llvm_literal(env)?;
+                    core::LLVMConstArray(elem.llvm_type, vec![elem.llvm_value; *count].as_mut_ptr(), *count as c_uint)
+                },
+                Literal::Bytes(b) => core::LLVMConstStringInContext(env.context, b.as_ptr() as *const c_char, b.len() as c_uint, true as LLVMBool),
                 _ => unimplemented!("ty to llvm ty")
             }
-        }})
+        }))
+    }
+
+    fn is_zero(&self) -> bool {
+        match &self.0 {
+            Literal::Char(c) => *c == '\0',
+            Literal::Number(NumLit::Integer(i), _, _) => *i == 0,
+            Literal::Number(NumLit::Float(f), _, _) => *f == 0.0,
+            Literal::Bool(b) => !*b,
+            Literal::Array(elems, _, _) => elems.iter().all(|e| e.is_zero()),
+            Literal::Repeat(elem, _) => elem.is_zero(),
+            Literal::Bytes(b) => b.iter().all(|b| *b == 0),
+            Literal::String(_) => false
+        }
     }
 }
 
 impl Type {
+    /// Whether this is the plain `bool` type
+    pub(crate) fn is_bool(&self) -> bool {
+        matches!(&self.0, Ty::Single(generics, base_type) if generics.is_empty() && base_type.0.len() == 1 && base_type.0[0].0 == "bool")
+    }
+
+    /// `Some(true)` for signed and `Some(false)` for unsigned integer types, `None` for anything else
+    pub(crate) fn int_signedness(&self) -> Option<bool> {
+        match &self.0 {
+            Ty::Single(generics, base_type) if generics.is_empty() && base_type.0.len() == 1 =>
+                match base_type.0[0].0.as_str() {
+                    "i8" | "i16" | "i32" | "i64" | "i128" | "iptr" => Some(true),
+                    "u8" | "u16" | "u32" | "u64" | "u128" | "uptr" => Some(false),
+                    _ => None
+                },
+            _ => None
+        }
+    }
+
+    /// Whether a value of this type can be used where `other` is expected, like `&u8` where `&` is,
+    /// or `(&u8, i32)` where `(&, i32)` is. Integers never convert, `u8` doesn't satisfy `i8`.
     pub(crate) fn satisfies(&self, other: &Type) -> bool {
         if self == other { true } else {
             match (&self.0, &other.0) {
@@ -326,7 +1207,7 @@ impl Type {
                 (Ty::Array(t1, l1), Ty::Array(t2, l2)) => t1.satisfies(t2) && l1 == l2,
                     (Ty::Array(t1, _l1), Ty::Slice(t2)) => t1.satisfies(t2), // array satisfies slice
                 (Ty::Slice(t1), Ty::Slice(t2)) => t1.satisfies(t2),
-                (Ty::Tuple(t1), Ty::Tuple(t2)) => t1.iter().zip(t2).all(|(t1, t2)|t1.satisfies(t2)),
+                (Ty::Tuple(t1), Ty::Tuple(t2)) => t1.len() == t2.len() && t1.iter().zip(t2).all(|(t1, t2)|t1.satisfies(t2)),
                 (Ty::Signature(a1, r1, unsafe_fn1, vararg1), Ty::Signature(a2, r2, unsafe_fn2, vararg2)) =>
                     ((a1.len() == a2.len() && vararg1 == vararg2) || *vararg2) &&
                     a1.iter().zip(a2).all(|(t1, t2) | t1.satisfies(t2)) &&
@@ -344,4 +1225,484 @@ impl Type {
             Err(ParseET::TypeError(other.print(), self.print()).ats(vec![self.1.clone(), other.1.clone()]))
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::testing::{allocated_by, build, build_err, ir, optimized_ir, run};
+
+    #[test]
+    fn tuple_is_struct_of_its_fields() {
+        let ir = ir("#[export]\nfn first(t: (i32, u8)) -> i32 { t.0 }\nfn main() {}", &["--shared"]);
+        assert!(ir.contains("define i32 @first({ i32, i8 }"), "{ir}");
+    }
+
+    #[test]
+    fn integer_literals_keep_all_bits() {
+        let ir = ir("#[export]\nfn a() -> u16 { 256u16 }\n#[export]\nfn b() -> u32 { 70000u32 }\n#[export]\nfn c() -> i32 { -1i32 }", &["--shared"]);
+        assert!(ir.contains("ret i16 256"), "{ir}");
+        assert!(ir.contains("ret i32 70000"), "{ir}");
+        assert!(ir.contains("ret i32 -1"), "{ir}");
+    }
+
+    #[test]
+    fn integer_literals_fit_their_type() {
+        ir("#[export]\nfn a() -> u8 { 255u8 }\n#[export]\nfn b() -> i8 { -128i8 }\n#[export]\nfn c() -> i8 { 127i8 }", &["--shared"]);
+        let err = build_err("#[export]\nfn a() -> u8 { 300u8 }", &["--shared"]);
+        assert!(err.contains("literal 300 does not fit in u8 (max 255)"), "{err}");
+        let err = build_err("#[export]\nfn a() -> i8 { -129i8 }", &["--shared"]);
+        assert!(err.contains("literal -129 does not fit in i8 (min -128)"), "{err}");
+        let err = build_err("#[export]\nfn a() -> i8 { 128i8 }", &["--shared"]);
+        assert!(err.contains("literal 128 does not fit in i8 (max 127)"), "{err}");
+    }
+
+    #[test]
+    fn unary_operators() {
+        let ir = ir("#[export]\nfn neg(x: i32) -> i32 { -x }\n#[export]\nfn lit() -> i32 { -(5i32) }\n#[export]\nfn not(x: u8) -> u8 { !x }", &["--shared"]);
+        assert!(ir.contains("sub i32 0, %load"), "{ir}");
+        assert!(ir.contains("ret i32 -5"), "{ir}");
+        assert!(ir.contains("xor i8 %load.0, -1"), "{ir}");
+        let err = build_err("#[export]\nfn neg(x: u32) -> u32 { -x }", &["--shared"]);
+        assert!(err.contains("expected signed integer found u32"), "{err}");
+    }
+
+    #[test]
+    fn assignment_respects_mutability() {
+        let ir = ir("#[export]\nfn f() -> i32 {\n    let mut x: i32 = 1i32;\n    x = 2i32;\n    x\n}", &["--shared"]);
+        assert!(ir.contains("store i32 2, i32* %x"), "{ir}");
+        let err = build_err("#[export]\nfn f() -> i32 {\n    let x: i32 = 1i32;\n    x = 2i32;\n    x\n}", &["--shared"]);
+        assert!(err.contains("cannot assign to immutable variable x"), "{err}");
+        // points at the declaration too
+        assert!(err.contains("<string>: 4:5") && err.contains("<string>: 3:9"), "{err}");
+    }
+
+    #[test]
+    fn unexported_functions_are_dropped_at_o1() {
+        let code = "fn helper(x: i32) -> i32 { x + 1i32 }\n#[export]\nfn shared(x: i32) -> i32 { x * 2i32 }\n#[export]\nfn f(x: i32) -> i32 { helper(x) + shared(x) }";
+        let ir = ir(code, &["--shared"]);
+        assert!(ir.contains("define internal i32 @helper(") && ir.contains("define i32 @shared("), "{ir}");
+        // inlined into its only caller, nothing else can call it
+        let optimized = optimized_ir(code, &["--shared"]);
+        assert!(!optimized.contains("@helper"), "{optimized}");
+        assert!(optimized.contains("@shared(") && optimized.contains("@f("), "{optimized}");
+    }
+
+    #[test]
+    fn functions_return_their_value() {
+        let ir = ir("#[export]\nfn four() -> i32 { 4i32 }\n#[export]\nfn unit() {}", &["--shared"]);
+        assert!(ir.contains("define i32 @four() {\nentry:\n  ret i32 4\n}"), "{ir}");
+        assert!(ir.contains("define void @unit() {\nentry:\n  ret void\n}"), "{ir}");
+    }
+
+    #[test]
+    fn unsuffixed_literals_take_the_expected_type() {
+        let ir = ir("fn take(x: u64) -> u64 { x }\n#[export]\nfn f() -> u32 {\n    let x: u32 = 5;\n    let y = take(7);\n    x + 1\n}", &["--shared"]);
+        assert!(ir.contains("store i32 5, i32* %x"), "{ir}");
+        assert!(ir.contains("call i64 @take(i64 7)"), "{ir}");
+        assert!(ir.contains("add i32 %load.0, 1"), "{ir}");
+        let err = build_err("#[export]\nfn f() {\n    let x = 5;\n}", &["--shared"]);
+        assert!(err.contains("cannot infer type of number literal, add a suffix or a type annotation") && err.contains("<string>: 3:13"), "{err}");
+    }
+
+    #[test]
+    fn char_literals_are_ascii_bytes() {
+        let ir = ir("#[export]\nfn a() -> u8 { 'a' }", &["--shared"]);
+        assert!(ir.contains("ret i8 97"), "{ir}");
+        for c in ["'€'", "'\\u{1F600}'"] {
+            let err = build_err(&format!("#[export]\nfn a() -> u8 {{ {c} }}"), &["--shared"]);
+            assert!(err.contains("is not ascii, chars are a single byte (u8)"), "{c}: {err}");
+        }
+    }
+
+    #[test]
+    fn char_literal_initializes_u8() {
+        let ir = ir("#[export]\nfn a() -> u8 {\n    let b: u8 = 'A';\n    b\n}", &["--shared"]);
+        assert!(ir.contains("store i8 65, i8* %b"), "{ir}");
+    }
+
+    #[test]
+    fn compound_assignment_through_places() {
+        let value = |body: &str| run(&format!("fn main() -> i32 {{\n{body}\n}}"), &[], &[]).code;
+        for (body, expected) in [
+            ("    let mut a = 1i32;\n    a += 2i32;\n    a", 3),
+            ("    let mut a = [1i32, 2i32, 3i32];\n    a[1] *= 5i32;\n    a[1]", 10),
+            ("    let mut t = (7i32, 1i32);\n    t.0 -= 2i32;\n    t.0", 5),
+            ("    let mut t = ((7i32, 9i32), 1i32);\n    t.0.1 %= 4i32;\n    t.0.1", 1),
+            ("    let mut t = (1i32, [1i32, 2i32]);\n    t.1[1] += 10i32;\n    t.1[1]", 12),
+            ("    let mut g = [[1i32, 2i32], [3i32, 9i32]];\n    g[1][1] /= 3i32;\n    g[1][1]", 3),
+            ("    let mut a = [1i32, 2i32];\n    let p = &a;\n    (*p)[0] += 4i32;\n    a[0]", 5),
+        ] {
+            assert_eq!(value(body), Some(expected), "{body}");
+        }
+        // arrays of tuples can't be written as literals, so indexing then accessing a field is only built
+        let ir = ir("#[export]\nfn f(a: [(i32, u8); 2]) -> u8 {\n    let mut b = a;\n    b[1].1 -= 1u8;\n    b[1].1\n}", &["--shared"]);
+        assert!(ir.contains("sub i8"), "{ir}");
+    }
+
+    /// Counts in a static from the main thread and a spawned one, `TAG` is the tag of the static
+    const THREADS: &str = "#[unsafe]\n#[extern(\"C\")]\nfn pthread_attr_init(attr: &) -> i32;\n\
+        #[unsafe]\n#[extern(\"C\")]\nfn pthread_create(thread: &u64, attr: &, start: &, arg: &) -> i32;\n\
+        #[unsafe]\n#[extern(\"C\")]\nfn pthread_join(thread: u64, ret: &) -> i32;\n\
+        TAGstatic COUNT: i32 = 0i32;\n\
+        fn worker(out: &i32) -> &i32 {\n    unsafe { COUNT = COUNT + 5i32; }\n    *out = COUNT;\n    out\n}\n\
+        fn main() -> i32 {\n    let thread = 0u64;\n    let attr = [0u64; 8];\n    let out = 0i32;\n    let ret = 0u64;\n    unsafe { COUNT = 1i32; }\n    unsafe { pthread_attr_init(&attr); }\n    unsafe { pthread_create(&thread, &attr, &worker, &out); }\n    unsafe { pthread_join(thread, &ret); }\n    COUNT * 10i32 + out\n}";
+
+    #[test]
+    fn thread_local_statics_are_per_thread() {
+        // the spawned thread adds to its own count, starting from the initializer
+        assert_eq!(run(&THREADS.replace("TAG", "#[thread_local]\n"), &[], &[]).code, Some(15));
+        assert_eq!(run(&THREADS.replace("TAG", ""), &[], &[]).code, Some(66));
+    }
+
+    #[test]
+    fn semicolon_discards_the_value() {
+        let code = "static CALLS: i32 = 0;\nfn bump() -> i32 {\n    unsafe { CALLS = CALLS + 1i32; }\n    5i32\n}\n\
+            fn main() -> i32 {\n    bump();\n    bump() + 1i32;\n    unsafe { CALLS }\n}";
+        assert_eq!(run(code, &[], &[]).code, Some(2));
+        let ir = ir("#[export]\nfn f() {\n    4i32;\n}\n#[export]\nfn g() -> i32 {\n    4i32\n}", &["--shared"]);
+        assert!(ir.contains("define void @f() {\nentry:\n  ret void\n}"), "{ir}");
+        assert!(ir.contains("define i32 @g() {\nentry:\n  ret i32 4\n}"), "{ir}");
+        // the discarded value is still checked
+        let err = build_err("#[export]\nfn f() {\n    1i32 + true;\n}", &["--shared"]);
+        assert!(err.contains("<string>: 3:5"), "{err}");
+        let err = build_err("#[export]\nfn f() -> i32 {\n    4i32;\n}", &["--shared"]);
+        assert!(err.contains("expected i32 found ()") && err.contains("<string>: 3:9"), "{err}");
+        let err = build_err("#[export]\nfn f() -> i32 {\n    4i32\n    5i32\n}", &["--shared"]);
+        assert!(err.contains("expected ';' after this statement, another one follows it"), "{err}");
+        let err = build_err("#[export]\nfn f() -> i32 {\n    { 4i32 }\n    5i32\n}", &["--shared"]);
+        assert!(err.contains("expected (), found i32, add a ; to discard the value") && err.contains("<string>: 3:5"), "{err}");
+    }
+
+    #[test]
+    fn compound_assignment_evaluates_indices_once() {
+        let code = "static CALLS: i32 = 0;\nfn next(i: u64) -> u64 {\n    unsafe { CALLS = CALLS + 1i32; }\n    i\n}\n\
+            fn main() -> i32 {\n    let mut g = [[1i32, 2i32], [3i32, 4i32]];\n    g[next(1u64)][next(0u64)] += 10i32;\n    g[1][0] + unsafe { CALLS } * 100i32\n}";
+        assert_eq!(run(code, &[], &[]).code, Some(213));
+    }
+
+    #[test]
+    fn compound_assignment_errors() {
+        let err = build_err("#[export]\nfn f() -> i32 {\n    let x = [1i32];\n    x[0] += 1i32;\n    x[0]\n}", &["--shared"]);
+        assert!(err.contains("cannot assign to immutable variable x") && err.contains("<string>: 4:5"), "{err}");
+        let err = build_err("#[export]\nfn f() -> bool {\n    let mut b = true;\n    b += false;\n    b\n}", &["--shared"]);
+        assert!(err.contains("expected integer found bool"), "{err}");
+        let err = build_err("#[export]\nfn f() -> i32 {\n    let mut x = 1i32;\n    x %= 0i32;\n    x\n}", &["--shared"]);
+        assert!(err.contains("division by zero") && err.contains("<string>: 4:10"), "{err}");
+    }
+
+    #[test]
+    fn for_loop_steps() {
+        let count = |range: &str| run(&format!("fn main() -> i32 {{\n    let mut n = 0i32;\n    for i in {range} {{\n        n += 1i32;\n    }}\n    n\n}}"), &[], &[]).code;
+        assert_eq!(count("0i32..10i32 step 3i32"), Some(4));
+        assert_eq!(count("0i32..=9i32 step 3i32"), Some(4));
+        assert_eq!(count("0i32..=10i32"), Some(11));
+        assert_eq!(count("5i32..5i32"), Some(0));
+        // the last step would wrap around to the start
+        assert_eq!(count("250u8..=255u8"), Some(6));
+        assert_eq!(count("200u8..=255u8 step 50u8"), Some(2));
+        assert_eq!(count("-5i8..=127i8 step 100i8"), Some(2));
+        let sum = run("fn main() -> i32 {\n    let mut sum = 0i32;\n    for i in 1i32..=10i32 step 2i32 {\n        sum += i;\n    }\n    sum\n}", &[], &[]);
+        assert_eq!(sum.code, Some(25));
+    }
+
+    #[test]
+    fn for_loop_step_must_be_positive() {
+        let err = build_err("#[export]\nfn f() {\n    for i in 0u8..10u8 step 0u8 {}\n}", &["--shared"]);
+        assert!(err.contains("step of for loop must not be zero") && err.contains("<string>: 3:29..3:31"), "{err}");
+        let err = build_err("#[export]\nfn f() {\n    for i in 0i8..10i8 step -1i8 {}\n}", &["--shared"]);
+        assert!(err.contains("step of for loop must be positive"), "{err}");
+        // steps only known at runtime are checked before the loop
+        let code = "fn count(step: i32) -> i32 {\n    let mut n = 0i32;\n    for i in 0i32..10i32 step step {\n        n += 1i32;\n    }\n    n\n}\nfn main() -> i32 {\n    count(0i32)\n}";
+        let zero = run(code, &[], &[]);
+        assert!(zero.code.is_none() && zero.stderr.starts_with("<string>:3: step of for loop is not positive\n"), "{zero:?}");
+        assert_eq!(run(&code.replace("count(0i32)", "count(4i32)"), &[], &[]).code, Some(3));
+    }
+
+    #[test]
+    fn labeled_loops() {
+        let code = |body: &str| format!("fn main() -> i32 {{\n    let mut n = 0i32;\n{body}\n    n\n}}");
+        let nested = "    'outer: for i in 0i32..10i32 {\n        let mut j = 0i32;\n        'inner: while j < 10i32 {\n            j += 1i32;\n            n += 1i32;\n            break 'outer;\n        }\n    }";
+        assert_eq!(run(&code(nested), &[], &[]).code, Some(1));
+        // skips the rest of both bodies, every round of the outer loop counts once
+        let skip = "    'outer: while n < 100i32 {\n        for j in 0i32..10i32 {\n            n += 1i32;\n            continue 'outer;\n        }\n        n += 1000i32;\n    }";
+        assert_eq!(run(&code(skip), &[], &[]).code, Some(100));
+        let unlabeled = "    for i in 0i32..3i32 {\n        'inner: for j in 0i32..3i32 {\n            n += 1i32;\n            break;\n        }\n    }";
+        assert_eq!(run(&code(unlabeled), &[], &[]).code, Some(3));
+    }
+
+    #[test]
+    fn unknown_loop_labels() {
+        let err = build_err("#[export]\nfn f() {\n    'a: for i in 0i32..3i32 {\n        break 'b;\n    }\n}", &["--shared"]);
+        assert!(err.contains("undefined loop label 'b") && err.contains("<string>: 4:15..4:16"), "{err}");
+        // labels of loops that were left can't be used
+        let err = build_err("#[export]\nfn f() {\n    'a: for i in 0i32..3i32 {}\n    for i in 0i32..3i32 {\n        continue 'a;\n    }\n}", &["--shared"]);
+        assert!(err.contains("undefined loop label 'a"), "{err}");
+        let err = build_err("#[export]\nfn f() {\n    'a: {\n        break 'a;\n    }\n}", &["--shared"]);
+        assert!(err.contains("expected a loop after the label") && err.contains("<string>: 3:9"), "{err}");
+    }
+
+    const GET: &str = "fn get(a: [i32; 3], i: u64) -> i32 {\n    a[i]\n}\n";
+
+    #[test]
+    fn constant_indices_are_checked_in_their_own_width() {
+        let index = |i: &str| build_err(&format!("#[export]\nfn f(a: [i32; 3]) -> i32 {{ a[{i}] }}"), &["--shared"]);
+        let err = index("18446744073709551617u128");
+        assert!(err.contains("expected integer of at most 64 bits found u128"), "{err}");
+        let err = index("18446744073709551615u64");
+        assert!(err.contains("index 18446744073709551615 is out of bounds for array of length 3"), "{err}");
+        let err = index("-1i32");
+        assert!(err.contains("index -1 is out of bounds for array of length 3"), "{err}");
+        let err = build_err("#[export]\nfn f(a: [i32; 3], i: u128) -> i32 { a[i] }", &["--shared"]);
+        assert!(err.contains("expected integer of at most 64 bits found u128"), "{err}");
+    }
+
+    #[test]
+    fn bounds_check_modes() {
+        let main = |i: &str| format!("{GET}fn main() -> i32 {{\n    get([1i32, 2i32, 3i32], {i})\n}}");
+        for mode in ["abort", "trap", "off"] {
+            let in_bounds = run(&main("2u64"), &["--bounds-checks", mode], &[]);
+            assert_eq!((in_bounds.code, in_bounds.stderr.as_str()), (Some(3), ""), "{mode}");
+        }
+        let aborted = run(&main("3u64"), &["--bounds-checks", "abort"], &[]);
+        assert!(aborted.code.is_none() && aborted.stderr.starts_with("<string>:2: index out of bounds\n"), "{aborted:?}");
+        let trapped = run(&main("3u64"), &["--bounds-checks", "trap"], &[]);
+        assert!(trapped.code.is_none() && !trapped.stderr.contains("index out of bounds"), "{trapped:?}");
+        // out of bounds is undefined without the check, so only the missing check is tested
+        let unchecked = ir(&main("3u64"), &["--bounds-checks", "off"]);
+        assert!(!unchecked.contains("in_bounds"), "{unchecked}");
+    }
+
+    #[test]
+    fn string_literals_are_utf8_bytes() {
+        let ir = ir("const S: &[u8; 4] = &\"aé\";\n#[export]\nfn f() -> &[u8; 4] { S }", &["--shared"]);
+        assert!(ir.contains("[4 x i8] c\"a\\C3\\A9\\00\""), "{ir}");
+    }
+
+    #[test]
+    fn zero_repeats_take_flat_memory() {
+        let code = |count: usize| format!("static ZEROS: [u8; {count}] = [0u8; {count}];\n#[export]\nfn first() -> u8 {{ ZEROS[0] }}");
+        let (small, small_bytes) = allocated_by(|| build(&code(16), &["--shared"]).is_ok());
+        let (large, large_bytes) = allocated_by(|| build(&code(16 * 1024 * 1024), &["--shared"]).is_ok());
+        assert!(small && large);
+        // the same up to the digits of the count
+        assert!(large_bytes < small_bytes + 1024, "{small_bytes} bytes for 16 zeros, {large_bytes} bytes for 16 MiB");
+    }
+
+    #[test]
+    fn repeats_count_their_element_handles() {
+        let code = |count: usize| format!("static ONES: [u8; {count}] = [1u8; {count}];\n#[export]\nfn first() -> u8 {{ ONES[0] }}");
+        let ir = ir(&code(4), &["--shared"]);
+        assert!(ir.contains("[4 x i8] c\"\\01\\01\\01\\01\""), "{ir}");
+        // 8 bytes of literal, but 64 bytes of handles
+        let err = build_err(&code(8), &["--shared", "--max-literal-bytes", "32"]);
+        assert!(err.contains("lowering the 8 elements of the literal takes up 64 bytes, exceeding the limit of 32 bytes"), "{err}");
+        let err = build_err(&code(16 * 1024 * 1024), &["--shared"]);
+        assert!(err.contains("exceeding the limit of 67108864 bytes"), "{err}");
+    }
+
+    #[test]
+    fn return_leaves_no_dead_blocks() {
+        let code = "fn mark(c: i32) {}\n#[export]\nfn f(n: i32) -> i32 {\n    defer mark(10i32);\n    let mut i = 0i32;\n    while true {\n        i += 1i32;\n        {\n            defer mark(46i32);\n            return i;\n        }\n    }\n    0i32\n}";
+        let ir = ir(code, &["--shared"]);
+        assert!(!ir.contains("dead") && !ir.contains("after."), "{ir}");
+        // each defer runs once on the way out, not again at the end of its block
+        assert_eq!(ir.matches("call void @mark(i32 46)").count(), 1, "{ir}");
+        assert_eq!(ir.matches("call void @mark(i32 10)").count(), 2, "{ir}");
+        let main = "fn main() -> i32 {\n    let mut n = 0i32;\n    while true {\n        n += 1i32;\n        {\n            {\n                return n + 6i32;\n            }\n        }\n    }\n    0i32\n}";
+        assert_eq!(run(main, &[], &[]).code, Some(7));
+    }
+
+    #[test]
+    fn constants_are_read_only() {
+        let code = |body: &str| format!("const S: &[u8; 4] = &\"abc\";\nstatic N: u8 = 0u8;\n#[export]\nfn f() -> u8 {{\n{body}\n}}");
+        let ir = ir(&code("    unsafe { N = S[1]; }\n    S[0] + N"), &["--shared"]);
+        assert!(ir.contains("constant [4 x i8] c\"abc\\00\""), "{ir}");
+        let err = build_err(&code("    S = &\"xyz\";\n    0u8"), &["--shared"]);
+        assert!(err.contains("cannot assign to constant S") && err.contains("<string>: 1:7\n") && err.contains("<string>: 5:5\n"), "{err}");
+        let err = build_err(&code("    S[0] = 1u8;\n    0u8"), &["--shared"]);
+        assert!(err.contains("cannot assign to the memory of constant S, constants are read only"), "{err}");
+        let err = build_err(&code("    N = 1u8;\n    N"), &["--shared"]);
+        assert!(err.contains("assignment to static N"), "{err}");
+    }
+
+    #[test]
+    fn blocks_as_arguments() {
+        let code = |arg: &str| format!("fn add(a: i32, b: i32) -> i32 {{\n    a + b\n}}\nfn main() -> i32 {{\n    add({arg}, 1i32)\n}}");
+        assert_eq!(run(&code("{\n        let x = 2i32;\n        x * 3i32\n    }"), &[], &[]).code, Some(7));
+        assert_eq!(run(&code("{ 4 }"), &[], &[]).code, Some(5));
+        let err = build_err(&code("{ true }"), &[]);
+        assert!(err.contains("expected i32 found bool") && err.contains("<string>: 5:11..5:14\n"), "{err}");
+    }
+
+    #[test]
+    fn tuple_elements() {
+        let code = "fn main() -> i32 {\n    let mut t = (1i32, (2i32, 3u8));\n    t.1.0 = 40i32;\n    let p = &t;\n    p.1.0 + t.0 + (t.1).0\n}";
+        assert_eq!(run(code, &[], &[]).code, Some(81));
+        let err = build_err("#[export]\nfn f(t: (i32, u8)) -> i32 {\n    t.2\n}", &["--shared"]);
+        assert!(err.contains("tuple (i32, u8) has 2 elements, there is no element 2") && err.contains("<string>: 3:5..3:7\n"), "{err}");
+        let err = build_err("#[export]\nfn f(t: i32) -> i32 {\n    t.0\n}", &["--shared"]);
+        assert!(err.contains("expected tuple found i32") && err.contains("<string>: 3:5\n"), "{err}");
+    }
+
+    #[test]
+    fn u128_literals_keep_all_bits() {
+        // u64::MAX + 6
+        let code = "static BIG: u128 = 18446744073709551621u128;\nfn main() -> i32 {\n    let mut n = 0i32;\n    \
+            while BIG - 18446744073709551615u128 == 6u128 {\n        n = 1i32;\n        break;\n    }\n    n\n}";
+        assert!(ir(code, &[]).contains("global i128 18446744073709551621"), "{}", ir(code, &[]));
+        assert_eq!(run(code, &[], &[]).code, Some(1));
+    }
+
+    #[test]
+    fn zero_length_arrays() {
+        let code = "static NONE: [u8; 0] = [1u8; 0];\nfn count(a: [i32; 0], b: &[u8]) -> [i32; 0] {\n    a\n}\n\
+            fn main() -> i32 {\n    let empty = [0i32; 0];\n    let back = count(empty, &NONE);\n    7i32\n}";
+        assert_eq!(run(code, &[], &[]).code, Some(7));
+        assert!(ir(code, &[]).contains("@NONE = internal global [0 x i8] zeroinitializer"), "{}", ir(code, &[]));
+        let err = build_err("#[export]\nfn f(a: [i32; 0], i: u64) -> i32 {\n    a[i]\n}", &["--shared"]);
+        assert!(err.contains("array of length 0 has no elements, a can't be indexed") && err.contains("<string>: 3:5..3:8\n"), "{err}");
+    }
+
+    #[test]
+    fn type_errors_suggest_fixes() {
+        let code = |body: &str| format!("fn take(x: u64, p: &[u8; 2], s: &[u8]) -> u64 {{\n    x\n}}\n#[export]\nfn f(a: [u8; 2], p: &u64) -> u64 {{\n    {body}\n}}");
+        let err = build_err(&code("take(5u32, &a, &a)"), &["--shared"]);
+        assert!(err.contains("help: change the suffix: `5u64`\n<string>: 6:10..6:13\n"), "{err}");
+        let err = build_err(&code("take(1u64, a, &a)"), &["--shared"]);
+        assert!(err.contains("help: take a pointer: `&a`\n<string>: 6:16\n"), "{err}");
+        let err = build_err(&code("take(1u64, &a, a)"), &["--shared"]);
+        assert!(err.contains("help: take a pointer: `&a`\n<string>: 6:20\n"), "{err}");
+        let err = build_err(&code("take(p, &a, &a)"), &["--shared"]);
+        assert!(err.contains("help: dereference the pointer: `*p`\n<string>: 6:10\n"), "{err}");
+        // nothing to suggest for unrelated types
+        assert!(!build_err(&code("take(true, &a, &a)"), &["--shared"]).contains("help:"));
+    }
+
+    #[test]
+    fn shared_library_must_export_a_function() {
+        let err = build_err("fn helper() -> i32 {\n    1i32\n}", &["--shared"]);
+        assert!(err.contains("shared library does not export any function, tag the ones it provides with #[export]")
+            && err.contains("while compiling library"), "{err}");
+        ir("#[export]\nfn helper() -> i32 {\n    1i32\n}", &["--shared"]);
+    }
+
+    #[test]
+    fn extern_types_live_behind_pointers() {
+        let code = "extern type FILE;\n#[unsafe]\n#[extern(\"C\")]\nfn tmpfile() -> &FILE;\n#[unsafe]\n#[extern(\"C\")]\nfn fputc(c: i32, f: &FILE) -> i32;\n\
+            #[unsafe]\n#[extern(\"C\")]\nfn fclose(f: &FILE) -> i32;\nfn main() -> i32 {\n    let f = unsafe { tmpfile() };\n    let c = unsafe { fputc(65i32, f) };\n    unsafe { fclose(f); }\n    c\n}";
+        let emitted = ir(code, &[]);
+        assert!(emitted.contains("%FILE = type opaque") && emitted.contains("declare %FILE* @tmpfile()"), "{emitted}");
+        assert_eq!(run(code, &[], &[]).code, Some(65));
+        let err = build_err("extern type FILE;\n#[export]\nfn f(file: FILE) {}", &["--shared"]);
+        assert!(err.contains("extern type FILE has no known size, it can only be used behind a pointer") && err.contains("<string>: 3:12..3:15\n"), "{err}");
+        let err = build_err("extern type FILE;\nextern type FILE;\nfn main() {}", &[]);
+        assert!(err.contains("extern type FILE was already defined") && err.contains("<string>: 1:13..1:16\n") && err.contains("<string>: 2:13..2:16\n"), "{err}");
+    }
+
+    #[test]
+    fn constructors_run_before_main() {
+        let code = "#[unsafe]\n#[extern(\"C\")]\nfn putchar(c: i32) -> i32;\nstatic STATE: i32 = 1i32;\n\
+            #[constructor]\nfn init() {\n    unsafe { STATE = STATE * 10i32 + 2i32; }\n}\n#[destructor]\nfn bye() {\n    unsafe { putchar(33i32); }\n}\n\
+            fn main() -> i32 {\n    unsafe { putchar(109i32); }\n    STATE\n}";
+        let run = run(code, &[], &[]);
+        // the constructor ran before main read the static, the destructor after main returned
+        assert_eq!((run.code, run.stdout.as_str()), (Some(12), "m!"), "{}", run.stderr);
+        // lli ignores the priorities, so their order is checked in the ir
+        let emitted = ir("#[constructor]\nfn last() {}\n#[constructor(200)]\nfn second() {}\n#[constructor(100)]\nfn first() {}\nfn main() {}", &[]);
+        assert!(emitted.contains("{ i32 100, void ()* @first, i8* null }, { i32, void ()*, i8* } { i32 200, void ()* @second, i8* null }, \
+            { i32, void ()*, i8* } { i32 65535, void ()* @last, i8* null }]"), "{emitted}");
+        let err = build_err("#[constructor]\nfn init(x: i32) {}\nfn main() {}", &[]);
+        assert!(err.contains("constructor function has to take no arguments and return ()") && err.contains("<string>: 1:3..1:13\n"), "{err}");
+        let err = build_err("#[destructor(70000)]\nfn fini() {}\nfn main() {}", &[]);
+        assert!(err.contains("with a priority up to 65535, found destructor(70000)") && err.contains("<string>: 1:3..1:19\n"), "{err}");
+    }
+
+    #[test]
+    fn functions_in_sections() {
+        let emitted = ir("#[export]\n#[section(\".text.hot\")]\nfn fast() -> i32 {\n    1i32\n}", &["--shared"]);
+        assert!(emitted.contains("define i32 @fast() section \".text.hot\""), "{emitted}");
+        let err = build_err("#[export]\n#[section(\"\")]\nfn f() {}", &["--shared"]);
+        assert!(err.contains("invalid section name \"\", expected a non-empty name without whitespace or quotes") && err.contains("<string>: 2:11..2:12\n"), "{err}");
+        let err = build_err("#[export]\n#[section(\".text hot\")]\nfn f() {}", &["--shared"]);
+        assert!(err.contains("invalid section name \".text hot\""), "{err}");
+        let err = build_err("#[unsafe]\n#[extern(\"C\")]\n#[section(\".text\")]\nfn abs(x: i32) -> i32;\nfn main() -> i32 {\n    unsafe { abs(1i32) }\n}", &[]);
+        assert!(err.contains("extern function has no body to place in a section"), "{err}");
+    }
+
+    #[test]
+    fn cold_and_hot_functions() {
+        let emitted = ir("#[export]\n#[cold]\nfn fail() {}\n#[export]\n#[hot]\nfn fast() {}", &["--shared"]);
+        let attributes = |function: &str| {
+            let group = emitted.lines().find(|l| l.starts_with(&format!("define void @{function}()"))).unwrap().split('#').nth(1).unwrap().trim_end_matches(" {").to_string();
+            emitted.lines().find(|l| l.starts_with(&format!("attributes #{group} "))).unwrap().to_string()
+        };
+        assert!(attributes("fail").contains(" cold "), "{emitted}");
+        assert!(attributes("fast").contains(" hot "), "{emitted}");
+        let err = build_err("#[export]\n#[cold]\n#[hot]\nfn f() {}", &["--shared"]);
+        assert!(err.contains("function can't be both cold and hot") && err.contains("<string>: 2:3..2:6\n") && err.contains("<string>: 3:3..3:5\n"), "{err}");
+        let err = build_err("#[export]\n#[cold(1)]\nfn f() {}", &["--shared"]);
+        assert!(err.contains("expected cold, found cold(1)"), "{err}");
+    }
+
+    #[test]
+    fn type_ascriptions() {
+        let code = "fn wide(x: u64) -> u64 {\n    x\n}\n#[export]\nfn f(p: &u8) -> u64 {\n    let small: u8 = (5: u8) + *p;\n    wide(7)\n    \
+            : u64\n}";
+        let emitted = ir(code, &["--shared"]);
+        assert!(emitted.contains("add i8 5, %load.") && emitted.contains("call i64 @wide(i64 7)"), "{emitted}");
+        let err = build_err("#[export]\nfn f(x: u32) -> u32 {\n    (x: u64)\n}", &["--shared"]);
+        assert!(err.contains("expected u64 found u32\n    while checking type ascription") && err.contains("<string>: 3:9..3:11\n"), "{err}");
+        let err = build_err("#[export]\nfn f() {\n    let t = (1i32: i32, 2i32);\n}", &["--shared"]);
+        assert!(err.contains("a tuple can't be ascribed through its first element, use ((a, b): (A, B))"), "{err}");
+        let err = build_err("#[export]\nfn f() {\n    let x = 3: u64;\n}", &["--shared"]);
+        assert!(err.contains("a let can't be ascribed, annotate the variable with `let x: T = ...` or the value with `(value: T)`")
+            && err.contains("<string>: 3:16..3:18\n"), "{err}");
+    }
+
+    #[test]
+    fn literal_suffix_against_annotation() {
+        let code = |annotation: &str, literal: &str| format!("#[export]\nfn f() {{\n    let x{annotation} = {literal};\n}}");
+        // (annotation, literal, stored value or error)
+        let matrix = [
+            (": u8", "5u8", Ok("store i8 5, i8* %x")),
+            (": u16", "5u16", Ok("store i16 5, i16* %x")),
+            ("", "5u16", Ok("store i16 5, i16* %x")),
+            (": u16", "5", Ok("store i16 5, i16* %x")),
+            (": u8", "5u16", Err("suffix u16 conflicts with expected type u8")),
+            (": u16", "5u8", Err("suffix u8 conflicts with expected type u16")),
+            (": u8", "300u16", Err("suffix u16 conflicts with expected type u8")),
+            (": u8", "300", Err("literal 300 does not fit in u8 (max 255)")),
+            ("", "5", Err("cannot infer type of number literal, add a suffix or a type annotation"))
+        ];
+        for (annotation, literal, expected) in matrix {
+            let code = code(annotation, literal);
+            match expected {
+                Ok(store) => assert!(ir(&code, &["--shared"]).contains(store), "{code}"),
+                Err(msg) => {
+                    let err = build_err(&code, &["--shared"]);
+                    assert!(err.contains(msg) && err.matches("Error:").count() == 1, "{err}");
+                }
+            }
+        }
+        // a conflict points at both the suffix and the annotation, the suffix is only suggested if the value fits
+        let err = build_err(&code(": u16", "300u8"), &["--shared"]);
+        assert!(err.contains("<string>: 3:12..3:14\n") && err.contains("<string>: 3:18..3:22\n") && err.contains("help: change the suffix: `300u16`"), "{err}");
+        assert!(!build_err(&code(": u8", "300u16"), &["--shared"]).contains("help:"));
+    }
+
+    #[test]
+    fn let_else_binds_non_null_pointers() {
+        let code = "#[unsafe]\n#[extern(\"C\")]\nfn getenv(name: &[u8; 7]) -> &u8;\nfn first(name: &[u8; 7]) -> u8 {\n    \
+            let value = unsafe { getenv(name) } else {\n        return 1u8;\n    };\n    *value\n}\nfn main() -> i32 {\n    let mut n = 0i32;\n    \
+            while first(&\"LT_SET\") == 120u8 {\n        n += 10i32;\n        break;\n    }\n    while first(&\"LT_NOT\") == 1u8 {\n        n += 1i32;\n        break;\n    }\n    n\n}";
+        assert_eq!(run(code, &[], &[("LT_SET", "x")]).code, Some(11));
+        let with = |body: &str| format!("#[export]\nfn f(p: &u8, x: u8) -> u8 {{\n{body}\n}}");
+        let err = build_err(&with("    let v = p else {\n        0u8;\n    };\n    *v"), &["--shared"]);
+        assert!(err.contains("else block of let else must diverge, use return, break or continue") && err.contains("<string>: 4:9..4:12\n"), "{err}");
+        let err = build_err(&with("    let v = x else {\n        return 0u8;\n    };\n    v"), &["--shared"]);
+        assert!(err.contains("expected pointer found u8") && err.contains("while compiling let else"), "{err}");
+        // the name is only bound after the statement
+        let err = build_err(&with("    let v = p else {\n        return *v;\n    };\n    *v"), &["--shared"]);
+        assert!(err.contains("could not find variable v") && err.contains("<string>: 4:17\n"), "{err}");
+    }
+}
@@ -1,13 +1,30 @@
+pub(crate) mod abi;
+pub(crate) mod coverage;
+pub(crate) mod data_layout;
 pub(crate) mod gen_llvm;
 pub(crate) mod llvm_ast;
+pub(crate) mod runtime;
+pub(crate) mod setup;
+pub(crate) mod target;
+pub(crate) mod unsafety;
+pub(crate) mod verify;
 
+use std::cell::Cell;
 use std::collections::HashMap;
-use std::ffi::c_uint;
+use std::ffi::{c_char, c_uint};
+use std::ops::{Deref, DerefMut};
+use std::ptr;
 
-use llvm_sys::{prelude, core};
-use crate::ast::Type;
+use llvm_sys::{prelude, core, LLVMModuleFlagBehavior};
+use crate::ast::{Expression, Ident, Ty, Type};
+use crate::ast::code_printer::CodePrinter;
+use crate::ast::expr_types::ExprTypes;
+use crate::compiler::{Arguments, OutputKind, RelocModel};
 use crate::error::{ParseError, ParseET};
+use crate::llvm::setup::install_diagnostic_handler;
+use crate::llvm::unsafety::UnsafeScope;
 use crate::source::span::Span;
+use crate::log;
 
 #[macro_export]
 macro_rules! c_str {
@@ -31,41 +48,175 @@ macro_rules! c_str_ptr {
 pub(crate) struct LLVMModGenEnv {
     globals: HashMap<String, Variable>,
     stack: Vec<StackEnv>,
+    loops: Vec<LoopEnv>,
+    /// source names of overloaded functions to their mangled symbols in `globals`
+    overloads: HashMap<String, Vec<String>>,
+    /// return type of the function currently being built
+    ret_type: Option<Type>,
+    /// slot the function currently being built returns through, see `sret_type`
+    sret: Option<prelude::LLVMValueRef>,
+    /// string globals by content, see `intern_str`
+    interned: HashMap<String, prelude::LLVMValueRef>,
+    /// type and value of the runtime failure function, once it was emitted
+    fail_fn: Option<(prelude::LLVMTypeRef, prelude::LLVMValueRef)>,
+    /// call counters of the functions by symbol, if they are instrumented, see `build_coverage`
+    coverage_counters: HashMap<String, prelude::LLVMValueRef>,
+    /// opaque struct types of the `extern type`s of the module, by name
+    opaque_types: HashMap<String, prelude::LLVMTypeRef>,
+    /// every unsafe scope built so far, see `enter_unsafe`
+    unsafe_scopes: Vec<UnsafeScope>,
+    /// indices into `unsafe_scopes` of the scopes currently being built, innermost last
+    open_unsafe: Vec<usize>,
+    /// the function currently being built has `#[allow(unused_unsafe)]`
+    allow_unused_unsafe: bool,
+    /// unnamed values built so far in the current function, see `value_name`
+    value_counter: Cell<usize>,
+    /// block terminated by the last `return`, `break` or `continue` and the one built after it, see `enter_dead_block`
+    dead_block: Option<(prelude::LLVMBasicBlockRef, prelude::LLVMBasicBlockRef)>,
+    expr_types: ExprTypes,
     mod_name: String,
+    args: Arguments,
+    context: prelude::LLVMContextRef,
     module: prelude::LLVMModuleRef,
     builder: prelude::LLVMBuilderRef
 }
 
+/// Owns a finished module together with the context it was created in.
+/// Both are disposed when this is dropped, the module first.
+pub(crate) struct LLVMModule {
+    pub(crate) context: prelude::LLVMContextRef,
+    pub(crate) module: prelude::LLVMModuleRef,
+    /// types of the expressions the module was built from
+    pub(crate) expr_types: ExprTypes,
+    pub(crate) warnings: Vec<ParseError>
+}
+
 pub(crate) struct StackEnv {
     vars: HashMap<String, Variable>,
+    /// expressions to build whenever the scope is left, in reverse order
+    defers: Vec<Expression>,
     opaque: bool,
     unsafe_ctx: bool,
 }
 
+/// Jump targets of the innermost loops, `continue` branches to the step block
+/// so the induction update is never skipped.
+pub(crate) struct LoopEnv {
+    label: Option<String>,
+    continue_block: prelude::LLVMBasicBlockRef,
+    break_block: prelude::LLVMBasicBlockRef,
+    /// index of the stack frame of the loop body, leaving the loop leaves it and all above it
+    depth: usize
+}
+
+/// State of the function being built, see [`LLVMModGenEnv::enter_fn`].
+/// Dropping it disposes the builder of the function and restores the state of the outer one,
+/// so returning early with an error doesn't leave `env` emitting into a half built function.
+pub(crate) struct FnScope<'a> {
+    env: &'a mut LLVMModGenEnv,
+    builder: prelude::LLVMBuilderRef,
+    ret_type: Option<Type>,
+    sret: Option<prelude::LLVMValueRef>,
+    allow_unused_unsafe: bool,
+    value_counter: usize
+}
+
+/// How the `llvm_value` of a [`Variable`] has to be accessed.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum VarKind {
+    /// ssa value of `llvm_type`
+    Value,
+    /// pointer to memory holding a value of `llvm_type`, has to be loaded before use
+    Place,
+    /// function of signature `llvm_type`
+    Function
+}
+
+/// A global whose memory a [`Variable`] is or points to, with its name and definition.
+#[derive(Debug, Clone)]
+pub(crate) enum Global {
+    /// read only
+    Const(String, Span),
+    /// only written in unsafe context
+    Static(String, Span)
+}
+
 #[derive(Debug, Clone)]
 pub(crate) struct Variable{
+    kind: VarKind,
     ast_type: Type,
     llvm_type: prelude::LLVMTypeRef,
-    llvm_value: prelude::LLVMValueRef
+    llvm_value: prelude::LLVMValueRef,
+    global: Option<Global>,
+    /// the `let` without `mut` this is the place of, or of a part of
+    immutable: Option<Ident>
+}
+
+impl Variable {
+    pub(crate) fn value(ast_type: Type, llvm_type: prelude::LLVMTypeRef, llvm_value: prelude::LLVMValueRef) -> Self {
+        Self { kind: VarKind::Value, ast_type, llvm_type, llvm_value, global: None, immutable: None }
+    }
+    pub(crate) fn place(ast_type: Type, llvm_type: prelude::LLVMTypeRef, llvm_value: prelude::LLVMValueRef) -> Self {
+        Self { kind: VarKind::Place, ast_type, llvm_type, llvm_value, global: None, immutable: None }
+    }
+    pub(crate) fn function(ast_type: Type, llvm_type: prelude::LLVMTypeRef, llvm_value: prelude::LLVMValueRef) -> Self {
+        Self { kind: VarKind::Function, ast_type, llvm_type, llvm_value, global: None, immutable: None }
+    }
+    pub(crate) fn of_global(self, global: Option<Global>) -> Self {
+        Self { global, ..self }
+    }
+    pub(crate) fn of_immutable(self, immutable: Option<Ident>) -> Self {
+        Self { immutable, ..self }
+    }
 }
 
 impl LLVMModGenEnv{
-    pub(crate) fn new(mod_name: String) -> Self{
-        let module = unsafe { core::LLVMModuleCreateWithName(c_str_ptr!(mod_name)) };
-        let main_entrypoint_function_type = unsafe {
-            core::LLVMFunctionType(core::LLVMVoidType(), [].as_mut_ptr(), 0, 0)
-        };
-        let main_entrypoint_function = unsafe { core::LLVMAddFunction(module, c_str_ptr!("main"), main_entrypoint_function_type) };
-        let entry_block = unsafe { core::LLVMAppendBasicBlock(main_entrypoint_function, c_str_ptr!("entry")) };
-        let builder = unsafe {
-            let b = core::LLVMCreateBuilder();
-            core::LLVMPositionBuilderAtEnd(b, entry_block);
-            b
+    pub(crate) fn new(mod_name: String, args: Arguments) -> Self{
+        let context = unsafe { core::LLVMContextCreate() };
+        install_diagnostic_handler(context);
+        let module = unsafe { core::LLVMModuleCreateWithNameInContext(c_str_ptr!(mod_name), context) };
+        let pic_levels: &[&str] = match args.relocation_model {
+            RelocModel::Default | RelocModel::Static => &[],
+            RelocModel::Pic => &["PIC Level"],
+            RelocModel::Pie => &["PIC Level", "PIE Level"]
         };
+        for level in pic_levels {
+            // same flags clang sets for -fPIC/-fPIE, level 2 being the large model
+            unsafe {
+                let value = core::LLVMValueAsMetadata(core::LLVMConstInt(core::LLVMInt32TypeInContext(context), 2, 0));
+                core::LLVMAddModuleFlag(module, LLVMModuleFlagBehavior::LLVMModuleFlagBehaviorError, level.as_ptr() as *const c_char, level.len(), value);
+            }
+        }
+        let builder = unsafe { core::LLVMCreateBuilderInContext(context) };
+        // libraries have no entry point
+        if args.output == OutputKind::Executable {
+            unsafe {
+                let main_entrypoint_function_type = core::LLVMFunctionType(core::LLVMInt32TypeInContext(context), [].as_mut_ptr(), 0, 0);
+                let main_entrypoint_function = core::LLVMAddFunction(module, c_str_ptr!("main"), main_entrypoint_function_type);
+                let entry_block = core::LLVMAppendBasicBlockInContext(context, main_entrypoint_function, c_str_ptr!("entry"));
+                core::LLVMPositionBuilderAtEnd(builder, entry_block);
+            }
+        }
         Self {
             globals: HashMap::new(),
             stack: vec![],
+            loops: vec![],
+            overloads: HashMap::new(),
+            ret_type: None,
+            sret: None,
+            interned: HashMap::new(),
+            fail_fn: None,
+            coverage_counters: HashMap::new(),
+            opaque_types: HashMap::new(),
+            unsafe_scopes: vec![],
+            open_unsafe: vec![],
+            allow_unused_unsafe: false,
+            value_counter: Cell::new(0),
+            dead_block: None,
+            expr_types: ExprTypes::default(),
             mod_name: mod_name.clone(),
+            args,
+            context,
             module,
             builder
         }
@@ -74,6 +225,7 @@ impl LLVMModGenEnv{
     pub(crate) fn push_stack(&mut self, opaque: bool, unsafe_ctx: bool){
         self.stack.push(StackEnv {
             vars: Default::default(),
+            defers: vec![],
             opaque,
             unsafe_ctx: unsafe_ctx || (!opaque && self.stack.last().map(|s| s.unsafe_ctx).unwrap_or(false)),
         })
@@ -83,6 +235,181 @@ impl LLVMModGenEnv{
         self.stack.pop();
     }
 
+    /// Index of the stack frame of the current function.
+    pub(crate) fn function_depth(&self) -> usize {
+        self.stack.iter().rposition(|frame| frame.opaque).unwrap_or(0)
+    }
+
+    /// Builds the deferred expressions of the stack frames from `depth` up, innermost frame first.
+    /// Has to be called before anything leaves those frames.
+    pub(crate) fn build_defers(&mut self, depth: usize) -> Result<(), ParseError> {
+        for i in (depth..self.stack.len()).rev() {
+            let defers = self.stack[i].defers.clone();
+            if defers.is_empty() {
+                continue
+            }
+            // deferred expressions must not see the variables of frames nested inside of theirs
+            let inner = self.stack.split_off(i + 1);
+            let built = defers.iter().rev().try_for_each(|expr| expr.build(self, None).map(|_| ()));
+            self.stack.extend(inner);
+            built?;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn unit(&self, loc: Span) -> Variable {
+        Variable::value(Type(Ty::empty(), loc), unsafe { core::LLVMVoidTypeInContext(self.context) }, ptr::null_mut())
+    }
+
+    /// Name of a new value, `name` if it is bound to one and `kind.N` otherwise,
+    /// numbered per function so unrelated edits only rename the values following them.
+    /// Unbound values stay unnamed with `--no-value-names`, llvm numbers them then.
+    pub(crate) fn value_name(&self, name: Option<String>, kind: &str) -> String {
+        match name {
+            Some(name) => name,
+            None if self.args.value_names => {
+                let n = self.value_counter.replace(self.value_counter.get() + 1);
+                format!("{kind}.{n}")
+            }
+            None => String::new()
+        }
+    }
+
+    /// Reads the value out of a place, values and functions are returned as they are.
+    pub(crate) fn load(&self, v: Variable, name: Option<String>) -> Variable {
+        match v.kind {
+            VarKind::Place => {
+                let value = unsafe { core::LLVMBuildLoad2(self.builder, v.llvm_type, v.llvm_value, c_str_ptr!(self.value_name(name, "load"))) };
+                Variable::value(v.ast_type, v.llvm_type, value)
+            },
+            VarKind::Value | VarKind::Function => v
+        }
+    }
+
+    /// Stores a value in a new stack slot and returns the slot as place.
+    pub(crate) fn build_local(&self, v: Variable, name: &str) -> Variable {
+        let slot = self.build_entry_alloca(v.llvm_type, c_str_ptr!(name));
+        unsafe { core::LLVMBuildStore(self.builder, v.llvm_value, slot); }
+        Variable::place(v.ast_type, v.llvm_type, slot)
+    }
+
+    pub(crate) fn current_function(&self) -> prelude::LLVMValueRef {
+        unsafe { core::LLVMGetBasicBlockParent(core::LLVMGetInsertBlock(self.builder)) }
+    }
+
+    /// Builds into `entry_block` of a function returning `ret_type`, until the returned scope is dropped.
+    pub(crate) fn enter_fn(&mut self, entry_block: prelude::LLVMBasicBlockRef, ret_type: Type, sret: Option<prelude::LLVMValueRef>, allow_unused_unsafe: bool) -> FnScope<'_> {
+        let builder = unsafe {
+            let b = core::LLVMCreateBuilderInContext(self.context);
+            core::LLVMPositionBuilderAtEnd(b, entry_block);
+            b
+        };
+        FnScope {
+            builder: std::mem::replace(&mut self.builder, builder),
+            ret_type: self.ret_type.replace(ret_type),
+            sret: std::mem::replace(&mut self.sret, sret),
+            allow_unused_unsafe: std::mem::replace(&mut self.allow_unused_unsafe, allow_unused_unsafe),
+            value_counter: self.value_counter.replace(0),
+            env: self
+        }
+    }
+
+    /// Allocates stack space in the entry block of the current function,
+    /// so allocas inside of loops don't grow the stack on every iteration.
+    pub(crate) fn build_entry_alloca(&self, ty: prelude::LLVMTypeRef, name: *const c_char) -> prelude::LLVMValueRef {
+        unsafe {
+            let entry = core::LLVMGetEntryBasicBlock(self.current_function());
+            let b = core::LLVMCreateBuilderInContext(self.context);
+            let first = core::LLVMGetFirstInstruction(entry);
+            if first.is_null() {
+                core::LLVMPositionBuilderAtEnd(b, entry);
+            } else {
+                core::LLVMPositionBuilderBefore(b, first);
+            }
+            let alloca = core::LLVMBuildAlloca(b, ty, name);
+            core::LLVMDisposeBuilder(b);
+            alloca
+        }
+    }
+
+    /// Whether the current block still needs a terminator.
+    pub(crate) fn is_block_open(&self) -> bool {
+        unsafe { core::LLVMGetBasicBlockTerminator(core::LLVMGetInsertBlock(self.builder)).is_null() }
+    }
+
+    /// Continues in a new block no branch leads to once the current one was terminated,
+    /// so the expressions around a `return`, `break` or `continue` still have somewhere to go.
+    pub(crate) fn enter_dead_block(&mut self) {
+        unsafe {
+            let terminated = core::LLVMGetInsertBlock(self.builder);
+            let dead = core::LLVMAppendBasicBlockInContext(self.context, self.current_function(), c_str_ptr!("dead"));
+            core::LLVMPositionBuilderAtEnd(self.builder, dead);
+            self.dead_block = Some((terminated, dead));
+        }
+    }
+
+    /// Whether the rest of the block being built is unreachable.
+    /// The block of [`enter_dead_block`](Self::enter_dead_block) is dropped again if nothing was built into it.
+    pub(crate) fn leave_dead_block(&mut self) -> bool {
+        unsafe {
+            if let Some((terminated, dead)) = self.dead_block.take() {
+                if core::LLVMGetInsertBlock(self.builder) == dead && core::LLVMGetFirstInstruction(dead).is_null() {
+                    core::LLVMDeleteBasicBlock(dead);
+                    core::LLVMPositionBuilderAtEnd(self.builder, terminated);
+                }
+            }
+        }
+        !self.is_block_open()
+    }
+
+    /// Branches to `target` unless the current block was already terminated, e.g. by a `break`.
+    pub(crate) fn build_br_if_open(&self, target: prelude::LLVMBasicBlockRef) {
+        if self.is_block_open() {
+            unsafe { core::LLVMBuildBr(self.builder, target); }
+        }
+    }
+
+    /// Innermost loop, or the innermost one carrying `label` if given.
+    pub(crate) fn get_loop(&self, label: Option<&Ident>, loc: &Span) -> Result<&LoopEnv, ParseError> {
+        match label {
+            None => self.loops.last()
+                .ok_or_else(|| ParseET::CompilationError("not inside of a loop".to_string()).at(loc.clone())),
+            Some(label) => self.loops.iter().rev()
+                .find(|l| l.label.as_ref() == Some(&label.0))
+                .ok_or_else(|| ParseET::CompilationError(format!("undefined loop label '{}", label.0)).at(label.1.clone()))
+        }
+    }
+
+    /// Looks up a function by its source name, overloads are resolved by the argument types.
+    /// `arg_types` are `None` for number literals without suffix, which fit any number parameter
+    pub(crate) fn resolve_fn(&self, name: &str, loc: &Span, arg_types: &[Option<&Type>]) -> Result<Variable, ParseError> {
+        let symbols = match self.overloads.get(name) {
+            None => return self.get_var(name, Some(loc)),
+            Some(symbols) => symbols
+        };
+        let candidates = symbols.iter().map(|s| self.globals[s].clone()).collect::<Vec<_>>();
+        let matching = candidates.iter().filter(|c| match &c.ast_type.0 {
+            Ty::Signature(params, _, _, vararg) =>
+                (params.len() == arg_types.len() || (*vararg && params.len() < arg_types.len()))
+                    && params.iter().zip(arg_types).all(|(p, a)| match a {
+                        Some(a) => a.satisfies(p),
+                        None => p.num_lit_ty().is_some()
+                    }),
+            _ => false
+        }).collect::<Vec<_>>();
+        if let [one] = matching.as_slice() {
+            log!(Typeck, Debug, "resolved {name}({}) to {}", arg_types.iter().map(|t| t.map_or("{number}".to_string(), |t| t.print())).collect::<Vec<_>>().join(", "), one.ast_type.print());
+            return Ok((*one).clone())
+        }
+        let mut locs = vec![loc.clone()];
+        locs.extend(candidates.iter().map(|c| c.ast_type.1.clone()));
+        Err(ParseET::CompilationError(format!("{} for {name}({}), candidates are:\n        {}",
+            if matching.is_empty() { "no matching overload" } else { "ambiguous overloads" },
+            arg_types.iter().map(|t| t.map_or("{number}".to_string(), |t| t.print())).collect::<Vec<_>>().join(", "),
+            candidates.iter().map(|c| c.ast_type.print()).collect::<Vec<_>>().join("\n        ")
+        )).ats(locs))
+    }
+
     pub(crate) fn get_var(&self, ident: &str, loc: Option<&Span>) -> Result<Variable, ParseError>{
         for frame in self.stack.iter().rev(){
             if let Some(v) = frame.vars.get(ident){
@@ -101,18 +428,169 @@ impl LLVMModGenEnv{
         }
     }
 
-    pub(crate) fn finish(self) -> Result<prelude::LLVMModuleRef, ParseError>{
-        unsafe {
-            let fun = self.get_var("main", None)?;
-            core::LLVMBuildCall2(self.builder, fun.llvm_type, fun.llvm_value, [].as_mut_ptr(), 0 as c_uint, c_str_ptr!(""));
-            core::LLVMBuildRetVoid(self.builder);
+    /// Completes the wrapper of an executable, which calls `entry` and returns its exit code, 0 if it returns ()
+    pub(crate) fn finish(mut self, entry: Option<&str>) -> Result<LLVMModule, ParseError>{
+        if let Some(entry) = entry {
+            unsafe {
+                let fun = self.get_var(entry, None)?;
+                let code = core::LLVMBuildCall2(self.builder, fun.llvm_type, fun.llvm_value, [].as_mut_ptr(), 0 as c_uint, c_str_ptr!(""));
+                match &fun.ast_type.0 {
+                    Ty::Signature(_, ret, _, _) if ret.0.is_empty() => core::LLVMBuildRet(self.builder, core::LLVMConstInt(core::LLVMInt32TypeInContext(self.context), 0, 0)),
+                    _ => core::LLVMBuildRet(self.builder, code)
+                };
+            }
         }
-        Ok(self.module)
+        // ownership of module and context moves to the returned value, so drop must not dispose them
+        let module = std::mem::replace(&mut self.module, ptr::null_mut());
+        let context = std::mem::replace(&mut self.context, ptr::null_mut());
+        Ok(LLVMModule {
+            context,
+            module,
+            expr_types: std::mem::take(&mut self.expr_types),
+            warnings: self.unused_unsafe_warnings()
+        })
+    }
+}
+
+impl Deref for FnScope<'_> {
+    type Target = LLVMModGenEnv;
+
+    fn deref(&self) -> &Self::Target {
+        self.env
+    }
+}
+
+impl DerefMut for FnScope<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.env
+    }
+}
+
+impl Drop for FnScope<'_> {
+    fn drop(&mut self) {
+        unsafe { core::LLVMDisposeBuilder(self.env.builder); }
+        self.env.builder = self.builder;
+        self.env.ret_type = self.ret_type.take();
+        self.env.sret = self.sret;
+        self.env.allow_unused_unsafe = self.allow_unused_unsafe;
+        self.env.value_counter.set(self.value_counter);
     }
 }
 
 impl Drop for LLVMModGenEnv {
     fn drop(&mut self) {
-        unsafe {core::LLVMDisposeBuilder(self.builder)}
+        unsafe {
+            core::LLVMDisposeBuilder(self.builder);
+            if !self.module.is_null() {
+                core::LLVMDisposeModule(self.module);
+            }
+            if !self.context.is_null() {
+                core::LLVMContextDispose(self.context);
+            }
+        }
     }
-}
\ No newline at end of file
+}
+
+impl Drop for LLVMModule {
+    fn drop(&mut self) {
+        unsafe {
+            core::LLVMDisposeModule(self.module);
+            core::LLVMContextDispose(self.context);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+    use llvm_sys::core;
+    use crate::compiler::Arguments;
+    use crate::llvm::LLVMModGenEnv;
+    use crate::util::testing::{allocated_by, build, build_err, ir, parse_module, retained_by, run};
+
+    const CODE: &str = "static TABLE: [u8; 4] = [1u8, 2u8, 3u8, 4u8];\nfn get(i: u64) -> u8 { TABLE[i] }\nfn main() -> i32 {\n    let t = (get(1u64), 2i32);\n    t.1\n}";
+
+    #[test]
+    fn building_twice_takes_the_same_memory() {
+        // statics initialized by the first build stay
+        build(CODE, &[]).unwrap();
+        let ((), first) = allocated_by(|| drop(build(CODE, &[]).unwrap()));
+        let ((), second) = allocated_by(|| drop(build(CODE, &[]).unwrap()));
+        assert_eq!(first, second);
+        // including the recursive patterns of the parser
+        assert_eq!(retained_by(|| drop(build(CODE, &[]).unwrap())), 0);
+        // the environment of a failed build is dropped instead of the module
+        let failing = CODE.replace("t.1", "t.2");
+        assert!(build(&failing, &[]).is_err());
+        assert_eq!(retained_by(|| assert!(build(&failing, &[]).is_err())), 0);
+    }
+    #[test]
+    fn failed_function_restores_the_outer_state() {
+        let module = parse_module("fn bad(x: i32) -> i32 {\n    let y = x;\n    true\n}\nfn good() -> i32 {\n    1i32\n}").unwrap();
+        let mut env = LLVMModGenEnv::new("main".to_string(), Arguments::parse(["--shared".to_string()].into_iter()).unwrap());
+        let builder = env.builder;
+        for (symbol, func) in &module.functions {
+            func.register(&mut env, symbol).unwrap();
+        }
+        let symbol = |name: &str| module.functions.iter().find(|(_, f)| f.name.0 == name).unwrap();
+        let (bad, func) = symbol("bad");
+        assert!(func.build(&mut env, bad).is_err());
+        assert_eq!(env.builder, builder);
+        assert!(env.ret_type.is_none() && env.sret.is_none());
+        let (good, func) = symbol("good");
+        func.build(&mut env, good).unwrap();
+        let module = env.finish(None).unwrap();
+        unsafe {
+            let ir = core::LLVMPrintValueToString(core::LLVMGetNamedFunction(module.module, c_str_ptr!(good.clone())));
+            let text = CStr::from_ptr(ir).to_string_lossy().to_string();
+            core::LLVMDisposeMessage(ir);
+            assert!(text.contains("entry:\n  ret i32 1\n}"), "{text}");
+        }
+    }
+    #[test]
+    fn overloads_are_resolved_by_their_arguments() {
+        const MAX: &str = "fn max(a: i32, b: i32) -> i32 {\n    a\n}\nfn max(a: i32, b: i32, c: i32) -> i32 {\n    c\n}\nfn max(a: u8, b: u8) -> u8 {\n    b\n}\n";
+        let code = format!("{MAX}fn main() -> i32 {{\n    let small = max(1u8, 2u8);\n    max(1i32, 2i32) + max(3i32, 4i32, 5i32)\n}}");
+        assert_eq!(run(&code, &[], &[]).code, Some(6));
+        let err = build_err(&format!("{MAX}fn main() -> i32 {{\n    max(1, 2)\n}}"), &[]);
+        assert!(err.contains("ambiguous overloads for max({number}, {number}), candidates are:"), "{err}");
+        let err = build_err(&format!("{MAX}fn main() -> i32 {{\n    max(true, 2i32)\n}}"), &[]);
+        assert!(err.contains("no matching overload for max(bool, i32), candidates are:\n"), "{err}");
+        for candidate in ["fn(i32, i32) -> i32", "fn(i32, i32, i32) -> i32", "fn(u8, u8) -> u8"] {
+            assert!(err.contains(&format!("\n        {candidate}\n")), "{err}");
+        }
+        // every candidate is pointed at
+        assert!(err.contains("<string>: 1:4..1:6\n") && err.contains("<string>: 4:4..4:6\n") && err.contains("<string>: 7:4..7:6\n"), "{err}");
+        let err = build_err("#[unsafe]\n#[extern(\"C\")]\nfn abs(x: i32) -> i32;\nfn abs(x: i64) -> i64 {\n    x\n}\nfn main() {}", &[]);
+        assert!(err.contains("while overloading extern function"), "{err}");
+    }
+
+    #[test]
+    fn defers_run_in_reverse_when_their_scope_exits() {
+        let code = "#[unsafe]\n#[extern(\"C\")]\nfn putchar(c: i32) -> i32;\nfn mark(c: i32) {\n    unsafe { putchar(c); }\n}\n\
+            fn early(n: i32) -> i32 {\n    defer mark(49i32);\n    {\n        defer mark(50i32);\n        while n > 0i32 {\n            return n;\n        }\n        mark(51i32);\n    }\n    0i32\n}\n\
+            fn main() -> i32 {\n    defer mark(10i32);\n    {\n        defer mark(97i32);\n        defer mark(98i32);\n        mark(120i32);\n    }\n    \
+            early(1i32);\n    early(0i32);\n    let mut i = 0i32;\n    while i < 3i32 {\n        defer mark(46i32);\n        i += 1i32;\n        continue;\n    }\n    \
+            while true {\n        defer mark(33i32);\n        {\n            defer mark(35i32);\n            break;\n        }\n    }\n    0i32\n}";
+        let run = run(code, &[], &[]);
+        // break and continue only run the defers of the scopes they leave, the function's runs last
+        assert_eq!((run.code, run.stdout.as_str()), (Some(0), "xba21321...#!\n"), "{}", run.stderr);
+    }
+
+    #[test]
+    fn added_statements_keep_the_names_before_them() {
+        let code = |extra: &str| format!("fn g(n: i32) -> i32 {{\n    n\n}}\n#[export]\nfn f(p: &i32) -> i32 {{\n    let x = g(*p);\n    let y = x + 2i32;\n{extra}    y\n}}");
+        let body = |ir: String| ir.split("define i32 @f(").nth(1).unwrap().split("\n}").next().unwrap().to_string();
+        let before = body(ir(&code(""), &["--shared"]));
+        assert_eq!(before, "i32* %0) {\nentry:\n  %y2 = alloca i32, align 4\n  %x1 = alloca i32, align 4\n  %p = alloca i32*, align 8\n  \
+            store i32* %0, i32** %p, align 8\n  %load.0 = load i32*, i32** %p, align 8\n  %load.1 = load i32, i32* %load.0, align 4\n  \
+            %x = call i32 @g(i32 %load.1)\n  store i32 %x, i32* %x1, align 4\n  %load.2 = load i32, i32* %x1, align 4\n  %y = add i32 %load.2, 2\n  \
+            store i32 %y, i32* %y2, align 4\n  %load.3 = load i32, i32* %y2, align 4\n  ret i32 %load.3");
+        let after = body(ir(&code("    g(5i32);\n"), &["--shared"]));
+        let kept = before.split("\n  %load.3").next().unwrap();
+        assert!(after.starts_with(kept) && after.contains("%call.g.3 = call i32 @g(i32 5)\n  %load.4 = load i32, i32* %y2"), "{after}");
+        // without names llvm numbers the values instead
+        let unnamed = body(ir(&code(""), &["--shared", "--no-value-names"]));
+        assert!(unnamed.contains("%1 = load i32*, i32** %p"), "{unnamed}");
+    }
+}
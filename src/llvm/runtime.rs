@@ -0,0 +1,177 @@
+use std::ffi::{c_uint, CStr};
+use llvm_sys::{core, prelude, LLVMLinkage};
+use llvm_sys::prelude::LLVMBool;
+use crate::c_str_ptr;
+use crate::compiler::BoundsChecks;
+use crate::llvm::LLVMModGenEnv;
+use crate::source::span::Span;
+
+// support code emitted into the module for failing runtime checks
+
+impl LLVMModGenEnv {
+    /// Pointer to a private, null terminated copy of `s`, equal strings share one global.
+    pub(crate) fn intern_str(&mut self, s: &str) -> prelude::LLVMValueRef {
+        if let Some(global) = self.interned.get(s) {
+            return *global
+        }
+        let global = unsafe {
+            let init = core::LLVMConstStringInContext(self.context, s.as_ptr() as *const _, s.len() as c_uint, false as LLVMBool);
+            let global = core::LLVMAddGlobal(self.module, core::LLVMTypeOf(init), c_str_ptr!(".str"));
+            core::LLVMSetInitializer(global, init);
+            core::LLVMSetGlobalConstant(global, true as LLVMBool);
+            core::LLVMSetLinkage(global, LLVMLinkage::LLVMPrivateLinkage);
+            core::LLVMSetUnnamedAddress(global, llvm_sys::LLVMUnnamedAddr::LLVMGlobalUnnamedAddr);
+            core::LLVMConstPointerCast(global, core::LLVMPointerType(core::LLVMInt8TypeInContext(self.context), 0))
+        };
+        self.interned.insert(s.to_string(), global);
+        global
+    }
+
+    /// Declares a c function, reusing the declaration of the program if it has one.
+    pub(crate) fn declare_c_fn(&self, name: &str, fn_ty: prelude::LLVMTypeRef) -> prelude::LLVMValueRef {
+        unsafe {
+            let existing = core::LLVMGetNamedFunction(self.module, c_str_ptr!(name));
+            if existing.is_null() {
+                core::LLVMAddFunction(self.module, c_str_ptr!(name), fn_ty)
+            } else {
+                core::LLVMConstPointerCast(existing, core::LLVMPointerType(fn_ty, 0))
+            }
+        }
+    }
+
+    /// `void lithia.fail(i8* file, i32 line, i8* reason)`, prints the location to stderr and aborts.
+    /// Emitted once per module on first use. With `--panic-handler` the handler of that name is declared instead.
+    fn fail_fn(&mut self) -> (prelude::LLVMTypeRef, prelude::LLVMValueRef) {
+        if let Some(f) = self.fail_fn {
+            return f
+        }
+        unsafe {
+            let i8_ptr = core::LLVMPointerType(core::LLVMInt8TypeInContext(self.context), 0);
+            let i32_ty = core::LLVMInt32TypeInContext(self.context);
+            let void = core::LLVMVoidTypeInContext(self.context);
+            let mut params = [i8_ptr, i32_ty, i8_ptr];
+            let fn_ty = core::LLVMFunctionType(void, params.as_mut_ptr(), params.len() as c_uint, false as LLVMBool);
+            if let Some(handler) = &self.args.panic_handler {
+                // defined by the user, for targets without libc
+                let function = self.declare_c_fn(handler, fn_ty);
+                self.fail_fn = Some((fn_ty, function));
+                return (fn_ty, function)
+            }
+            let format = self.intern_str("%s:%d: %s\n");
+            let function = core::LLVMAddFunction(self.module, c_str_ptr!("lithia.fail"), fn_ty);
+            core::LLVMSetLinkage(function, LLVMLinkage::LLVMInternalLinkage);
+            let b = core::LLVMCreateBuilderInContext(self.context);
+            core::LLVMPositionBuilderAtEnd(b, core::LLVMAppendBasicBlockInContext(self.context, function, c_str_ptr!("entry")));
+            // the c runtime of the target decides how stderr is found, not the one the compiler runs on
+            let triple = CStr::from_ptr(core::LLVMGetTarget(self.module)).to_string_lossy().to_string();
+            let stderr = if triple.contains("windows") {
+                let mut iob_params = [i32_ty];
+                let iob_ty = core::LLVMFunctionType(i8_ptr, iob_params.as_mut_ptr(), 1, false as LLVMBool);
+                let mut args = [core::LLVMConstInt(i32_ty, 2, false as LLVMBool)];
+                core::LLVMBuildCall2(b, iob_ty, self.declare_c_fn("__acrt_iob_func", iob_ty), args.as_mut_ptr(), 1, c_str_ptr!("stderr"))
+            } else {
+                let name = if triple.contains("apple") || triple.contains("darwin") { "__stderrp" } else { "stderr" };
+                let mut global = core::LLVMGetNamedGlobal(self.module, c_str_ptr!(name));
+                if global.is_null() {
+                    global = core::LLVMAddGlobal(self.module, i8_ptr, c_str_ptr!(name));
+                }
+                core::LLVMBuildLoad2(b, i8_ptr, global, c_str_ptr!("stderr"))
+            };
+            let mut fprintf_params = [i8_ptr, i8_ptr];
+            let fprintf_ty = core::LLVMFunctionType(i32_ty, fprintf_params.as_mut_ptr(), 2, true as LLVMBool);
+            let mut args = [stderr, format, core::LLVMGetParam(function, 0), core::LLVMGetParam(function, 1), core::LLVMGetParam(function, 2)];
+            core::LLVMBuildCall2(b, fprintf_ty, self.declare_c_fn("fprintf", fprintf_ty), args.as_mut_ptr(), args.len() as c_uint, c_str_ptr!(""));
+            let abort_ty = core::LLVMFunctionType(void, [].as_mut_ptr(), 0, false as LLVMBool);
+            core::LLVMBuildCall2(b, abort_ty, self.declare_c_fn("abort", abort_ty), [].as_mut_ptr(), 0, c_str_ptr!(""));
+            core::LLVMBuildUnreachable(b);
+            core::LLVMDisposeBuilder(b);
+            self.fail_fn = Some((fn_ty, function));
+            (fn_ty, function)
+        }
+    }
+
+    /// Reports `reason` at the source location of `loc` and aborts, terminates the current block.
+    /// Branches on `ok`, failing as configured by `--bounds-checks` if it is false.
+    /// Nothing is emitted if bounds checks are off.
+    pub(crate) fn build_bounds_check(&mut self, ok: prelude::LLVMValueRef, loc: &Span) {
+        if self.args.bounds_checks == BoundsChecks::Off {
+            return
+        }
+        unsafe {
+            let function = self.current_function();
+            let fail_block = core::LLVMAppendBasicBlockInContext(self.context, function, c_str_ptr!("index.fail"));
+            let ok_block = core::LLVMAppendBasicBlockInContext(self.context, function, c_str_ptr!("index.ok"));
+            core::LLVMBuildCondBr(self.builder, ok, ok_block, fail_block);
+            core::LLVMPositionBuilderAtEnd(self.builder, fail_block);
+            match self.args.bounds_checks {
+                BoundsChecks::Abort => self.build_fail(loc, "index out of bounds"),
+                BoundsChecks::Trap => self.build_trap(),
+                BoundsChecks::Off => unreachable!()
+            }
+            core::LLVMPositionBuilderAtEnd(self.builder, ok_block);
+        }
+    }
+
+    /// Calls `llvm.trap`, which ends the program without printing anything.
+    pub(crate) fn build_trap(&mut self) {
+        unsafe {
+            let trap_ty = core::LLVMFunctionType(core::LLVMVoidTypeInContext(self.context), [].as_mut_ptr(), 0, false as LLVMBool);
+            core::LLVMBuildCall2(self.builder, trap_ty, self.declare_c_fn("llvm.trap", trap_ty), [].as_mut_ptr(), 0, c_str_ptr!(""));
+            core::LLVMBuildUnreachable(self.builder);
+        }
+    }
+
+    pub(crate) fn build_fail(&mut self, loc: &Span, reason: &str) {
+        let (fn_ty, function) = self.fail_fn();
+        let file = self.intern_str(&format!("{:?}", loc.source));
+        let reason = self.intern_str(reason);
+        unsafe {
+            let line = core::LLVMConstInt(core::LLVMInt32TypeInContext(self.context), loc.start().pos().0 as u64, false as LLVMBool);
+            let mut args = [file, line, reason];
+            core::LLVMBuildCall2(self.builder, fn_ty, function, args.as_mut_ptr(), args.len() as c_uint, c_str_ptr!(""));
+            core::LLVMBuildUnreachable(self.builder);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::Arguments;
+    use crate::util::testing::{ir, run};
+
+    const OUT_OF_BOUNDS: &str = "fn get(a: [i32; 3], i: u64) -> i32 {\n    a[i]\n}\nfn main() -> i32 {\n    get([1i32, 2i32, 3i32], 5u64)\n}";
+
+    #[test]
+    fn failed_check_prints_location() {
+        let failed = run(OUT_OF_BOUNDS, &[], &[]);
+        assert!(failed.stderr.starts_with("<string>:2: index out of bounds\n"), "{failed:?}");
+        // killed by abort
+        assert_eq!(failed.code, None, "{failed:?}");
+        let passed = run(&OUT_OF_BOUNDS.replace("5u64", "2u64"), &[], &[]);
+        assert_eq!((passed.code, passed.stderr.as_str()), (Some(3), ""));
+    }
+
+    #[test]
+    fn stderr_of_the_target() {
+        let stderr_of = |triple: &str| ir(OUT_OF_BOUNDS, &["--target", triple]);
+        let linux = stderr_of("x86_64-unknown-linux-gnu");
+        assert!(linux.contains("load i8*, i8** @stderr"), "{linux}");
+        let macos = stderr_of("x86_64-apple-darwin");
+        assert!(macos.contains("load i8*, i8** @__stderrp"), "{macos}");
+        let windows = stderr_of("x86_64-pc-windows-msvc");
+        assert!(windows.contains("call i8* @__acrt_iob_func(i32 2)"), "{windows}");
+    }
+
+    #[test]
+    fn panic_handler_replaces_abort() {
+        let emitted = ir(OUT_OF_BOUNDS, &["--panic-handler", "on_panic"]);
+        assert!(emitted.contains("declare void @on_panic(i8*, i32, i8*)") && emitted.contains("call void @on_panic(")
+            && !emitted.contains("@abort") && !emitted.contains("@fprintf"), "{emitted}");
+        // the handler can be written in lithia too, this one exits with the line
+        let handler = "#[unsafe]\n#[extern(\"C\")]\nfn exit(code: i32);\n#[export]\nfn on_panic(file: &u8, line: i32, reason: &u8) {\n    unsafe { exit(line + 40i32); }\n}\n";
+        let failed = run(&format!("{handler}{OUT_OF_BOUNDS}"), &["--panic-handler", "on_panic"], &[]);
+        assert_eq!((failed.code, failed.stderr.as_str()), (Some(49), ""), "{failed:?}");
+        let err = Arguments::parse(["--panic-handler".to_string(), String::new()].into_iter()).err().unwrap().render(false);
+        assert!(err.contains("expected a symbol after --panic-handler, found ''"), "{err}");
+    }
+}
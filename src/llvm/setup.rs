@@ -0,0 +1,88 @@
+use std::cell::RefCell;
+use std::ffi::{c_char, c_void, CStr};
+use std::process::Command;
+use llvm_sys::{core, error_handling, target, LLVMDiagnosticSeverity};
+use llvm_sys::prelude::{LLVMContextRef, LLVMDiagnosticInfoRef};
+use crate::error::{ParseError, ParseET};
+use crate::util::log;
+
+// making sure llvm is usable before the first call into it, and routing its messages into our diagnostics
+
+/// Major version of the llvm this compiler is built against
+const LLVM_MAJOR: u32 = 15;
+
+thread_local! {
+    /// what the compiler is doing, outermost first, named in the messages of llvm
+    static ACTIVITY: RefCell<Vec<String>> = const { RefCell::new(vec![]) };
+    /// warnings and errors llvm reported, see [`take_llvm_diagnostics`]
+    static DIAGNOSTICS: RefCell<Vec<ParseError>> = const { RefCell::new(vec![]) };
+}
+
+/// Checks that the llvm at `LLVM_SYS_150_PREFIX` has the version this compiler was built for
+/// and supports the host, then installs a handler for its fatal errors.
+pub(crate) fn check_llvm() -> Result<(), ParseError> {
+    let prefix = env!("LLVM_SYS_150_PREFIX");
+    let setup = |found: String| ParseET::SetupError(format!("{found}, this compiler needs llvm {LLVM_MAJOR} with support for the host.\n    \
+        install it and rebuild with LLVM_SYS_150_PREFIX pointing at it, currently {prefix}")).error();
+    let llvm_config = format!("{prefix}/bin/llvm-config{}", std::env::consts::EXE_SUFFIX);
+    let output = Command::new(&llvm_config).arg("--version").output()
+        .map_err(|e| setup(format!("could not run {llvm_config}: {e}")))?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    if version.split('.').next().and_then(|major| major.parse().ok()) != Some(LLVM_MAJOR) {
+        return Err(setup(format!("found llvm {version}")))
+    }
+    unsafe {
+        if target::LLVM_InitializeNativeTarget() != 0 || target::LLVM_InitializeNativeAsmPrinter() != 0 {
+            return Err(setup(format!("llvm {version} can't generate code for the host")))
+        }
+        error_handling::LLVMInstallFatalErrorHandler(Some(fatal_error));
+    }
+    Ok(())
+}
+
+/// Collects the warnings and errors llvm reports for anything in `context`
+pub(crate) fn install_diagnostic_handler(context: LLVMContextRef) {
+    unsafe { core::LLVMContextSetDiagnosticHandler(context, Some(diagnostic), std::ptr::null_mut()) }
+}
+
+/// The warnings and errors llvm reported since the last call
+pub(crate) fn take_llvm_diagnostics() -> Vec<ParseError> {
+    DIAGNOSTICS.with(|d| d.take())
+}
+
+/// Runs `f`, messages of llvm and log lines in the meantime say they happened while doing `activity`
+pub(crate) fn with_activity<T>(activity: String, f: impl FnOnce() -> T) -> T {
+    ACTIVITY.with(|a| a.borrow_mut().push(activity.clone()));
+    let r = log::with_context(activity, f);
+    ACTIVITY.with(|a| a.borrow_mut().pop());
+    r
+}
+
+fn in_activity(mut err: ParseError) -> ParseError {
+    for activity in ACTIVITY.with(|a| a.borrow().clone()).into_iter().rev() {
+        err = err.when(activity);
+    }
+    err
+}
+
+extern "C" fn fatal_error(reason: *const c_char) {
+    let reason = unsafe { CStr::from_ptr(reason) }.to_string_lossy().to_string();
+    eprintln!("{}", in_activity(ParseET::InternalError(format!("llvm failed: {reason}")).error()));
+    // llvm exits after the handler returns anyway
+    std::process::exit(1)
+}
+
+extern "C" fn diagnostic(info: LLVMDiagnosticInfoRef, _: *mut c_void) {
+    let (severity, message) = unsafe {
+        let description = core::LLVMGetDiagInfoDescription(info);
+        let message = CStr::from_ptr(description).to_string_lossy().to_string();
+        core::LLVMDisposeMessage(description);
+        (core::LLVMGetDiagInfoSeverity(info), message)
+    };
+    let et = match severity {
+        LLVMDiagnosticSeverity::LLVMDSError => ParseET::InternalError(format!("llvm: {message}")),
+        LLVMDiagnosticSeverity::LLVMDSWarning => ParseET::Warning(format!("llvm: {message}")),
+        LLVMDiagnosticSeverity::LLVMDSRemark | LLVMDiagnosticSeverity::LLVMDSNote => return
+    };
+    DIAGNOSTICS.with(|d| d.borrow_mut().push(in_activity(et.error())));
+}
@@ -0,0 +1,152 @@
+use std::ffi::{CStr, CString};
+use std::ptr;
+use llvm_sys::{core, prelude, target, target_machine};
+use llvm_sys::target_machine::{LLVMCodeGenFileType, LLVMCodeGenOptLevel, LLVMCodeModel, LLVMRelocMode};
+use crate::compiler::{Arguments, RelocModel};
+use crate::error::{ParseError, ParseET};
+
+/// Triple of `--target`, or of the host if there is none
+pub(crate) fn target_triple(args: &Arguments) -> CString {
+    match &args.target {
+        Some(triple) => CString::new(triple.as_str()).unwrap(),
+        None => unsafe {
+            let triple_ptr = target_machine::LLVMGetDefaultTargetTriple();
+            let triple = CStr::from_ptr(triple_ptr).to_owned();
+            core::LLVMDisposeMessage(triple_ptr);
+            triple
+        }
+    }
+}
+
+/// Target machine of `--target` or the host, owns the data layout derived from it.
+pub(crate) struct Target {
+    machine: target_machine::LLVMTargetMachineRef,
+    pub(crate) data: target::LLVMTargetDataRef,
+    pub(crate) triple: CString
+}
+
+impl Target {
+    pub(crate) fn new(args: &Arguments) -> Result<Self, ParseError> {
+        unsafe {
+            if args.target.is_some() {
+                target::LLVM_InitializeAllTargetInfos();
+                target::LLVM_InitializeAllTargets();
+                target::LLVM_InitializeAllTargetMCs();
+                target::LLVM_InitializeAllAsmPrinters();
+            } else if target::LLVM_InitializeNativeTarget() != 0 || target::LLVM_InitializeNativeAsmPrinter() != 0 {
+                return Err(ParseET::CompilationError("could not initialize the native target".to_string()).error())
+            }
+            let triple = target_triple(args);
+            let mut llvm_target = ptr::null_mut();
+            let mut err = ptr::null_mut();
+            if target_machine::LLVMGetTargetFromTriple(triple.as_ptr(), &mut llvm_target, &mut err) != 0 {
+                let msg = CStr::from_ptr(err).to_string_lossy().to_string();
+                core::LLVMDisposeMessage(err);
+                return Err(ParseET::CompilationError(format!("unsupported target {}: {msg}", triple.to_string_lossy())).error())
+            }
+            let reloc = match args.relocation_model {
+                RelocModel::Default => LLVMRelocMode::LLVMRelocDefault,
+                RelocModel::Static => LLVMRelocMode::LLVMRelocStatic,
+                RelocModel::Pic | RelocModel::Pie => LLVMRelocMode::LLVMRelocPIC
+            };
+            let machine = target_machine::LLVMCreateTargetMachine(llvm_target, triple.as_ptr(), c"generic".as_ptr(), c"".as_ptr(),
+                LLVMCodeGenOptLevel::LLVMCodeGenLevelDefault, reloc, LLVMCodeModel::LLVMCodeModelDefault);
+            let data = target_machine::LLVMCreateTargetDataLayout(machine);
+            Ok(Self {
+                machine,
+                data,
+                triple
+            })
+        }
+    }
+
+    /// Sets triple and data layout of the module, so sizes and alignments are the ones of the target.
+    pub(crate) fn apply(&self, module: prelude::LLVMModuleRef) {
+        unsafe {
+            core::LLVMSetTarget(module, self.triple.as_ptr());
+            target::LLVMSetModuleDataLayout(module, self.data);
+        }
+    }
+}
+
+impl Target {
+    /// Writes the assembly of the module to `path`, leaving the module as it is
+    pub(crate) fn emit_asm(&self, module: prelude::LLVMModuleRef, path: &str) -> Result<(), ParseError> {
+        let path = CString::new(path).unwrap();
+        unsafe {
+            // code generation may rewrite the ir it runs on
+            let copy = core::LLVMCloneModule(module);
+            let mut err = ptr::null_mut();
+            let failed = target_machine::LLVMTargetMachineEmitToFile(self.machine, copy, path.as_ptr() as *mut _,
+                LLVMCodeGenFileType::LLVMAssemblyFile, &mut err) != 0;
+            core::LLVMDisposeModule(copy);
+            if failed {
+                let msg = CStr::from_ptr(err).to_string_lossy().to_string();
+                core::LLVMDisposeMessage(err);
+                return Err(ParseET::CompilationError(format!("could not emit assembly to {}: {msg}", path.to_string_lossy())).error())
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Target {
+    fn drop(&mut self) {
+        unsafe {
+            target::LLVMDisposeTargetData(self.data);
+            target_machine::LLVMDisposeTargetMachine(self.machine);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+    use std::process::Command;
+    use llvm_sys::target_machine;
+    use llvm_sys::target_machine::LLVMCodeGenFileType;
+    use crate::c_str_ptr;
+    use crate::compiler::Arguments;
+    use crate::llvm::target::Target;
+    use crate::util::testing::{build, temp_dir};
+
+    /// Relocations of the object file the code compiles to with `args`, as listed by `readelf`
+    fn relocations(code: &str, args: &[&str]) -> String {
+        let module = build(code, args).unwrap_or_else(|e| panic!("{}", e.render(false)));
+        let target = Target::new(&Arguments::parse(args.iter().map(|a| a.to_string())).unwrap()).unwrap();
+        let object = temp_dir().join("main.o");
+        let mut err = ptr::null_mut();
+        unsafe {
+            assert_eq!(target_machine::LLVMTargetMachineEmitToFile(target.machine, module.module, c_str_ptr!(object.to_string_lossy()) as *mut _,
+                LLVMCodeGenFileType::LLVMObjectFile, &mut err), 0);
+        }
+        let output = Command::new("readelf").arg("-r").arg(&object).output().expect("could not run readelf");
+        String::from_utf8_lossy(&output.stdout).to_string()
+    }
+
+    #[test]
+    fn pic_reaches_globals_through_the_got() {
+        if Command::new("readelf").arg("--version").output().is_err() {
+            return
+        }
+        let code = "#[export]\nstatic COUNT: i32 = 0i32;\n#[export]\nfn count() -> i32 {\n    COUNT\n}\nfn main() -> i32 {\n    count()\n}";
+        let pic = relocations(code, &["--relocation-model", "pic"]);
+        assert!(pic.contains("R_X86_64_GOTPCREL") && pic.contains("COUNT - 4"), "{pic}");
+        // absolute addresses would need the text to be patched at load time
+        assert!(!pic.contains("R_X86_64_32 ") && !pic.contains("R_X86_64_32S") && !pic.contains("R_X86_64_64"), "{pic}");
+    }
+
+    #[test]
+    fn assembly_of_an_add() {
+        let args = ["--shared", "--target", "x86_64-pc-linux-gnu"];
+        let module = build("#[export]\nfn add(a: i32, b: i32) -> i32 {\n    a + b\n}", &args).unwrap_or_else(|e| panic!("{}", e.render(false)));
+        let target = Target::new(&Arguments::parse(args.iter().map(|a| a.to_string())).unwrap()).unwrap();
+        let dir = temp_dir();
+        let file = dir.join("main.s");
+        target.emit_asm(module.module, &file.to_string_lossy()).unwrap();
+        let asm = std::fs::read_to_string(&file).unwrap();
+        std::fs::remove_dir_all(dir).unwrap();
+        assert!(asm.contains("\t.globl\tadd\n") && asm.contains("\nadd:\n"), "{asm}");
+        assert!(asm.contains("\tleal\t(%rdi,%rsi), %eax\n\tretq\n"), "{asm}");
+    }
+}
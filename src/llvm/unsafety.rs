@@ -0,0 +1,95 @@
+use std::collections::HashMap;
+use crate::ast::{Tag, TagValue};
+use crate::error::{ParseError, ParseET};
+use crate::llvm::LLVMModGenEnv;
+use crate::source::span::Span;
+
+// tracking which unsafe blocks and #[unsafe] functions actually need to be unsafe
+
+/// An `unsafe` block or the `#[unsafe]` tag of a function
+pub(crate) struct UnsafeScope {
+    /// the `unsafe` keyword or tag
+    loc: Span,
+    /// what to call it in the warning
+    what: String,
+    /// whether something in it needed unsafe
+    used: bool,
+    /// `#[allow(unused_unsafe)]`
+    allowed: bool
+}
+
+/// Whether the tags contain `#[allow(lint)]`
+pub(crate) fn allows(tags: &HashMap<String, Tag>, lint: &str) -> bool {
+    tags.get("allow").map_or(false, |tag| tag.1.iter().any(|v| matches!(v, TagValue::Ident(i) if i.0 == lint)))
+}
+
+impl LLVMModGenEnv {
+    /// Opens an unsafe scope, until [`LLVMModGenEnv::leave_unsafe`].
+    /// Code that is built more than once, like deferred expressions, opens the same scope each time.
+    pub(crate) fn enter_unsafe(&mut self, loc: &Span, what: String, allowed: bool) {
+        let index = match self.unsafe_scopes.iter().position(|s| s.loc.equals(loc)) {
+            Some(index) => index,
+            None => {
+                self.unsafe_scopes.push(UnsafeScope { loc: loc.clone(), what, used: false, allowed });
+                self.unsafe_scopes.len() - 1
+            }
+        };
+        self.open_unsafe.push(index);
+    }
+
+    pub(crate) fn leave_unsafe(&mut self) {
+        self.open_unsafe.pop();
+    }
+
+    /// Marks the innermost unsafe scope as needed
+    pub(crate) fn use_unsafe(&mut self) {
+        if let Some(index) = self.open_unsafe.last() {
+            self.unsafe_scopes[*index].used = true;
+        }
+    }
+
+    /// Warnings for the unsafe scopes nothing needed unsafe in
+    pub(crate) fn unused_unsafe_warnings(&self) -> Vec<ParseError> {
+        self.unsafe_scopes.iter()
+            .filter(|s| !s.used && !s.allowed)
+            .map(|s| ParseET::Warning(format!("unnecessary {}, nothing in it needs unsafe (allow with #[allow(unused_unsafe)])", s.what))
+                .at(s.loc.clone()))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::compiler::Arguments;
+    use crate::llvm::gen_llvm::build_llvm_ir;
+    use crate::util::testing::{build_err, ir, parse_module};
+
+    /// The warnings of generating the code, which building the whole program prints instead
+    fn warnings(code: &str) -> Vec<String> {
+        let args = Arguments::parse(["--shared".to_string()].into_iter()).unwrap();
+        let module = build_llvm_ir(parse_module(code).unwrap(), None, &args).unwrap_or_else(|e| panic!("{}", e.render(false)));
+        module.warnings.iter().map(|w| w.render(false)).collect()
+    }
+
+    #[test]
+    fn unused_unsafe_is_reported() {
+        // only the inner block needs unsafe to write the static
+        let nested = warnings("static N: i32 = 0i32;\n#[export]\nfn f() {\n    unsafe {\n        unsafe { N = 1i32; }\n    }\n}");
+        assert!(matches!(nested.as_slice(), [w] if w.contains("unnecessary unsafe block, nothing in it needs unsafe") && w.contains("<string>: 4:5..4:10\n")), "{nested:?}");
+        let tag = warnings("#[export]\n#[unsafe]\nfn f() -> i32 {\n    1i32\n}");
+        assert!(matches!(tag.as_slice(), [w] if w.contains("unnecessary #[unsafe] on fn f") && w.contains("<string>: 2:3..2:8\n")), "{tag:?}");
+        assert!(warnings("#[export]\n#[unsafe]\n#[allow(unused_unsafe)]\nfn f() -> i32 {\n    unsafe { 1i32 }\n}").is_empty());
+        assert!(warnings("static N: i32 = 0i32;\n#[export]\nfn f() {\n    unsafe { N = 1i32; }\n}").is_empty());
+    }
+
+    #[test]
+    fn unsafe_blocks_cover_their_statements() {
+        let code = |body: &str| format!("#[unsafe]\nfn danger(x: i32) -> i32 {{\n    x\n}}\n#[export]\nfn f() -> i32 {{\n{body}\n}}");
+        ir(&code("    let a = unsafe { danger(1i32) };\n    a"), &["--shared"]);
+        let err = build_err(&code("    let a = unsafe { danger(1i32) };\n    danger(a)"), &["--shared"]);
+        assert!(err.contains("cannot use unsafe function in safe context.\n    wrap it as `unsafe { danger(a) }` or tag the expr or func with #[unsafe]")
+            && err.contains("<string>: 8:5..8:10\n"), "{err}");
+        // the tag on the function grants its whole body
+        ir(&code("    danger(1i32)").replace("#[export]\nfn f()", "#[export]\n#[unsafe]\nfn f()"), &["--shared"]);
+    }
+}
@@ -0,0 +1,119 @@
+use std::ffi::CStr;
+use llvm_sys::{core, prelude, LLVMTypeKind};
+use crate::ast::Ty;
+use crate::error::{ParseError, ParseET};
+use crate::llvm::{Variable, VarKind};
+use crate::llvm::data_layout::value_name;
+use crate::source::span::Span;
+
+// internal invariants of the generated ir, checked with --verify-internal
+
+impl Variable {
+    /// Checks that the llvm type is of the kind the ast type lowers to.
+    pub(crate) fn verify_internal(&self, loc: &Span) -> Result<(), ParseError> {
+        let kind = unsafe { core::LLVMGetTypeKind(self.llvm_type) };
+        let expected = match &self.ast_type.0 {
+            Ty::Single(_, item) => match item.0.last().map(|i| i.0.as_str()) {
+                Some("f32") => vec![LLVMTypeKind::LLVMFloatTypeKind],
+                Some("f64") => vec![LLVMTypeKind::LLVMDoubleTypeKind],
+                Some(_) => vec![LLVMTypeKind::LLVMIntegerTypeKind],
+                None => return Ok(())
+            },
+            Ty::RawPointer | Ty::Pointer(_) => vec![LLVMTypeKind::LLVMPointerTypeKind],
+            Ty::Array(..) | Ty::Slice(_) => vec![LLVMTypeKind::LLVMArrayTypeKind],
+            Ty::Tuple(elems) if elems.is_empty() => vec![LLVMTypeKind::LLVMVoidTypeKind],
+            Ty::Tuple(_) => vec![LLVMTypeKind::LLVMStructTypeKind, LLVMTypeKind::LLVMPointerTypeKind],
+            Ty::Signature(..) => vec![LLVMTypeKind::LLVMFunctionTypeKind, LLVMTypeKind::LLVMPointerTypeKind]
+        };
+        if !expected.contains(&kind) {
+            return Err(ParseET::InternalError(format!("value of type {:?} was lowered to llvm type of kind {kind:?}", self.ast_type.0)).at(loc.clone()))
+        }
+        if self.kind == VarKind::Place && unsafe { core::LLVMGetTypeKind(core::LLVMTypeOf(self.llvm_value)) } != LLVMTypeKind::LLVMPointerTypeKind {
+            return Err(ParseET::InternalError(format!("place of type {:?} is not backed by a pointer", self.ast_type.0)).at(loc.clone()))
+        }
+        Ok(())
+    }
+}
+
+/// Checks that every basic block ends in exactly one terminator.
+pub(crate) fn verify_module(module: prelude::LLVMModuleRef, loc: &Span) -> Result<(), ParseError> {
+    unsafe {
+        let mut function = core::LLVMGetFirstFunction(module);
+        while !function.is_null() {
+            let fn_name = value_name(function);
+            let mut block = core::LLVMGetFirstBasicBlock(function);
+            while !block.is_null() {
+                let block_name = CStr::from_ptr(core::LLVMGetBasicBlockName(block)).to_string_lossy().to_string();
+                let violation = |what: &str| ParseET::InternalError(format!("basic block '{block_name}' {what}")).at(loc.clone()).when_fn(&fn_name);
+                if core::LLVMGetBasicBlockTerminator(block).is_null() {
+                    return Err(violation("has no terminator"))
+                }
+                let last = core::LLVMGetLastInstruction(block);
+                let mut inst = core::LLVMGetFirstInstruction(block);
+                while inst != last {
+                    if !core::LLVMIsATerminatorInst(inst).is_null() {
+                        return Err(violation("has a terminator before its end"))
+                    }
+                    inst = core::LLVMGetNextInstruction(inst);
+                }
+                block = core::LLVMGetNextBasicBlock(block);
+            }
+            function = core::LLVMGetNextFunction(function);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use llvm_sys::core;
+    use crate::ast::{Item, Ty, Type};
+    use crate::c_str_ptr;
+    use crate::llvm::Variable;
+    use crate::llvm::verify::verify_module;
+    use crate::source::Source;
+    use crate::source::span::Span;
+    use crate::util::testing::build;
+
+    fn loc() -> Span {
+        Span { source: Rc::new(Source::from_string("f".to_string())), start: 0, end: 0, expansion: None }
+    }
+
+    #[test]
+    fn blocks_end_in_exactly_one_terminator() {
+        let code = "#[export]\nfn f() -> i32 {\n    let mut i = 0i32;\n    while true {\n        i += 1i32;\n        { return i; }\n    }\n    0i32\n}";
+        let module = build(code, &["--shared", "--verify-internal"]).unwrap_or_else(|e| panic!("{}", e.render(false)));
+        unsafe {
+            let f = core::LLVMGetNamedFunction(module.module, c_str_ptr!("f"));
+            let builder = core::LLVMCreateBuilderInContext(module.context);
+            let open = core::LLVMAppendBasicBlockInContext(module.context, f, c_str_ptr!("open"));
+            core::LLVMPositionBuilderAtEnd(builder, open);
+            core::LLVMBuildUnreachable(builder);
+            assert!(verify_module(module.module, &loc()).is_ok());
+            core::LLVMBuildUnreachable(builder);
+            let err = verify_module(module.module, &loc()).unwrap_err().render(false);
+            assert!(err.contains("basic block 'open' has a terminator before its end"), "{err}");
+            let _ = core::LLVMAppendBasicBlockInContext(module.context, f, c_str_ptr!("missing"));
+            core::LLVMInstructionEraseFromParent(core::LLVMGetFirstInstruction(open));
+            let err = verify_module(module.module, &loc()).unwrap_err().render(false);
+            assert!(err.contains("basic block 'missing' has no terminator"), "{err}");
+            core::LLVMDisposeBuilder(builder);
+        }
+    }
+
+    #[test]
+    fn variables_match_their_llvm_type() {
+        let module = build("#[export]\nfn f() {}", &["--shared"]).unwrap_or_else(|e| panic!("{}", e.render(false)));
+        let i32_type = Type(Ty::Single(vec![], Item::new(&vec!["i32"], loc())), loc());
+        unsafe {
+            let int = core::LLVMInt32TypeInContext(module.context);
+            let float = core::LLVMFloatTypeInContext(module.context);
+            assert!(Variable::value(i32_type.clone(), int, core::LLVMConstInt(int, 1, 0)).verify_internal(&loc()).is_ok());
+            let err = Variable::value(i32_type.clone(), float, core::LLVMConstReal(float, 1.0)).verify_internal(&loc()).unwrap_err().render(false);
+            assert!(err.contains("was lowered to llvm type of kind LLVMFloatTypeKind"), "{err}");
+            let err = Variable::place(i32_type, int, core::LLVMConstInt(int, 1, 0)).verify_internal(&loc()).unwrap_err().render(false);
+            assert!(err.contains("is not backed by a pointer"), "{err}");
+        }
+    }
+}
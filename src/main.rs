@@ -10,7 +10,8 @@
 extern crate core;
 
 use std::process::exit;
-use crate::compiler::{compile, Arguments};
+use crate::compiler::{check_type_satisfies, compile, format_file, Arguments, ColorChoice};
+use crate::util::log;
 
 pub(crate) mod ast;
 pub(crate) mod llvm;
@@ -21,14 +22,21 @@ pub(crate) mod compiler;
 pub(crate) mod util;
 
 fn main() {
-   let args = Arguments {
-
-   };
-   match compile(args) {
+   let args = Arguments::parse(std::env::args().skip(1));
+   // errors in the arguments themselves are colored as if there were none
+   let color = args.as_ref().map(|args| args.color).unwrap_or(ColorChoice::Auto).enabled();
+   match args.and_then(|args| {
+      log::init(args.verbosity)?;
+      match (args.format.clone(), args.type_satisfies.clone()) {
+         (Some(path), _) => format_file(&path, &args),
+         (_, Some((found, expected))) => check_type_satisfies(&found, &expected),
+         _ => compile(args)
+      }
+   }) {
       Ok(_) => (),
       Err(e) => {
-         println!("{e}");
-         exit(1)
+         eprintln!("{}", e.render(color));
+         exit(e.exit_code())
       }
    }
 }
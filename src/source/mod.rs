@@ -2,7 +2,7 @@ pub(crate) mod span;
 
 use std::fmt::{Debug, Display, Formatter};
 use std::fs::File;
-use std::io::{Read};
+use std::io::{ErrorKind, Read};
 use std::path::Path;
 use std::rc::Rc;
 use crate::error::ParseError;
@@ -40,15 +40,38 @@ impl Debug for Source {
 }
 
 impl Source {
-    pub(crate) fn from_file<P: AsRef<Path> + Display>(path: P) -> Result<Self, ParseError> {
+    /// Reads a source file of at most `max_bytes`, which has to be valid utf-8
+    pub(crate) fn from_file<P: AsRef<Path> + Display>(path: P, max_bytes: usize) -> Result<Self, ParseError> {
+        let err = |kind: ErrorKind, msg: String| ParseError::from(std::io::Error::new(kind, msg));
+        let describe = |e: std::io::Error| match e.kind() {
+            ErrorKind::NotFound => err(e.kind(), format!("source file {path} does not exist")),
+            ErrorKind::PermissionDenied => err(e.kind(), format!("no permission to read source file {path}")),
+            _ => err(e.kind(), format!("could not read source file {path}: {e}"))
+        };
+        let metadata = std::fs::metadata(&path).map_err(describe)?;
+        if metadata.is_dir() {
+            return Err(err(ErrorKind::InvalidInput, format!("source path {path} is a directory, expected a file")))
+        }
+        let too_large = |size: u64| err(ErrorKind::InvalidData,
+            format!("source file {path} takes up {size} bytes, exceeding the limit of {max_bytes} bytes (see --max-source-bytes)"));
+        if metadata.len() > max_bytes as u64 {
+            return Err(too_large(metadata.len()))
+        }
+        let mut bytes = vec![];
+        // the file may have grown since
+        File::open(&path).map_err(describe)?.take(max_bytes as u64 + 1).read_to_end(&mut bytes).map_err(describe)?;
+        if bytes.len() > max_bytes {
+            return Err(too_large(bytes.len() as u64))
+        }
+        let source = String::from_utf8(bytes).map_err(|e| {
+            let offset = e.utf8_error().valid_up_to();
+            let invalid = e.utf8_error().error_len().unwrap_or(e.as_bytes().len() - offset);
+            err(ErrorKind::InvalidData, format!("source file {path} is not valid utf-8, at byte {offset}: {}",
+                hex_window(e.as_bytes(), offset, offset + invalid)))
+        })?;
         Ok(Self {
             st: SourceType::File(path.to_string()),
-            source: {
-                let mut f = File::open(path)?;
-                let mut buffer = String::new();
-                f.read_to_string(&mut buffer)?;
-                buffer
-            }
+            source
         })
     }
 
@@ -58,6 +81,37 @@ impl Source {
             source
         }
     }
+
+    /// Byte index of the char at a 1-based line and column, `None` if there is no such position.
+    /// Columns count chars like [`CodePoint::line_col`], the column one past the end of a line is the line break.
+    pub(crate) fn index_of(&self, line: usize, col: usize) -> Option<usize> {
+        let mut start = 0;
+        for (i, l) in self.source.split('\n').enumerate() {
+            if i + 1 == line {
+                return l.char_indices().map(|(i, _)| i).chain(std::iter::once(l.len())).nth(col.checked_sub(1)?).map(|i| start + i)
+            }
+            start += l.len() + 1;
+        }
+        None
+    }
+
+    /// Path of the file the source was read from, `None` for sources that aren't files
+    pub(crate) fn path(&self) -> Option<&Path> {
+        match &self.st {
+            SourceType::File(path) => Some(Path::new(path)),
+            SourceType::String => None
+        }
+    }
+}
+
+/// Hex dump of a few bytes around `start..end`, those in brackets
+fn hex_window(bytes: &[u8], start: usize, end: usize) -> String {
+    const CONTEXT: usize = 8;
+    let hex = |range: &[u8]| range.iter().map(|b| format!("{b:02x}")).collect::<Vec<_>>();
+    let mut window = hex(&bytes[start.saturating_sub(CONTEXT)..start]);
+    window.push(format!("[{}]", hex(&bytes[start..end]).join(" ")));
+    window.extend(hex(&bytes[end..usize::min(end + CONTEXT, bytes.len())]));
+    window.join(" ")
 }
 
 #[derive(Clone, PartialEq)]
@@ -80,9 +134,9 @@ pub(crate) struct CodePoint(pub(crate) Rc<Source>, pub(crate) usize);
 
 impl CodePoint {
     #[allow(non_camel_case_types)]
-    type line = usize;
+    pub(crate) type line = usize;
     #[allow(non_camel_case_types)]
-    type index_in_line = usize;
+    pub(crate) type index_in_line = usize;
     pub(crate) fn span(self) -> Span {
         Span::single(self)
     }
@@ -92,11 +146,104 @@ impl CodePoint {
         let mut lines_split = first_part.split("\n").collect::<Vec<&str>>();
         (lines_split.len(), lines_split.pop().unwrap().len())
     }
+
+    /// 1-based line and column, unlike [`CodePoint::pos`] the column counts chars instead of bytes
+    pub(crate) fn line_col(&self) -> (usize, usize) {
+        let bytes = self.0.source.as_bytes();
+        let before = &bytes[..self.1];
+        let line_start = before.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+        // continuation bytes of multi-byte chars don't start a column, they are in the column of their char
+        let is_char_start = |b: &u8| b & 0xC0 != 0x80;
+        let col = before[line_start..].iter().filter(|b| is_char_start(b)).count()
+            + bytes.get(self.1).map_or(true, is_char_start) as usize;
+        (before.iter().filter(|b| **b == b'\n').count() + 1, col)
+    }
 }
 
 impl Debug for CodePoint {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let (l, i) = self.pos();
-        write!(f, "{}:{}", l, i+1)
+        let (l, c) = self.line_col();
+        write!(f, "{}:{}", l, c)
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::source::{CodePoint, Source};
+    use crate::util::testing::temp_dir;
+
+    #[test]
+    fn line_col_counts_chars() {
+        let source = Rc::new(Source::from_string("ab\ncé d\nx".to_string()));
+        let at = |i: usize| CodePoint(source.clone(), i).line_col();
+        assert_eq!(at(0), (1, 1));
+        // the line break is one past the end of its line
+        assert_eq!(at(2), (1, 3));
+        assert_eq!(at(3), (2, 1));
+        assert_eq!(at(4), (2, 2));
+        // both bytes of the `é` are in its column
+        assert_eq!(at(5), (2, 2));
+        assert_eq!(at(7), (2, 4));
+        assert_eq!(at(9), (3, 1));
+    }
+
+    #[test]
+    fn index_of_counts_chars() {
+        let source = Rc::new(Source::from_string("ab\ncé d\nx".to_string()));
+        assert_eq!(source.index_of(1, 1), Some(0));
+        assert_eq!(source.index_of(1, 3), Some(2));
+        assert_eq!(source.index_of(2, 2), Some(4));
+        // the char after the `é` starts two bytes later
+        assert_eq!(source.index_of(2, 3), Some(6));
+        assert_eq!(source.index_of(2, 4), Some(7));
+        assert_eq!(source.index_of(2, 5), Some(8));
+        assert_eq!(source.index_of(2, 6), None);
+        assert_eq!(source.index_of(1, 0), None);
+        assert_eq!(source.index_of(4, 1), None);
+        for i in [0, 2, 3, 4, 6, 7, 9] {
+            let (line, col) = CodePoint(source.clone(), i).line_col();
+            assert_eq!(source.index_of(line, col), Some(i), "{line}:{col}");
+        }
+    }
+
+    fn read_err(path: &std::path::Path, max_bytes: usize) -> String {
+        match Source::from_file(path.display().to_string(), max_bytes) {
+            Ok(_) => panic!("expected reading {} to fail", path.display()),
+            Err(e) => e.render(false)
+        }
+    }
+
+    #[test]
+    fn from_file_failures() {
+        let dir = temp_dir();
+        let err = read_err(&dir.join("missing.li"), 1024);
+        assert!(err.contains("missing.li does not exist"), "{err}");
+        let err = read_err(&dir, 1024);
+        assert!(err.contains("is a directory, expected a file"), "{err}");
+
+        let large = dir.join("large.li");
+        std::fs::write(&large, "fn main() {}").unwrap();
+        let err = read_err(&large, 4);
+        assert!(err.contains("large.li takes up 12 bytes, exceeding the limit of 4 bytes"), "{err}");
+        assert!(Source::from_file(large.display().to_string(), 12).is_ok());
+
+        let binary = dir.join("binary.li");
+        std::fs::write(&binary, b"fn main() { \xff\xfe }").unwrap();
+        let err = read_err(&binary, 1024);
+        assert!(err.contains("binary.li is not valid utf-8, at byte 12: 61 69 6e 28 29 20 7b 20 [ff] fe 20 7d"), "{err}");
+
+        let locked = dir.join("locked.li");
+        std::fs::write(&locked, "fn main() {}").unwrap();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            std::fs::set_permissions(&locked, std::fs::Permissions::from_mode(0o000)).unwrap();
+            // root reads it anyway
+            if std::fs::read(&locked).is_err() {
+                let err = read_err(&locked, 1024);
+                assert!(err.contains("no permission to read source file"), "{err}");
+            }
+        }
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
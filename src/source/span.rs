@@ -1,12 +1,24 @@
 use std::fmt::{Debug, Formatter};
 use std::rc::Rc;
 use crate::source::{CodePoint, Source};
+use crate::util::indexer::Indexable;
 
 #[derive(Clone)]
 pub(crate) struct Span {
     pub(crate) source: Rc<Source>,
     pub(crate) start: usize,
-    pub(crate) end: usize
+    pub(crate) end: usize,
+    /// `None` for code as written, set for code the compiler generated
+    pub(crate) expansion: Option<Rc<Expansion>>
+}
+
+/// Where generated code came from, so diagnostics in it can say so
+#[derive(Debug)]
+pub(crate) struct Expansion {
+    /// what generated the code, like "desugaring for loop"
+    pub(crate) description: String,
+    /// the code the generated code replaces
+    pub(crate) origin: Span
 }
 
 impl PartialEq for Span {
@@ -20,7 +32,8 @@ impl Span {
         Self {
             source: p.0,
             start: p.1,
-            end: p.1
+            end: p.1,
+            expansion: None
         }
     }
     #[deprecated(note=
@@ -32,7 +45,8 @@ impl Span {
         Self {
             source: Rc::new(Source::from_string("".to_string())),
             start: 0,
-            end: 0
+            end: 0,
+            expansion: None
         }
     }
 
@@ -40,12 +54,29 @@ impl Span {
         Rc::ptr_eq(&self.source, &other.source) && self.start == other.start && self.end == other.end
     }
 
+    pub(crate) fn in_bounds(&self) -> bool {
+        self.start <= self.end && self.end <= Indexable::len(&self.source)
+    }
+
+    pub(crate) fn is_within(&self, outer: &Span) -> bool {
+        Rc::ptr_eq(&self.source, &outer.source) && outer.start <= self.start && self.end <= outer.end
+    }
+
     pub(crate) fn from_points(a: CodePoint, b: CodePoint) -> Self{
         assert!(Rc::ptr_eq(&a.0, &b.0), "CodePoints should be of same Source");
         Self {
             source: a.0.clone(),
             start: usize::min(a.1, b.1),
-            end: usize::max(a.1, b.1)
+            end: usize::max(a.1, b.1),
+            expansion: None
+        }
+    }
+
+    /// The same span marked as code generated by `description`, from the code at this span
+    pub(crate) fn expanded<T: Into<String>>(&self, description: T) -> Self {
+        Self {
+            expansion: Some(Rc::new(Expansion { description: description.into(), origin: self.clone() })),
+            ..self.clone()
         }
     }
 
@@ -54,6 +85,11 @@ impl Span {
          CodePoint(self.source.clone(), self.end))
     }
 
+    /// Source code the span covers
+    pub(crate) fn text(&self) -> &str {
+        &self.source.source[self.start..usize::min(self.end + 1, self.source.source.len())]
+    }
+
     pub(crate) fn start(&self) -> CodePoint {
         CodePoint(self.source.clone(), self.start)
     }
@@ -75,23 +111,25 @@ impl Span {
     }
 
     pub(crate) fn render_span_code(&self, line_pad: usize) -> String {
-        let (sl, sp) = self.start().pos();
-        let (el, ep) = self.end().pos();
+        // carets are placed by char, so they stay under the code after multi-byte chars
+        let ((sl, sp), (el, ep)) = (self.start().line_col(), self.end().line_col());
+        let (sp, ep) = (sp - 1, ep - 1);
         let lines_split = &self.source.source.split("\n").collect::<Vec<&str>>();
         let mut render = vec![];
         for i in usize::max(sl.saturating_sub(line_pad), 1)..=usize::min(el+line_pad, lines_split.len()) {
             render.push(format!("{i:3} | {}", lines_split[i-1]));
+            let line_len = lines_split[i-1].chars().count();
             if i == sl && i == el {
                 render.push(format!("    | {}{}", " ".repeat(sp), "^".repeat(ep - sp + 1)));
             }
             else if i == sl {
-                render.push(format!("    | {}{}", " ".repeat(sp), "^".repeat(lines_split[i-1].len() - sp + 1)));
+                render.push(format!("    | {}{}", " ".repeat(sp), "^".repeat(line_len - sp + 1)));
             }
             else if i == el {
-                render.push(format!("    | {}{}", "^".repeat(ep + 1), " ".repeat(lines_split[i-1].len() - ep)));
+                render.push(format!("    | {}{}", "^".repeat(ep + 1), " ".repeat(line_len - ep)));
             }
             else if i > sl && i < el {
-                render.push(format!("    | {}", "^".repeat(lines_split[i-1].len())));
+                render.push(format!("    | {}", "^".repeat(line_len)));
             }
         }
         render.join("\n")
@@ -44,7 +44,11 @@ pub(crate) type glued = bool;
 pub(crate) enum TokenType {
     Particle(char, glued),
     Ident(String),
-    Literal(Literal)
+    /// loop label like `'outer`, stored without the leading quote
+    Label(String),
+    Literal(Literal),
+    /// `// ...` or `/* ... */` as written, only if the tokenizer keeps comments
+    Comment(String)
 }
 
 impl TokenType {
@@ -57,9 +61,14 @@ impl TokenType {
 pub(crate) enum Literal {
     String(String),
     Char(char),
-    Number(NumLit, Option<NumLitTy>),
+    /// the text of the literal as written, `None` for literals made up by the compiler
+    Number(NumLit, Option<NumLitTy>, Option<String>),
     Bool(bool),
-    Array(Vec<AstLiteral>, Type, usize),
+    /// the element type is `None` while all elements are number literals without suffix
+    Array(Vec<AstLiteral>, Option<Type>, usize),
+    Repeat(Box<AstLiteral>, usize),
+    /// `[u8; N]` stored compactly, from embedded files
+    Bytes(Vec<u8>),
 }
 
 #[derive(Debug, Clone, PartialEq)]
@@ -86,6 +95,45 @@ pub(crate) enum NumLitTy {
     F64,
 }
 
+impl NumLitTy {
+    pub(crate) fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "u8" => NumLitTy::U8,
+            "u16" => NumLitTy::U16,
+            "u32" => NumLitTy::U32,
+            "u64" => NumLitTy::U64,
+            "u128" => NumLitTy::U128,
+            "uptr" => NumLitTy::UPtr,
+            "i8" => NumLitTy::I8,
+            "i16" => NumLitTy::I16,
+            "i32" => NumLitTy::I32,
+            "i64" => NumLitTy::I64,
+            "i128" => NumLitTy::I128,
+            "iptr" => NumLitTy::IPtr,
+            "f32" => NumLitTy::F32,
+            "f64" => NumLitTy::F64,
+            _ => return None
+        })
+    }
+    pub(crate) fn is_float(&self) -> bool {
+        matches!(self, NumLitTy::F32 | NumLitTy::F64)
+    }
+    pub(crate) fn is_unsigned(&self) -> bool {
+        matches!(self, NumLitTy::U8 | NumLitTy::U16 | NumLitTy::U32 | NumLitTy::U64 | NumLitTy::U128 | NumLitTy::UPtr)
+    }
+    /// Width of integer types, `None` for floats and for `uptr` and `iptr`, which depend on the target
+    pub(crate) fn int_bits(&self) -> Option<u32> {
+        Some(match self {
+            NumLitTy::U8 | NumLitTy::I8 => 8,
+            NumLitTy::U16 | NumLitTy::I16 => 16,
+            NumLitTy::U32 | NumLitTy::I32 => 32,
+            NumLitTy::U64 | NumLitTy::I64 => 64,
+            NumLitTy::U128 | NumLitTy::I128 => 128,
+            _ => return None
+        })
+    }
+}
+
 impl Display for NumLitTy {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         write!(f, "{}", match self {
@@ -112,10 +160,12 @@ impl Debug for Literal {
         write!(f, "{}", match self {
             Literal::String(s) => format!("String(\"{s}\")"),
             Literal::Char(c) => format!("Char('{c}')"),
-            Literal::Number(NumLit::Integer(i), t) => format!("Integer({i}, {t:?})"),
-            Literal::Number(NumLit::Float(f), t) => format!("Float({f}, {t:?})"),
+            Literal::Number(NumLit::Integer(i), t, _) => format!("Integer({i}, {t:?})"),
+            Literal::Number(NumLit::Float(f), t, _) => format!("Float({f}, {t:?})"),
             Literal::Bool(b) => format!("Bool({b})"),
             Literal::Array(v, l, s) => format!("Array({v:?};{l:?})"),
+            Literal::Repeat(v, count) => format!("Repeat({v:?};{count})"),
+            Literal::Bytes(b) => format!("Bytes({})", b.len()),
         })
     }
 }
\ No newline at end of file
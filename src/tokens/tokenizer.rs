@@ -1,57 +1,106 @@
+use std::num::IntErrorKind;
 use std::rc::Rc;
 use std::str::FromStr;
-use std::str::pattern::Pattern;
 use crate::error::{OnParseErr, ParseError, ParseET};
 use crate::util::indexer::Indexer;
 use crate::source::{Source, SourceIter};
 use crate::source::span::Span;
 use crate::tokens::{Literal, NumLit, NumLitTy, Token, TokenType};
 
-pub(crate) fn tokenize(source: Source) -> Result<Vec<Token>, ParseError>{
-    let mut iter = Indexer::new(Rc::new(source));
+/// With `keep_comments` comments are [`TokenType::Comment`] tokens, otherwise they are skipped.
+pub(crate) fn tokenize(source: Rc<Source>, keep_comments: bool) -> Result<Vec<Token>, ParseError>{
+    let mut iter = Indexer::new(source);
     let mut tokens = vec![];
     while iter.elems_left() > 0 {
         match iter.this()? {
             '"' => {
-                let (string, span) = collect_until(&mut iter, true, true, false,
-                                                   |c| c != '"').e_when("tokenizing string literal".to_string())?;
+                let (_, span) = collect_until(&mut iter, true, true, false,
+                                              |c| c != '"').e_when("tokenizing string literal".to_string())?;
+                // like char literals, the text decodes chars that take up multiple bytes
+                let string = span.text()[1..span.text().len() - 1].to_string();
                 tokens.push(TokenType::Literal(Literal::String(string)).at(span));
             }
             '/' => {
+                let mut start = iter.here();
                 iter.next();
-                let r: Result<(), ParseError> = try {
-                    match iter.this()? {
-                        '/' => {
-                            let _comment = collect_until(&mut iter, true, true, false,
+                let r: Result<bool, ParseError> = try {
+                    match iter.this() {
+                        // up to the end of the line or file, the line break stays for the next token
+                        Ok('/') => {
+                            let _comment = collect_until(&mut iter, true, false, true,
                                                          |c| c != '\n').e_when("tokenizing single line comment".to_string())?;
+                            true
                         },
-                        '*' => {
+                        // nested comments each need their own `*/`, the openings not closed yet are kept for the error
+                        Ok('*') => {
+                            iter.index -= 1;
+                            let mut open: Vec<Span> = vec![];
                             loop {
-                                let _comment = collect_until(&mut iter, true, true, false,
-                                                             |c| c != '*').e_when("tokenizing single line comment".to_string())?;
-                                iter.next();
-                                if iter.this()? == '/' {
-                                    break
+                                match (iter.this(), iter.peek()) {
+                                    (Ok('/'), Ok('*')) => {
+                                        let mut start = iter.here();
+                                        iter.next();
+                                        start.combine(iter.here());
+                                        open.push(start);
+                                    }
+                                    (Ok('*'), Ok('/')) => {
+                                        iter.next();
+                                        open.pop();
+                                        if open.is_empty() {
+                                            break
+                                        }
+                                    }
+                                    (Err(_), _) => Err(ParseET::TokenizationError(format!("unterminated block comment, expected {} more */", open.len()))
+                                        .ats(open.clone()).when("tokenizing block comment"))?,
+                                    _ => ()
                                 }
+                                iter.next();
                             }
+                            true
                         }
                         _ => { // was just normal division slash or sth other
                             iter.index -= 1;
                             tokens.push(TokenType::Particle('/', if let Ok(t) = iter.peekn(-1) {
                                 !(t.is_ascii_alphanumeric() || t == '_' || t == ' ')
-                            } else {false}).at(iter.here()))
+                            } else {false}).at(iter.here()));
+                            false
                         }
                     }
                 };
-                r.e_when(String::from("tokenizing comment"))?;
+                if r.e_when(String::from("tokenizing comment"))? && keep_comments {
+                    start.combine(iter.here());
+                    tokens.push(TokenType::Comment(start.text().to_string()).at(start));
+                }
+            }
+            '\'' if iter.peek().map(|c| c.is_ascii_alphabetic() || c == '_').unwrap_or(false)
+                && iter.peekn(2).map(|c| c != '\'').unwrap_or(true) => {
+                let mut start = iter.here();
+                iter.next();
+                let (label, span) = collect_until(&mut iter, false, false, true,
+                                                  |c| c.is_ascii_alphanumeric() || c == '_').e_when("tokenizing label".to_string())?;
+                start.combine(span);
+                // `'ab'` is no label followed by a quote, but a char literal with too many chars
+                if iter.peek().map(|c| c == '\'').unwrap_or(false) {
+                    iter.next();
+                    start.combine(iter.here());
+                    return Err(ParseET::TokenizationError(format!("expected a single char, found '{label}'")).at(start).when("tokenizing char literal"))
+                }
+                tokens.push(TokenType::Label(label).at(start));
             }
             '\'' => {
-                let (char_src, span) = collect_until(&mut iter, true, true, false,
-                                                     |c| c != '\'').e_when("tokenizing char literal".to_string())?;
-                if char_src.len() != 1 {
-                    return Err(ParseET::TokenizationError(format!("Expected char, found: '{}'", char_src)).at(span))
+                let mut span = iter.here();
+                iter.next();
+                // up to the closing quote, skipping the char after a backslash so `'\''` doesn't end early
+                while iter.this().e_when("tokenizing char literal".to_string())? != '\'' {
+                    if iter.this()? == '\\' {
+                        iter.next();
+                    }
+                    iter.next();
                 }
-                let char = char_src.chars().nth(0).unwrap();
+                span.combine(iter.here());
+                // the source is read bytewise, so the text decodes chars that take up multiple bytes
+                let char_src = &span.text()[1..span.text().len() - 1];
+                let char = unescape_char(char_src, &span).e_when("tokenizing char literal".to_string())?;
                 tokens.push(TokenType::Literal(Literal::Char(char)).at(span));
             }
             c if c.is_whitespace() => {
@@ -67,12 +116,44 @@ pub(crate) fn tokenize(source: Source) -> Result<Vec<Token>, ParseError>{
                 }.at(span));
             }
             c if c.is_ascii_digit() => {
-                iter.index -= 1;
-                let (num, span) = collect_until(&mut iter, true, true, true,
-                                                |c| c.is_ascii_alphanumeric() || c == '_').e_when("tokenizing number literal".to_string())?;
-                iter.index -= 1;
-                let (lit, ty) = str_to_num_lit(num).e_at(span.clone())?;
-                tokens.push(TokenType::Literal(Literal::Number(lit, ty)).at(span));
+                // the suffix is part of the literal, whatever follows it (like an operator) is not
+                let (mut num, mut span) = collect_until(&mut iter, false, false, true,
+                                                        |c| c.is_ascii_alphanumeric() || c == '_').e_when("tokenizing number literal".to_string())?;
+                let radix_prefix = num.starts_with('0') && num.chars().nth(1).map(|c| "bqozx".contains(c)).unwrap_or(false);
+                let digit_at = |iter: &SourceIter, n: isize| iter.peekn(n).map(|c| c.is_ascii_digit()).unwrap_or(false);
+                // `tuple.1.0` accesses elements, the `1` is not the start of a float
+                let tuple_element = matches!(tokens.last(), Some(Token { tt: TokenType::Particle('.', _), .. }))
+                    && !matches!(tokens.iter().rev().nth(1), Some(Token { tt: TokenType::Particle('.', _), .. }));
+                // `2.5`, but not `0..5` or `5.max()`
+                if !radix_prefix && !tuple_element && iter.peek().map(|c| c == '.').unwrap_or(false) && digit_at(&iter, 2) {
+                    iter.next();
+                    iter.next();
+                    let (fraction, fraction_span) = collect_until(&mut iter, false, false, true,
+                                                                  |c| c.is_ascii_alphanumeric() || c == '_').e_when("tokenizing number literal".to_string())?;
+                    num = format!("{num}.{fraction}");
+                    span.combine(fraction_span);
+                }
+                // `2.5e-3`, the sign of the exponent, which would be an operator otherwise
+                if !radix_prefix && !tuple_element && num.ends_with(['e', 'E']) && iter.peek().map(|c| c == '+' || c == '-').unwrap_or(false) && digit_at(&iter, 2) {
+                    iter.next();
+                    let sign = iter.this()?;
+                    iter.next();
+                    let (exponent, exponent_span) = collect_until(&mut iter, false, false, true,
+                                                                  |c| c.is_ascii_alphanumeric() || c == '_').e_when("tokenizing number literal".to_string())?;
+                    num = format!("{num}{sign}{exponent}");
+                    span.combine(exponent_span);
+                }
+                // `5'u8`, the suffix after an explicit separator
+                if iter.peek().map(|c| c == '\'').unwrap_or(false) && iter.peekn(2).map(|c| c.is_ascii_alphabetic()).unwrap_or(false) {
+                    iter.next();
+                    iter.next();
+                    let (suffix, suffix_span) = collect_until(&mut iter, false, false, true,
+                                                              |c| c.is_ascii_alphanumeric() || c == '_').e_when("tokenizing number literal".to_string())?;
+                    num = format!("{num}'{suffix}");
+                    span.combine(suffix_span);
+                }
+                let (lit, ty) = str_to_num_lit(&num, &span).e_when("tokenizing number literal".to_string())?;
+                tokens.push(TokenType::Literal(Literal::Number(lit, ty, Some(num))).at(span));
             }
             c => tokens.push(TokenType::Particle(c,
                 tokens.last().map(|l| if let TokenType::Particle(_, _) = l.tt { true } else { false }).unwrap_or(false)
@@ -108,81 +189,278 @@ fn collect_until(iter: &mut SourceIter, skip_first: bool, consume_break: bool, a
     Ok((result, start))
 }
 
-pub(crate) fn str_to_num_lit(mut num: String) -> Result<(NumLit, Option<NumLitTy>), ParseError>{
-    num = num.replace('_', "");
-    let radix = if num.len() > 2 {
-        if num.chars().nth(0).unwrap() == '0' {
-            let r = match num.chars().nth(1).unwrap() {
-                'b' => Some(0b10), // binary
-                'q' => Some(4),    // quaternal
-                'o' => Some(0o10), // octal
-                'z' => Some(12),   // dozenal
-                'x' => Some(0x10), // hexadecimal
-                _ => None         // decimal (or invalid)
-            };
-            if let Some(r) = r {
-                num.remove(0);
-                num.remove(0);
-                r
-            } else { 10 }
-        } else { 10 }
-    } else { 10 };
-    let float_like = num.contains('.');
-    if float_like && radix != 10 {
-        return Err(ParseET::LiteralError(Literal::Number(NumLit::Float(0f64), None), format!("expected radix 10 for floating point literal, found {radix}")).error())
-    }
-    let mut float_like_ty = false;
-    let ty = {
-        let i = (|| {
-            for (i, c) in num.chars().enumerate() {
-                if c.is_numeric() || (float_like && c == '.') || (!float_like && c.is_contained_in("abcdefABCDEF") && (radix == 16 || && c != &&'f')) {
-                    continue
+/// The char a char literal stands for, `src` being the text between its quotes.
+/// Escapes are `\n`, `\r`, `\t`, `\0`, `\\`, `\'`, `\"`, `\xNN` up to `\x7f` and `\u{NNNN}` with up to 6 hex digits.
+fn unescape_char(src: &str, loc: &Span) -> Result<char, ParseError> {
+    let mut chars = src.chars();
+    let c = match chars.next() {
+        None => return Err(ParseET::TokenizationError("empty char literal, expected a char between the quotes".to_string()).at(loc.clone())),
+        Some('\\') => match chars.next() {
+            Some('n') => '\n',
+            Some('r') => '\r',
+            Some('t') => '\t',
+            Some('0') => '\0',
+            Some(c @ ('\\' | '\'' | '"')) => c,
+            Some('x') => {
+                let hex = chars.by_ref().take(2).collect::<String>();
+                match u8::from_str_radix(&hex, 16) {
+                    Ok(b) if hex.len() == 2 && b.is_ascii() => b as char,
+                    _ => return Err(ParseET::TokenizationError(format!("invalid escape \\x{hex}, expected two hex digits up to \\x7f")).at(loc.clone()))
                 }
-                return Some(i)
             }
-            None
-        })();
-        if let Some(i) = i {
-            let (n, t) = {
-                let s = num.split_at(i);
-                (s.0.to_string(), s.1.to_string())
-            };
-            num = n;
-            let t = match t.as_str() {
-                "u8" => NumLitTy::U8,
-                "u16" => NumLitTy::U16,
-                "u32" => NumLitTy::U32,
-                "u64" => NumLitTy::U64,
-                "u128" => NumLitTy::U128,
-                "uptr" => NumLitTy::UPtr,
-                "i8" => NumLitTy::I8,
-                "i16" => NumLitTy::I16,
-                "i32" => NumLitTy::I32,
-                "i64" => NumLitTy::I64,
-                "i128" => NumLitTy::I128,
-                "iptr" => NumLitTy::IPtr,
-                "f32" => { float_like_ty = true; NumLitTy::F32 },
-                "f64" => { float_like_ty = true; NumLitTy::F64 },
-                t => return Err(ParseET::LiteralError(Literal::Number(if float_like {
-                    NumLit::Float(0f64)
-                } else {
-                    NumLit::Integer(0)
-                }, None), format!("unsupported type suffix: '{t}'")).error())
-            };
-            if float_like && !float_like_ty {
-                return Err(ParseET::LiteralError(Literal::Number(NumLit::Float(0f64), None), format!("expected floating point type for floating point literal, found '{t}'")).error())
+            Some('u') => {
+                let rest = chars.as_str();
+                let hex = rest.strip_prefix('{').and_then(|r| r.split_once('}')).map(|(hex, _)| hex)
+                    .ok_or_else(|| ParseET::TokenizationError(format!("invalid escape \\u{rest}, expected hex digits in braces like \\u{{1F600}}")).at(loc.clone()))?;
+                chars = rest[hex.len() + 2..].chars();
+                match u32::from_str_radix(hex, 16).ok().filter(|_| (1..=6).contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit())).and_then(char::from_u32) {
+                    Some(c) => c,
+                    None => return Err(ParseET::TokenizationError(format!("invalid escape \\u{{{hex}}}, expected up to 6 hex digits of a unicode scalar value")).at(loc.clone()))
+                }
             }
-            Some(t)
-        } else { None }
+            Some(c) => return Err(ParseET::TokenizationError(format!("unknown escape \\{c}")).at(loc.clone())),
+            None => return Err(ParseET::TokenizationError("incomplete escape, expected a char after \\".to_string()).at(loc.clone()))
+        },
+        Some(c) => c
     };
-    let lit = if float_like || float_like_ty {
-        f64::from_str(&num).map(|f|NumLit::Float(f)).map_err(|_|
-            ParseET::LiteralError(Literal::Number(NumLit::Float(0f64), None), format!("invalid float literal")).error()
+    if chars.next().is_some() {
+        return Err(ParseET::TokenizationError(format!("expected a single char, found '{src}'")).at(loc.clone()))
+    }
+    Ok(c)
+}
+
+/// Parses the text of a number literal spanning `loc`, errors point at the offending part of it.
+/// Decimal digits may have a fraction and an exponent, as `2.5e-3`.
+/// The digits may be followed by a type suffix, as `5u8`, `5_u8` or `5'u8`.
+/// Without the `'` separator letters that are digits of the radix belong to the number, so `0x10'f32` needs it.
+pub(crate) fn str_to_num_lit(num: &str, loc: &Span) -> Result<(NumLit, Option<NumLitTy>), ParseError>{
+    // chars `start..end` of the literal
+    let part = |start: usize, end: usize| Span { source: loc.source.clone(), start: loc.start + start, end: loc.start + usize::max(start, end - 1), expansion: None };
+    let radix = match (num.len() > 2, num.strip_prefix('0').and_then(|n| n.chars().next())) {
+        (true, Some('b')) => 0b10, // binary
+        (true, Some('q')) => 4,    // quaternal
+        (true, Some('o')) => 0o10, // octal
+        (true, Some('z')) => 12,   // dozenal
+        (true, Some('x')) => 0x10, // hexadecimal
+        _ => 10                    // decimal (or invalid)
+    };
+    let prefix_len = if radix == 10 { 0 } else { 2 };
+    let radix_name = match radix { 2 => "binary", 4 => "quaternal", 8 => "octal", 12 => "dozenal", 16 => "hexadecimal", _ => "decimal" };
+    let invalid_digit = |i: usize, c: char| ParseET::LiteralError(Literal::Number(NumLit::Integer(0), None, Some(num.to_string())),
+        format!("invalid digit '{c}' in {radix_name} literal '{num}'")).at(part(i, i + c.len_utf8()));
+    let mantissa_end = num.char_indices().skip(prefix_len)
+        .find(|(_, c)| !(c.is_digit(radix) || *c == '_' || (radix == 10 && *c == '.')))
+        .map(|(i, _)| i)
+        .unwrap_or(num.len());
+    // `1e9` or `2.5e-3`, no suffix starts with an `e`
+    let exponent_end = match num[mantissa_end..].chars().next() {
+        Some('e' | 'E') if radix == 10 => {
+            let sign = usize::from(num[mantissa_end + 1..].starts_with(['+', '-']));
+            let digits = num[mantissa_end + 1 + sign..].chars().take_while(|c| c.is_ascii_digit() || *c == '_').count();
+            if digits == 0 {
+                return Err(ParseET::LiteralError(Literal::Number(NumLit::Float(0f64), None, Some(num.to_string())),
+                    format!("expected the digits of the exponent after '{}' in '{num}'", &num[mantissa_end..mantissa_end + 1 + sign])).at(part(mantissa_end, mantissa_end + 1 + sign)))
+            }
+            Some(mantissa_end + 1 + sign + digits)
+        }
+        _ => None
+    };
+    let float_like = num.contains('.') || exponent_end.is_some();
+    if float_like && radix != 10 {
+        return Err(ParseET::LiteralError(Literal::Number(NumLit::Float(0f64), None, Some(num.to_string())), format!("expected radix 10 for floating point literal '{num}', found {radix}")).at(part(0, prefix_len)))
+    }
+    let (digits_end, suffix_start) = match num.find('\'') {
+        Some(sep) => (sep, sep + 1),
+        None => {
+            let end = exponent_end.unwrap_or(mantissa_end);
+            (end, end)
+        }
+    };
+    let (digits, suffix) = (&num[..digits_end], &num[suffix_start..]);
+    let ty = if suffix.is_empty() { None } else {
+        // `0b102` or `0xFG`, where no suffix can start
+        match suffix.chars().next() {
+            Some(c) if suffix_start == digits_end && NumLitTy::from_name(suffix).is_none() && (c.is_ascii_digit() || radix == 16) =>
+                return Err(invalid_digit(suffix_start, c)),
+            _ => ()
+        }
+        let t = NumLitTy::from_name(suffix).ok_or_else(|| ParseET::LiteralError(Literal::Number(if float_like {
+            NumLit::Float(0f64)
+        } else {
+            NumLit::Integer(0)
+        }, None, Some(num.to_string())), format!("unsupported type suffix '{suffix}' of '{num}'")).at(part(suffix_start, num.len())))?;
+        if float_like && !t.is_float() {
+            return Err(ParseET::LiteralError(Literal::Number(NumLit::Float(0f64), None, Some(num.to_string())), format!("expected floating point type for floating point literal '{num}', found '{suffix}'")).at(part(suffix_start, num.len())))
+        }
+        Some(t)
+    };
+    let digits_loc = part(prefix_len, digits_end);
+    let digits = digits[prefix_len..].replace('_', "");
+    let lit = if float_like {
+        f64::from_str(&digits).map(|f|NumLit::Float(f)).map_err(|_|
+            ParseET::LiteralError(Literal::Number(NumLit::Float(0f64), None, Some(num.to_string())), format!("invalid float literal '{num}'")).at(digits_loc)
         )
     } else {
-        u128::from_str_radix(&num, radix).map(|i|NumLit::Integer(i)).map_err(|_|
-            ParseET::LiteralError(Literal::Number(NumLit::Integer(0), None), format!("invalid integer literal")).error()
+        // digits of any radix with a float suffix, like `0x10'f32`
+        let float = ty.as_ref().map(|t| t.is_float()).unwrap_or(false);
+        u128::from_str_radix(&digits, radix).map(|i| if float { NumLit::Float(i as f64) } else { NumLit::Integer(i) }).map_err(|e|
+            // digits before an explicit separator aren't checked yet, like the `2` in `0b102'u8`
+            match num[prefix_len..digits_end].char_indices().find(|(_, c)| !(c.is_digit(radix) || *c == '_')) {
+                Some((i, c)) => invalid_digit(prefix_len + i, c),
+                None if *e.kind() == IntErrorKind::PosOverflow => ParseET::LiteralError(Literal::Number(NumLit::Integer(0), None, Some(num.to_string())),
+                    format!("literal {num} does not fit in any integer type (max {})", u128::MAX)).at(digits_loc),
+                None => ParseET::LiteralError(Literal::Number(NumLit::Integer(0), None, Some(num.to_string())), format!("invalid integer literal '{num}'")).at(digits_loc)
+            }
         )
     }?;
     Ok((lit, ty))
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use std::rc::Rc;
+    use crate::source::Source;
+    use crate::source::span::Span;
+    use crate::tokens::{Literal, NumLit, NumLitTy, TokenType};
+    use crate::tokens::tokenizer::{str_to_num_lit, tokenize};
+
+    fn tokens(code: &str) -> Result<Vec<TokenType>, String> {
+        tokenize(Rc::new(Source::from_string(code.to_string())), false)
+            .map(|tokens| tokens.into_iter().map(|t| t.tt).collect())
+            .map_err(|e| e.render(false))
+    }
+
+    fn num(code: &str) -> Result<(NumLit, Option<NumLitTy>), String> {
+        let loc = Span { source: Rc::new(Source::from_string(code.to_string())), start: 0, end: code.len() - 1, expansion: None };
+        str_to_num_lit(code, &loc).map_err(|e| e.render(false))
+    }
+
+    /// The tokens as written with their position, like `a@1:1`
+    fn located(code: &str, keep_comments: bool) -> Result<Vec<String>, String> {
+        let tokens = tokenize(Rc::new(Source::from_string(code.to_string())), keep_comments).map_err(|e| e.render(false))?;
+        Ok(tokens.into_iter().map(|t| format!("{}@{:?}", t.loc.text(), t.loc)).collect())
+    }
+
+    fn char_lit(code: &str) -> Result<char, String> {
+        match tokens(code)?.as_slice() {
+            [TokenType::Literal(Literal::Char(c))] => Ok(*c),
+            other => panic!("expected a single char literal, found {other:?}")
+        }
+    }
+
+    #[test]
+    fn unicode_char_literals() {
+        assert_eq!(char_lit("'a'"), Ok('a'));
+        assert_eq!(char_lit("'€'"), Ok('€'));
+        assert_eq!(char_lit("'\\u{1F600}'"), Ok('😀'));
+        assert_eq!(char_lit("'\\u{41}'"), Ok('A'));
+        for invalid in ["'\\u{}'", "'\\u{1234567}'", "'\\u{D800}'", "'\\u{+41}'", "'\\u41'", "'\\u{41'"] {
+            let err = char_lit(invalid).unwrap_err();
+            assert!(err.contains("invalid escape \\u"), "{invalid}: {err}");
+        }
+    }
+
+    #[test]
+    fn number_followed_by_operator() {
+        let int = |i: u128, text: &str| TokenType::Literal(Literal::Number(NumLit::Integer(i), None, Some(text.to_string())));
+        assert_eq!(tokens("5+3"), Ok(vec![int(5, "5"), TokenType::Particle('+', false), int(3, "3")]));
+        assert_eq!(tokens("5u8*2"), Ok(vec![
+            TokenType::Literal(Literal::Number(NumLit::Integer(5), Some(NumLitTy::U8), Some("5u8".to_string()))),
+            TokenType::Particle('*', false),
+            int(2, "2")
+        ]));
+    }
+
+    #[test]
+    fn numbers_without_suffix() {
+        let five = TokenType::Literal(Literal::Number(NumLit::Integer(5), None, Some("5".to_string())));
+        assert_eq!(tokens("5;"), Ok(vec![five.clone(), TokenType::Particle(';', false)]));
+        assert_eq!(tokens("5)"), Ok(vec![five.clone(), TokenType::Particle(')', false)]));
+        assert_eq!(tokens("5"), Ok(vec![five]));
+    }
+
+    #[test]
+    fn number_suffixes() {
+        assert_eq!(num("5"), Ok((NumLit::Integer(5), None)));
+        assert_eq!(num("5u8"), Ok((NumLit::Integer(5), Some(NumLitTy::U8))));
+        assert_eq!(num("5_i64"), Ok((NumLit::Integer(5), Some(NumLitTy::I64))));
+        assert_eq!(num("0x10'f32"), Ok((NumLit::Float(16.0), Some(NumLitTy::F32))));
+        let err = num("5u9").unwrap_err();
+        assert!(err.contains("unsupported type suffix 'u9' of '5u9'") && err.contains("<string>: 1:2..1:3"), "{err}");
+        let err = num("0b102").unwrap_err();
+        assert!(err.contains("invalid digit '2' in binary literal '0b102'") && err.contains("<string>: 1:5\n"), "{err}");
+    }
+
+    #[test]
+    fn char_literals() {
+        assert_eq!(char_lit("'A'"), Ok('A'));
+        assert_eq!(char_lit("'\\n'"), Ok('\n'));
+        assert_eq!(char_lit("'\\''"), Ok('\''));
+        assert_eq!(char_lit("'\\\\'"), Ok('\\'));
+        assert_eq!(char_lit("'\\0'"), Ok('\0'));
+        assert_eq!(char_lit("'\\x41'"), Ok('A'));
+        let err = char_lit("''").unwrap_err();
+        assert!(err.contains("empty char literal") && err.contains("<string>: 1:1..1:2"), "{err}");
+        let err = char_lit("'ab'").unwrap_err();
+        assert!(err.contains("expected a single char, found 'ab'") && err.contains("<string>: 1:1..1:4"), "{err}");
+        let err = char_lit("'\\x80'").unwrap_err();
+        assert!(err.contains("invalid escape \\x80"), "{err}");
+        let err = char_lit("'\\q'").unwrap_err();
+        assert!(err.contains("unknown escape \\q"), "{err}");
+        // a label, not a char literal
+        assert_eq!(tokens("'outer"), Ok(vec![TokenType::Label("outer".to_string())]));
+    }
+
+    #[test]
+    fn float_exponents() {
+        assert_eq!(num("1e9f64"), Ok((NumLit::Float(1e9), Some(NumLitTy::F64))));
+        assert_eq!(num("2.5e-3f32"), Ok((NumLit::Float(2.5e-3), Some(NumLitTy::F32))));
+        assert_eq!(num("1E+2"), Ok((NumLit::Float(100.0), None)));
+        // the sign is only part of the literal if digits follow it
+        let float = |f: f64, text: &str| TokenType::Literal(Literal::Number(NumLit::Float(f), Some(NumLitTy::F32), Some(text.to_string())));
+        assert_eq!(tokens("2.5e-3f32"), Ok(vec![float(2.5e-3, "2.5e-3f32")]));
+        let err = num("1e").unwrap_err();
+        assert!(err.contains("expected the digits of the exponent after 'e' in '1e'") && err.contains("<string>: 1:2\n"), "{err}");
+        let err = num("1e-f32").unwrap_err();
+        assert!(err.contains("expected the digits of the exponent after 'e-' in '1e-f32'") && err.contains("<string>: 1:2..1:3"), "{err}");
+        let err = num("1e5u8").unwrap_err();
+        assert!(err.contains("expected floating point type for floating point literal '1e5u8', found 'u8'"), "{err}");
+    }
+
+    #[test]
+    fn string_literals_keep_utf8() {
+        assert_eq!(tokens("\"é€\""), Ok(vec![TokenType::Literal(Literal::String("é€".to_string()))]));
+        assert_eq!(tokens("\"\""), Ok(vec![TokenType::Literal(Literal::String(String::new()))]));
+    }
+
+    #[test]
+    fn line_comments() {
+        let code = "// head\nlet a = b; // after\n// between\nc;\n// last";
+        assert_eq!(located(code, false), Ok(["let@2:1..2:3", "a@2:5", "=@2:7", "b@2:9", ";@2:10", "c@4:1", ";@4:2"].map(String::from).to_vec()));
+        assert_eq!(located(code, true), Ok(["// head@1:1..1:7", "let@2:1..2:3", "a@2:5", "=@2:7", "b@2:9", ";@2:10", "// after@2:12..2:19",
+            "// between@3:1..3:10", "c@4:1", ";@4:2", "// last@5:1..5:7"].map(String::from).to_vec()));
+        assert_eq!(located("a //", false), Ok(vec!["a@1:1".to_string()]));
+        assert_eq!(located("a / b", false), Ok(["a@1:1", "/@1:3", "b@1:5"].map(String::from).to_vec()));
+    }
+
+    #[test]
+    fn nested_block_comments() {
+        let code = "a /* outer /* inner */ still\ncomment */ b\n/**/c";
+        assert_eq!(located(code, false), Ok(["a@1:1", "b@2:12", "c@3:5"].map(String::from).to_vec()));
+        assert_eq!(located(code, true), Ok(["a@1:1", "/* outer /* inner */ still\ncomment */@1:3..2:10", "b@2:12", "/**/@3:1..3:4", "c@3:5"].map(String::from).to_vec()));
+        let err = located("a\n  /* never closed", false).unwrap_err();
+        assert!(err.contains("unterminated block comment, expected 1 more */") && err.contains("<string>: 2:3..2:4\n"), "{err}");
+    }
+
+    #[test]
+    fn code_mixed_with_both_comment_styles() {
+        let code = "fn f() { // starts\n    /* a // line comment inside\n    */ x /* y */ + z // */\n}";
+        assert_eq!(located(code, false), Ok(["fn@1:1..1:2", "f@1:4", "(@1:5", ")@1:6", "{@1:8", "x@3:8", "+@3:18", "z@3:20", "}@4:1"].map(String::from).to_vec()));
+        // a `/*` inside a line comment opens nothing
+        assert_eq!(located("a // /*\nb", false), Ok(["a@1:1", "b@2:1"].map(String::from).to_vec()));
+        let err = located("/* one\n/* two */\n  /* three\n/* four", false).unwrap_err();
+        assert!(err.contains("unterminated block comment, expected 3 more */"), "{err}");
+        assert!(err.contains("<string>: 1:1..1:2\n") && err.contains("<string>: 3:3..3:4\n") && err.contains("<string>: 4:1..4:2\n"), "{err}");
+        assert!(!err.contains("<string>: 2:1..2:2\n"), "{err}");
+    }
+}
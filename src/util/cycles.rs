@@ -0,0 +1,80 @@
+use std::collections::HashSet;
+use std::fmt::Display;
+use std::hash::Hash;
+use crate::error::{ParseError, ParseET};
+use crate::source::span::Span;
+
+/// Depth first visitor state for definitions that refer to each other, detecting cycles between them
+pub(crate) struct Cycles<K> {
+    done: HashSet<K>,
+    /// the definitions currently being visited, outermost first
+    path: Vec<K>
+}
+
+impl<K: Eq + Hash + Clone> Cycles<K> {
+    pub(crate) fn new() -> Self {
+        Self { done: HashSet::new(), path: vec![] }
+    }
+
+    /// Starts visiting `key`, until [`Cycles::leave`]. `Ok(false)` if it was visited already,
+    /// the cycle from `key` back to itself if it is being visited.
+    pub(crate) fn enter(&mut self, key: &K) -> Result<bool, Vec<K>> {
+        if self.done.contains(key) {
+            return Ok(false)
+        }
+        if let Some(start) = self.path.iter().position(|k| k == key) {
+            return Err(self.path[start..].to_vec())
+        }
+        self.path.push(key.clone());
+        Ok(true)
+    }
+
+    /// Finishes visiting the innermost definition
+    pub(crate) fn leave(&mut self) {
+        if let Some(key) = self.path.pop() {
+            self.done.insert(key);
+        }
+    }
+}
+
+/// `what A is recursive: A -> B -> A`, at the definition of each part of the cycle
+pub(crate) fn cycle_error<K: Display>(what: &str, cycle: &[K], loc: impl Fn(&K) -> Span) -> ParseError {
+    let names = cycle.iter().chain(cycle.first()).map(|k| k.to_string()).collect::<Vec<_>>();
+    ParseET::CompilationError(format!("{what} {} is recursive: {}", names[0], names.join(" -> ")))
+        .ats(cycle.iter().map(loc).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::cycles::Cycles;
+
+    #[test]
+    fn finds_the_cycle_back_to_the_entered_key() {
+        let mut cycles = Cycles::new();
+        assert_eq!(cycles.enter(&"A"), Ok(true));
+        assert_eq!(cycles.enter(&"B"), Ok(true));
+        assert_eq!(cycles.enter(&"C"), Ok(true));
+        assert_eq!(cycles.enter(&"B"), Err(vec!["B", "C"]));
+        assert_eq!(cycles.enter(&"A"), Err(vec!["A", "B", "C"]));
+    }
+
+    #[test]
+    fn visited_keys_are_no_cycle() {
+        let mut cycles = Cycles::new();
+        assert_eq!(cycles.enter(&"A"), Ok(true));
+        assert_eq!(cycles.enter(&"B"), Ok(true));
+        cycles.leave();
+        // A refers to B twice, like `type A = (B, B)`
+        assert_eq!(cycles.enter(&"B"), Ok(false));
+        cycles.leave();
+        assert_eq!(cycles.enter(&"A"), Ok(false));
+        assert_eq!(cycles.enter(&"A"), Ok(false));
+    }
+
+    #[test]
+    fn self_reference_is_a_cycle() {
+        let mut cycles = Cycles::new();
+        assert_eq!(cycles.enter(&"A"), Ok(true));
+        assert_eq!(cycles.enter(&"A"), Err(vec!["A"]));
+    }
+}
@@ -0,0 +1,49 @@
+/// Whether `text` matches `pattern`, where `*` stands for any number of chars and `?` for exactly one
+pub(crate) fn glob_matches(pattern: &str, text: &str) -> bool {
+    let (pattern, text) = (pattern.chars().collect::<Vec<_>>(), text.chars().collect::<Vec<_>>());
+    // the last `*` seen and where in the text it started matching, to retry with it matching one more char
+    let (mut p, mut t, mut star) = (0, 0, None);
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false
+            }
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::util::glob::glob_matches;
+
+    #[test]
+    fn wildcards() {
+        assert!(glob_matches("main", "main"));
+        assert!(!glob_matches("main", "mains"));
+        assert!(glob_matches("*", ""));
+        assert!(glob_matches("build_*", "build_fn"));
+        assert!(glob_matches("*_fn", "build_fn"));
+        assert!(!glob_matches("build_*", "rebuild_fn"));
+        assert!(glob_matches("a?c", "abc"));
+        assert!(!glob_matches("a?c", "ac"));
+        // the first `*` has to give up chars for the rest to match
+        assert!(glob_matches("*a*b", "xaxab"));
+        assert!(!glob_matches("*a*b", "xaxa"));
+        assert!(glob_matches("f(*)", "f(i32,u8)"));
+        assert!(glob_matches("é?", "éü"));
+    }
+}
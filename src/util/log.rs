@@ -0,0 +1,161 @@
+use std::cell::{Cell, RefCell};
+use std::sync::OnceLock;
+use crate::error::{ParseError, ParseET, Phase};
+
+// internal logging of the compiler to stderr, enabled by `-v` or `LITHIA_LOG=codegen=debug,parser=trace`
+
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+pub(crate) enum Level {
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace
+}
+
+impl Level {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "error" => Level::Error,
+            "warn" => Level::Warn,
+            "info" => Level::Info,
+            "debug" => Level::Debug,
+            "trace" => Level::Trace,
+            _ => return None
+        })
+    }
+}
+
+/// Part of the compiler a log line comes from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Target {
+    Tokenizer,
+    Parser,
+    Typeck,
+    Codegen
+}
+
+impl Target {
+    const ALL: [Target; 4] = [Target::Tokenizer, Target::Parser, Target::Typeck, Target::Codegen];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Target::Tokenizer => "tokenizer",
+            Target::Parser => "parser",
+            Target::Typeck => "typeck",
+            Target::Codegen => "codegen"
+        }
+    }
+}
+
+/// Most verbose level enabled per target, indexed like [`Target::ALL`]
+static FILTER: OnceLock<[Option<Level>; 4]> = OnceLock::new();
+
+thread_local! {
+    static PHASE: Cell<&'static str> = Cell::new("starting");
+    static CONTEXT: RefCell<Vec<String>> = RefCell::new(vec![]);
+}
+
+/// `-v` enables info, `-vv` debug and `-vvv` trace logs of every target.
+/// `LITHIA_LOG` is a comma separated list of `target=level` or a plain `level` for every target, it wins over `-v`.
+pub(crate) fn init(verbosity: u8) -> Result<(), ParseError> {
+    let mut filter = [match verbosity {
+        0 => None,
+        1 => Some(Level::Info),
+        2 => Some(Level::Debug),
+        _ => Some(Level::Trace)
+    }; 4];
+    let spec = std::env::var("LITHIA_LOG").unwrap_or_default();
+    for directive in spec.split(',').map(str::trim).filter(|d| !d.is_empty()) {
+        let (targets, level) = match directive.split_once('=') {
+            Some((target, level)) => {
+                let i = Target::ALL.iter().position(|t| t.name() == target).ok_or_else(|| ParseET::ArgumentError(
+                    format!("expected one of tokenizer, parser, typeck or codegen in LITHIA_LOG, found '{target}'")).error())?;
+                (i..i + 1, level)
+            }
+            None => (0..Target::ALL.len(), directive)
+        };
+        let level = Level::from_name(level).ok_or_else(|| ParseET::ArgumentError(
+            format!("expected one of error, warn, info, debug or trace in LITHIA_LOG, found '{level}'")).error())?;
+        filter[targets].fill(Some(level));
+    }
+    // the first filter stays, like the first parsed arguments do
+    let _ = FILTER.set(filter);
+    Ok(())
+}
+
+pub(crate) fn enabled(target: Target, level: Level) -> bool {
+    FILTER.get().map_or(false, |filter| filter[Target::ALL.iter().position(|t| *t == target).unwrap()] >= Some(level))
+}
+
+/// Names the phase following log lines happen in, like the phase errors are reported in
+pub(crate) fn set_phase(phase: Phase) {
+    PHASE.with(|p| p.set(phase.name()));
+}
+
+/// Runs `f`, log lines in the meantime say they happened while doing `context`
+pub(crate) fn with_context<T>(context: String, f: impl FnOnce() -> T) -> T {
+    CONTEXT.with(|c| c.borrow_mut().push(context));
+    let r = f();
+    CONTEXT.with(|c| c.borrow_mut().pop());
+    r
+}
+
+/// Writes the line prefixed by its level, target, phase and context, use [`log!`](crate::log) to skip formatting disabled lines
+pub(crate) fn write(target: Target, level: Level, message: String) {
+    let context = CONTEXT.with(|c| c.borrow().iter().map(|c| format!(" > {c}")).collect::<String>());
+    eprintln!("[{} {}] {}{context}: {message}", format!("{level:?}").to_lowercase(), target.name(), PHASE.with(|p| p.get()));
+}
+
+/// `log!(Codegen, Debug, "building {symbol}")`
+#[macro_export]
+macro_rules! log {
+    ($target:ident, $level:ident, $($arg:tt)*) => (
+        if $crate::util::log::enabled($crate::util::log::Target::$target, $crate::util::log::Level::$level) {
+            $crate::util::log::write($crate::util::log::Target::$target, $crate::util::log::Level::$level, format!($($arg)*))
+        }
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+    use crate::util::log::init;
+    use crate::util::testing::build;
+
+    /// The filter can only be set once per process, so every configuration runs [`log_in_child`] in its own test process
+    const CHILD: &str = "LITHIA_LOG_TEST_VERBOSITY";
+
+    #[test]
+    fn log_in_child() {
+        let Ok(verbosity) = std::env::var(CHILD) else { return };
+        init(verbosity.parse().unwrap()).unwrap();
+        build("#[export]\nfn answer() -> i32 {\n    42\n}", &["--shared"]).unwrap();
+    }
+
+    fn stderr_of(verbosity: u8, spec: Option<&str>) -> String {
+        let mut child = Command::new(std::env::current_exe().unwrap());
+        child.args(["--exact", "util::log::tests::log_in_child", "--nocapture", "--test-threads=1"]).env(CHILD, verbosity.to_string());
+        match spec {
+            Some(spec) => child.env("LITHIA_LOG", spec),
+            None => child.env_remove("LITHIA_LOG")
+        };
+        let out = child.output().unwrap();
+        assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+        String::from_utf8_lossy(&out.stderr).into_owned()
+    }
+
+    #[test]
+    fn logs_only_when_enabled() {
+        let building = "[debug codegen] generating code > compiling fn answer: building fn() -> i32";
+        let parsed = "[info parser] parsing: parsed 1 functions, 0 constants and 0 statics";
+        let quiet = stderr_of(0, None);
+        assert!(!quiet.contains("[debug") && !quiet.contains("[info"), "{quiet}");
+        let verbose = stderr_of(1, None);
+        assert!(verbose.contains(parsed) && !verbose.contains(building), "{verbose}");
+        let very_verbose = stderr_of(2, None);
+        assert!(very_verbose.contains(parsed) && very_verbose.contains(building), "{very_verbose}");
+        let codegen = stderr_of(0, Some("codegen=debug"));
+        assert!(codegen.contains(building) && !codegen.contains(parsed), "{codegen}");
+    }
+}
@@ -1 +1,6 @@
+pub(crate) mod cycles;
+pub(crate) mod glob;
 pub(crate) mod indexer;
+pub(crate) mod log;
+#[cfg(test)]
+pub(crate) mod testing;
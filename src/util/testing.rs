@@ -0,0 +1,156 @@
+use std::alloc::{GlobalAlloc, Layout, System};
+use std::cell::Cell;
+use std::ffi::CStr;
+use std::path::PathBuf;
+use std::process::Command;
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Once;
+use llvm_sys::{bit_writer, core};
+use crate::ast::cfg::Cfg;
+use crate::ast::Module;
+use crate::ast::parser::parse;
+use crate::c_str_ptr;
+use crate::compiler::{build_ir, Arguments};
+use crate::error::ParseError;
+use crate::llvm::LLVMModule;
+use crate::llvm::setup::check_llvm;
+use crate::source::Source;
+use crate::tokens::tokenizer::tokenize;
+
+// helpers for the tests of the compiler, which build code from strings instead of files
+
+static SETUP: Once = Once::new();
+
+/// Counts the bytes each thread allocates and frees, tests run in threads of their own
+struct CountingAlloc;
+
+thread_local! {
+    static ALLOCATED: Cell<usize> = const { Cell::new(0) };
+    static FREED: Cell<usize> = const { Cell::new(0) };
+}
+
+unsafe impl GlobalAlloc for CountingAlloc {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        // not counted while the thread is torn down
+        let _ = ALLOCATED.try_with(|a| a.set(a.get() + layout.size()));
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let _ = FREED.try_with(|f| f.set(f.get() + layout.size()));
+        System.dealloc(ptr, layout)
+    }
+}
+
+#[global_allocator]
+static ALLOCATOR: CountingAlloc = CountingAlloc;
+
+/// Runs `f`, returning what it returns and the bytes it allocated on the heap in total, freed or not.
+/// Memory llvm allocates itself is not counted.
+pub(crate) fn allocated_by<T>(f: impl FnOnce() -> T) -> (T, usize) {
+    let before = ALLOCATED.with(|a| a.get());
+    let r = f();
+    (r, ALLOCATED.with(|a| a.get()) - before)
+}
+
+/// Runs `f`, returning the bytes it allocated on the heap that are still allocated after it returns
+pub(crate) fn retained_by(f: impl FnOnce()) -> isize {
+    let live = || ALLOCATED.with(|a| a.get()) as isize - FREED.with(|f| f.get()) as isize;
+    let before = live();
+    f();
+    live() - before
+}
+
+/// Parses `code` as the main module, without desugaring it
+pub(crate) fn parse_module(code: &str) -> Result<Module, String> {
+    let tokens = tokenize(Rc::new(Source::from_string(code.to_string())), false).map_err(|e| e.render(false))?;
+    parse(tokens, ("main".to_string(), None), &Cfg::new("x86_64-pc-linux-gnu".to_string(), vec![])).map_err(|e| e.render(false))
+}
+
+/// Builds the ir of `code` as the main module, `args` are passed like on the command line
+pub(crate) fn build(code: &str, args: &[&str]) -> Result<LLVMModule, ParseError> {
+    build_source(Rc::new(Source::from_string(code.to_string())), args)
+}
+
+/// Like [`build`], for tests that need the source to point into it
+pub(crate) fn build_source(source: Rc<Source>, args: &[&str]) -> Result<LLVMModule, ParseError> {
+    SETUP.call_once(|| check_llvm().expect("llvm is not set up"));
+    let args = Arguments::parse(args.iter().map(|a| a.to_string()))?;
+    build_ir(source, &args, &mut 0)
+}
+
+/// The error building `code` fails with, rendered without color
+pub(crate) fn build_err(code: &str, args: &[&str]) -> String {
+    match build(code, args) {
+        Ok(_) => panic!("expected an error building:\n{code}"),
+        Err(e) => e.render(false)
+    }
+}
+
+/// The textual ir of `code`, panics with the rendered error if it doesn't build
+pub(crate) fn ir(code: &str, args: &[&str]) -> String {
+    let module = build(code, args).unwrap_or_else(|e| panic!("{}", e.render(false)));
+    unsafe {
+        let ir = core::LLVMPrintModuleToString(module.module);
+        let text = CStr::from_ptr(ir).to_string_lossy().to_string();
+        core::LLVMDisposeMessage(ir);
+        text
+    }
+}
+
+/// Output of a program run by [`run`]
+#[derive(Debug)]
+pub(crate) struct Run {
+    pub(crate) stdout: String,
+    pub(crate) stderr: String,
+    /// `None` if the program was killed, like by `abort`
+    pub(crate) code: Option<i32>
+}
+
+/// A fresh directory for the files of a single test
+pub(crate) fn temp_dir() -> PathBuf {
+    static COUNT: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!("lithia-test-{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed)));
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Builds `code` and runs it with the `lli` of the llvm the compiler is built against, with `envs` set
+pub(crate) fn run(code: &str, args: &[&str], envs: &[(&str, &str)]) -> Run {
+    run_source(Rc::new(Source::from_string(code.to_string())), args, envs)
+}
+
+/// Like [`run`], for programs that read files next to their source
+pub(crate) fn run_source(source: Rc<Source>, args: &[&str], envs: &[(&str, &str)]) -> Run {
+    let module = build_source(source, args).unwrap_or_else(|e| panic!("{}", e.render(false)));
+    let dir = temp_dir();
+    let bitcode = dir.join("main.bc");
+    assert_eq!(unsafe { bit_writer::LLVMWriteBitcodeToFile(module.module, c_str_ptr!(bitcode.to_string_lossy())) }, 0);
+    drop(module);
+    let output = Command::new(llvm_tool("lli")).arg(&bitcode).envs(envs.iter().copied()).output().expect("could not run lli");
+    std::fs::remove_dir_all(dir).unwrap();
+    Run {
+        stdout: String::from_utf8_lossy(&output.stdout).to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).to_string(),
+        code: output.status.code()
+    }
+}
+
+/// The ir of `code` after llvm's `opt` optimized it at `-O1`
+pub(crate) fn optimized_ir(code: &str, args: &[&str]) -> String {
+    let dir = temp_dir();
+    let file = dir.join("main.ll");
+    std::fs::write(&file, ir(code, args)).unwrap();
+    let output = Command::new(llvm_tool("opt")).args(["-O1", "-S"]).arg(&file).output().expect("could not run opt");
+    std::fs::remove_dir_all(dir).unwrap();
+    assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+    String::from_utf8_lossy(&output.stdout).to_string()
+}
+
+/// Path of a tool of the llvm the compiler is built against, like `lli`
+fn llvm_tool(name: &str) -> PathBuf {
+    let llvm_config = format!("{}/bin/llvm-config{}", env!("LLVM_SYS_150_PREFIX"), std::env::consts::EXE_SUFFIX);
+    let output = Command::new(llvm_config).arg("--bindir").output().expect("could not run llvm-config");
+    PathBuf::from(String::from_utf8_lossy(&output.stdout).trim()).join(format!("{name}{}", std::env::consts::EXE_SUFFIX))
+}